@@ -0,0 +1,43 @@
+//! Decode pipeline benchmarks on synthetic frame sets.
+//!
+//! `cargo bench` times each stage at a few transfer sizes; an optional
+//! argument only runs the stages whose name contains it. Without `--bench`,
+//! as under `cargo test --benches`, every stage runs once on a small set.
+
+use qr_recv::bench::{measure, STAGES};
+use qr_recv::synth::{Options, Transfer};
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let timed = args.iter().any(|a| a == "--bench");
+    let filter = args.iter().find(|a| !a.starts_with("--"));
+    let (sizes, iterations): (&[usize], u32) = if timed {
+        (&[4 * 1024, 64 * 1024, 512 * 1024], 10)
+    } else {
+        (&[4 * 1024], 1)
+    };
+    for &size in sizes {
+        let workload = Transfer::new(&Options {
+            size,
+            ..Options::default()
+        });
+        let frames = workload.frames.len() as f64;
+        for (name, stage) in STAGES {
+            if filter.is_some_and(|f| !name.contains(f.as_str())) {
+                continue;
+            }
+            let best = measure(&workload, stage, iterations);
+            if timed {
+                println!(
+                    "{}/{}: {:.1?} per pass, {:.1} frames/s",
+                    name,
+                    size,
+                    best,
+                    frames / best.as_secs_f64()
+                );
+            } else {
+                println!("{}/{}: ok", name, size);
+            }
+        }
+    }
+}
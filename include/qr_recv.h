@@ -0,0 +1,60 @@
+/* Generated with cbindgen --config cbindgen.toml; do not edit by hand. */
+
+#ifndef QR_RECV_H
+#define QR_RECV_H
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+typedef enum QrRecvStatus {
+  QR_RECV_STATUS_OK = 0,
+  /* A null handle or buffer was passed. */
+  QR_RECV_STATUS_NULL_POINTER = 1,
+  /* The frame could not be decoded or failed its hash. */
+  QR_RECV_STATUS_INVALID_FRAME = 2,
+  /* Segments or the hash frame are still missing. */
+  QR_RECV_STATUS_INCOMPLETE = 3,
+  /* Every segment arrived but the assembled file does not match its hash. */
+  QR_RECV_STATUS_HASH_MISMATCH = 4,
+  /* The decoder panicked on malformed input; the handle should be freed. */
+  QR_RECV_STATUS_INTERNAL = 5,
+  /* The sender speaks a protocol major version this library does not decode. */
+  QR_RECV_STATUS_UNSUPPORTED_VERSION = 6,
+} QrRecvStatus;
+
+/* Opaque receiver handle. */
+typedef struct QrRecv QrRecv;
+
+typedef struct QrRecvProgress {
+  /* Number of data segments, only meaningful when `has_total` is set. */
+  uint64_t total;
+  bool has_total;
+  uint64_t received;
+  bool has_hash;
+  bool complete;
+} QrRecvProgress;
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+QrRecv *qr_recv_new(void);
+
+void qr_recv_free(QrRecv *recv);
+
+QrRecvStatus qr_recv_push_frame(QrRecv *recv, const uint8_t *ptr, size_t len);
+
+QrRecvStatus qr_recv_push_text(QrRecv *recv, const uint8_t *ptr, size_t len);
+
+QrRecvStatus qr_recv_progress(const QrRecv *recv, QrRecvProgress *out);
+
+QrRecvStatus qr_recv_take_output(QrRecv *recv, uint8_t **out, size_t *out_len);
+
+void qr_recv_free_output(uint8_t *ptr, size_t len);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* QR_RECV_H */
@@ -0,0 +1,228 @@
+use std::path;
+
+use serde::Serialize;
+
+use crate::decoder::QrSendDecoder;
+use crate::images::{ImageSequence, ReadOptions};
+use crate::protocol::{self, PayloadEncoding};
+use crate::qr::{self, EccLevel};
+use crate::stats::CaptureStats;
+
+/// Below this many pixels per module zbar stops finding symbols reliably.
+const MIN_MODULE_PX: f64 = 3.0;
+/// Each displayed frame should be captured at least this many times.
+const TARGET_CAPTURES_PER_FRAME: f64 = 2.5;
+const MAX_TILES: u32 = 4;
+/// Id width assumed when the sample contains no metadata to read it from.
+const DEFAULT_ID_LEN: usize = 2;
+
+#[derive(clap::Args)]
+pub struct AdviseArgs {
+    /// Directory holding a short trial capture
+    #[clap(short, long)]
+    sample: String,
+    #[clap(flatten)]
+    read: ReadOptions,
+    /// Frame rate the trial capture was recorded at
+    #[clap(long, default_value_t = 30.0)]
+    capture_fps: f64,
+    /// Error correction level used by the trial sender
+    #[clap(long, value_enum, default_value_t = EccLevel::M)]
+    ecc: EccLevel,
+}
+
+/// Sender settings for the next transfer.
+#[derive(Serialize, Debug)]
+pub struct Advice {
+    pub version: u32,
+    pub ecc: EccLevel,
+    pub fps: f64,
+    pub tiles: (u32, u32),
+    /// Content bytes per data frame, the sender's `--chunk-size`.
+    pub chunk_size: usize,
+    pub predicted_throughput: f64,
+}
+impl Advice {
+    pub fn print(&self) {
+        println!("recommended qr version: {}", self.version);
+        println!("recommended ecc level: {:?}", self.ecc);
+        println!("recommended sender fps: {:.1}", self.fps);
+        println!("recommended tiling: {}x{}", self.tiles.0, self.tiles.1);
+        println!("recommended chunk size: {} bytes", self.chunk_size);
+        println!(
+            "predicted throughput: {:.0} bytes/s",
+            self.predicted_throughput
+        );
+    }
+}
+
+fn recommend_ecc(success: f64) -> EccLevel {
+    if success >= 0.95 {
+        EccLevel::L
+    } else if success >= 0.85 {
+        EccLevel::M
+    } else if success >= 0.7 {
+        EccLevel::Q
+    } else {
+        EccLevel::H
+    }
+}
+
+/// Frame bytes a symbol holds once they are written in `encoding`, after
+/// the protocol prefix when `magic` is set.
+fn frame_capacity(version: u32, ecc: EccLevel, encoding: PayloadEncoding, magic: bool) -> usize {
+    let prefix = if magic { protocol::MAGIC.len() } else { 0 };
+    let bytes = qr::byte_capacity(version, ecc).saturating_sub(prefix);
+    let alphanumeric = qr::alphanumeric_capacity(version, ecc).saturating_sub(prefix);
+    match encoding {
+        PayloadEncoding::Base64 | PayloadEncoding::Base64Url => bytes / 4 * 3,
+        PayloadEncoding::Base45 => alphanumeric / 3 * 2,
+        PayloadEncoding::Base32 => alphanumeric / 8 * 5,
+        PayloadEncoding::Raw => bytes,
+    }
+}
+
+/// Frame bytes left for segment content once the tag, id and hash are paid for.
+fn chunk_size(
+    version: u32,
+    ecc: EccLevel,
+    encoding: PayloadEncoding,
+    magic: bool,
+    id_len: usize,
+    hash_len: usize,
+) -> usize {
+    frame_capacity(version, ecc, encoding, magic).saturating_sub(1 + id_len + hash_len)
+}
+
+pub fn advise(stats: &CaptureStats, capture_fps: f64, current_ecc: EccLevel) -> Option<Advice> {
+    let success = stats.verify_rate();
+    let side = stats.median_symbol_side()?;
+    let text_len = stats.median_text_len()?;
+    let (width, height) = stats.frame_size()?;
+    let hash_len = stats.median_hash_len().unwrap_or(8);
+
+    // What the sample tells us about the current symbol density.
+    let current_version = qr::min_version_for(text_len, current_ecc).unwrap_or(qr::MAX_VERSION);
+    let module_px = side / qr::side_modules(current_version) as f64;
+
+    // Denser symbols when decoding is comfortable, coarser ones when it struggles.
+    let target_px = if success >= 0.95 {
+        (module_px * 0.8).max(MIN_MODULE_PX)
+    } else if success >= 0.75 {
+        module_px.max(MIN_MODULE_PX)
+    } else {
+        (module_px * 1.25).max(MIN_MODULE_PX)
+    };
+    let max_modules = (side / target_px) as u32;
+    let version = (max_modules.saturating_sub(17) / 4).clamp(qr::MIN_VERSION, qr::MAX_VERSION);
+    let ecc = recommend_ecc(success);
+
+    // Only tile once a single symbol cannot grow any further.
+    let tiles = if version == qr::MAX_VERSION {
+        let tile_px = (qr::side_modules(version) + 2 * qr::QUIET_ZONE) as f64 * target_px;
+        (
+            ((width as f64 / tile_px) as u32).clamp(1, MAX_TILES),
+            ((height as f64 / tile_px) as u32).clamp(1, MAX_TILES),
+        )
+    } else {
+        (1, 1)
+    };
+
+    let fps = (capture_fps / TARGET_CAPTURES_PER_FRAME).max(1.0);
+    let chunk_size = chunk_size(
+        version,
+        ecc,
+        PayloadEncoding::Base64,
+        false,
+        DEFAULT_ID_LEN,
+        hash_len,
+    );
+    let predicted_success = success.max(0.5);
+    let predicted_throughput =
+        chunk_size as f64 * (tiles.0 * tiles.1) as f64 * fps * predicted_success;
+    Some(Advice {
+        version,
+        ecc,
+        fps,
+        tiles,
+        chunk_size,
+        predicted_throughput,
+    })
+}
+
+/// Advice from how the frames of a finished run decoded, for when only the
+/// decoder's counters are left. Symbol sizes are not known here, so the
+/// version follows the failure rate from the one the frames needed, which
+/// assumes the sender's default error correction.
+pub fn advise_from_run(decoder: &QrSendDecoder, capture_fps: f64) -> Option<Advice> {
+    let md = decoder.metadata.as_ref()?;
+    let current_chunk = decoder
+        .data_segments
+        .values()
+        .map(|s| s.data().len())
+        .max()?;
+    if decoder.frames_read == 0 {
+        return None;
+    }
+    let failed = decoder.undecoded_frames
+        + decoder.rejected_frames
+        + decoder.undersized_frames
+        + decoder.wrong_length_frames
+        + decoder.out_of_range_frames
+        + decoder.unknown_frames;
+    let success = 1.0 - (failed as f64 / decoder.frames_read as f64).min(1.0);
+
+    let id_len = protocol::try_id_len(&md.id_type).unwrap_or(DEFAULT_ID_LEN);
+    let hash_len = md.hash_len as usize;
+    let frame_len = 1 + id_len + current_chunk + hash_len;
+    let current_version = (qr::MIN_VERSION..=qr::MAX_VERSION)
+        .find(|&v| frame_capacity(v, EccLevel::M, md.payload_encoding, md.magic) >= frame_len)
+        .unwrap_or(qr::MAX_VERSION);
+    let version = if success >= 0.95 {
+        current_version + 2
+    } else if success >= 0.75 {
+        current_version
+    } else {
+        current_version.saturating_sub(2)
+    }
+    .clamp(qr::MIN_VERSION, qr::MAX_VERSION);
+    let ecc = recommend_ecc(success);
+
+    let fps = (capture_fps / TARGET_CAPTURES_PER_FRAME).max(1.0);
+    let chunk_size = chunk_size(
+        version,
+        ecc,
+        md.payload_encoding,
+        md.magic,
+        id_len,
+        hash_len,
+    );
+    Some(Advice {
+        version,
+        ecc,
+        fps,
+        tiles: (1, 1),
+        chunk_size,
+        predicted_throughput: chunk_size as f64 * fps * success.max(0.5),
+    })
+}
+
+pub fn run(args: &AdviseArgs) {
+    let img_seq = ImageSequence {
+        image_dir: path::PathBuf::from(&args.sample),
+        options: args.read.clone(),
+    };
+    let stats = CaptureStats::collect(img_seq.into_iter());
+    println!("sampled frames: {}", stats.frames.len());
+    println!("detect rate: {:.1}%", stats.detect_rate() * 100.0);
+    println!("verify rate: {:.1}%", stats.verify_rate() * 100.0);
+    println!("mean decode time: {:?}", stats.mean_decode_time());
+    if let Some(c) = stats.captures_per_sender_frame() {
+        println!("captures per sender frame: {:.2}", c);
+        println!("estimated sender fps: {:.1}", args.capture_fps / c);
+    }
+    match advise(&stats, args.capture_fps, args.ecc) {
+        Some(advice) => advice.print(),
+        None => println!("no qrcode found in sample, cannot advise"),
+    }
+}
@@ -0,0 +1,172 @@
+use std::collections::HashSet;
+use std::path;
+use std::time::Duration;
+
+use crate::advise::advise;
+use crate::decoder::QrSendDecoder;
+use crate::images::{ImageSequence, ReadOptions};
+use crate::qr::EccLevel;
+use crate::stats::{CaptureStats, FrameStat};
+
+#[derive(clap::Args)]
+pub struct AnalyzeArgs {
+    /// Capture directory to analyze
+    #[clap(short, long)]
+    from: String,
+    #[clap(flatten)]
+    read: ReadOptions,
+    /// Number of equal stretches of the capture failures are counted over
+    #[clap(long, default_value_t = 10, value_parser = clap::value_parser!(u64).range(1..))]
+    buckets: u64,
+    /// Also print one line per frame
+    #[clap(long)]
+    per_frame: bool,
+}
+
+/// What became of one frame, for the per-frame listing.
+fn outcome(frame: &FrameStat, seen: &mut HashSet<Vec<u8>>) -> &'static str {
+    match &frame.payload {
+        Some(payload) if !seen.insert(payload.clone()) => "duplicate",
+        Some(_) => "verified",
+        None if frame.detected() => "unverified",
+        None => "no qrcode",
+    }
+}
+
+/// Runs of consecutive frames that did not verify, as `(first, length)`.
+pub fn failure_runs(frames: &[FrameStat]) -> Vec<(usize, usize)> {
+    let mut runs = Vec::new();
+    let mut start = None;
+    for (i, frame) in frames.iter().enumerate() {
+        match (frame.verified(), start) {
+            (false, None) => start = Some(i),
+            (true, Some(s)) => {
+                runs.push((s, i - s));
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    if let Some(s) = start {
+        runs.push((s, frames.len() - s));
+    }
+    runs
+}
+
+fn percentile(sorted: &[Duration], p: f64) -> Duration {
+    sorted[((sorted.len() - 1) as f64 * p).round() as usize]
+}
+
+fn millis(d: Duration) -> f64 {
+    d.as_secs_f64() * 1000.0
+}
+
+pub fn run(args: &AnalyzeArgs) {
+    let img_seq = ImageSequence {
+        image_dir: path::PathBuf::from(&args.from),
+        options: args.read.clone(),
+    };
+    let stats = CaptureStats::collect(img_seq.into_iter());
+    let frames = &stats.frames;
+    println!("frames: {}", frames.len());
+    if frames.is_empty() {
+        return;
+    }
+    println!("detect rate: {:.1}%", stats.detect_rate() * 100.0);
+    println!("verify rate: {:.1}%", stats.verify_rate() * 100.0);
+
+    let mut latencies: Vec<Duration> = frames.iter().map(|f| f.decode_time).collect();
+    latencies.sort();
+    println!(
+        "decode latency: min {:.1} ms, median {:.1} ms, p95 {:.1} ms, max {:.1} ms",
+        millis(latencies[0]),
+        millis(percentile(&latencies, 0.5)),
+        millis(percentile(&latencies, 0.95)),
+        millis(latencies[latencies.len() - 1])
+    );
+
+    if let Some(brightness) = stats.median_brightness() {
+        println!(
+            "exposure: median brightness {}, {:.1}% under-exposed, {:.1}% over-exposed, {:.1}% washed out",
+            brightness,
+            stats.exposure_rate("under-exposed") * 100.0,
+            stats.exposure_rate("over-exposed") * 100.0,
+            stats.exposure_rate("washed out") * 100.0
+        );
+    }
+
+    let mut seen = HashSet::new();
+    let outcomes: Vec<&str> = frames.iter().map(|f| outcome(f, &mut seen)).collect();
+    let verified = frames.iter().filter(|f| f.verified()).count();
+    let duplicates = outcomes.iter().filter(|&&o| o == "duplicate").count();
+    if verified > 0 {
+        println!(
+            "duplicates: {} of {} verified frames ({:.1}%)",
+            duplicates,
+            verified,
+            duplicates as f64 * 100.0 / verified as f64
+        );
+    }
+
+    // Replaying the verified frames tells how much of the file they carry.
+    let mut decoder = QrSendDecoder::new();
+    for payload in frames.iter().filter_map(|f| f.payload.clone()) {
+        decoder.push(payload);
+    }
+    match &decoder.metadata {
+        Some(md) => {
+            let bytes = decoder.assembled_len();
+            println!(
+                "content: {} bytes in {} of {} segments, {:.1} bytes per frame",
+                bytes,
+                decoder.data_segments.len(),
+                md.qrcode_count,
+                bytes as f64 / frames.len() as f64
+            );
+        }
+        None => println!("content: unknown, the capture holds no metadata frame"),
+    }
+
+    // The capture does not say which level the sender used; assume its default.
+    if let Some(advice) = advise(&stats, args.read.source_fps, EccLevel::M) {
+        advice.print();
+    }
+
+    let runs = failure_runs(frames);
+    match runs.iter().max_by_key(|&&(_, len)| len) {
+        Some(&(first, len)) => println!(
+            "failure runs: {}, longest {} frames from frame {}",
+            runs.len(),
+            len,
+            first
+        ),
+        None => println!("failure runs: none"),
+    }
+    println!("failures along the capture:");
+    let per_bucket = (frames.len() as u64).div_ceil(args.buckets) as usize;
+    for (i, bucket) in frames.chunks(per_bucket).enumerate() {
+        let failed = bucket.iter().filter(|f| !f.verified()).count();
+        println!(
+            "  frames {:>6}-{:<6} {:5.1}%",
+            i * per_bucket,
+            i * per_bucket + bucket.len() - 1,
+            failed as f64 * 100.0 / bucket.len() as f64
+        );
+    }
+
+    if args.per_frame {
+        for (i, (frame, outcome)) in frames.iter().zip(&outcomes).enumerate() {
+            println!(
+                "frame {:>6}: {:8.1} ms {:>5} bytes  levels {:>3}-{:>3}-{:<3} {:<13}  {}",
+                i,
+                millis(frame.decode_time),
+                frame.text_len.unwrap_or(0),
+                frame.exposure.low,
+                frame.exposure.median,
+                frame.exposure.high,
+                frame.exposure.label(),
+                outcome
+            );
+        }
+    }
+}
@@ -0,0 +1,171 @@
+use std::{fmt, io};
+
+use crate::decoder::QrSendDecoder;
+use crate::protocol::{decode_payload, Manifest};
+use crate::ranges::SegmentRanges;
+use crate::transfer::Protocol;
+
+/// Anything that yields captured frames in capture order.
+///
+/// Implemented for every iterator over images, so a `Vec`, a directory
+/// walker or a camera adapter can all be handed to [`Decoder::run`].
+pub trait FrameSource {
+    fn next_frame(&mut self) -> Option<image::DynamicImage>;
+}
+impl<I: Iterator<Item = image::DynamicImage>> FrameSource for I {
+    fn next_frame(&mut self) -> Option<image::DynamicImage> {
+        self.next()
+    }
+}
+
+/// How far a transfer has come.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Progress {
+    /// Number of data segments in the transfer, once the metadata is known.
+    pub total: Option<u64>,
+    pub received: u64,
+    /// Whether the whole-file hash frame has been seen.
+    pub has_hash: bool,
+    /// All segments and the hash have arrived; [`Decoder::finish`] can succeed.
+    pub complete: bool,
+}
+
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// No complete metadata was received.
+    NoMetadata,
+    /// The sender speaks a protocol major version this receiver does not decode.
+    UnsupportedVersion(String),
+    /// Some data segments never arrived.
+    Incomplete { missing: SegmentRanges },
+    /// The whole-file hash frame never arrived.
+    NoHash,
+    /// The assembled file does not match the sender's hash.
+    HashMismatch,
+    /// A frame could not be decoded or failed its per-frame hash.
+    InvalidFrame,
+    /// The verified file could not be written out.
+    Io(io::Error),
+}
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::NoMetadata => write!(f, "no metadata received"),
+            Error::UnsupportedVersion(v) => write!(f, "unsupported protocol version {}", v),
+            Error::Incomplete { missing } => write!(f, "{} segments missing", missing.len()),
+            Error::NoHash => write!(f, "no hash frame received"),
+            Error::HashMismatch => write!(f, "file hash check failed"),
+            Error::InvalidFrame => write!(f, "invalid frame"),
+            Error::Io(e) => write!(f, "could not write the file: {}", e),
+        }
+    }
+}
+impl std::error::Error for Error {}
+
+/// Order-tolerant receiver for a single transfer.
+///
+/// Frames may be pushed as images, as the text carried by a qrcode, or as
+/// already decoded frame bytes; data frames seen before the metadata are
+/// held, up to a limit, and replayed once it arrives.
+pub struct Decoder {
+    inner: QrSendDecoder,
+}
+impl Default for Decoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+impl Decoder {
+    pub fn new() -> Self {
+        Decoder {
+            inner: QrSendDecoder::new(),
+        }
+    }
+
+    #[cfg(feature = "zbar")]
+    /// Scan a captured image and feed the first qrcode found in it, or in
+    /// each color channel when the metadata declares them.
+    pub fn push_image(&mut self, img: &image::DynamicImage) -> Progress {
+        self.inner.push_image(img);
+        self.progress()
+    }
+
+    /// Feed the content of a qrcode, e.g. from a browser scanner, in any
+    /// supported payload encoding.
+    pub fn push_text(&mut self, text: &[u8]) -> Result<Progress, Error> {
+        let Some((_, frame)) = decode_payload(text, self.inner.metadata.as_ref()) else {
+            self.inner.push_foreign();
+            return Err(Error::InvalidFrame);
+        };
+        self.push_frame(&frame)
+    }
+
+    /// Feed decoded frame bytes. Frames of unknown type are skipped.
+    pub fn push_frame(&mut self, frame: &[u8]) -> Result<Progress, Error> {
+        let invalid = |d: &QrSendDecoder| {
+            d.rejected_frames + d.undersized_frames + d.wrong_length_frames + d.out_of_range_frames
+        };
+        let before = invalid(&self.inner);
+        self.inner.push(frame.to_vec());
+        if invalid(&self.inner) != before {
+            return Err(Error::InvalidFrame);
+        }
+        Ok(self.progress())
+    }
+
+    #[cfg(feature = "zbar")]
+    /// Feed every frame of `source`, stopping early once the transfer is complete.
+    pub fn run<S: FrameSource>(&mut self, mut source: S) -> Progress {
+        while let Some(img) = source.next_frame() {
+            if self.push_image(&img).complete {
+                break;
+            }
+        }
+        self.progress()
+    }
+
+    pub fn progress(&self) -> Progress {
+        Progress {
+            total: self.inner.metadata.as_ref().map(|md| md.qrcode_count),
+            received: self.inner.data_segments.len() as u64,
+            has_hash: !self.inner.total_hash.is_empty(),
+            complete: self.inner.is_complete(),
+        }
+    }
+
+    /// Protocol version announced by a sender this receiver cannot decode.
+    pub fn unsupported_version(&self) -> Option<&str> {
+        self.inner.unsupported_version.as_deref()
+    }
+
+    /// Who sent the transfer, once a manifest frame has named it.
+    pub fn manifest(&self) -> Option<&Manifest> {
+        self.inner.manifest.as_ref()
+    }
+
+    /// Runs of data segment ids still missing, empty until the metadata is
+    /// known. As runs, the set stays small however many segments the
+    /// metadata claims.
+    pub fn missing(&self) -> SegmentRanges {
+        self.inner.missing_ranges()
+    }
+
+    /// Assemble and verify the transferred file.
+    pub fn finish(self) -> Result<Vec<u8>, Error> {
+        self.try_finish()
+    }
+
+    /// [`finish`](Self::finish), leaving the decoder as it was when it fails.
+    pub(crate) fn try_finish(&self) -> Result<Vec<u8>, Error> {
+        Protocol::finish(&self.inner)
+    }
+
+    /// Verify the transferred file and write it to `out` segment by
+    /// segment, without assembling it in memory. Returns its length.
+    pub fn finish_into(self, out: &mut impl io::Write) -> Result<u64, Error> {
+        self.inner.verify()?;
+        self.inner.write_in_order(out).map_err(Error::Io)
+    }
+}
@@ -0,0 +1,231 @@
+//! [`AsyncDecoder`], the [`Decoder`] for async frontends.
+//!
+//! The decoder runs on a thread of its own and the futures only wait for
+//! its replies, so scanning an image never holds up an executor thread.
+//! Nothing here depends on a particular runtime.
+
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+use std::thread;
+
+use crate::api::{Decoder, Error, Progress};
+use crate::ranges::SegmentRanges;
+
+/// Work handed to the decoder thread.
+enum Request {
+    #[cfg(feature = "zbar")]
+    Image(image::DynamicImage, Reply<Progress>),
+    Text(Vec<u8>, Reply<Result<Progress, Error>>),
+    Frame(Vec<u8>, Reply<Result<Progress, Error>>),
+    Missing(Reply<SegmentRanges>),
+    Finish(Reply<Result<Vec<u8>, Error>>),
+}
+
+/// One value on its way from the decoder thread, and the task waiting on it.
+struct Slot<T> {
+    value: Option<T>,
+    waker: Option<Waker>,
+    /// The decoder thread is gone without replying.
+    dropped: bool,
+}
+
+/// The decoder thread's end of a [`Pending`] reply.
+struct Reply<T>(Arc<Mutex<Slot<T>>>);
+impl<T> Reply<T> {
+    fn send(self, value: T) {
+        let mut slot = self.0.lock().unwrap();
+        slot.value = Some(value);
+        if let Some(waker) = slot.waker.take() {
+            waker.wake();
+        }
+    }
+}
+impl<T> Drop for Reply<T> {
+    fn drop(&mut self) {
+        let mut slot = self.0.lock().unwrap();
+        slot.dropped = true;
+        if let Some(waker) = slot.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+/// A reply of the decoder thread, ready once it has done the work.
+struct Pending<T>(Arc<Mutex<Slot<T>>>);
+impl<T> Future for Pending<T> {
+    type Output = T;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<T> {
+        let mut slot = self.0.lock().unwrap();
+        if let Some(value) = slot.value.take() {
+            return Poll::Ready(value);
+        }
+        // Requests outlive the thread only when it panicked.
+        assert!(!slot.dropped, "the decoder thread panicked");
+        slot.waker = Some(cx.waker().clone());
+        Poll::Pending
+    }
+}
+
+fn reply<T>() -> (Reply<T>, Pending<T>) {
+    let slot = Arc::new(Mutex::new(Slot {
+        value: None,
+        waker: None,
+        dropped: false,
+    }));
+    (Reply(slot.clone()), Pending(slot))
+}
+
+/// The latest progress, numbered so watchers can tell it changed.
+struct Watched {
+    progress: Progress,
+    version: u64,
+    wakers: Vec<Waker>,
+}
+impl Watched {
+    fn update(&mut self, progress: Progress) {
+        if progress == self.progress {
+            return;
+        }
+        self.progress = progress;
+        self.version += 1;
+        for waker in self.wakers.drain(..) {
+            waker.wake();
+        }
+    }
+}
+
+/// A [`Decoder`] on a thread of its own, fed and watched through futures.
+///
+/// Handles are cheap to clone, so a capture task can push frames while a
+/// UI task waits on [`AsyncDecoder::complete`] or a [`ProgressWatch`]. The
+/// thread stops once every handle is dropped.
+#[derive(Clone)]
+pub struct AsyncDecoder {
+    requests: mpsc::Sender<Request>,
+    watched: Arc<Mutex<Watched>>,
+}
+impl Default for AsyncDecoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+impl AsyncDecoder {
+    pub fn new() -> Self {
+        Self::from_decoder(Decoder::new())
+    }
+
+    /// Move `decoder`, with whatever it already holds, onto its own thread.
+    pub fn from_decoder(mut decoder: Decoder) -> Self {
+        let (requests, incoming) = mpsc::channel();
+        let watched = Arc::new(Mutex::new(Watched {
+            progress: decoder.progress(),
+            version: 0,
+            wakers: Vec::new(),
+        }));
+        let shared = watched.clone();
+        thread::spawn(move || {
+            for request in incoming {
+                match request {
+                    #[cfg(feature = "zbar")]
+                    Request::Image(img, reply) => reply.send(decoder.push_image(&img)),
+                    Request::Text(text, reply) => reply.send(decoder.push_text(&text)),
+                    Request::Frame(frame, reply) => reply.send(decoder.push_frame(&frame)),
+                    Request::Missing(reply) => reply.send(decoder.missing()),
+                    Request::Finish(reply) => reply.send(std::mem::take(&mut decoder).finish()),
+                }
+                shared.lock().unwrap().update(decoder.progress());
+            }
+        });
+        AsyncDecoder { requests, watched }
+    }
+
+    fn request<T>(&self, request: impl FnOnce(Reply<T>) -> Request) -> Pending<T> {
+        let (reply, pending) = reply();
+        // The thread outlives every handle, unless it panicked; the dropped
+        // reply then says so.
+        let _ = self.requests.send(request(reply));
+        pending
+    }
+
+    #[cfg(feature = "zbar")]
+    /// Scan a captured image on the decoder thread, as [`Decoder::push_image`].
+    pub fn push_image(&self, img: image::DynamicImage) -> impl Future<Output = Progress> {
+        self.request(|reply| Request::Image(img, reply))
+    }
+
+    /// Feed the content of a qrcode, as [`Decoder::push_text`].
+    pub fn push_text(&self, text: &[u8]) -> impl Future<Output = Result<Progress, Error>> {
+        let text = text.to_vec();
+        self.request(|reply| Request::Text(text, reply))
+    }
+
+    /// Feed decoded frame bytes, as [`Decoder::push_frame`].
+    pub fn push_frame(&self, frame: &[u8]) -> impl Future<Output = Result<Progress, Error>> {
+        let frame = frame.to_vec();
+        self.request(|reply| Request::Frame(frame, reply))
+    }
+
+    /// Progress as of the last frame the decoder thread took.
+    pub fn progress(&self) -> Progress {
+        self.watched.lock().unwrap().progress.clone()
+    }
+
+    /// Runs of data segment ids still missing, as [`Decoder::missing`].
+    pub fn missing(&self) -> impl Future<Output = SegmentRanges> {
+        self.request(Request::Missing)
+    }
+
+    /// Notified of each change of progress from now on.
+    pub fn watch(&self) -> ProgressWatch {
+        ProgressWatch {
+            watched: self.watched.clone(),
+            seen: self.watched.lock().unwrap().version,
+        }
+    }
+
+    /// Ready with the final progress once every segment and the hash have
+    /// arrived, for [`AsyncDecoder::finish`] to succeed.
+    pub fn complete(&self) -> impl Future<Output = Progress> {
+        let mut watch = self.watch();
+        async move {
+            let mut progress = watch.watched.lock().unwrap().progress.clone();
+            while !progress.complete {
+                progress = watch.changed().await;
+            }
+            progress
+        }
+    }
+
+    /// Assemble and verify the transferred file, as [`Decoder::finish`],
+    /// once the frames pushed before it are in. Frames pushed afterwards
+    /// through other handles start a new transfer.
+    pub fn finish(self) -> impl Future<Output = Result<Vec<u8>, Error>> {
+        self.request(Request::Finish)
+    }
+}
+
+/// Progress of an [`AsyncDecoder`] as it changes.
+pub struct ProgressWatch {
+    watched: Arc<Mutex<Watched>>,
+    seen: u64,
+}
+impl ProgressWatch {
+    /// The progress once it differs from the last one this watch returned.
+    pub fn changed(&mut self) -> impl Future<Output = Progress> + '_ {
+        std::future::poll_fn(move |cx| {
+            let mut watched = self.watched.lock().unwrap();
+            if watched.version != self.seen {
+                self.seen = watched.version;
+                return Poll::Ready(watched.progress.clone());
+            }
+            if !watched.wakers.iter().any(|w| w.will_wake(cx.waker())) {
+                watched.wakers.push(cx.waker().clone());
+            }
+            Poll::Pending
+        })
+    }
+}
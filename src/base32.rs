@@ -0,0 +1,44 @@
+//! Base32 (RFC 4648) without padding: five bytes in eight characters, all
+//! of them in the QR alphanumeric set, which `=` is not.
+
+const CHARSET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+pub fn encode(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity((data.len() * 8).div_ceil(5));
+    let (mut bits, mut pending) = (0u32, 0u32);
+    for &byte in data {
+        bits = bits << 8 | byte as u32;
+        pending += 8;
+        while pending >= 5 {
+            pending -= 5;
+            out.push(CHARSET[(bits >> pending) as usize & 31]);
+        }
+    }
+    if pending > 0 {
+        out.push(CHARSET[(bits << (5 - pending)) as usize & 31]);
+    }
+    out
+}
+
+/// Append the bytes of `text` to `out`; false, with `out` left partly
+/// written, when `text` is no base32. Padded input is accepted too, and
+/// lowercase letters.
+pub fn decode_into(text: &[u8], out: &mut Vec<u8>) -> bool {
+    let end = text.iter().rposition(|&c| c != b'=').map_or(0, |i| i + 1);
+    out.reserve(end * 5 / 8);
+    let (mut bits, mut pending) = (0u32, 0u32);
+    for &c in &text[..end] {
+        let Some(value) = CHARSET.iter().position(|&a| a == c.to_ascii_uppercase()) else {
+            return false;
+        };
+        bits = bits << 5 | value as u32;
+        pending += 5;
+        if pending >= 8 {
+            pending -= 8;
+            out.push((bits >> pending) as u8);
+        }
+    }
+    // Leftover bits are padding and must be zero; five or more would mean
+    // a character too many.
+    pending < 5 && bits & ((1 << pending) - 1) == 0
+}
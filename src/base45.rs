@@ -0,0 +1,42 @@
+//! Base45 (RFC 9285): two bytes in three characters of the QR alphanumeric
+//! set, which alphanumeric mode packs into 16.5 bits.
+
+const CHARSET: &[u8] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZ $%*+-./:";
+
+pub fn encode(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len().div_ceil(2) * 3);
+    for pair in data.chunks(2) {
+        let (mut n, digits) = match pair {
+            [a, b] => ((*a as usize) << 8 | *b as usize, 3),
+            [a] => (*a as usize, 2),
+            _ => unreachable!(),
+        };
+        for _ in 0..digits {
+            out.push(CHARSET[n % 45]);
+            n /= 45;
+        }
+    }
+    out
+}
+
+/// Append the bytes of `text` to `out`; false, with `out` left partly
+/// written, when `text` is no base45.
+pub fn decode_into(text: &[u8], out: &mut Vec<u8>) -> bool {
+    out.reserve(text.len() / 3 * 2 + 1);
+    for group in text.chunks(3) {
+        let (mut n, mut weight) = (0, 1);
+        for c in group {
+            let Some(value) = CHARSET.iter().position(|a| a == c) else {
+                return false;
+            };
+            n += value * weight;
+            weight *= 45;
+        }
+        match group.len() {
+            3 if n <= u16::MAX as usize => out.extend_from_slice(&(n as u16).to_be_bytes()),
+            2 if n <= u8::MAX as usize => out.push(n as u8),
+            _ => return false,
+        }
+    }
+    true
+}
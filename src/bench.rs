@@ -0,0 +1,95 @@
+//! Decode pipeline stages timed by `qr-recv bench` and `benches/`.
+//! Not part of the stable library API.
+
+use std::time::{Duration, Instant};
+
+use crate::decoder::{scan, QrSendDecoder};
+use crate::protocol::{guess_hash_len, PayloadEncoding};
+use crate::synth::{Options, Transfer};
+
+#[derive(clap::Args)]
+pub struct BenchArgs {
+    /// Size of the synthetic file that is sent, in bytes
+    #[clap(long, default_value_t = 64 * 1024)]
+    size: usize,
+    /// Content bytes carried by each data frame
+    #[clap(long, default_value_t = 512)]
+    chunk_size: usize,
+    /// Passes over the frame set per stage; the fastest one is reported
+    #[clap(long, default_value_t = 3)]
+    iterations: u32,
+}
+
+/// Locate and read every qrcode.
+pub fn grid_detection(w: &Transfer) -> usize {
+    w.images.iter().map(|img| scan(img).len()).sum()
+}
+
+/// Turn every qrcode text back into frame bytes.
+pub fn base64_decode(w: &Transfer) -> usize {
+    w.texts
+        .iter()
+        .filter_map(|t| PayloadEncoding::Base64.decode(t))
+        .count()
+}
+
+/// Check every frame's seal without knowing the hash length.
+pub fn hash_verification(w: &Transfer) -> usize {
+    w.frames.iter().filter_map(|f| guess_hash_len(f)).count()
+}
+
+/// Scan, verify and assemble the whole transfer from its images.
+pub fn assembly(w: &Transfer) -> usize {
+    let mut decoder = QrSendDecoder::new();
+    for img in &w.images {
+        decoder.push_image(img);
+    }
+    let file = decoder.assemble().expect("synthetic transfer is complete");
+    assert_eq!(file, w.file);
+    file.len()
+}
+
+pub type Stage = (&'static str, fn(&Transfer) -> usize);
+
+pub const STAGES: [Stage; 4] = [
+    ("grid detection", grid_detection),
+    ("base64 decode", base64_decode),
+    ("hash verification", hash_verification),
+    ("end-to-end assembly", assembly),
+];
+
+/// Fastest of `iterations` passes of `stage` over `w`.
+pub fn measure(w: &Transfer, stage: fn(&Transfer) -> usize, iterations: u32) -> Duration {
+    (0..iterations.max(1))
+        .map(|_| {
+            let start = Instant::now();
+            std::hint::black_box(stage(std::hint::black_box(w)));
+            start.elapsed()
+        })
+        .min()
+        .unwrap()
+}
+
+pub fn run(args: &BenchArgs) {
+    log::info!(
+        "building {} byte transfer in {} byte chunks",
+        args.size,
+        args.chunk_size
+    );
+    let workload = Transfer::new(&Options {
+        size: args.size,
+        chunk_size: args.chunk_size,
+        ..Options::default()
+    });
+    let frames = workload.frames.len() as f64;
+    println!("frames: {}", workload.frames.len());
+    for (name, stage) in STAGES {
+        let best = measure(&workload, stage, args.iterations);
+        println!(
+            "{:>20}: {:>10.1} frames/s ({:.1?} per pass)",
+            name,
+            frames / best.as_secs_f64(),
+            best
+        );
+    }
+}
@@ -0,0 +1,313 @@
+//! BLAKE3 in its default hash mode with a 32 byte output, for the
+//! whole-file check.
+
+const IV: [u32; 8] = [
+    0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab, 0x5be0cd19,
+];
+
+const PERMUTATION: [usize; 16] = [2, 6, 3, 10, 7, 0, 4, 13, 1, 11, 12, 5, 9, 14, 15, 8];
+
+const CHUNK_START: u32 = 1;
+const CHUNK_END: u32 = 2;
+const PARENT: u32 = 4;
+const ROOT: u32 = 8;
+
+const BLOCK_LEN: usize = 64;
+const CHUNK_LEN: usize = 1024;
+
+fn g(s: &mut [u32; 16], a: usize, b: usize, c: usize, d: usize, x: u32, y: u32) {
+    s[a] = s[a].wrapping_add(s[b]).wrapping_add(x);
+    s[d] = (s[d] ^ s[a]).rotate_right(16);
+    s[c] = s[c].wrapping_add(s[d]);
+    s[b] = (s[b] ^ s[c]).rotate_right(12);
+    s[a] = s[a].wrapping_add(s[b]).wrapping_add(y);
+    s[d] = (s[d] ^ s[a]).rotate_right(8);
+    s[c] = s[c].wrapping_add(s[d]);
+    s[b] = (s[b] ^ s[c]).rotate_right(7);
+}
+
+fn compress(cv: &[u32; 8], block: &[u32; 16], counter: u64, len: u32, flags: u32) -> [u32; 8] {
+    let mut s = [0; 16];
+    s[..8].copy_from_slice(cv);
+    s[8..12].copy_from_slice(&IV[..4]);
+    s[12..].copy_from_slice(&[counter as u32, (counter >> 32) as u32, len, flags]);
+    let mut m = *block;
+    for round in 0..7 {
+        g(&mut s, 0, 4, 8, 12, m[0], m[1]);
+        g(&mut s, 1, 5, 9, 13, m[2], m[3]);
+        g(&mut s, 2, 6, 10, 14, m[4], m[5]);
+        g(&mut s, 3, 7, 11, 15, m[6], m[7]);
+        g(&mut s, 0, 5, 10, 15, m[8], m[9]);
+        g(&mut s, 1, 6, 11, 12, m[10], m[11]);
+        g(&mut s, 2, 7, 8, 13, m[12], m[13]);
+        g(&mut s, 3, 4, 9, 14, m[14], m[15]);
+        if round < 6 {
+            m = PERMUTATION.map(|i| m[i]);
+        }
+    }
+    let mut out = [0; 8];
+    for i in 0..8 {
+        out[i] = s[i] ^ s[i + 8];
+    }
+    out
+}
+
+fn words(block: &[u8; BLOCK_LEN]) -> [u32; 16] {
+    let mut words = [0; 16];
+    for (word, bytes) in words.iter_mut().zip(block.chunks_exact(4)) {
+        *word = u32::from_le_bytes(bytes.try_into().unwrap());
+    }
+    words
+}
+
+/// A compression not yet run, kept so the last one can be flagged as the root.
+struct Output {
+    cv: [u32; 8],
+    block: [u32; 16],
+    counter: u64,
+    len: u32,
+    flags: u32,
+}
+impl Output {
+    fn chaining_value(&self) -> [u32; 8] {
+        compress(&self.cv, &self.block, self.counter, self.len, self.flags)
+    }
+    fn parent(left: [u32; 8], right: [u32; 8]) -> Self {
+        let mut block = [0; 16];
+        block[..8].copy_from_slice(&left);
+        block[8..].copy_from_slice(&right);
+        Output {
+            cv: IV,
+            block,
+            counter: 0,
+            len: BLOCK_LEN as u32,
+            flags: PARENT,
+        }
+    }
+}
+
+#[derive(Clone)]
+struct Chunk {
+    cv: [u32; 8],
+    counter: u64,
+    block: [u8; BLOCK_LEN],
+    filled: usize,
+    blocks_done: usize,
+}
+impl Chunk {
+    fn new(counter: u64) -> Self {
+        Chunk {
+            cv: IV,
+            counter,
+            block: [0; BLOCK_LEN],
+            filled: 0,
+            blocks_done: 0,
+        }
+    }
+    fn len(&self) -> usize {
+        self.blocks_done * BLOCK_LEN + self.filled
+    }
+    fn start_flag(&self) -> u32 {
+        if self.blocks_done == 0 {
+            CHUNK_START
+        } else {
+            0
+        }
+    }
+    fn update(&mut self, mut data: &[u8]) {
+        while !data.is_empty() {
+            // A full block is only compressed once more input shows it is
+            // not the chunk's last.
+            if self.filled == BLOCK_LEN {
+                let flags = self.start_flag();
+                let block = words(&self.block);
+                self.cv = compress(&self.cv, &block, self.counter, BLOCK_LEN as u32, flags);
+                self.blocks_done += 1;
+                self.block = [0; BLOCK_LEN];
+                self.filled = 0;
+            }
+            let take = (BLOCK_LEN - self.filled).min(data.len());
+            self.block[self.filled..self.filled + take].copy_from_slice(&data[..take]);
+            self.filled += take;
+            data = &data[take..];
+        }
+    }
+    fn output(&self) -> Output {
+        Output {
+            cv: self.cv,
+            block: words(&self.block),
+            counter: self.counter,
+            len: self.filled as u32,
+            flags: self.start_flag() | CHUNK_END,
+        }
+    }
+}
+
+/// Incremental BLAKE3.
+#[derive(Clone)]
+pub struct Blake3 {
+    chunk: Chunk,
+    /// Chaining values of the complete subtrees to the left, one per set
+    /// bit of the chunk count.
+    stack: Vec<[u32; 8]>,
+}
+impl Default for Blake3 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+impl Blake3 {
+    pub fn new() -> Self {
+        Blake3 {
+            chunk: Chunk::new(0),
+            stack: Vec::new(),
+        }
+    }
+    fn push_chunk(&mut self, mut cv: [u32; 8], mut chunks: u64) {
+        while chunks & 1 == 0 {
+            cv = Output::parent(self.stack.pop().unwrap(), cv).chaining_value();
+            chunks >>= 1;
+        }
+        self.stack.push(cv);
+    }
+    pub fn update(&mut self, mut data: &[u8]) {
+        while !data.is_empty() {
+            if self.chunk.len() == CHUNK_LEN {
+                let cv = self.chunk.output().chaining_value();
+                let chunks = self.chunk.counter + 1;
+                self.push_chunk(cv, chunks);
+                self.chunk = Chunk::new(chunks);
+            }
+            let take = (CHUNK_LEN - self.chunk.len()).min(data.len());
+            self.chunk.update(&data[..take]);
+            data = &data[take..];
+        }
+    }
+    pub fn finalize(self) -> [u8; 32] {
+        let mut output = self.chunk.output();
+        for &left in self.stack.iter().rev() {
+            output = Output::parent(left, output.chaining_value());
+        }
+        output.flags |= ROOT;
+        output.counter = 0;
+        let mut out = [0; 32];
+        for (word, bytes) in output.chaining_value().iter().zip(out.chunks_mut(4)) {
+            bytes.copy_from_slice(&word.to_le_bytes());
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Official BLAKE3 test vectors (test_vectors.json), hash mode, of the
+    /// input bytes `i % 251`.
+    const VECTORS: [(usize, &str); 22] = [
+        (
+            0,
+            "af1349b9f5f9a1a6a0404dea36dcc9499bcb25c9adc112b7cc9a93cae41f3262",
+        ),
+        (
+            1,
+            "2d3adedff11b61f14c886e35afa036736dcd87a74d27b5c1510225d0f592e213",
+        ),
+        (
+            1023,
+            "10108970eeda3eb932baac1428c7a2163b0e924c9a9e25b35bba72b28f70bd11",
+        ),
+        (
+            1024,
+            "42214739f095a406f3fc83deb889744ac00df831c10daa55189b5d121c855af7",
+        ),
+        (
+            1025,
+            "d00278ae47eb27b34faecf67b4fe263f82d5412916c1ffd97c8cb7fb814b8444",
+        ),
+        (
+            2048,
+            "e776b6028c7cd22a4d0ba182a8bf62205d2ef576467e838ed6f2529b85fba24a",
+        ),
+        (
+            2049,
+            "5f4d72f40d7a5f82b15ca2b2e44b1de3c2ef86c426c95c1af0b6879522563030",
+        ),
+        (
+            3072,
+            "b98cb0ff3623be03326b373de6b9095218513e64f1ee2edd2525c7ad1e5cffd2",
+        ),
+        (
+            3073,
+            "7124b49501012f81cc7f11ca069ec9226cecb8a2c850cfe644e327d22d3e1cd3",
+        ),
+        (
+            4096,
+            "015094013f57a5277b59d8475c0501042c0b642e531b0a1c8f58d2163229e969",
+        ),
+        (
+            4097,
+            "9b4052b38f1c5fc8b1f9ff7ac7b27cd242487b3d890d15c96a1c25b8aa0fb995",
+        ),
+        (
+            5120,
+            "9cadc15fed8b5d854562b26a9536d9707cadeda9b143978f319ab34230535833",
+        ),
+        (
+            5121,
+            "628bd2cb2004694adaab7bbd778a25df25c47b9d4155a55f8fbd79f2fe154cff",
+        ),
+        (
+            6144,
+            "3e2e5b74e048f3add6d21faab3f83aa44d3b2278afb83b80b3c35164ebeca205",
+        ),
+        (
+            6145,
+            "f1323a8631446cc50536a9f705ee5cb619424d46887f3c376c695b70e0f0507f",
+        ),
+        (
+            7168,
+            "61da957ec2499a95d6b8023e2b0e604ec7f6b50e80a9678b89d2628e99ada77a",
+        ),
+        (
+            7169,
+            "a003fc7a51754a9b3c7fae0367ab3d782dccf28855a03d435f8cfe74605e7817",
+        ),
+        (
+            8192,
+            "aae792484c8efe4f19e2ca7d371d8c467ffb10748d8a5a1ae579948f718a2a63",
+        ),
+        (
+            8193,
+            "bab6c09cb8ce8cf459261398d2e7aef35700bf488116ceb94a36d0f5f1b7bc3b",
+        ),
+        (
+            16384,
+            "f875d6646de28985646f34ee13be9a576fd515f76b5b0a26bb324735041ddde4",
+        ),
+        (
+            31744,
+            "62b6960e1a44bcc1eb1a611a8d6235b6b4b78f32e7abc4fb4c6cdcce94895c47",
+        ),
+        (
+            102400,
+            "bc3e3d41a1146b069abffad3c0d44860cf664390afce4d9661f7902e7943e085",
+        ),
+    ];
+
+    #[test]
+    fn official_vectors() {
+        for (len, expected) in VECTORS {
+            let input: Vec<u8> = (0..len).map(|i| (i % 251) as u8).collect();
+            let mut whole = Blake3::new();
+            whole.update(&input);
+            assert_eq!(hex::encode(whole.finalize()), expected, "length {}", len);
+            // Fed in pieces that straddle block and chunk boundaries.
+            let mut pieces = Blake3::new();
+            for piece in input.chunks(63) {
+                pieces.update(piece);
+            }
+            assert_eq!(hex::encode(pieces.finalize()), expected, "length {}", len);
+        }
+    }
+}
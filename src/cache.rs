@@ -0,0 +1,105 @@
+//! On-disk cache of what each captured image decoded to, so that a rerun
+//! over the same directory skips the qrcode scan for images seen before.
+
+use std::fs;
+use std::io;
+use std::path;
+
+use blake2::digest::{Update, VariableOutput};
+use blake2::Blake2bVar;
+use image::GenericImageView;
+
+const KEY_LEN: usize = 16;
+/// Leading byte of an entry for an image without a usable qrcode.
+const NOTHING: u8 = 0;
+/// Leading byte of an entry holding the decoded frame.
+const FRAME: u8 = 1;
+/// Leading byte of an entry holding several frames, each after its
+/// length as four little-endian bytes.
+const FRAMES: u8 = 2;
+
+pub struct DecodeCache {
+    dir: path::PathBuf,
+    /// Images answered from the cache.
+    pub hits: u64,
+    pub misses: u64,
+}
+impl DecodeCache {
+    pub fn open(dir: &path::Path) -> io::Result<Self> {
+        fs::create_dir_all(dir)?;
+        Ok(DecodeCache {
+            dir: dir.to_path_buf(),
+            hits: 0,
+            misses: 0,
+        })
+    }
+    /// Entry name for `img` decoded under `settings`, which must describe
+    /// everything besides the pixels that changes the outcome.
+    pub fn key(img: &image::DynamicImage, settings: &str) -> String {
+        let mut hasher = Blake2bVar::new(KEY_LEN).unwrap();
+        let (w, h) = img.dimensions();
+        hasher.update(settings.as_bytes());
+        hasher.update(&w.to_le_bytes());
+        hasher.update(&h.to_le_bytes());
+        hasher.update(format!("{:?}", img.color()).as_bytes());
+        hasher.update(img.as_bytes());
+        let mut key = [0; KEY_LEN];
+        hasher.finalize_variable(&mut key).unwrap();
+        hex::encode(key)
+    }
+    /// The frames the image behind `key` decoded to last time, none when no
+    /// qrcode was found; `None` when it was never decoded.
+    pub fn get(&mut self, key: &str) -> Option<Vec<Vec<u8>>> {
+        let entry = match fs::read(self.dir.join(key)) {
+            Ok(entry) => entry,
+            Err(_) => {
+                self.misses += 1;
+                return None;
+            }
+        };
+        let decoded = match entry.split_first() {
+            Some((&FRAME, frame)) => Some(vec![frame.to_vec()]),
+            Some((&NOTHING, [])) => Some(Vec::new()),
+            Some((&FRAMES, frames)) => split_frames(frames),
+            _ => None,
+        };
+        let Some(decoded) = decoded else {
+            log::warn!("ignoring corrupt cache entry {}", key);
+            self.misses += 1;
+            return None;
+        };
+        self.hits += 1;
+        Some(decoded)
+    }
+    pub fn put(&self, key: &str, decoded: &[Vec<u8>]) {
+        let entry = match decoded {
+            [] => vec![NOTHING],
+            [frame] => [&[FRAME][..], frame].concat(),
+            frames => {
+                let mut entry = vec![FRAMES];
+                for frame in frames {
+                    entry.extend_from_slice(&(frame.len() as u32).to_le_bytes());
+                    entry.extend_from_slice(frame);
+                }
+                entry
+            }
+        };
+        // Written aside and renamed, so an interrupted run leaves no torn entry.
+        let tmp = self.dir.join(format!("{}.tmp", key));
+        let written = fs::write(&tmp, entry).and_then(|_| fs::rename(&tmp, self.dir.join(key)));
+        if let Err(e) = written {
+            log::warn!("could not write cache entry {}: {}", key, e);
+        }
+    }
+}
+
+/// The length-prefixed frames of a [`FRAMES`] entry; `None` when truncated.
+fn split_frames(mut rest: &[u8]) -> Option<Vec<Vec<u8>>> {
+    let mut frames = Vec::new();
+    while let Some((len, tail)) = rest.split_first_chunk::<4>() {
+        let len = u32::from_le_bytes(*len) as usize;
+        frames.push(tail.get(..len)?.to_vec());
+        rest = &tail[len..];
+    }
+    rest.is_empty().then_some(frames)
+}
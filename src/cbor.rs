@@ -0,0 +1,174 @@
+//! The slice of CBOR (RFC 8949) metadata needs, mapped onto `serde_json::Value`
+//! so the serde definitions of the metadata are shared with the JSON format.
+
+use serde_json::{Map, Number, Value};
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum CborError {
+    /// More bytes are needed, e.g. the rest of a metadata frame sequence.
+    Truncated,
+    /// Malformed input or an item with no JSON equivalent.
+    Invalid,
+}
+
+pub fn to_vec(value: &Value) -> Vec<u8> {
+    let mut out = Vec::new();
+    encode(value, &mut out);
+    out
+}
+
+fn head(major: u8, arg: u64, out: &mut Vec<u8>) {
+    let major = major << 5;
+    match arg {
+        0..=23 => out.push(major | arg as u8),
+        24..=0xff => out.extend_from_slice(&[major | 24, arg as u8]),
+        0x100..=0xffff => {
+            out.push(major | 25);
+            out.extend_from_slice(&(arg as u16).to_be_bytes());
+        }
+        0x1_0000..=0xffff_ffff => {
+            out.push(major | 26);
+            out.extend_from_slice(&(arg as u32).to_be_bytes());
+        }
+        _ => {
+            out.push(major | 27);
+            out.extend_from_slice(&arg.to_be_bytes());
+        }
+    }
+}
+
+fn encode(value: &Value, out: &mut Vec<u8>) {
+    match value {
+        Value::Null => out.push(0xf6),
+        Value::Bool(false) => out.push(0xf4),
+        Value::Bool(true) => out.push(0xf5),
+        Value::Number(n) => {
+            if let Some(u) = n.as_u64() {
+                head(0, u, out);
+            } else if let Some(i) = n.as_i64() {
+                head(1, !(i as u64), out);
+            } else {
+                out.push(0xfb);
+                out.extend_from_slice(&n.as_f64().unwrap_or(0.0).to_be_bytes());
+            }
+        }
+        Value::String(s) => {
+            head(3, s.len() as u64, out);
+            out.extend_from_slice(s.as_bytes());
+        }
+        Value::Array(items) => {
+            head(4, items.len() as u64, out);
+            items.iter().for_each(|v| encode(v, out));
+        }
+        Value::Object(map) => {
+            head(5, map.len() as u64, out);
+            for (k, v) in map {
+                head(3, k.len() as u64, out);
+                out.extend_from_slice(k.as_bytes());
+                encode(v, out);
+            }
+        }
+    }
+}
+
+/// Decode exactly one item spanning all of `data`.
+pub fn from_slice(data: &[u8]) -> Result<Value, CborError> {
+    let mut pos = 0;
+    let value = decode(data, &mut pos, 0)?;
+    if pos != data.len() {
+        return Err(CborError::Invalid);
+    }
+    Ok(value)
+}
+
+/// Nesting limit so hostile frames cannot exhaust the stack.
+const MAX_DEPTH: usize = 16;
+
+fn take<'a>(data: &'a [u8], pos: &mut usize, n: usize) -> Result<&'a [u8], CborError> {
+    let end = pos.checked_add(n).ok_or(CborError::Invalid)?;
+    let bytes = data.get(*pos..end).ok_or(CborError::Truncated)?;
+    *pos = end;
+    Ok(bytes)
+}
+
+fn argument(data: &[u8], pos: &mut usize, info: u8) -> Result<u64, CborError> {
+    let n = match info {
+        0..=23 => return Ok(info as u64),
+        24 => 1,
+        25 => 2,
+        26 => 4,
+        27 => 8,
+        _ => return Err(CborError::Invalid),
+    };
+    let bytes = take(data, pos, n)?;
+    Ok(bytes.iter().fold(0u64, |acc, &b| acc << 8 | b as u64))
+}
+
+fn decode(data: &[u8], pos: &mut usize, depth: usize) -> Result<Value, CborError> {
+    if depth > MAX_DEPTH {
+        return Err(CborError::Invalid);
+    }
+    let initial = take(data, pos, 1)?[0];
+    let (major, info) = (initial >> 5, initial & 0x1f);
+    if major == 7 {
+        return match info {
+            20 => Ok(Value::Bool(false)),
+            21 => Ok(Value::Bool(true)),
+            22 | 23 => Ok(Value::Null),
+            26 => {
+                let bytes = take(data, pos, 4)?;
+                let f = f32::from_be_bytes(bytes.try_into().unwrap()) as f64;
+                Number::from_f64(f)
+                    .map(Value::Number)
+                    .ok_or(CborError::Invalid)
+            }
+            27 => {
+                let bytes = take(data, pos, 8)?;
+                let f = f64::from_be_bytes(bytes.try_into().unwrap());
+                Number::from_f64(f)
+                    .map(Value::Number)
+                    .ok_or(CborError::Invalid)
+            }
+            _ => Err(CborError::Invalid),
+        };
+    }
+    let arg = argument(data, pos, info)?;
+    match major {
+        0 => Ok(Value::Number(arg.into())),
+        1 => i64::try_from(arg)
+            .map(|a| Value::Number((-1 - a).into()))
+            .map_err(|_| CborError::Invalid),
+        2 => {
+            let bytes = take(data, pos, arg as usize)?;
+            Ok(Value::Array(
+                bytes.iter().map(|&b| Value::Number(b.into())).collect(),
+            ))
+        }
+        3 => {
+            let bytes = take(data, pos, arg as usize)?;
+            String::from_utf8(bytes.to_vec())
+                .map(Value::String)
+                .map_err(|_| CborError::Invalid)
+        }
+        4 => {
+            let mut items = Vec::new();
+            for _ in 0..arg {
+                items.push(decode(data, pos, depth + 1)?);
+            }
+            Ok(Value::Array(items))
+        }
+        5 => {
+            let mut map = Map::new();
+            for _ in 0..arg {
+                let Value::String(key) = decode(data, pos, depth + 1)? else {
+                    return Err(CborError::Invalid);
+                };
+                map.insert(key, decode(data, pos, depth + 1)?);
+            }
+            Ok(Value::Object(map))
+        }
+        // Tags are skipped, the tagged item is what matters here.
+        6 => decode(data, pos, depth + 1),
+        _ => Err(CborError::Invalid),
+    }
+}
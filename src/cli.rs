@@ -0,0 +1,143 @@
+//! Command line entry point. Not part of the stable library API.
+
+use clap::{CommandFactory, Parser, Subcommand};
+use std::ffi::OsString;
+use std::process::ExitCode;
+
+use crate::{
+    advise, analyze, bench, completions, config, describe, inspect, logging, man, nack, receive,
+    replay, resume, selftest, send, simulate, verify,
+};
+
+/// Exit statuses of `receive`, `resume`, `replay` and `verify`, part of the stable
+/// command line interface so wrappers can tell failures apart.
+pub const EXIT_STATUS: &str = "\
+Exit status:
+  0   the file arrived complete and verified
+  1   any other error, e.g. an unreadable session, batch or config file
+  2   the transfer is incomplete, whether or not --allow-partial wrote it
+      out; --expect-complete also logs it as an error
+  3   the assembled file does not match the sender's hash or size
+  4   no metadata frame was found, so nothing could be assembled
+  5   the sender uses an unsupported protocol version
+  6   --strict refused an ambiguity
+  7   the output file exists or could not be written
+  64  the command line could not be parsed";
+
+/// Exit status for a command line that does not parse, as `sysexits.h`
+/// has it, away from the statuses of [`EXIT_STATUS`].
+const EXIT_USAGE: u8 = 64;
+
+#[derive(Parser)]
+#[clap(after_help = format!("{}\n\n{}", config::HELP, EXIT_STATUS))]
+struct Args {
+    /// Log more detail, repeat for per-frame tracing
+    #[clap(short, long, action = clap::ArgAction::Count, global = true)]
+    verbose: u8,
+    /// Only log errors
+    #[clap(short, long, global = true, conflicts_with = "verbose")]
+    quiet: bool,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Decode a directory of captured frames into the transferred file
+    Receive(Box<receive::ReceiveArgs>),
+    /// Render a file as a directory of qrcode frames
+    Send(send::SendArgs),
+    /// Continue a saved session with a new capture
+    Resume(resume::ResumeArgs),
+    /// Rerun the decoder on the frames a trace recorded, from the decode cache
+    Replay(replay::ReplayArgs),
+    /// List the segments a session or capture is still missing
+    Nack(nack::NackArgs),
+    /// Decode a single frame image and describe its contents
+    Inspect(inspect::InspectArgs),
+    /// Check an assembled file against the hash frame of a capture
+    Verify(verify::VerifyArgs),
+    /// Analyze a short trial capture and recommend sender settings
+    Advise(advise::AdviseArgs),
+    /// Report decode latency, duplicates and where failures cluster in a capture
+    Analyze(analyze::AnalyzeArgs),
+    /// Measure decode pipeline throughput on synthetic frames
+    Bench(bench::BenchArgs),
+    /// Send random files through this build's own encoder and decoder
+    Selftest(selftest::SelftestArgs),
+    /// Report how frames at each sender setting decode after synthetic blur,
+    /// compression, rotation and moiré
+    Simulate(simulate::SimulateArgs),
+    /// Describe the frame formats this receiver decodes, for sender authors
+    Protocol(describe::ProtocolArgs),
+    /// Print a completion script for bash, zsh or fish
+    Completions(completions::CompletionsArgs),
+    /// Print the manual page, in roff
+    Man,
+}
+
+/// Print a command line that does not parse, or help asked for.
+fn usage_error(e: clap::Error) -> ExitCode {
+    // --help and --version go to stdout and succeed.
+    if !e.use_stderr() {
+        e.exit();
+    }
+    let _ = e.print();
+    ExitCode::from(EXIT_USAGE)
+}
+
+/// The command line definition, which completions and the manual are
+/// generated from.
+pub(crate) fn command() -> clap::Command {
+    Args::command()
+}
+
+pub fn main() -> ExitCode {
+    let mut argv: Vec<OsString> = std::env::args_os().collect();
+    let command = command();
+    let matches = match command.clone().try_get_matches_from(&argv) {
+        Ok(matches) => matches,
+        Err(e) => return usage_error(e),
+    };
+    // Defaults go after what was given, as options the command line left out.
+    let loaded = config::Defaults::load().and_then(|defaults| {
+        let applied = defaults.apply(&command, &matches, &mut argv)?;
+        Ok((defaults, applied))
+    });
+    let (defaults, applied) = match loaded {
+        Ok(loaded) => loaded,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+    let args = match Args::try_parse_from(&argv) {
+        Ok(args) => args,
+        Err(e) => return usage_error(e),
+    };
+    logging::init(logging::level_filter(args.verbose, args.quiet));
+    for line in &applied {
+        log::debug!("default {}", line);
+    }
+    for name in defaults.unknown(&command) {
+        log::warn!("ignoring default for no option: {}", name);
+    }
+    match args.command {
+        Command::Receive(receive_args) => return receive::run(&receive_args),
+        Command::Send(send_args) => send::run(&send_args),
+        Command::Resume(resume_args) => return resume::run(&resume_args),
+        Command::Replay(replay_args) => return replay::run(&replay_args),
+        Command::Nack(nack_args) => nack::run(&nack_args),
+        Command::Inspect(inspect_args) => inspect::run(&inspect_args),
+        Command::Verify(verify_args) => return verify::run(&verify_args),
+        Command::Advise(advise_args) => advise::run(&advise_args),
+        Command::Analyze(analyze_args) => analyze::run(&analyze_args),
+        Command::Bench(bench_args) => bench::run(&bench_args),
+        Command::Selftest(selftest_args) => selftest::run(&selftest_args),
+        Command::Simulate(simulate_args) => simulate::run(&simulate_args),
+        Command::Protocol(protocol_args) => describe::run(&protocol_args),
+        Command::Completions(completions_args) => completions::run(&completions_args, &command),
+        Command::Man => man::run(&command),
+    }
+    ExitCode::SUCCESS
+}
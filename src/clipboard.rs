@@ -0,0 +1,124 @@
+use std::process::Command;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// External clipboard readers, one per platform, as for screen grabs.
+#[derive(Debug, Clone, Copy)]
+enum PasteTool {
+    /// `wl-paste` on Wayland compositors.
+    WlPaste,
+    /// `xclip` on X11.
+    Xclip,
+    /// `pngpaste` on macOS.
+    Pngpaste,
+}
+impl PasteTool {
+    fn detect() -> Self {
+        if cfg!(target_os = "macos") {
+            PasteTool::Pngpaste
+        } else if std::env::var_os("WAYLAND_DISPLAY").is_some() {
+            PasteTool::WlPaste
+        } else {
+            PasteTool::Xclip
+        }
+    }
+    fn command(self) -> Command {
+        match self {
+            PasteTool::WlPaste => {
+                let mut cmd = Command::new("wl-paste");
+                cmd.args(["--no-newline", "--type", "image/png"]);
+                cmd
+            }
+            PasteTool::Xclip => {
+                let mut cmd = Command::new("xclip");
+                cmd.args(["-selection", "clipboard", "-target", "image/png", "-out"]);
+                cmd
+            }
+            PasteTool::Pngpaste => {
+                let mut cmd = Command::new("pngpaste");
+                cmd.arg("-");
+                cmd
+            }
+        }
+    }
+}
+
+/// Images put on the clipboard, e.g. by a screenshot hotkey pressed once
+/// per sender frame, polled at a fixed rate.
+///
+/// Each image is yielded once, when it first shows up. Ends when the time
+/// limit runs out or the clipboard tool cannot be run.
+pub struct ClipboardWatch {
+    paste: PasteTool,
+    interval: Duration,
+    next_at: Instant,
+    deadline: Option<Instant>,
+    /// Digest of the clipboard content seen last.
+    last: Option<md5::Digest>,
+    images_seen: u64,
+}
+impl ClipboardWatch {
+    pub fn new(polls_per_sec: f64, limit: Option<Duration>) -> Self {
+        let now = Instant::now();
+        ClipboardWatch {
+            paste: PasteTool::detect(),
+            interval: Duration::from_secs_f64(1.0 / polls_per_sec),
+            next_at: now,
+            deadline: limit.map(|l| now + l),
+            last: None,
+            images_seen: 0,
+        }
+    }
+    /// The clipboard image, if there is one and it changed since the last poll.
+    fn poll(&mut self) -> Result<Option<image::DynamicImage>, String> {
+        let output = self
+            .paste
+            .command()
+            .output()
+            .map_err(|e| format!("cannot run {:?} clipboard tool: {}", self.paste, e))?;
+        // Fails while the clipboard holds no image, which is no reason to stop.
+        if !output.status.success() || output.stdout.is_empty() {
+            return Ok(None);
+        }
+        let digest = md5::compute(&output.stdout);
+        if self.last == Some(digest) {
+            return Ok(None);
+        }
+        self.last = Some(digest);
+        match image::load_from_memory(&output.stdout) {
+            Ok(img) => {
+                self.images_seen += 1;
+                log::debug!("clipboard image {}", self.images_seen);
+                Ok(Some(img))
+            }
+            Err(e) => {
+                log::warn!("skipping unreadable clipboard content: {}", e);
+                Ok(None)
+            }
+        }
+    }
+}
+impl Iterator for ClipboardWatch {
+    type Item = image::DynamicImage;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let now = Instant::now();
+            if self.deadline.is_some_and(|d| now >= d) {
+                return None;
+            }
+            if self.next_at > now {
+                thread::sleep(self.next_at - now);
+            }
+            self.next_at = Instant::now().max(self.next_at) + self.interval;
+            match self.poll() {
+                Ok(Some(img)) => return Some(img),
+                Ok(None) => continue,
+                Err(e) => {
+                    log::error!("{}", e);
+                    return None;
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,350 @@
+//! `qr-recv completions SHELL`: a completion script generated from the
+//! command line definition itself, so every command and option is in it.
+//! Options with a fixed set of values complete those, others file names.
+
+use clap::{Arg, ArgAction, Command};
+use std::fmt::Write;
+
+#[derive(clap::Args)]
+pub struct CompletionsArgs {
+    /// Shell to print the completion script for
+    #[clap(value_enum)]
+    shell: Shell,
+}
+
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Shell {
+    /// Source it from ~/.bashrc, or put it in /etc/bash_completion.d
+    Bash,
+    /// Put it in a directory on $fpath as _qr-recv
+    Zsh,
+    /// Put it in ~/.config/fish/completions/qr-recv.fish
+    Fish,
+}
+
+/// Arguments shown to users, without clap's own help and version.
+fn visible(command: &Command) -> impl Iterator<Item = &Arg> {
+    command
+        .get_arguments()
+        .filter(|a| !a.is_hide_set())
+        .filter(|a| !matches!(a.get_action(), ArgAction::Help | ArgAction::Version))
+}
+
+fn takes_value(arg: &Arg) -> bool {
+    matches!(arg.get_action(), ArgAction::Set | ArgAction::Append)
+}
+
+/// The values `arg` is limited to, if it takes any and they are.
+fn possible_values(arg: &Arg) -> Vec<String> {
+    if !takes_value(arg) {
+        return Vec::new();
+    }
+    arg.get_possible_values()
+        .iter()
+        .filter(|v| !v.is_hide_set())
+        .map(|v| v.get_name().to_string())
+        .collect()
+}
+
+/// First line of the help of `arg`, for shells that show descriptions.
+fn summary(help: Option<&clap::builder::StyledStr>) -> String {
+    let help = help.map(|h| h.to_string()).unwrap_or_default();
+    help.lines().next().unwrap_or_default().to_string()
+}
+
+/// `-s` and `--long` of `arg`, whichever it has.
+fn flags(arg: &Arg) -> Vec<String> {
+    let short = arg.get_short().map(|s| format!("-{}", s));
+    let long = arg.get_long().map(|l| format!("--{}", l));
+    short.into_iter().chain(long).collect()
+}
+
+fn bash(command: &Command, name: &str) -> String {
+    let function = format!("_{}", name.replace('-', "_"));
+    let mut out = String::new();
+    writeln!(out, "{}() {{", function).unwrap();
+    writeln!(out, "    local cur=\"${{COMP_WORDS[COMP_CWORD]}}\"").unwrap();
+    writeln!(out, "    local prev=\"${{COMP_WORDS[COMP_CWORD-1]}}\"").unwrap();
+    writeln!(out, "    local command=\"\" word").unwrap();
+    // Global options take no value, so the first word that is no option
+    // is the command.
+    writeln!(
+        out,
+        "    for word in \"${{COMP_WORDS[@]:1:COMP_CWORD-1}}\"; do"
+    )
+    .unwrap();
+    writeln!(
+        out,
+        "        case \"$word\" in -*) ;; *) command=\"$word\"; break ;; esac"
+    )
+    .unwrap();
+    writeln!(out, "    done").unwrap();
+    writeln!(out, "    case \"$command\" in").unwrap();
+    let commands: Vec<&str> = command.get_subcommands().map(|c| c.get_name()).collect();
+    let globals: Vec<String> = visible(command).flat_map(flags).collect();
+    writeln!(
+        out,
+        "        \"\") COMPREPLY=($(compgen -W \"{} {}\" -- \"$cur\")) ;;",
+        commands.join(" "),
+        globals.join(" ")
+    )
+    .unwrap();
+    for subcommand in command.get_subcommands() {
+        writeln!(out, "        {})", subcommand.get_name()).unwrap();
+        writeln!(out, "            case \"$prev\" in").unwrap();
+        for arg in visible(subcommand).filter(|&a| takes_value(a) && !a.is_positional()) {
+            let values = possible_values(arg);
+            let reply = if values.is_empty() {
+                "compgen -f -- \"$cur\"".to_string()
+            } else {
+                format!("compgen -W \"{}\" -- \"$cur\"", values.join(" "))
+            };
+            writeln!(
+                out,
+                "                {}) COMPREPLY=($({})); return ;;",
+                flags(arg).join("|"),
+                reply
+            )
+            .unwrap();
+        }
+        writeln!(out, "            esac").unwrap();
+        let options: Vec<String> = visible(subcommand)
+            .chain(visible(command))
+            .flat_map(flags)
+            .collect();
+        writeln!(out, "            if [[ \"$cur\" == -* ]]; then").unwrap();
+        writeln!(
+            out,
+            "                COMPREPLY=($(compgen -W \"{}\" -- \"$cur\"))",
+            options.join(" ")
+        )
+        .unwrap();
+        writeln!(out, "            else").unwrap();
+        let positional: Vec<String> = visible(subcommand)
+            .filter(|a| a.is_positional())
+            .flat_map(possible_values)
+            .collect();
+        if positional.is_empty() {
+            writeln!(out, "                COMPREPLY=($(compgen -f -- \"$cur\"))").unwrap();
+        } else {
+            writeln!(
+                out,
+                "                COMPREPLY=($(compgen -W \"{}\" -- \"$cur\"))",
+                positional.join(" ")
+            )
+            .unwrap();
+        }
+        writeln!(out, "            fi").unwrap();
+        writeln!(out, "            ;;").unwrap();
+    }
+    writeln!(out, "    esac").unwrap();
+    writeln!(out, "}}").unwrap();
+    writeln!(out, "complete -o filenames -F {} {}", function, name).unwrap();
+    out
+}
+
+/// `text` inside single quotes, and inside the brackets of a zsh
+/// `_arguments` description.
+fn zsh_quote(text: &str) -> String {
+    text.replace('\'', "'\\''")
+        .replace('[', "\\[")
+        .replace(']', "\\]")
+        .replace(':', "\\:")
+}
+
+/// `_arguments` specs of the visible arguments of `command`.
+fn zsh_specs(command: &Command) -> Vec<String> {
+    let mut specs = Vec::new();
+    for arg in visible(command) {
+        let help = zsh_quote(&summary(arg.get_help()));
+        let values = possible_values(arg);
+        let action = if values.is_empty() {
+            "_files".to_string()
+        } else {
+            format!("({})", values.join(" "))
+        };
+        if arg.is_positional() {
+            specs.push(format!("':{}:{}'", help, action));
+            continue;
+        }
+        let repeat = matches!(arg.get_action(), ArgAction::Append | ArgAction::Count);
+        let star = if repeat { "*" } else { "" };
+        for flag in flags(arg) {
+            specs.push(if !takes_value(arg) {
+                format!("'{}{}[{}]'", star, flag, help)
+            } else {
+                // `--long=value` or `--long value`, `-svalue` or `-s value`.
+                let joined = if flag.starts_with("--") { "=" } else { "+" };
+                let value = arg.get_id().as_str().to_uppercase();
+                format!(
+                    "'{}{}{}[{}]:{}:{}'",
+                    star, flag, joined, help, value, action
+                )
+            });
+        }
+    }
+    specs
+}
+
+fn zsh(command: &Command, name: &str) -> String {
+    let function = format!("_{}", name.replace('-', "_"));
+    let mut out = String::new();
+    writeln!(out, "#compdef {}", name).unwrap();
+    writeln!(out).unwrap();
+    writeln!(out, "{}() {{", function).unwrap();
+    writeln!(out, "    local line state").unwrap();
+    writeln!(out, "    _arguments -C \\").unwrap();
+    for spec in zsh_specs(command) {
+        writeln!(out, "        {} \\", spec).unwrap();
+    }
+    writeln!(out, "        '1: :->command' \\").unwrap();
+    writeln!(out, "        '*:: :->arguments'").unwrap();
+    writeln!(out, "    case $state in").unwrap();
+    writeln!(out, "        command)").unwrap();
+    writeln!(out, "            _values 'command' \\").unwrap();
+    let subcommands: Vec<&Command> = command.get_subcommands().collect();
+    for (i, subcommand) in subcommands.iter().enumerate() {
+        let more = if i + 1 < subcommands.len() { " \\" } else { "" };
+        writeln!(
+            out,
+            "                '{}[{}]'{}",
+            subcommand.get_name(),
+            zsh_quote(&summary(subcommand.get_about())),
+            more
+        )
+        .unwrap();
+    }
+    writeln!(out, "            ;;").unwrap();
+    writeln!(out, "        arguments)").unwrap();
+    writeln!(out, "            case $line[1] in").unwrap();
+    for subcommand in &subcommands {
+        writeln!(out, "                {})", subcommand.get_name()).unwrap();
+        let mut specs = zsh_specs(subcommand);
+        specs.extend(zsh_specs(command));
+        writeln!(out, "                    _arguments \\").unwrap();
+        for (i, spec) in specs.iter().enumerate() {
+            let more = if i + 1 < specs.len() { " \\" } else { "" };
+            writeln!(out, "                        {}{}", spec, more).unwrap();
+        }
+        writeln!(out, "                    ;;").unwrap();
+    }
+    writeln!(out, "            esac").unwrap();
+    writeln!(out, "            ;;").unwrap();
+    writeln!(out, "    esac").unwrap();
+    writeln!(out, "}}").unwrap();
+    writeln!(out).unwrap();
+    writeln!(out, "{} \"$@\"", function).unwrap();
+    out
+}
+
+/// `text` inside single quotes for fish.
+fn fish_quote(text: &str) -> String {
+    format!("'{}'", text.replace('\\', "\\\\").replace('\'', "\\'"))
+}
+
+/// `complete` lines for the visible arguments of `command`, under
+/// `condition` when given.
+fn fish_arguments(out: &mut String, name: &str, command: &Command, condition: Option<&str>) {
+    for arg in visible(command) {
+        let mut line = format!("complete -c {}", name);
+        if let Some(condition) = condition {
+            write!(line, " -n {}", fish_quote(condition)).unwrap();
+        }
+        if let Some(short) = arg.get_short() {
+            write!(line, " -s {}", short).unwrap();
+        }
+        if let Some(long) = arg.get_long() {
+            write!(line, " -l {}", long).unwrap();
+        }
+        let values = possible_values(arg);
+        if !values.is_empty() {
+            write!(line, " -x -a {}", fish_quote(&values.join(" "))).unwrap();
+        } else if takes_value(arg) && !arg.is_positional() {
+            line.push_str(" -r");
+        }
+        let help = summary(arg.get_help());
+        if !help.is_empty() {
+            write!(line, " -d {}", fish_quote(&help)).unwrap();
+        }
+        writeln!(out, "{}", line).unwrap();
+    }
+}
+
+fn fish(command: &Command, name: &str) -> String {
+    let mut out = String::new();
+    // Global options are taken anywhere.
+    fish_arguments(&mut out, name, command, None);
+    for subcommand in command.get_subcommands() {
+        writeln!(
+            out,
+            "complete -c {} -n __fish_use_subcommand -f -a {} -d {}",
+            name,
+            subcommand.get_name(),
+            fish_quote(&summary(subcommand.get_about()))
+        )
+        .unwrap();
+    }
+    for subcommand in command.get_subcommands() {
+        let condition = format!("__fish_seen_subcommand_from {}", subcommand.get_name());
+        fish_arguments(&mut out, name, subcommand, Some(&condition));
+    }
+    out
+}
+
+pub fn run(args: &CompletionsArgs, command: &Command) {
+    let name = command.get_name();
+    let script = match args.shell {
+        Shell::Bash => bash(command, name),
+        Shell::Zsh => zsh(command, name),
+        Shell::Fish => fish(command, name),
+    };
+    print!("{}", script);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// What a script for `shell` must contain for `arg`: its flags, or the
+    /// values of a positional argument limited to some.
+    fn mentions(shell: Shell, arg: &Arg) -> Vec<String> {
+        if arg.is_positional() {
+            return possible_values(arg);
+        }
+        match shell {
+            Shell::Fish => {
+                let short = arg.get_short().map(|s| format!("-s {}", s));
+                let long = arg.get_long().map(|l| format!("-l {}", l));
+                short.into_iter().chain(long).collect()
+            }
+            Shell::Bash | Shell::Zsh => flags(arg),
+        }
+    }
+
+    #[test]
+    fn scripts_mention_every_visible_argument() {
+        let command = crate::cli::command();
+        let name = command.get_name();
+        for shell in [Shell::Bash, Shell::Zsh, Shell::Fish] {
+            let script = match shell {
+                Shell::Bash => bash(&command, name),
+                Shell::Zsh => zsh(&command, name),
+                Shell::Fish => fish(&command, name),
+            };
+            for subcommand in std::iter::once(&command).chain(command.get_subcommands()) {
+                assert!(script.contains(subcommand.get_name()));
+                for arg in visible(subcommand) {
+                    for mention in mentions(shell, arg) {
+                        assert!(
+                            script.contains(&mention),
+                            "{:?} script lacks {} of {}",
+                            shell,
+                            mention,
+                            subcommand.get_name()
+                        );
+                    }
+                }
+            }
+        }
+    }
+}
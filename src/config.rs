@@ -0,0 +1,408 @@
+//! Defaults for command line options from a config file and from the
+//! environment, for captures received again and again with the same flags.
+//!
+//! Any long option of a command can be given a default under its name:
+//! `pipeline-depth = 4` in `~/.config/qr-recv/config.toml`, at the top for
+//! every command that has the option or in a table such as `[receive]` for
+//! one command, or `QR_RECV_PIPELINE_DEPTH=4` in the environment. The file
+//! is read as a small subset of TOML: tables, strings, numbers, booleans
+//! and arrays of those on one line.
+
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::{env, fs, io, path};
+
+use clap::error::ErrorKind;
+use clap::parser::ValueSource;
+use clap::{ArgAction, ArgMatches, Command};
+
+/// Prefix of the environment variables read.
+const ENV_PREFIX: &str = "QR_RECV_";
+/// Names the config file to read instead of the usual one, or none when empty.
+const CONFIG_VAR: &str = "QR_RECV_CONFIG";
+/// Variables with the prefix that are not option defaults.
+const OTHER_VARS: [&str; 2] = [CONFIG_VAR, crate::session::PASSPHRASE_VAR];
+
+/// How defaults are given, for the help of the command line.
+pub const HELP: &str = "\
+Defaults:
+  Any long option can be given a default in ~/.config/qr-recv/config.toml,
+  e.g. `pipeline-depth = 4`, at the top for every command or in a table such
+  as [receive] for one, or in the environment as QR_RECV_PIPELINE_DEPTH=4.
+  The command line wins over the environment, which wins over the file.
+  QR_RECV_CONFIG names another file to read, or none when empty.";
+
+/// Values of options by name, each a list for options that repeat.
+type Settings = BTreeMap<String, Vec<String>>;
+
+/// Defaults found in the config file and the environment.
+#[derive(Default)]
+pub struct Defaults {
+    file: Option<path::PathBuf>,
+    /// Settings at the top of the file, for every command.
+    global: Settings,
+    /// Settings of the file's tables, by command name.
+    commands: BTreeMap<String, Settings>,
+    env: Settings,
+}
+impl Defaults {
+    /// Read the config file, if there is one, and the environment.
+    pub fn load() -> Result<Self, String> {
+        let mut defaults = Defaults::default();
+        if let Some((file, explicit)) = config_path() {
+            match fs::read_to_string(&file) {
+                Ok(text) => {
+                    (defaults.global, defaults.commands) =
+                        parse(&text).map_err(|e| format!("{}: {}", file.display(), e))?;
+                    defaults.file = Some(file);
+                }
+                Err(e) if e.kind() == io::ErrorKind::NotFound && !explicit => {}
+                Err(e) => return Err(format!("cannot read {}: {}", file.display(), e)),
+            }
+        }
+        defaults.env = env_settings(env::vars());
+        Ok(defaults)
+    }
+    /// Values for option `name` of `command`, and where they were set.
+    fn lookup(&self, command: &str, name: &str) -> Option<(&[String], String)> {
+        if let Some(values) = self.env.get(name) {
+            let var = format!("{}{}", ENV_PREFIX, name.replace('-', "_").to_uppercase());
+            return Some((values, var));
+        }
+        let file = self.file.as_ref()?.display();
+        if let Some(values) = self.commands.get(command).and_then(|s| s.get(name)) {
+            return Some((values, format!("[{}] in {}", command, file)));
+        }
+        let values = self.global.get(name)?;
+        Some((values, file.to_string()))
+    }
+    /// Append to `args` the defaults of the options the command `matches`
+    /// selected was not given, returning a line on each default applied or
+    /// left out. Defaults that conflict with the options given, or need
+    /// options that were not, are left out.
+    pub fn apply(
+        &self,
+        command: &Command,
+        matches: &ArgMatches,
+        args: &mut Vec<OsString>,
+    ) -> Result<Vec<String>, String> {
+        let Some((name, matches)) = matches.subcommand() else {
+            return Ok(Vec::new());
+        };
+        let subcommand = command
+            .find_subcommand(name)
+            .expect("the matched subcommand exists");
+        let mut applied = Vec::new();
+        for arg in subcommand.get_arguments() {
+            let Some(long) = arg.get_long() else {
+                continue;
+            };
+            if matches.value_source(arg.get_id().as_str()) == Some(ValueSource::CommandLine) {
+                continue;
+            }
+            let Some((values, source)) = self.lookup(name, long) else {
+                continue;
+            };
+            let mut extended = args.clone();
+            match arg.get_action() {
+                ArgAction::SetTrue => {
+                    let [value] = values else {
+                        return Err(format!("{}: --{} takes a single value", source, long));
+                    };
+                    if !flag(value).ok_or_else(|| {
+                        format!("{}: --{} is true or false, not {:?}", source, long, value)
+                    })? {
+                        continue;
+                    }
+                    extended.push(format!("--{}", long).into());
+                }
+                ArgAction::Set | ArgAction::Append => {
+                    extended.extend(values.iter().map(|v| format!("--{}={}", long, v).into()));
+                }
+                _ => continue,
+            }
+            match command.clone().try_get_matches_from(&extended) {
+                Ok(_) => {
+                    *args = extended;
+                    applied.push(format!("--{} from {}", long, source));
+                }
+                Err(e)
+                    if matches!(
+                        e.kind(),
+                        ErrorKind::ArgumentConflict | ErrorKind::MissingRequiredArgument
+                    ) =>
+                {
+                    applied.push(format!("--{} from {} left out: {}", long, source, e.kind()));
+                }
+                Err(e) => {
+                    let error = e.render().to_string();
+                    let reason = error.lines().next().unwrap_or_default();
+                    return Err(format!(
+                        "{}: {}",
+                        source,
+                        reason.trim_start_matches("error: ")
+                    ));
+                }
+            }
+        }
+        Ok(applied)
+    }
+    /// Settings that match no option of any command, nor a command.
+    pub fn unknown(&self, command: &Command) -> Vec<String> {
+        let known = |name: &str| {
+            command
+                .get_subcommands()
+                .flat_map(|c| c.get_arguments())
+                .any(|a| a.get_long() == Some(name))
+        };
+        let mut unknown = Vec::new();
+        let file = self.file.as_ref().map(|f| f.display());
+        for name in self.global.keys().filter(|n| !known(n)) {
+            unknown.push(format!("{} in {}", name, file.as_ref().unwrap()));
+        }
+        for (table, settings) in &self.commands {
+            if command.find_subcommand(table).is_none() {
+                unknown.push(format!("[{}] in {}", table, file.as_ref().unwrap()));
+                continue;
+            }
+            for name in settings.keys().filter(|n| !known(n)) {
+                unknown.push(format!("{} in [{}]", name, table));
+            }
+        }
+        for name in self.env.keys().filter(|n| !known(n)) {
+            let var = format!("{}{}", ENV_PREFIX, name.replace('-', "_").to_uppercase());
+            unknown.push(var);
+        }
+        unknown
+    }
+}
+
+/// The config file to read and whether it was named explicitly.
+fn config_path() -> Option<(path::PathBuf, bool)> {
+    if let Some(file) = env::var_os(CONFIG_VAR) {
+        return (!file.is_empty()).then(|| (file.into(), true));
+    }
+    let dir = env::var_os("XDG_CONFIG_HOME")
+        .filter(|d| !d.is_empty())
+        .map(path::PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|h| path::Path::new(&h).join(".config")))
+        .or_else(|| env::var_os("APPDATA").map(path::PathBuf::from))?;
+    Some((dir.join("qr-recv").join("config.toml"), false))
+}
+
+/// Settings given by the prefixed variables among `vars`.
+fn env_settings(vars: impl Iterator<Item = (String, String)>) -> Settings {
+    vars.filter(|(key, _)| !OTHER_VARS.contains(&key.as_str()))
+        .filter_map(|(key, value)| {
+            let name = key.strip_prefix(ENV_PREFIX)?;
+            Some((option_name(name), vec![value]))
+        })
+        .collect()
+}
+
+/// `name` as the long option it sets, whichever separator it is written with.
+fn option_name(name: &str) -> String {
+    name.trim().replace('_', "-").to_lowercase()
+}
+
+fn flag(value: &str) -> Option<bool> {
+    match value.to_lowercase().as_str() {
+        "true" | "1" | "yes" | "on" => Some(true),
+        "false" | "0" | "no" | "off" | "" => Some(false),
+        _ => None,
+    }
+}
+
+/// Settings at the top of `text` and in each of its tables.
+fn parse(text: &str) -> Result<(Settings, BTreeMap<String, Settings>), String> {
+    let mut global = Settings::new();
+    let mut commands: BTreeMap<String, Settings> = BTreeMap::new();
+    let mut table = None;
+    for (i, line) in text.lines().enumerate() {
+        let at = |e: String| format!("line {}: {}", i + 1, e);
+        let line = match unquoted(line).find(|&(_, c)| c == '#') {
+            Some((end, _)) => &line[..end],
+            None => line,
+        }
+        .trim();
+        if line.is_empty() {
+            continue;
+        }
+        if let Some(name) = line.strip_prefix('[') {
+            let name = name
+                .strip_suffix(']')
+                .ok_or_else(|| at("unclosed table header".to_string()))?;
+            table = Some(name.trim().to_string());
+            continue;
+        }
+        let (key, value) = line
+            .split_once('=')
+            .ok_or_else(|| at("expected `name = value`".to_string()))?;
+        let values = parse_value(value.trim()).map_err(at)?;
+        let settings = match &table {
+            Some(table) => commands.entry(table.clone()).or_default(),
+            None => &mut global,
+        };
+        let name = option_name(key.trim().trim_matches('"'));
+        if settings.contains_key(&name) {
+            return Err(at(format!("{} is set twice", name)));
+        }
+        settings.insert(name, values);
+    }
+    Ok((global, commands))
+}
+
+/// A scalar as a one-element list, or the elements of an array.
+fn parse_value(value: &str) -> Result<Vec<String>, String> {
+    let Some(items) = value.strip_prefix('[') else {
+        return parse_scalar(value).map(|v| vec![v]);
+    };
+    let items = items
+        .strip_suffix(']')
+        .ok_or_else(|| "unclosed array".to_string())?;
+    let mut values = Vec::new();
+    let mut start = 0;
+    let commas = unquoted(items).filter(|&(_, c)| c == ',').map(|(i, _)| i);
+    for end in commas.chain([items.len()]) {
+        let item = items[start..end].trim();
+        start = end + 1;
+        // A trailing comma is allowed.
+        if !item.is_empty() || end < items.len() {
+            values.push(parse_scalar(item)?);
+        }
+    }
+    Ok(values)
+}
+
+fn parse_scalar(value: &str) -> Result<String, String> {
+    if let Some(literal) = value.strip_prefix('\'') {
+        return literal
+            .strip_suffix('\'')
+            .map(str::to_string)
+            .ok_or_else(|| "unclosed string".to_string());
+    }
+    let Some(quoted) = value.strip_prefix('"') else {
+        // Numbers may group their digits with `_`.
+        let bare = value.replace('_', "");
+        if value == "true" || value == "false" || bare.parse::<f64>().is_ok() {
+            return Ok(bare);
+        }
+        return Err(format!("{:?} is not a value; strings need quotes", value));
+    };
+    let quoted = quoted
+        .strip_suffix('"')
+        .ok_or_else(|| "unclosed string".to_string())?;
+    let mut out = String::new();
+    let mut chars = quoted.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        out.push(match chars.next() {
+            Some('n') => '\n',
+            Some('t') => '\t',
+            Some('r') => '\r',
+            Some(c @ ('"' | '\\')) => c,
+            other => return Err(format!("unknown escape \\{}", other.unwrap_or(' '))),
+        });
+    }
+    Ok(out)
+}
+
+/// Characters of `line` outside quoted strings, with their byte offsets.
+fn unquoted(line: &str) -> impl Iterator<Item = (usize, char)> + '_ {
+    let mut quote = None;
+    let mut escaped = false;
+    line.char_indices().filter(move |&(_, c)| {
+        match quote {
+            Some('"') if escaped => escaped = false,
+            Some('"') if c == '\\' => escaped = true,
+            Some(q) if c == q => quote = None,
+            Some(_) => {}
+            None if c == '"' || c == '\'' => quote = Some(c),
+            None => return true,
+        }
+        false
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn values(text: &str, name: &str) -> Vec<String> {
+        let (global, _) = parse(text).unwrap();
+        global[name].clone()
+    }
+
+    #[test]
+    fn strings_keep_quoted_comments_and_escapes() {
+        assert_eq!(values(r#"a = "x # y""#, "a"), ["x # y"]);
+        assert_eq!(values(r#"a = "say \"hi\"\t\\n""#, "a"), ["say \"hi\"\t\\n"]);
+        assert_eq!(values(r"a = 'C:\dir\n'", "a"), [r"C:\dir\n"]);
+        assert_eq!(values(r#"a = "1 = 2" # the rest"#, "a"), ["1 = 2"]);
+        assert_eq!(values("a = 1_000 # count", "a"), ["1000"]);
+        assert_eq!(values("\"a_b\" = true", "a-b"), ["true"]);
+    }
+
+    #[test]
+    fn arrays_split_outside_strings() {
+        assert_eq!(
+            values(r#"a = [1, "b, c", 'd', true,]"#, "a"),
+            ["1", "b, c", "d", "true"]
+        );
+        assert!(values("a = []", "a").is_empty());
+        assert_eq!(
+            parse("a = [1,, 2]").unwrap_err(),
+            r#"line 1: "" is not a value; strings need quotes"#
+        );
+    }
+
+    #[test]
+    fn tables_and_comments() {
+        let text =
+            "# defaults\nfps = 30\n\n[receive]  # one command\nfps = 60\n[inspect]\nfps = 10\n";
+        let (global, commands) = parse(text).unwrap();
+        assert_eq!(global["fps"], ["30"]);
+        assert_eq!(commands["receive"]["fps"], ["60"]);
+        assert_eq!(commands["inspect"]["fps"], ["10"]);
+    }
+
+    #[test]
+    fn errors_name_their_line() {
+        for (text, error) in [
+            ("a = 1\n[receive\n", "line 2: unclosed table header"),
+            ("\n\nfps\n", "line 3: expected `name = value`"),
+            ("a = \"open", "line 1: unclosed string"),
+            ("a = 'open", "line 1: unclosed string"),
+            ("a = [1, 2", "line 1: unclosed array"),
+            (r#"a = "\q""#, r"line 1: unknown escape \q"),
+            (
+                "a = word",
+                r#"line 1: "word" is not a value; strings need quotes"#,
+            ),
+            ("fps = 1\nfps = 2", "line 2: fps is set twice"),
+            (
+                "[receive]\npipeline_depth = 1\npipeline-depth = 2",
+                "line 3: pipeline-depth is set twice",
+            ),
+        ] {
+            assert_eq!(parse(text).unwrap_err(), error, "{:?}", text);
+        }
+        // The same name at the top and in a table is no duplicate.
+        assert!(parse("fps = 1\n[receive]\nfps = 2").is_ok());
+    }
+
+    #[test]
+    fn only_option_variables_are_defaults() {
+        let vars = [
+            ("QR_RECV_PIPELINE_DEPTH", "4"),
+            ("QR_RECV_CONFIG", "/dev/null"),
+            ("QR_RECV_SESSION_PASSPHRASE", "secret"),
+            ("HOME", "/root"),
+        ];
+        let settings = env_settings(vars.iter().map(|&(k, v)| (k.to_string(), v.to_string())));
+        assert_eq!(settings.keys().collect::<Vec<_>>(), ["pipeline-depth"]);
+    }
+}
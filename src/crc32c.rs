@@ -0,0 +1,64 @@
+//! CRC-32C (Castagnoli), a cheap check run on data frames before their
+//! blake2b hash.
+
+const POLY: u32 = 0x82f6_3b78;
+
+const TABLE: [u32; 256] = {
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 {
+                crc >> 1 ^ POLY
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
+
+pub fn checksum(data: &[u8]) -> u32 {
+    !data.iter().fold(!0, |crc, &b| {
+        TABLE[((crc ^ b as u32) & 0xff) as usize] ^ crc >> 8
+    })
+}
+
+/// `frame` without its trailing little-endian CRC-32C, if that matches.
+pub fn strip(frame: &[u8]) -> Option<&[u8]> {
+    let split = frame.len().checked_sub(4).filter(|&s| s > 0)?;
+    let (body, crc) = frame.split_at(split);
+    (checksum(body).to_le_bytes() == crc).then_some(body)
+}
+
+/// Append the CRC-32C of `frame`.
+pub fn append(mut frame: Vec<u8>) -> Vec<u8> {
+    let crc = checksum(&frame);
+    frame.extend_from_slice(&crc.to_le_bytes());
+    frame
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_value() {
+        // The CRC-32C catalogue's check value.
+        assert_eq!(checksum(b"123456789"), 0xe306_9283);
+    }
+
+    #[test]
+    fn append_then_strip() {
+        let frame = append(b"D\x00\x01payload".to_vec());
+        assert_eq!(strip(&frame), Some(&b"D\x00\x01payload"[..]));
+        let mut damaged = frame;
+        damaged[3] ^= 1;
+        assert_eq!(strip(&damaged), None);
+    }
+}
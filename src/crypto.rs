@@ -0,0 +1,239 @@
+//! Passphrase encryption for data left on the receiving machine.
+//!
+//! PBKDF2-HMAC-SHA256 stretches the passphrase with a fresh salt for every
+//! file, ChaCha20 (RFC 8439) encrypts, and keyed BLAKE2b-256 authenticates
+//! the header and ciphertext. A sealed file is
+//! `MAGIC | iterations (u32 LE) | salt | ciphertext | tag`.
+
+use blake2::digest::consts::U32;
+use blake2::digest::{KeyInit, Mac};
+use blake2::Blake2bMac;
+use std::io;
+
+use crate::sha256::Sha256;
+
+const MAGIC: &[u8; 8] = b"qrrecv\x00\x01";
+/// PBKDF2 rounds for newly sealed files.
+const ITERATIONS: u32 = 600_000;
+/// Most rounds accepted when opening, so a crafted header cannot stall the receiver.
+const MAX_ITERATIONS: u32 = 10_000_000;
+const SALT_LEN: usize = 16;
+const TAG_LEN: usize = 32;
+const HEADER_LEN: usize = MAGIC.len() + 4 + SALT_LEN;
+
+/// Whether `data` was written by [`seal`].
+pub fn is_sealed(data: &[u8]) -> bool {
+    data.starts_with(MAGIC)
+}
+
+/// Encrypt and authenticate `plaintext` under `passphrase`.
+pub fn seal(passphrase: &[u8], plaintext: &[u8]) -> io::Result<Vec<u8>> {
+    Ok(seal_with(
+        passphrase,
+        plaintext,
+        &random_salt()?,
+        ITERATIONS,
+    ))
+}
+
+#[cfg(unix)]
+fn random_salt() -> io::Result<[u8; SALT_LEN]> {
+    use std::io::Read;
+    let mut salt = [0; SALT_LEN];
+    std::fs::File::open("/dev/urandom")?.read_exact(&mut salt)?;
+    Ok(salt)
+}
+
+#[cfg(not(unix))]
+fn random_salt() -> io::Result<[u8; SALT_LEN]> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "no random source for the salt on this platform",
+    ))
+}
+
+fn seal_with(passphrase: &[u8], plaintext: &[u8], salt: &[u8], iterations: u32) -> Vec<u8> {
+    let mut out = Vec::with_capacity(HEADER_LEN + plaintext.len() + TAG_LEN);
+    out.extend_from_slice(MAGIC);
+    out.extend_from_slice(&iterations.to_le_bytes());
+    out.extend_from_slice(salt);
+    let (enc_key, mac_key) = keys(passphrase, salt, iterations);
+    let start = out.len();
+    out.extend_from_slice(plaintext);
+    chacha20(&enc_key, &mut out[start..]);
+    let tag = blake2b_mac(&mac_key, &out);
+    out.extend_from_slice(&tag);
+    out
+}
+
+/// The plaintext of a file written by [`seal`], or why it cannot be had.
+pub fn open(passphrase: &[u8], sealed: &[u8]) -> Result<Vec<u8>, &'static str> {
+    if !is_sealed(sealed) {
+        return Err("not an encrypted file");
+    }
+    if sealed.len() < HEADER_LEN + TAG_LEN {
+        return Err("encrypted file is truncated");
+    }
+    let iterations = u32::from_le_bytes(sealed[MAGIC.len()..][..4].try_into().unwrap());
+    if iterations == 0 || iterations > MAX_ITERATIONS {
+        return Err("encrypted file header is damaged");
+    }
+    let salt = &sealed[MAGIC.len() + 4..HEADER_LEN];
+    let (body, tag) = sealed.split_at(sealed.len() - TAG_LEN);
+    let (enc_key, mac_key) = keys(passphrase, salt, iterations);
+    let mut mac = <Blake2bMac<U32> as KeyInit>::new_from_slice(&mac_key).unwrap();
+    mac.update(body);
+    if mac.verify_slice(tag).is_err() {
+        return Err("wrong passphrase or damaged file");
+    }
+    let mut plaintext = body[HEADER_LEN..].to_vec();
+    chacha20(&enc_key, &mut plaintext);
+    Ok(plaintext)
+}
+
+/// Encryption and authentication keys for `passphrase` and `salt`. The
+/// salt is fresh for every sealed file, so each key encrypts only once.
+fn keys(passphrase: &[u8], salt: &[u8], iterations: u32) -> ([u8; 32], [u8; 32]) {
+    let master = pbkdf2_sha256(passphrase, salt, iterations);
+    (
+        blake2b_mac(&master, b"qr-recv encrypt"),
+        blake2b_mac(&master, b"qr-recv authenticate"),
+    )
+}
+
+fn blake2b_mac(key: &[u8], data: &[u8]) -> [u8; 32] {
+    let mut mac = <Blake2bMac<U32> as KeyInit>::new_from_slice(key).unwrap();
+    mac.update(data);
+    mac.finalize().into_bytes().into()
+}
+
+/// HMAC-SHA256 with its padded key already absorbed, for PBKDF2's many rounds.
+struct Hmac {
+    inner: Sha256,
+    outer: Sha256,
+}
+impl Hmac {
+    fn new(key: &[u8]) -> Self {
+        let mut block = [0; 64];
+        if key.len() > 64 {
+            block[..32].copy_from_slice(&crate::sha256::digest(key));
+        } else {
+            block[..key.len()].copy_from_slice(key);
+        }
+        let (mut inner, mut outer) = (Sha256::new(), Sha256::new());
+        inner.update(&block.map(|b| b ^ 0x36));
+        outer.update(&block.map(|b| b ^ 0x5c));
+        Hmac { inner, outer }
+    }
+    fn mac(&self, parts: &[&[u8]]) -> [u8; 32] {
+        let mut inner = self.inner.clone();
+        for part in parts {
+            inner.update(part);
+        }
+        let mut outer = self.outer.clone();
+        outer.update(&inner.finalize());
+        outer.finalize()
+    }
+}
+
+/// The first 32 byte block of PBKDF2-HMAC-SHA256.
+fn pbkdf2_sha256(passphrase: &[u8], salt: &[u8], iterations: u32) -> [u8; 32] {
+    let hmac = Hmac::new(passphrase);
+    let mut u = hmac.mac(&[salt, &1u32.to_be_bytes()]);
+    let mut out = u;
+    for _ in 1..iterations {
+        u = hmac.mac(&[&u]);
+        for (o, b) in out.iter_mut().zip(u) {
+            *o ^= b;
+        }
+    }
+    out
+}
+
+fn quarter_round(s: &mut [u32; 16], a: usize, b: usize, c: usize, d: usize) {
+    s[a] = s[a].wrapping_add(s[b]);
+    s[d] = (s[d] ^ s[a]).rotate_left(16);
+    s[c] = s[c].wrapping_add(s[d]);
+    s[b] = (s[b] ^ s[c]).rotate_left(12);
+    s[a] = s[a].wrapping_add(s[b]);
+    s[d] = (s[d] ^ s[a]).rotate_left(8);
+    s[c] = s[c].wrapping_add(s[d]);
+    s[b] = (s[b] ^ s[c]).rotate_left(7);
+}
+
+/// XOR `data` with the ChaCha20 keystream for `key`, a zero nonce and a
+/// block counter starting at zero.
+fn chacha20(key: &[u8; 32], data: &mut [u8]) {
+    let mut initial = [0u32; 16];
+    initial[..4].copy_from_slice(&[0x6170_7865, 0x3320_646e, 0x7962_2d32, 0x6b20_6574]);
+    for (word, bytes) in initial[4..12].iter_mut().zip(key.chunks_exact(4)) {
+        *word = u32::from_le_bytes(bytes.try_into().unwrap());
+    }
+    for (counter, block) in data.chunks_mut(64).enumerate() {
+        initial[12] = counter as u32;
+        let mut s = initial;
+        for _ in 0..10 {
+            quarter_round(&mut s, 0, 4, 8, 12);
+            quarter_round(&mut s, 1, 5, 9, 13);
+            quarter_round(&mut s, 2, 6, 10, 14);
+            quarter_round(&mut s, 3, 7, 11, 15);
+            quarter_round(&mut s, 0, 5, 10, 15);
+            quarter_round(&mut s, 1, 6, 11, 12);
+            quarter_round(&mut s, 2, 7, 8, 13);
+            quarter_round(&mut s, 3, 4, 9, 14);
+        }
+        let stream = s
+            .iter()
+            .zip(initial)
+            .flat_map(|(w, i)| w.wrapping_add(i).to_le_bytes());
+        for (b, k) in block.iter_mut().zip(stream) {
+            *b ^= k;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pbkdf2_sha256_known_answers() {
+        // The RFC 6070 inputs, with HMAC-SHA256 in place of HMAC-SHA1.
+        assert_eq!(
+            hex::encode(pbkdf2_sha256(b"password", b"salt", 1)),
+            "120fb6cffcf8b32c43e7225256c4f837a86548c92ccc35480805987cb70be17b"
+        );
+        assert_eq!(
+            hex::encode(pbkdf2_sha256(b"password", b"salt", 4096)),
+            "c5e478d59288c841aa530db6845c4c8d962893a001ce4e11a4963873aa98134a"
+        );
+    }
+
+    #[test]
+    fn chacha20_known_keystream() {
+        // RFC 8439 A.1, test vector 1: zero key, zero nonce, block 0.
+        let mut block = [0; 64];
+        chacha20(&[0; 32], &mut block);
+        assert_eq!(
+            hex::encode(block),
+            "76b8e0ada0f13d90405d6ae55386bd28bdd219b8a08ded1aa836efcc8b770dc7\
+             da41597c5157488d7724e03fb8d84a376a43b8f41518a11cc387b669b2ee6586"
+        );
+    }
+
+    #[test]
+    fn wrong_passphrase_is_refused() {
+        let sealed = seal_with(b"right", b"some file", &[7; SALT_LEN], 2);
+        assert_eq!(open(b"right", &sealed).unwrap(), b"some file");
+        assert_eq!(
+            open(b"wrong", &sealed),
+            Err("wrong passphrase or damaged file")
+        );
+        let mut damaged = sealed.clone();
+        damaged[HEADER_LEN] ^= 1;
+        assert_eq!(
+            open(b"right", &damaged),
+            Err("wrong passphrase or damaged file")
+        );
+    }
+}
@@ -0,0 +1,1637 @@
+#[cfg(feature = "zbar")]
+use image::GenericImageView;
+use std::collections::{BTreeMap, HashSet};
+use std::io;
+use std::sync::atomic::{AtomicU64, Ordering};
+#[cfg(feature = "zbar")]
+use std::time::Instant;
+#[cfg(feature = "zbar")]
+use std::{fs, path, thread};
+
+#[cfg(feature = "zbar")]
+use crate::cache::DecodeCache;
+use crate::cbor::{self, CborError};
+use crate::crc32c;
+#[cfg(feature = "zbar")]
+use crate::exposure;
+#[cfg(feature = "zbar")]
+use crate::images::{Crop, ImageSequenceIterator};
+#[cfg(feature = "zbar")]
+use crate::luma::{self, channel_planes, Rect};
+#[cfg(feature = "zbar")]
+use crate::perspective;
+use crate::pool;
+#[cfg(feature = "zbar")]
+use crate::protocol::decode_payload;
+use crate::protocol::{
+    decode_metadata, get_id_and_len, guess_hash_len, id_type_holds, is_known_id_type,
+    sealed_data_frame, sync_counter, verify_hash, FileHash, Frame, FrameType, Manifest,
+    MetadataChunk, QrSendData, QrSendMetadata, PROTOCOL_MAJOR,
+};
+use crate::ranges::SegmentRanges;
+#[cfg(feature = "zbar")]
+use crate::screen::ScreenRegion;
+use crate::segments::Segments;
+#[cfg(feature = "zbar")]
+use crate::symbols::{SymbolReader, Zbar};
+use crate::sync::SyncTracker;
+#[cfg(feature = "zbar")]
+use crate::trace::{Trace, Verdict};
+
+/// Resolutions tried when a frame has no qrcode at native size. High
+/// resolution photos often only decode downscaled, tiny codes upscaled.
+pub const RETRY_SCALES: [f64; 3] = [0.5, 0.25, 2.0];
+/// Data frames kept while waiting for the metadata, enough for any
+/// transfer a capture holds while bounding a capture that has none.
+const MAX_HELD_FRAMES: usize = 1 << 16;
+/// Rescaled copies outside these bounds are not worth scanning.
+const MIN_RETRY_SIDE: u32 = 64;
+const MAX_RETRY_SIDE: u32 = 8192;
+/// White border added around frames cropped too tightly to keep the quiet
+/// zone readers look for, as a fraction of the longer side. A fifth is
+/// four modules even for the smallest qrcode.
+const QUIET_ZONE_FRACTION: u32 = 5;
+
+#[cfg(feature = "zbar")]
+pub use crate::symbols::scan;
+
+#[cfg(feature = "zbar")]
+fn decode_with(
+    readers: &[Box<dyn SymbolReader>],
+    img: &image::DynamicImage,
+    md: Option<&QrSendMetadata>,
+    foreign: Option<&AtomicU64>,
+) -> Vec<Vec<u8>> {
+    locate(readers, img, md, foreign)
+        .into_iter()
+        .map(|(frame, _)| frame)
+        .collect()
+}
+
+#[cfg(feature = "zbar")]
+/// Decode every code of `img` the first reader to find any sees, each
+/// along with the region to lock onto for it. Printed pages carry several.
+///
+/// Once the metadata is known only readers for the symbology it declares
+/// are tried, so codes of another kind never reach the frame parser.
+/// Codes that are no frame at all are left out, and counted in `foreign`
+/// when given.
+fn locate(
+    readers: &[Box<dyn SymbolReader>],
+    img: &image::DynamicImage,
+    md: Option<&QrSendMetadata>,
+    foreign: Option<&AtomicU64>,
+) -> Vec<(Vec<u8>, Option<ScreenRegion>)> {
+    let symbols = readers
+        .iter()
+        .filter(|r| md.is_none_or(|md| md.symbology == r.symbology()))
+        .map(|r| r.read(img))
+        .find(|symbols| !symbols.is_empty())
+        .unwrap_or_default();
+    let mut located = Vec::new();
+    for symbol in &symbols {
+        match decode_payload(&symbol.data, md) {
+            Some((_, frame)) => located.push((frame, lock_region(&symbol.points))),
+            None => {
+                if let Some(foreign) = foreign {
+                    log::debug!(
+                        "skipped a code that is no frame: {}",
+                        String::from_utf8_lossy(&symbol.data)
+                    );
+                    foreign.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+        }
+    }
+    located
+}
+
+#[cfg(feature = "zbar")]
+/// `img` in gray on a white canvas a [`QUIET_ZONE_FRACTION`] of its longer
+/// side wider on every edge.
+fn with_quiet_zone(img: &image::DynamicImage) -> image::DynamicImage {
+    let (w, h) = img.dimensions();
+    let pad = (w.max(h) / QUIET_ZONE_FRACTION).max(8);
+    let mut padded = image::GrayImage::from_pixel(w + 2 * pad, h + 2 * pad, image::Luma([255]));
+    image::imageops::replace(
+        &mut padded,
+        &luma::gray(img, Rect::whole(img)),
+        pad as i64,
+        pad as i64,
+    );
+    image::DynamicImage::ImageLuma8(padded)
+}
+
+#[cfg(feature = "zbar")]
+/// What `decode` finds in `img`, or with `color_channels` in each of its
+/// red, green and blue channels, each payload once.
+fn decode_channels(
+    img: &image::DynamicImage,
+    color_channels: bool,
+    mut decode: impl FnMut(&image::DynamicImage) -> Vec<Vec<u8>>,
+) -> Vec<Vec<u8>> {
+    if !color_channels {
+        return decode(img);
+    }
+    let mut decoded = Vec::new();
+    for plane in channel_planes(img) {
+        // Gray images carry the same frame in every channel.
+        for data in decode(&plane) {
+            if !decoded.contains(&data) {
+                decoded.push(data);
+            }
+        }
+    }
+    decoded
+}
+
+#[cfg(feature = "zbar")]
+/// Part of `img` inside `region`, clamped to the image and in gray, which is
+/// all the readers look at; `None` when nothing is left.
+fn crop_to(img: &image::DynamicImage, region: ScreenRegion) -> Option<image::DynamicImage> {
+    let (w, h) = img.dimensions();
+    let x = region.x.clamp(0, w as i32) as u32;
+    let y = region.y.clamp(0, h as i32) as u32;
+    let rect = Rect {
+        x,
+        y,
+        width: region.width.min(w - x),
+        height: region.height.min(h - y),
+    };
+    (rect.width > 0 && rect.height > 0).then(|| luma::gray(img, rect).into())
+}
+
+#[cfg(all(feature = "zbar", unix))]
+fn write_at(file: &fs::File, buf: &[u8], offset: u64) -> io::Result<()> {
+    use std::os::unix::fs::FileExt;
+    file.write_all_at(buf, offset)
+}
+
+#[cfg(all(feature = "zbar", windows))]
+fn write_at(file: &fs::File, mut buf: &[u8], mut offset: u64) -> io::Result<()> {
+    use std::os::windows::fs::FileExt;
+    while !buf.is_empty() {
+        let n = file.seek_write(buf, offset)?;
+        buf = &buf[n..];
+        offset += n as u64;
+    }
+    Ok(())
+}
+
+#[cfg(feature = "zbar")]
+/// Smallest region covering both `a` and `b`.
+fn union(a: ScreenRegion, b: ScreenRegion) -> ScreenRegion {
+    let x = a.x.min(b.x);
+    let y = a.y.min(b.y);
+    let right = (a.x + a.width as i32).max(b.x + b.width as i32);
+    let bottom = (a.y + a.height as i32).max(b.y + b.height as i32);
+    ScreenRegion {
+        x,
+        y,
+        width: (right - x) as u32,
+        height: (bottom - y) as u32,
+    }
+}
+
+#[cfg(feature = "zbar")]
+/// Region around the qrcode at `points`, padded by half its size so that a
+/// slightly moving code stays inside.
+fn lock_region(points: &[(i32, i32)]) -> Option<ScreenRegion> {
+    let min_x = points.iter().map(|p| p.0).min()?;
+    let max_x = points.iter().map(|p| p.0).max()?;
+    let min_y = points.iter().map(|p| p.1).min()?;
+    let max_y = points.iter().map(|p| p.1).max()?;
+    let pad = (max_x - min_x).max(max_y - min_y) / 2;
+    if pad == 0 {
+        return None;
+    }
+    Some(ScreenRegion {
+        x: min_x - pad,
+        y: min_y - pad,
+        width: (max_x - min_x + 2 * pad) as u32,
+        height: (max_y - min_y + 2 * pad) as u32,
+    })
+}
+
+#[cfg(feature = "zbar")]
+/// What finding and decoding the codes of an image takes, borrowed from a
+/// decoder so that scanning can run on a thread of its own.
+pub struct Scanner<'a> {
+    pub readers: &'a [Box<dyn SymbolReader>],
+    pub metadata: Option<&'a QrSendMetadata>,
+    pub perspective: bool,
+    pub exposure_retry: bool,
+    pub pad_retry: bool,
+    pub scale_retry: bool,
+    pub rotation_retry: bool,
+    /// Counts the codes of the image as given that are no frame; copies
+    /// tried on retry are not counted again.
+    pub foreign: &'a AtomicU64,
+}
+#[cfg(feature = "zbar")]
+impl Scanner<'_> {
+    /// Decode one image, first with its perspective corrected when enabled.
+    pub fn decode(&self, img: &image::DynamicImage) -> Vec<Vec<u8>> {
+        if self.perspective {
+            if let Some(flat) = perspective::rectify(img) {
+                let decoded = self.decode_retrying(&flat, None);
+                if !decoded.is_empty() {
+                    log::trace!("decoded with the perspective corrected");
+                    return decoded;
+                }
+            }
+        }
+        self.decode_retrying(img, Some(self.foreign))
+    }
+    /// Decode one image, falling back to copies with the exposure
+    /// normalized, padded, rescaled and rotated, in that order, when enabled and nothing is found as it is.
+    fn decode_retrying(
+        &self,
+        img: &image::DynamicImage,
+        foreign: Option<&AtomicU64>,
+    ) -> Vec<Vec<u8>> {
+        let decoded = decode_with(self.readers, img, self.metadata, foreign);
+        if !decoded.is_empty() {
+            return decoded;
+        }
+        self.decode_transformed(img)
+    }
+    fn decode_transformed(&self, img: &image::DynamicImage) -> Vec<Vec<u8>> {
+        let md = self.metadata;
+        if self.exposure_retry && !exposure::Exposure::measure(img).is_normal() {
+            if let Some(normalized) = exposure::normalize(img) {
+                let decoded = decode_with(self.readers, &normalized, md, None);
+                if !decoded.is_empty() {
+                    log::trace!("decoded with the exposure normalized");
+                    return decoded;
+                }
+            }
+        }
+        if self.pad_retry {
+            let decoded = decode_with(self.readers, &with_quiet_zone(img), md, None);
+            if !decoded.is_empty() {
+                log::trace!("decoded with a quiet zone added");
+                return decoded;
+            }
+        }
+        if self.scale_retry {
+            let (w, h) = img.dimensions();
+            let luma = luma::gray(img, Rect::whole(img));
+            for scale in RETRY_SCALES {
+                let (sw, sh) = ((w as f64 * scale) as u32, (h as f64 * scale) as u32);
+                if sw.min(sh) < MIN_RETRY_SIDE || sw.max(sh) > MAX_RETRY_SIDE {
+                    continue;
+                }
+                let scaled = luma::resize(&luma, sw, sh).into();
+                let decoded = decode_with(self.readers, &scaled, md, None);
+                if !decoded.is_empty() {
+                    log::trace!("decoded at {}x scale", scale);
+                    return decoded;
+                }
+            }
+        }
+        if self.rotation_retry {
+            let rotations: [fn(&image::DynamicImage) -> image::DynamicImage; 3] = [
+                image::DynamicImage::rotate90,
+                image::DynamicImage::rotate180,
+                image::DynamicImage::rotate270,
+            ];
+            return rotations
+                .iter()
+                .map(|rotate| decode_with(self.readers, &rotate(img), md, None))
+                .find(|decoded| !decoded.is_empty())
+                .unwrap_or_default();
+        }
+        Vec::new()
+    }
+    /// Frames of one image, each color channel decoded on its own once the
+    /// metadata declares them.
+    pub fn frames(&self, img: &image::DynamicImage) -> Vec<Vec<u8>> {
+        let color_channels = self.metadata.is_some_and(|md| md.color_channels);
+        decode_channels(img, color_channels, |img| self.decode(img))
+    }
+}
+
+/// Distinct copies of one metadata chunk, with how often each was seen.
+type ChunkCopies = Vec<(Vec<u8>, u64)>;
+
+pub struct QrSendDecoder {
+    pub metadata: Option<QrSendMetadata>,
+    pub data_segments: Segments,
+    /// Segment ids that arrived with differing content, with the number of
+    /// conflicting copies seen.
+    pub conflicts: BTreeMap<u64, u64>,
+    /// Whole-file hash from the first hash frame that passed its frame
+    /// hash. Senders repeat it every loop; later copies are only compared.
+    pub total_hash: Vec<u8>,
+    /// Who sent the transfer, from the first manifest frame; later ones
+    /// naming another transfer are ambiguities.
+    pub manifest: Option<Manifest>,
+    /// Hash frames seen, the first included.
+    pub hash_frames: u64,
+    /// Hash frames whose whole-file hash differs from the first.
+    pub conflicting_hash_frames: u64,
+    pub sync: SyncTracker,
+    /// Images fed through the decoder so far.
+    pub frames_read: u64,
+    pub unknown_frames: u64,
+    /// Verified frames of registered types this receiver does not act on.
+    pub skipped_frames: BTreeMap<FrameType, u64>,
+    /// Images in which no qrcode was found.
+    pub undecoded_frames: u64,
+    /// Codes that are no frame in any payload encoding, such as a Wi-Fi
+    /// qrcode on a poster behind the screen; see [`Self::foreign_codes`].
+    foreign_codes: AtomicU64,
+    /// Frames whose hash did not verify.
+    pub rejected_frames: u64,
+    /// Data frames that verified but are too short to hold the id and hash
+    /// the metadata declares.
+    pub undersized_frames: u64,
+    /// Data frames that verified but whose content length disagrees with the
+    /// segment size the metadata declares.
+    pub wrong_length_frames: u64,
+    /// Data frames that verified but name a segment past the count the
+    /// metadata declares, which only corruption or another transfer makes.
+    pub out_of_range_frames: u64,
+    /// Version of metadata that was received but is not one this receiver decodes.
+    pub unsupported_version: Option<String>,
+    /// Sender passes over the data frames seen so far, counting each time
+    /// a segment already seen on the current pass comes round again.
+    /// Senders may interleave their segments, so ids need not ascend.
+    pub passes: u64,
+    /// Pass during which the last missing segment arrived.
+    pub complete_after_pass: Option<u64>,
+    last_data_id: Option<u64>,
+    /// Segments seen on the current pass.
+    pass_ids: HashSet<u64>,
+    /// The most recent frame that verified.
+    pub last_payload: Option<Vec<u8>>,
+    /// While set, ids of the data frames accepted since a run last took
+    /// them, repeats included, so runs can tell which source decoded which
+    /// segments.
+    pub decoded_ids: Option<Vec<u64>>,
+    metadata_buf: Vec<u8>,
+    /// Data frames that verified before the metadata arrived, fed in once
+    /// it does so that no capture order loses them.
+    held_data: Vec<Vec<u8>>,
+    /// Copies of each indexed metadata chunk, keyed by chunk count and index.
+    metadata_votes: BTreeMap<(u8, u8), ChunkCopies>,
+    /// Hash length the first metadata chunk verified with, tried first on the
+    /// chunks that follow before falling back to guessing.
+    metadata_hash_len: Option<usize>,
+    /// Refuse data frames whose hash length would have to be guessed.
+    pub strict_hash_len: bool,
+    /// Only store these segments, for a pass that fills known gaps.
+    pub only_segments: Option<SegmentRanges>,
+    /// Data frames of segments outside `only_segments`, dropped once their
+    /// hash checks out, before anything else is done with them.
+    pub filtered_frames: u64,
+    /// Fail closed: refuse every frame but metadata whose hash length would
+    /// be guessed, and stop at the first unknown frame type, inconsistent
+    /// metadata or conflicting segment.
+    pub strict: bool,
+    /// What stopped a strict decode.
+    pub violation: Option<String>,
+    /// Retry images without a qrcode rotated by 90, 180 and 270 degrees.
+    pub rotation_retry: bool,
+    /// Warp the screen of each image back to a rectangle before scanning,
+    /// for photos taken at an angle.
+    pub perspective: bool,
+    /// Retry over- and under-exposed images without a qrcode with their
+    /// levels and gamma normalized.
+    pub exposure_retry: bool,
+    /// Retry images without a qrcode inside a white border, for
+    /// screenshots cropped right to the code's edge.
+    pub pad_retry: bool,
+    /// Retry images without a qrcode at the [`RETRY_SCALES`].
+    pub scale_retry: bool,
+    /// Where to write the raw bytes of frames with unrecognized type bytes.
+    #[cfg(feature = "zbar")]
+    pub unknown_dump: Option<path::PathBuf>,
+    /// Where to write every payload decoded, before it is verified.
+    #[cfg(feature = "zbar")]
+    pub payload_dump: Option<path::PathBuf>,
+    /// Where a record of each image read and the codes in it goes.
+    #[cfg(feature = "zbar")]
+    pub trace: Option<Trace>,
+    /// Only scan this part of each image.
+    #[cfg(feature = "zbar")]
+    pub crop: Option<Crop>,
+    /// Where [`Crop::Auto`] last found the qrcode.
+    #[cfg(feature = "zbar")]
+    locked_region: Option<ScreenRegion>,
+    /// Stop reading images once this many have been read.
+    #[cfg(feature = "zbar")]
+    pub max_frames: Option<u64>,
+    /// Stop reading images after this instant.
+    #[cfg(feature = "zbar")]
+    pub deadline: Option<Instant>,
+    /// Reading stopped because `max_frames` or `deadline` ran out.
+    #[cfg(feature = "zbar")]
+    pub budget_spent: bool,
+    /// Decode results of images seen on earlier runs.
+    #[cfg(feature = "zbar")]
+    pub cache: Option<DecodeCache>,
+    /// Readers tried on each image, in order.
+    #[cfg(feature = "zbar")]
+    pub readers: Vec<Box<dyn SymbolReader>>,
+}
+impl QrSendDecoder {
+    pub fn new() -> Self {
+        QrSendDecoder {
+            metadata: None,
+            data_segments: Segments::new(),
+            conflicts: BTreeMap::new(),
+            total_hash: Vec::new(),
+            manifest: None,
+            hash_frames: 0,
+            conflicting_hash_frames: 0,
+            sync: SyncTracker::default(),
+            frames_read: 0,
+            unknown_frames: 0,
+            skipped_frames: BTreeMap::new(),
+            undecoded_frames: 0,
+            foreign_codes: AtomicU64::new(0),
+            rejected_frames: 0,
+            undersized_frames: 0,
+            wrong_length_frames: 0,
+            out_of_range_frames: 0,
+            unsupported_version: None,
+            passes: 0,
+            complete_after_pass: None,
+            last_data_id: None,
+            pass_ids: HashSet::new(),
+            last_payload: None,
+            decoded_ids: None,
+            metadata_buf: Vec::new(),
+            metadata_votes: BTreeMap::new(),
+            metadata_hash_len: None,
+            held_data: Vec::new(),
+            strict_hash_len: false,
+            only_segments: None,
+            filtered_frames: 0,
+            strict: false,
+            violation: None,
+            rotation_retry: false,
+            perspective: false,
+            exposure_retry: false,
+            pad_retry: false,
+            scale_retry: false,
+            #[cfg(feature = "zbar")]
+            unknown_dump: None,
+            #[cfg(feature = "zbar")]
+            payload_dump: None,
+            #[cfg(feature = "zbar")]
+            trace: None,
+            #[cfg(feature = "zbar")]
+            crop: None,
+            #[cfg(feature = "zbar")]
+            locked_region: None,
+            #[cfg(feature = "zbar")]
+            max_frames: None,
+            #[cfg(feature = "zbar")]
+            deadline: None,
+            #[cfg(feature = "zbar")]
+            budget_spent: false,
+            #[cfg(feature = "zbar")]
+            cache: None,
+            #[cfg(feature = "zbar")]
+            readers: vec![Box::new(Zbar)],
+        }
+    }
+    /// The hash length `data` verifies with: the metadata's once known,
+    /// otherwise guessed.
+    fn frame_hash_len(&self, data: &[u8]) -> Option<usize> {
+        if let Some(md) = &self.metadata {
+            let len = md.hash_len as usize;
+            return verify_hash(data, len).then_some(len);
+        }
+        let frame_type = FrameType::of(data);
+        if self.strict_hash_len && frame_type == Some(FrameType::Data) {
+            return None;
+        }
+        if self.strict && frame_type != Some(FrameType::Metadata) {
+            return None;
+        }
+        self.metadata_hash_len
+            .filter(|&len| verify_hash(data, len))
+            .or_else(|| guess_hash_len(data))
+    }
+    /// Report something a well-behaved sender never produces: a warning
+    /// normally, the end of decoding in strict mode.
+    fn ambiguity(&mut self, what: String) {
+        if !self.strict {
+            log::warn!("{}", what);
+        } else if self.violation.is_none() {
+            log::error!("strict mode: {}", what);
+            self.violation = Some(what);
+        }
+    }
+    /// `data` without the CRC-32C the metadata says data frames end in,
+    /// or `None` when it does not match. Before the metadata is known a
+    /// matching CRC is stripped and anything else passed on as it is.
+    fn without_crc(&self, mut data: Vec<u8>) -> Option<Vec<u8>> {
+        let declared = self.metadata.as_ref().map(|md| md.crc32c);
+        if FrameType::of(&data) != Some(FrameType::Data) || declared == Some(false) {
+            return Some(data);
+        }
+        match crc32c::strip(&data) {
+            Some(body) => {
+                let len = body.len();
+                data.truncate(len);
+                Some(data)
+            }
+            None if declared == Some(true) => {
+                log::trace!("rejected frame with bad crc: {}", hex::encode(&data));
+                None
+            }
+            None => Some(data),
+        }
+    }
+    /// Codes seen that are no frame in any payload encoding. They are
+    /// skipped without counting as bad frames.
+    pub fn foreign_codes(&self) -> u64 {
+        self.foreign_codes.load(Ordering::Relaxed)
+    }
+    /// Count a code fed as text that is no frame.
+    pub fn push_foreign(&mut self) {
+        *self.foreign_codes.get_mut() += 1;
+    }
+    pub fn verify_segment(&self, data: &[u8]) -> bool {
+        self.frame_hash_len(data).is_some()
+    }
+    #[cfg(feature = "zbar")]
+    /// Decode one image, from the cache when it has been decoded before.
+    fn decode_image(&mut self, img: &image::DynamicImage) -> Vec<Vec<u8>> {
+        if self.cache.is_none() {
+            return self.scan_image(img);
+        }
+        // Metadata picks the payload encoding, so it is part of the key.
+        let settings = format!(
+            "{:?} {} {} {} {} {} {:?} {:?}",
+            self.crop,
+            self.perspective,
+            self.exposure_retry,
+            self.pad_retry,
+            self.scale_retry,
+            self.rotation_retry,
+            self.metadata
+                .as_ref()
+                .map(|md| (md.payload_encoding, md.symbology, md.magic)),
+            self.readers
+                .iter()
+                .map(|r| r.symbology())
+                .collect::<Vec<_>>()
+        );
+        let key = DecodeCache::key(img, &settings);
+        if let Some(trace) = self.trace.as_mut() {
+            trace.cached(&key);
+        }
+        if let Some(decoded) = self.cache.as_mut().unwrap().get(&key) {
+            log::trace!("frame {}: decoded from cache", self.frames_read);
+            return decoded;
+        }
+        let decoded = self.scan_image(img);
+        self.cache.as_ref().unwrap().put(&key, &decoded);
+        decoded
+    }
+    #[cfg(feature = "zbar")]
+    /// Scan one image, only inside the crop region when one is set.
+    ///
+    /// In auto mode the whole image is scanned until a qrcode is found, and
+    /// again whenever nothing decodes inside the region locked onto.
+    fn scan_image(&mut self, img: &image::DynamicImage) -> Vec<Vec<u8>> {
+        let region = match self.crop {
+            Some(Crop::Region(region)) => Some(region),
+            Some(Crop::Auto) => self.locked_region,
+            None => None,
+        };
+        if let Some(region) = region {
+            let decoded = crop_to(img, region)
+                .map(|c| self.scanner().decode(&c))
+                .unwrap_or_default();
+            if !decoded.is_empty() || self.crop != Some(Crop::Auto) {
+                return decoded;
+            }
+            log::debug!("qrcode left {:?}, scanning the whole image", region);
+        }
+        if self.crop == Some(Crop::Auto) {
+            let located = locate(
+                &self.readers,
+                img,
+                self.metadata.as_ref(),
+                Some(&self.foreign_codes),
+            );
+            if located.is_empty() {
+                return self.scanner().decode_transformed(img);
+            }
+            let mut decoded = Vec::new();
+            for (data, found) in located {
+                // Grow rather than move the region, so codes of different
+                // sizes drawn at the same spot all end up inside it.
+                self.locked_region = match (self.locked_region, found) {
+                    (Some(locked), Some(found)) => Some(union(locked, found)),
+                    (locked, found) => found.or(locked),
+                };
+                decoded.push(data);
+            }
+            log::debug!("auto crop locked onto {:?}", self.locked_region);
+            return decoded;
+        }
+        self.scanner().decode(img)
+    }
+    #[cfg(feature = "zbar")]
+    fn scanner(&self) -> Scanner<'_> {
+        Scanner {
+            readers: &self.readers,
+            metadata: self.metadata.as_ref(),
+            perspective: self.perspective,
+            exposure_retry: self.exposure_retry,
+            pad_retry: self.pad_retry,
+            scale_retry: self.scale_retry,
+            rotation_retry: self.rotation_retry,
+            foreign: &self.foreign_codes,
+        }
+    }
+    #[cfg(feature = "zbar")]
+    /// Whether the frame or time budget has run out, logging it the first time.
+    pub fn out_of_budget(&mut self) -> bool {
+        if self.budget_spent {
+            return true;
+        }
+        if self.max_frames.is_some_and(|max| self.frames_read >= max) {
+            log::warn!("frame budget spent after {} frames", self.frames_read);
+        } else if self.deadline.is_some_and(|at| Instant::now() >= at) {
+            log::warn!("time budget spent after {} frames", self.frames_read);
+        } else {
+            return false;
+        }
+        self.budget_spent = true;
+        true
+    }
+    #[cfg(feature = "zbar")]
+    /// Decode and verify one image, accounting sync frames on the way.
+    ///
+    /// Once the metadata declares color channels each channel is decoded
+    /// on its own, so one image yields up to three frames.
+    fn read_frames(&mut self, img: &image::DynamicImage) -> Vec<Vec<u8>> {
+        let index = self.frames_read;
+        self.frames_read += 1;
+        let (start, foreign) = (Instant::now(), self.foreign_codes());
+        if let Some(trace) = self.trace.as_mut() {
+            trace.begin(index);
+        }
+        let color_channels = self.metadata.as_ref().is_some_and(|md| md.color_channels);
+        let decoded = decode_channels(img, color_channels, |img| self.decode_codes(index, img));
+        if decoded.is_empty() {
+            self.undecoded_frames += 1;
+        }
+        let decode_time = start.elapsed();
+        self.dump_payloads(index, &decoded);
+        let accepted = decoded
+            .into_iter()
+            .filter_map(|data| self.accept_payload(data))
+            .collect();
+        let foreign = self.foreign_codes() - foreign;
+        if let Some(trace) = self.trace.as_mut() {
+            trace.end(Some(decode_time), foreign);
+        }
+        accepted
+    }
+    #[cfg(feature = "zbar")]
+    fn decode_codes(&mut self, index: u64, img: &image::DynamicImage) -> Vec<Vec<u8>> {
+        let start = Instant::now();
+        let decoded = self.decode_image(img);
+        let decode_time = start.elapsed();
+        if decoded.is_empty() {
+            log::debug!("frame {}: no qrcode found in {:?}", index, decode_time);
+            self.sync.on_failure();
+        }
+        for data in &decoded {
+            log::debug!(
+                "frame {}: decoded {} bytes in {:?}",
+                index,
+                data.len(),
+                decode_time
+            );
+        }
+        decoded
+    }
+    /// Verify a decoded payload, returning it if it is a frame worth dispatching.
+    fn accept_payload(&mut self, data: Vec<u8>) -> Option<Vec<u8>> {
+        #[cfg(feature = "zbar")]
+        if self.trace.is_some() {
+            return self.accept_traced(data);
+        }
+        self.verify_payload(data)
+    }
+    #[cfg(feature = "zbar")]
+    /// [`verify_payload`](Self::verify_payload), adding to the trace what
+    /// became of `data`, told by the counter it moved.
+    fn accept_traced(&mut self, data: Vec<u8>) -> Option<Vec<u8>> {
+        let (len, frame_type) = (data.len(), FrameType::of(&data));
+        let counters = |d: &Self| {
+            [
+                (d.unknown_frames, Verdict::UnknownType),
+                (d.rejected_frames, Verdict::Rejected),
+                (d.undersized_frames, Verdict::Undersized),
+                (d.wrong_length_frames, Verdict::WrongLength),
+                (d.out_of_range_frames, Verdict::OutOfRange),
+                (d.filtered_frames, Verdict::Filtered),
+            ]
+        };
+        let before = counters(self);
+        let accepted = self.verify_payload(data);
+        let verdict = match &accepted {
+            Some(_) => Verdict::Accepted,
+            None => counters(self)
+                .into_iter()
+                .zip(before)
+                .find(|((after, _), (before, _))| after != before)
+                .map_or(Verdict::Skipped, |((_, verdict), _)| verdict),
+        };
+        let segment = accepted
+            .as_ref()
+            .filter(|_| frame_type == Some(FrameType::Data))
+            .zip(self.metadata.as_ref())
+            .and_then(|(data, md)| get_id_and_len(&data[1..], md).ok())
+            .map(|(id, _)| id);
+        let trace = self.trace.as_mut().expect("tracing");
+        trace.code(len, frame_type.map(FrameType::name), segment, verdict);
+        accepted
+    }
+    /// Check a decoded payload's type, hash and length, counting it under
+    /// the first it fails.
+    fn verify_payload(&mut self, data: Vec<u8>) -> Option<Vec<u8>> {
+        let Some(frame_type) = FrameType::of(&data) else {
+            self.on_unknown(&data);
+            return None;
+        };
+        let Some(data) = self.without_crc(data) else {
+            self.rejected_frames += 1;
+            self.sync.on_failure();
+            return None;
+        };
+        if !self.verify_segment(&data) {
+            log::trace!("rejected frame with bad hash: {}", hex::encode(&data));
+            self.rejected_frames += 1;
+            self.sync.on_failure();
+            return None;
+        }
+        if let Some(id) = self.filtered_id(&data) {
+            log::trace!("skipped data frame of segment {}, not asked for", id);
+            self.filtered_frames += 1;
+            self.count_pass(id);
+            pool::give(data);
+            return None;
+        }
+        if self.is_undersized(&data) {
+            log::debug!("rejected undersized data frame: {}", hex::encode(&data));
+            self.undersized_frames += 1;
+            self.sync.on_failure();
+            return None;
+        }
+        if let Some(id) = self.out_of_range_id(&data) {
+            log::debug!("rejected data frame naming segment {}, past the last", id);
+            self.out_of_range_frames += 1;
+            self.sync.on_failure();
+            return None;
+        }
+        if self.is_wrong_length(&data) {
+            log::debug!(
+                "rejected data frame of the wrong length: {}",
+                hex::encode(&data)
+            );
+            self.wrong_length_frames += 1;
+            self.sync.on_failure();
+            return None;
+        }
+        if frame_type == FrameType::Sync {
+            if let Some(counter) = sync_counter(&data) {
+                self.sync.on_sync(counter);
+            }
+            return None;
+        }
+        if !frame_type.is_handled() {
+            log::debug!("skipped {} frame", frame_type.name());
+            *self.skipped_frames.entry(frame_type).or_default() += 1;
+            return None;
+        }
+        self.last_payload = Some(data.clone());
+        self.sync.on_payload(&data);
+        Some(data)
+    }
+    /// Whether `data` is a data frame with no room for the id and hash of the
+    /// known metadata. A short hash can verify by chance on a truncated frame.
+    fn is_undersized(&self, data: &[u8]) -> bool {
+        let Some(md) = &self.metadata else {
+            return false;
+        };
+        if FrameType::of(data) != Some(FrameType::Data) {
+            return false;
+        }
+        match get_id_and_len(&data[1..], md) {
+            Ok((_, id_len)) => (data.len() as u64) < 1 + id_len as u64 + md.hash_len,
+            Err(_) => true,
+        }
+    }
+    /// The id of `data` when it is a data frame naming a segment at or past
+    /// the declared count.
+    fn out_of_range_id(&self, data: &[u8]) -> Option<u64> {
+        let md = self.metadata.as_ref()?;
+        if FrameType::of(data) != Some(FrameType::Data) {
+            return None;
+        }
+        let (id, _) = get_id_and_len(&data[1..], md).ok()?;
+        (id >= md.qrcode_count).then_some(id)
+    }
+    /// The id of `data` when it is a data frame of a segment outside
+    /// `only_segments`. Only asked of frames whose hash verified, so a bit
+    /// error in the id neither drops a wanted segment nor counts a pass.
+    fn filtered_id(&self, data: &[u8]) -> Option<u64> {
+        let only = self.only_segments.as_ref()?;
+        let md = self.metadata.as_ref()?;
+        if FrameType::of(data) != Some(FrameType::Data) {
+            return None;
+        }
+        let (id, _) = get_id_and_len(&data[1..], md).ok()?;
+        (!only.contains(id)).then_some(id)
+    }
+    /// Whether `data` is a data frame whose content is not as long as the
+    /// declared segment size makes it. A bit error that cuts or stretches a
+    /// frame can still leave a short hash verifying.
+    fn is_wrong_length(&self, data: &[u8]) -> bool {
+        let Some(md) = &self.metadata else {
+            return false;
+        };
+        let Some(size) = md.segment_size else {
+            return false;
+        };
+        if FrameType::of(data) != Some(FrameType::Data) {
+            return false;
+        }
+        let Ok((id, id_len)) = get_id_and_len(&data[1..], md) else {
+            return false;
+        };
+        // Not undersized, so the id and hash fit.
+        let len = (data.len() - 1 - id_len) as u64 - md.hash_len;
+        if id + 1 < md.qrcode_count {
+            return len != size;
+        }
+        // Metadata that overflows here is refused, but a session can hold it.
+        let Some(before_last) = md.qrcode_count.saturating_sub(1).checked_mul(size) else {
+            return true;
+        };
+        match md.file_size {
+            Some(file_size) => len != file_size.saturating_sub(before_last),
+            None => len > size,
+        }
+    }
+    /// Feed one decoded payload regardless of phase.
+    pub fn push(&mut self, data: Vec<u8>) {
+        if let Some(data) = self.accept_payload(data) {
+            self.dispatch(data);
+        }
+    }
+    #[cfg(feature = "zbar")]
+    /// Verify and feed the frames a [`Scanner`] found in one image, for
+    /// images scanned away from the decoder, along with the number of codes
+    /// in it that were no frame.
+    pub fn push_scanned(&mut self, decoded: Vec<Vec<u8>>, foreign: u64) {
+        if let Some(trace) = self.trace.as_mut() {
+            trace.begin(self.frames_read);
+        }
+        self.dump_payloads(self.frames_read, &decoded);
+        self.frames_read += 1;
+        *self.foreign_codes.get_mut() += foreign;
+        if decoded.is_empty() {
+            self.undecoded_frames += 1;
+            self.sync.on_failure();
+        }
+        for data in decoded {
+            self.push(data);
+        }
+        if let Some(trace) = self.trace.as_mut() {
+            trace.end(None, foreign);
+        }
+    }
+    #[cfg(feature = "zbar")]
+    /// Decode one image and feed it regardless of phase, for captures that cannot rewind.
+    pub fn push_image(&mut self, img: &image::DynamicImage) {
+        for data in self.read_frames(img) {
+            self.dispatch(data);
+        }
+    }
+    /// Act on a frame that passed [`accept_payload`](Self::accept_payload).
+    fn dispatch(&mut self, data: Vec<u8>) {
+        match FrameType::of(&data) {
+            Some(FrameType::Metadata)
+                if self.metadata.is_none() && self.unsupported_version.is_none() =>
+            {
+                self.on_metadata_chunk(&data);
+            }
+            Some(FrameType::Data) if self.metadata.is_some() => {
+                self.on_data(data);
+                return;
+            }
+            Some(FrameType::Data) if self.unsupported_version.is_none() => {
+                self.hold(data);
+                return;
+            }
+            Some(FrameType::Hash) => self.on_hash(&data),
+            Some(FrameType::Manifest) => self.on_manifest(&data),
+            _ => {}
+        }
+        pool::give(data);
+    }
+    /// Append a metadata chunk, returning true once the metadata is complete
+    /// or has turned out to be from an unsupported protocol version.
+    fn on_metadata_chunk(&mut self, data: &[u8]) -> bool {
+        let Some(hash_len) = self.frame_hash_len(data) else {
+            return false;
+        };
+        self.metadata_hash_len = Some(hash_len);
+        let chunk = match Frame::parse(data, hash_len, None) {
+            Ok(Frame::Metadata(chunk)) => chunk,
+            Ok(_) => return false,
+            Err(e) => {
+                self.ambiguity(format!("dropping unparsable metadata frame: {:?}", e));
+                return false;
+            }
+        };
+        let md: QrSendMetadata = match chunk {
+            MetadataChunk::Indexed {
+                index,
+                count,
+                check,
+                piece,
+            } => match self.on_indexed_chunk(index, count, check, piece) {
+                Some(md) => md,
+                None => return false,
+            },
+            MetadataChunk::Cbor(chunk) => {
+                self.metadata_buf.extend_from_slice(chunk);
+                let parsed = match cbor::from_slice(&self.metadata_buf) {
+                    Err(CborError::Truncated) => return false,
+                    Err(CborError::Invalid) => None,
+                    Ok(value) => serde_json::from_value(value).ok(),
+                };
+                let Some(md) = parsed else {
+                    log::warn!("discarding malformed cbor metadata");
+                    self.metadata_buf.clear();
+                    return false;
+                };
+                md
+            }
+            MetadataChunk::Json(body) => {
+                self.metadata_buf.extend_from_slice(body);
+                if body.last() != Some(&b'}') {
+                    return false;
+                }
+                match serde_json::from_slice(&self.metadata_buf) {
+                    Ok(md) => md,
+                    Err(e) => {
+                        log::warn!(
+                            "discarding malformed metadata, a metadata frame was probably missed: {}",
+                            e
+                        );
+                        self.metadata_buf.clear();
+                        return false;
+                    }
+                }
+            }
+        };
+        if !is_known_id_type(&md.id_type) || !(1..=64).contains(&md.hash_len) {
+            self.ambiguity(format!(
+                "discarding metadata with id type {:?} and hash length {}",
+                md.id_type, md.hash_len
+            ));
+            self.metadata_buf.clear();
+            self.metadata_votes.clear();
+            return false;
+        }
+        // The one hash length guessed in strict mode is the metadata's own,
+        // which what it declares must confirm.
+        if hash_len as u64 != md.hash_len {
+            self.ambiguity(format!(
+                "metadata sealed with {} byte hashes declares {} byte hashes",
+                hash_len, md.hash_len
+            ));
+        }
+        if !id_type_holds(&md.id_type, md.qrcode_count) {
+            self.ambiguity(format!(
+                "discarding metadata declaring {} segments, more than id type {} can number",
+                md.qrcode_count, md.id_type
+            ));
+            self.metadata_buf.clear();
+            self.metadata_votes.clear();
+            return false;
+        }
+        if !md.layout_fits() {
+            self.ambiguity(format!(
+                "discarding metadata declaring {} segments of {:?} bytes in a file of {:?}",
+                md.qrcode_count, md.segment_size, md.file_size
+            ));
+            self.metadata_buf.clear();
+            self.metadata_votes.clear();
+            return false;
+        }
+        if !md.is_supported() {
+            log::error!(
+                "sender uses protocol version {}, this receiver decodes {}.x",
+                md.version,
+                PROTOCOL_MAJOR
+            );
+            self.unsupported_version = Some(md.version);
+            return true;
+        }
+        log::info!("got metadata: {:?}", md);
+        #[cfg(feature = "zbar")]
+        if !self.readers.iter().any(|r| r.symbology() == md.symbology) {
+            let name = clap::ValueEnum::to_possible_value(&md.symbology).unwrap();
+            log::error!(
+                "frames are {} codes, which are not being read; pass --symbology {}",
+                name.get_name(),
+                name.get_name()
+            );
+        }
+        self.data_segments.reserve(md.qrcode_count);
+        self.metadata = Some(md);
+        self.release_held();
+        true
+    }
+    /// Keep a data frame seen before the metadata, up to [`MAX_HELD_FRAMES`].
+    fn hold(&mut self, data: Vec<u8>) {
+        if self.held_data.len() < MAX_HELD_FRAMES {
+            self.held_data.push(data);
+        } else {
+            log::debug!("dropping data frame seen before the metadata, too many held");
+        }
+    }
+    /// Feed the data frames held while the metadata was missing, now that
+    /// their hash length and id type are known.
+    fn release_held(&mut self) {
+        let held = std::mem::take(&mut self.held_data);
+        if !held.is_empty() {
+            log::debug!(
+                "feeding {} data frames seen before the metadata",
+                held.len()
+            );
+        }
+        for data in held {
+            if !self.verify_segment(&data) {
+                self.rejected_frames += 1;
+                continue;
+            }
+            if let Some(id) = self.filtered_id(&data) {
+                self.filtered_frames += 1;
+                self.count_pass(id);
+                pool::give(data);
+                continue;
+            }
+            if !self.is_undersized(&data)
+                && self.out_of_range_id(&data).is_none()
+                && !self.is_wrong_length(&data)
+            {
+                self.on_data(data);
+            } else {
+                self.rejected_frames += 1;
+            }
+        }
+    }
+    /// Record a copy of an indexed metadata chunk, returning the metadata
+    /// once every chunk has a copy seen more often than any other copy of
+    /// it, and those copies parse. A `check` is the length and CRC-32C the
+    /// copies must add up to.
+    fn on_indexed_chunk(
+        &mut self,
+        index: u8,
+        count: u8,
+        check: Option<(usize, u32)>,
+        piece: &[u8],
+    ) -> Option<QrSendMetadata> {
+        if index >= count {
+            self.ambiguity(format!("metadata chunk index {} out of {}", index, count));
+            return None;
+        }
+        let copies = self.metadata_votes.entry((count, index)).or_default();
+        match copies.iter_mut().find(|(copy, _)| copy == piece) {
+            Some((_, votes)) => *votes += 1,
+            None => {
+                copies.push((piece.to_vec(), 1));
+                if copies.len() > 1 {
+                    self.ambiguity(format!(
+                        "metadata chunk {} of {} arrived with conflicting content",
+                        index, count
+                    ));
+                }
+            }
+        }
+        let mut encoded = Vec::new();
+        for i in 0..count {
+            let mut ranked: Vec<&(Vec<u8>, u64)> =
+                self.metadata_votes.get(&(count, i))?.iter().collect();
+            ranked.sort_by_key(|(_, votes)| std::cmp::Reverse(*votes));
+            if ranked
+                .get(1)
+                .is_some_and(|(_, votes)| *votes == ranked[0].1)
+            {
+                log::debug!(
+                    "metadata chunk {} copies disagree, waiting for a majority",
+                    i
+                );
+                return None;
+            }
+            encoded.extend_from_slice(&ranked[0].0);
+        }
+        if let Some((len, crc)) = check {
+            if encoded.len() != len || crc32c::checksum(&encoded) != crc {
+                log::debug!(
+                    "reassembled metadata is {} bytes and fails its checksum, waiting for more copies",
+                    encoded.len()
+                );
+                return None;
+            }
+        }
+        let md = decode_metadata(&encoded);
+        if md.is_none() {
+            log::debug!("reassembled metadata does not parse, waiting for more copies");
+        }
+        md
+    }
+    /// Store a data frame, returning its segment id.
+    fn on_data(&mut self, data: Vec<u8>) -> Option<u64> {
+        let data = match QrSendData::from_frame(data, self.metadata.as_ref().unwrap()) {
+            Ok(data) => data,
+            Err((e, data)) => {
+                self.ambiguity(format!("dropping unparsable data frame: {:?}", e));
+                pool::give(data);
+                return None;
+            }
+        };
+        let id = data.id;
+        log::debug!("got data id: {}", data.id);
+        self.count_pass(id);
+        if let Some(ids) = self.decoded_ids.as_mut() {
+            ids.push(id);
+        }
+        self.insert_segment(data);
+        let count = self.metadata.as_ref().map_or(0, |md| md.qrcode_count);
+        if self.complete_after_pass.is_none() && self.data_segments.first_missing(count).is_none() {
+            self.complete_after_pass = Some(self.passes);
+        }
+        Some(id)
+    }
+    /// Follow the sender's passes over the segments, `id` being the one shown.
+    fn count_pass(&mut self, id: u64) {
+        // A segment shown on consecutive frames is still the same pass.
+        let repeated = self.last_data_id != Some(id) && self.pass_ids.contains(&id);
+        if self.last_data_id.is_none() || repeated {
+            self.passes += 1;
+            self.pass_ids.clear();
+            if self.passes > 1 {
+                log::debug!("sender pass {} started at segment {}", self.passes, id);
+            }
+        }
+        self.pass_ids.insert(id);
+        self.last_data_id = Some(id);
+    }
+    /// Whether `seg` carries a hash of the full metadata-declared length that
+    /// matches its content.
+    fn seals_fully(&self, seg: &QrSendData) -> bool {
+        let Some(md) = &self.metadata else {
+            return false;
+        };
+        let frame = sealed_data_frame(seg.id, seg.data(), md);
+        seg.hash().len() == md.hash_len as usize && frame.ends_with(seg.hash())
+    }
+    /// Store a segment. A copy that disagrees with the one already held is
+    /// recorded as a conflict and only replaces it when the held copy does not
+    /// verify against the full hash length but the new one does. The buffer
+    /// of the copy not kept goes back to the [`pool`]. Segments past the
+    /// declared count, as a stale session can hold, are dropped.
+    pub fn insert_segment(&mut self, seg: QrSendData) {
+        if let Some(md) = self
+            .metadata
+            .as_ref()
+            .filter(|md| seg.id >= md.qrcode_count)
+        {
+            self.ambiguity(format!(
+                "dropping segment {}, past the {} segments declared",
+                seg.id, md.qrcode_count
+            ));
+            return;
+        }
+        let Some(existing) = self.data_segments.get(seg.id) else {
+            self.data_segments.insert(seg);
+            return;
+        };
+        if existing.data() == seg.data() {
+            pool::give(seg.into_buf());
+            return;
+        }
+        let replace = !self.seals_fully(existing) && self.seals_fully(&seg);
+        self.ambiguity(format!(
+            "segment {} arrived with conflicting content, keeping the {} copy",
+            seg.id,
+            if replace { "new" } else { "earlier" }
+        ));
+        *self.conflicts.entry(seg.id).or_default() += 1;
+        let dropped = if replace {
+            self.data_segments.insert(seg)
+        } else {
+            Some(seg)
+        };
+        if let Some(dropped) = dropped {
+            pool::give(dropped.into_buf());
+        }
+    }
+    /// Keep the first whole-file hash, checking repeats against it.
+    fn on_hash(&mut self, data: &[u8]) {
+        let Some(hash_len) = self.frame_hash_len(data) else {
+            return;
+        };
+        let Ok(Frame::Hash { digest }) = Frame::parse(data, hash_len, None) else {
+            return;
+        };
+        self.hash_frames += 1;
+        if self.total_hash.is_empty() {
+            self.total_hash = digest.to_vec();
+        } else if digest != self.total_hash {
+            self.conflicting_hash_frames += 1;
+            if self.conflicting_hash_frames == 1 {
+                self.ambiguity(format!(
+                    "hash frame with {} differs from the first one seen, {}",
+                    hex::encode(digest),
+                    hex::encode(&self.total_hash)
+                ));
+            }
+        }
+    }
+    /// Keep the first manifest, checking repeats name the same transfer.
+    fn on_manifest(&mut self, data: &[u8]) {
+        let Some(hash_len) = self.frame_hash_len(data) else {
+            return;
+        };
+        let Ok(Frame::Manifest { json }) = Frame::parse(data, hash_len, None) else {
+            return;
+        };
+        let manifest: Manifest = match serde_json::from_slice(json) {
+            Ok(manifest) => manifest,
+            Err(e) => {
+                self.ambiguity(format!("dropping unparsable manifest frame: {}", e));
+                return;
+            }
+        };
+        match &self.manifest {
+            None => {
+                log::info!(
+                    "transfer {} from {}",
+                    manifest.transfer_id,
+                    manifest.hostname.as_deref().unwrap_or("an unnamed host")
+                );
+                self.manifest = Some(manifest);
+            }
+            Some(first) if first.transfer_id != manifest.transfer_id => {
+                let what = format!(
+                    "manifest of transfer {}, not {} as before; is another sender in view?",
+                    manifest.transfer_id, first.transfer_id
+                );
+                self.ambiguity(what);
+            }
+            Some(_) => {}
+        }
+    }
+    fn on_unknown(&mut self, data: &[u8]) {
+        log::trace!("skipped frame with unknown type: {}", hex::encode(data));
+        self.unknown_frames += 1;
+        if self.strict {
+            let tag = data.first().copied().unwrap_or(0);
+            self.ambiguity(format!("frame with unknown type byte {:#04x}", tag));
+        }
+        self.sync.on_payload(data);
+        #[cfg(feature = "zbar")]
+        self.dump_unknown(data);
+    }
+    #[cfg(feature = "zbar")]
+    fn dump_unknown(&self, data: &[u8]) {
+        let Some(dir) = &self.unknown_dump else {
+            return;
+        };
+        let tag = data.first().copied().unwrap_or(0);
+        let index = self.frames_read.saturating_sub(1);
+        let name = format!("frame_{:06}_tag_{:02x}.bin", index, tag);
+        if let Err(e) = fs::create_dir_all(dir).and_then(|_| fs::write(dir.join(&name), data)) {
+            log::warn!("could not dump unknown frame {}: {}", name, e);
+        }
+    }
+    #[cfg(feature = "zbar")]
+    /// Write the payloads decoded from image `index` as they came out of
+    /// the scan, one file each, named so they sort in the order read.
+    fn dump_payloads(&self, index: u64, decoded: &[Vec<u8>]) {
+        let Some(dir) = &self.payload_dump else {
+            return;
+        };
+        for (i, data) in decoded.iter().enumerate() {
+            let name = format!("frame_{:06}_{:02}.bin", index, i);
+            if let Err(e) = fs::create_dir_all(dir).and_then(|_| fs::write(dir.join(&name), data)) {
+                log::warn!("could not dump payload {}: {}", name, e);
+            }
+        }
+    }
+    #[cfg(feature = "zbar")]
+    /// Run whichever decode phases are still outstanding over `img_iter`.
+    pub fn consume(&mut self, img_iter: &mut ImageSequenceIterator) {
+        if self.metadata.is_none() {
+            self.get_metadata(img_iter);
+        }
+        if self.metadata.is_none() {
+            return;
+        }
+        img_iter.start_sampling();
+        self.get_data(img_iter);
+        self.rescan_gaps(img_iter);
+    }
+    #[cfg(feature = "zbar")]
+    /// Decode the images a sampled pass skipped around what is still missing.
+    fn rescan_gaps(&mut self, img_iter: &ImageSequenceIterator) {
+        if self.is_complete() || self.out_of_budget() {
+            return;
+        }
+        let mut wanted = self.missing_segments();
+        if self.total_hash.is_empty() {
+            wanted.push(self.metadata.as_ref().unwrap().qrcode_count);
+        }
+        let Some(mut rescan) = img_iter.around(&wanted) else {
+            return;
+        };
+        log::info!(
+            "rescanning {} skipped images around {} missing frames",
+            rescan.len(),
+            wanted.len()
+        );
+        // Out of order by nature, so not a sender pass.
+        let (passes, last_data_id) = (self.passes, self.last_data_id);
+        let pass_ids = self.pass_ids.clone();
+        self.get_data(&mut rescan);
+        self.passes = passes;
+        self.last_data_id = last_data_id;
+        self.pass_ids = pass_ids;
+        if self.complete_after_pass.is_some_and(|p| p > passes) {
+            self.complete_after_pass = Some(passes);
+        }
+    }
+    /// All segments and the whole-file hash have arrived.
+    pub fn is_complete(&self) -> bool {
+        !self.total_hash.is_empty()
+            && self
+                .metadata
+                .as_ref()
+                .is_some_and(|md| self.data_segments.len() as u64 == md.qrcode_count)
+    }
+    pub fn missing_segments(&self) -> Vec<u64> {
+        match &self.metadata {
+            Some(md) => self.data_segments.missing(md.qrcode_count).collect(),
+            None => Vec::new(),
+        }
+    }
+    /// [`missing_segments`](Self::missing_segments) as runs.
+    pub fn missing_ranges(&self) -> SegmentRanges {
+        match &self.metadata {
+            Some(md) => {
+                SegmentRanges::from_ranges(self.data_segments.missing_ranges(md.qrcode_count))
+            }
+            None => SegmentRanges::default(),
+        }
+    }
+    /// What arrived of metadata that never completed, if anything did.
+    pub fn partial_metadata(&self) -> Option<String> {
+        if self.metadata.is_some() {
+            return None;
+        }
+        // Chunks of the count most of them agree on, should a stray disagree.
+        let mut chunks: BTreeMap<u8, usize> = BTreeMap::new();
+        for &(count, _) in self.metadata_votes.keys() {
+            *chunks.entry(count).or_default() += 1;
+        }
+        if let Some((count, got)) = chunks.into_iter().max_by_key(|&(_, got)| got) {
+            return Some(format!("got {} of {} chunks", got, count));
+        }
+        (!self.metadata_buf.is_empty()).then(|| {
+            format!(
+                "got {} bytes, a metadata frame was probably missed",
+                self.metadata_buf.len()
+            )
+        })
+    }
+    /// Hash the sender declared for the whole file, MD5 until the metadata says otherwise.
+    pub fn file_hash(&self) -> FileHash {
+        self.metadata
+            .as_ref()
+            .map_or(FileHash::default(), |md| md.file_hash)
+    }
+    /// [`file_hash`](Self::file_hash) of the segments in id order, fed one
+    /// at a time, or `None` while any are missing.
+    pub fn segments_hash(&self) -> Option<Vec<u8>> {
+        let md = self.metadata.as_ref()?;
+        let mut hasher = md.file_hash.hasher();
+        for i in 0..md.qrcode_count {
+            hasher.update(self.data_segments.get(i)?.data());
+        }
+        Some(hasher.finalize())
+    }
+    /// Total length of the segments held.
+    pub fn assembled_len(&self) -> u64 {
+        self.data_segments
+            .values()
+            .map(|s| s.data().len() as u64)
+            .sum()
+    }
+    #[cfg(feature = "zbar")]
+    /// Write the file to `path` without assembling it in memory: the file is
+    /// preallocated and worker threads write each segment at its offset.
+    /// Every segment must be present.
+    pub fn write_segments(&self, path: &path::Path) -> io::Result<()> {
+        let md = self.metadata.as_ref().expect("metadata is known");
+        let mut offsets = Vec::with_capacity(md.qrcode_count as usize);
+        let mut offset = 0;
+        for i in 0..md.qrcode_count {
+            offsets.push((i, offset));
+            offset += self.data_segments[i].data().len() as u64;
+        }
+        self.write_at_offsets(path, offset, &offsets)
+    }
+    #[cfg(feature = "zbar")]
+    /// Length of every segment but the last, read off a received one.
+    pub fn segment_size(&self) -> Option<u64> {
+        let md = self.metadata.as_ref()?;
+        if md.segment_size.is_some() {
+            return md.segment_size;
+        }
+        self.data_segments
+            .values()
+            .find(|seg| seg.id + 1 < md.qrcode_count)
+            .map(|seg| seg.data().len() as u64)
+    }
+    #[cfg(feature = "zbar")]
+    /// Write the segments held to a `len` byte file at `path`, each at its
+    /// id times `segment_size`, leaving zeros where segments are missing.
+    pub fn write_with_holes(
+        &self,
+        path: &path::Path,
+        len: u64,
+        segment_size: u64,
+    ) -> io::Result<()> {
+        let mut offsets: Vec<(u64, u64)> = self
+            .data_segments
+            .ids()
+            .filter_map(|id| Some((id, id.checked_mul(segment_size)?)))
+            .filter(|&(_, at)| at < len)
+            .collect();
+        offsets.sort_unstable();
+        self.write_at_offsets(path, len, &offsets)
+    }
+    #[cfg(feature = "zbar")]
+    /// Write those of `ids` that are held into the existing file at `path`,
+    /// each at its id times `segment_size`, returning how many were.
+    pub fn patch_segments(
+        &self,
+        path: &path::Path,
+        segment_size: u64,
+        ids: &[u64],
+    ) -> io::Result<usize> {
+        let file = fs::File::options().write(true).open(path)?;
+        let mut patched = 0;
+        for id in ids {
+            if let Some(seg) = self.data_segments.get(*id) {
+                let offset = id.checked_mul(segment_size).ok_or_else(|| {
+                    io::Error::new(io::ErrorKind::InvalidInput, "segment offset overflows")
+                })?;
+                write_at(&file, seg.data(), offset)?;
+                patched += 1;
+            }
+        }
+        file.sync_all()?;
+        Ok(patched)
+    }
+    #[cfg(feature = "zbar")]
+    fn write_at_offsets(
+        &self,
+        path: &path::Path,
+        len: u64,
+        offsets: &[(u64, u64)],
+    ) -> io::Result<()> {
+        let file = fs::File::create(path)?;
+        file.set_len(len)?;
+        let workers = thread::available_parallelism().map_or(1, |n| n.get());
+        let per_worker = offsets.len().div_ceil(workers).max(1);
+        thread::scope(|scope| {
+            let handles: Vec<_> = offsets
+                .chunks(per_worker)
+                .map(|batch| {
+                    let file = &file;
+                    scope.spawn(move || {
+                        batch.iter().try_for_each(|&(id, at)| {
+                            write_at(file, self.data_segments[id].data(), at)
+                        })
+                    })
+                })
+                .collect();
+            handles
+                .into_iter()
+                .try_for_each(|h| h.join().expect("segment writer panicked"))
+        })?;
+        file.sync_all()
+    }
+    /// Write the segments to `out` in id order, returning the file length.
+    /// Every segment must be present.
+    pub fn write_in_order(&self, out: &mut impl io::Write) -> io::Result<u64> {
+        let md = self.metadata.as_ref().expect("metadata is known");
+        let mut len = 0;
+        for i in 0..md.qrcode_count {
+            let data = self.data_segments[i].data();
+            out.write_all(data)?;
+            len += data.len() as u64;
+        }
+        Ok(len)
+    }
+    /// Concatenate all segments in id order, or `None` while any are missing.
+    pub fn assemble(&self) -> Option<Vec<u8>> {
+        let md = self.metadata.as_ref()?;
+        let mut data = Vec::new();
+        for i in 0..md.qrcode_count {
+            data.extend_from_slice(self.data_segments.get(i)?.data());
+        }
+        Some(data)
+    }
+    #[cfg(feature = "zbar")]
+    fn next_within_budget(
+        &mut self,
+        img_iter: &mut ImageSequenceIterator,
+    ) -> Option<image::DynamicImage> {
+        if self.out_of_budget() || self.violation.is_some() {
+            return None;
+        }
+        let img = img_iter.next();
+        if let (Some(trace), Some(source)) = (self.trace.as_mut(), img_iter.last_read()) {
+            trace.source = source;
+        }
+        img
+    }
+    #[cfg(feature = "zbar")]
+    fn get_metadata(&mut self, img_iter: &mut ImageSequenceIterator) {
+        // Data and hash frames seen on the way are kept, since a capture
+        // that starts mid-loop sees them first.
+        while let Some(img) = self.next_within_budget(img_iter) {
+            for data in self.read_frames(&img) {
+                self.dispatch(data);
+            }
+            if self.metadata.is_some() || self.unsupported_version.is_some() {
+                return;
+            }
+        }
+    }
+    #[cfg(feature = "zbar")]
+    fn get_data(&mut self, img_iter: &mut ImageSequenceIterator) {
+        while let Some(img) = self.next_within_budget(img_iter) {
+            for data in self.read_frames(&img) {
+                match FrameType::of(&data) {
+                    Some(FrameType::Data) => {
+                        if let Some(id) = self.on_data(data) {
+                            img_iter.note_segment(id);
+                        }
+                        if self.is_complete() {
+                            log::info!("all segments and the hash received, skipping the rest");
+                            return;
+                        }
+                    }
+                    // A looping sender follows the hash with its next pass, which
+                    // may carry the segments this one lost.
+                    Some(FrameType::Hash) => {
+                        self.on_hash(&data);
+                        if self.is_complete() {
+                            return;
+                        }
+                    }
+                    Some(FrameType::Manifest) => self.on_manifest(&data),
+                    _ => continue,
+                }
+            }
+        }
+    }
+    #[cfg(feature = "zbar")]
+    /// Scan for the first hash frame and the metadata that names its hash,
+    /// guessing hash lengths while no metadata is known.
+    pub fn get_hash(&mut self, img_iter: &mut ImageSequenceIterator) {
+        while let Some(img) = self.next_within_budget(img_iter) {
+            for data in self.read_frames(&img) {
+                if FrameType::of(&data) != Some(FrameType::Data) {
+                    self.dispatch(data);
+                }
+                if !self.total_hash.is_empty() && self.metadata.is_some() {
+                    return;
+                }
+            }
+        }
+    }
+}
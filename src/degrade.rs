@@ -0,0 +1,177 @@
+//! Synthetic damage done to rendered frames, standing in for what a camera
+//! pointed at a screen does to them: tilt, moiré from the camera sensor
+//! beating against the pixel grid, defocus and lossy compression.
+
+use std::fmt;
+use std::io::Cursor;
+use std::str::FromStr;
+
+use image::codecs::jpeg::JpegEncoder;
+use image::{DynamicImage, GrayImage, ImageFormat, Luma};
+
+use crate::luma::{self, Rect};
+
+/// Period of the moiré bands in pixels and the angle they run at, chosen
+/// to beat against nothing a renderer at a whole pixel scale draws.
+const MOIRE_PERIOD: f64 = 9.7;
+const MOIRE_ANGLE: f64 = 17.0;
+
+/// One set of damage, applied in the order a capture suffers it.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Degradation {
+    /// Rotation in degrees, counterclockwise.
+    pub rotate: f64,
+    /// Amplitude of the moiré bands, as a fraction of full brightness.
+    pub moire: f64,
+    /// Standard deviation of the Gaussian blur, in pixels.
+    pub blur: f32,
+    /// JPEG quality the frame is compressed at, 1 to 100.
+    pub jpeg: Option<u8>,
+}
+impl Degradation {
+    /// A spread of damage from mild to severe, each kind alone and then
+    /// all together.
+    pub fn standard() -> Vec<Degradation> {
+        [
+            "none",
+            "blur=1",
+            "blur=2",
+            "jpeg=30",
+            "jpeg=10",
+            "rotate=7",
+            "rotate=30",
+            "moire=0.2",
+            "moire=0.4",
+            "rotate=5,moire=0.2,blur=1,jpeg=30",
+        ]
+        .iter()
+        .map(|s| s.parse().expect("standard profiles parse"))
+        .collect()
+    }
+    pub fn apply(&self, img: &DynamicImage) -> DynamicImage {
+        let mut gray = luma::gray(img, Rect::whole(img));
+        if self.rotate != 0.0 {
+            gray = rotate(&gray, self.rotate);
+        }
+        if self.moire != 0.0 {
+            add_moire(&mut gray, self.moire);
+        }
+        if self.blur > 0.0 {
+            gray = image::imageops::blur(&gray, self.blur);
+        }
+        match self.jpeg {
+            Some(quality) => compress(&gray, quality),
+            None => DynamicImage::ImageLuma8(gray),
+        }
+    }
+}
+impl FromStr for Degradation {
+    type Err = String;
+
+    /// `none`, or comma separated `blur=SIGMA`, `jpeg=QUALITY`,
+    /// `rotate=DEGREES` and `moire=STRENGTH`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut degradation = Degradation::default();
+        if s.trim() == "none" {
+            return Ok(degradation);
+        }
+        for part in s.split(',') {
+            let (name, value) = part
+                .split_once('=')
+                .ok_or_else(|| format!("expected NAME=VALUE, not {:?}", part))?;
+            let number = |max: f64| {
+                value
+                    .trim()
+                    .parse::<f64>()
+                    .ok()
+                    .filter(|v| v.abs() <= max)
+                    .ok_or_else(|| format!("{} takes a number up to {}", name, max))
+            };
+            match name.trim() {
+                "rotate" => degradation.rotate = number(360.0)?,
+                "moire" => degradation.moire = number(1.0)?,
+                "blur" => degradation.blur = number(100.0)?.abs() as f32,
+                "jpeg" => {
+                    let quality = number(100.0)?.abs().max(1.0);
+                    degradation.jpeg = Some(quality as u8);
+                }
+                other => return Err(format!("unknown degradation {:?}", other)),
+            }
+        }
+        Ok(degradation)
+    }
+}
+impl fmt::Display for Degradation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut parts = Vec::new();
+        if self.rotate != 0.0 {
+            parts.push(format!("rotate={}", self.rotate));
+        }
+        if self.moire != 0.0 {
+            parts.push(format!("moire={}", self.moire));
+        }
+        if self.blur > 0.0 {
+            parts.push(format!("blur={}", self.blur));
+        }
+        if let Some(quality) = self.jpeg {
+            parts.push(format!("jpeg={}", quality));
+        }
+        if parts.is_empty() {
+            return write!(f, "none");
+        }
+        write!(f, "{}", parts.join(","))
+    }
+}
+
+/// `img` turned by `degrees` on a white canvas large enough to hold it.
+fn rotate(img: &GrayImage, degrees: f64) -> GrayImage {
+    let (w, h) = (img.width() as f64, img.height() as f64);
+    let (sin, cos) = degrees.to_radians().sin_cos();
+    let side_w = (w * cos.abs() + h * sin.abs()).ceil() as u32;
+    let side_h = (w * sin.abs() + h * cos.abs()).ceil() as u32;
+    let (cx, cy) = (w / 2.0, h / 2.0);
+    let (ox, oy) = (side_w as f64 / 2.0, side_h as f64 / 2.0);
+    GrayImage::from_fn(side_w, side_h, |x, y| {
+        // Back from the output pixel to where it came from.
+        let (dx, dy) = (x as f64 + 0.5 - ox, y as f64 + 0.5 - oy);
+        let sx = cos * dx - sin * dy + cx - 0.5;
+        let sy = sin * dx + cos * dy + cy - 0.5;
+        Luma([bilinear(img, sx, sy)])
+    })
+}
+
+/// Bilinear sample of `img` at `(x, y)`, white outside it.
+fn bilinear(img: &GrayImage, x: f64, y: f64) -> u8 {
+    let (w, h) = (img.width() as i64, img.height() as i64);
+    let (x0, y0) = (x.floor() as i64, y.floor() as i64);
+    let (fx, fy) = (x - x0 as f64, y - y0 as f64);
+    let p = |x: i64, y: i64| {
+        if x < 0 || y < 0 || x >= w || y >= h {
+            255.0
+        } else {
+            img.get_pixel(x as u32, y as u32)[0] as f64
+        }
+    };
+    let top = p(x0, y0) * (1.0 - fx) + p(x0 + 1, y0) * fx;
+    let bottom = p(x0, y0 + 1) * (1.0 - fx) + p(x0 + 1, y0 + 1) * fx;
+    (top * (1.0 - fy) + bottom * fy).round() as u8
+}
+
+/// Brighten and darken `img` in slanted bands of `strength` amplitude.
+fn add_moire(img: &mut GrayImage, strength: f64) {
+    let (sin, cos) = MOIRE_ANGLE.to_radians().sin_cos();
+    for (x, y, p) in img.enumerate_pixels_mut() {
+        let along = x as f64 * cos + y as f64 * sin;
+        let wave = (along / MOIRE_PERIOD * std::f64::consts::TAU).cos();
+        p[0] = (p[0] as f64 + strength * 127.0 * wave).clamp(0.0, 255.0) as u8;
+    }
+}
+
+/// `img` after a round trip through JPEG at `quality`.
+fn compress(img: &GrayImage, quality: u8) -> DynamicImage {
+    let mut encoded = Vec::new();
+    JpegEncoder::new_with_quality(&mut encoded, quality)
+        .encode_image(img)
+        .expect("encoding to memory does not fail");
+    image::load(Cursor::new(encoded), ImageFormat::Jpeg).expect("the image just encoded decodes")
+}
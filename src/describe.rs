@@ -0,0 +1,139 @@
+//! `qr-recv protocol --describe`: the frame formats this receiver decodes,
+//! taken from the protocol module itself so the description cannot drift
+//! from what is accepted.
+
+use serde_json::{json, Value};
+
+use crate::protocol::{
+    value_names, FileHash, FrameType, PayloadEncoding, QrSendMetadata, Symbology, ID_TYPES, MAGIC,
+    MAX_HASH_LEN, PROTOCOL_MAJOR, PROTOCOL_VERSION,
+};
+
+#[derive(clap::Args)]
+pub struct ProtocolArgs {
+    /// Print the frame formats, payload encodings and metadata fields
+    #[clap(long, required = true)]
+    describe: bool,
+    /// Print JSON, with a JSON Schema of the metadata, instead of text
+    #[clap(long)]
+    json: bool,
+}
+
+/// Everything a sender needs to target this receiver.
+pub fn description() -> Value {
+    let frame_types: Vec<Value> = FrameType::ALL
+        .iter()
+        .map(|t| {
+            json!({
+                "tag": (t.tag() as char).to_string(),
+                "name": t.name(),
+                "handled": t.is_handled(),
+                "layout": t.layout(),
+            })
+        })
+        .collect();
+    json!({
+        "protocol_version": PROTOCOL_VERSION,
+        "decoded_major_version": PROTOCOL_MAJOR,
+        "frame": {
+            "layout": "tag byte, body, then the seal",
+            "seal": "blake2b of the tag and body, `hash_len` bytes long",
+            "max_hash_len": MAX_HASH_LEN,
+            "magic_prefix": String::from_utf8_lossy(MAGIC),
+        },
+        "frame_types": frame_types,
+        "payload_encodings": value_names::<PayloadEncoding>(),
+        "id_types": ID_TYPES,
+        "file_hashes": value_names::<FileHash>(),
+        "symbologies": value_names::<Symbology>(),
+        "metadata_schema": QrSendMetadata::json_schema(),
+    })
+}
+
+fn names(values: &Value) -> String {
+    values
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(Value::as_str)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn print_text(description: &Value) {
+    let frame = &description["frame"];
+    println!(
+        "qr-send protocol {}, decoding major version {}",
+        description["protocol_version"].as_str().unwrap_or_default(),
+        description["decoded_major_version"]
+    );
+    println!();
+    println!(
+        "Each qrcode carries one frame: {}. The seal is {}, 1 to {} bytes.",
+        frame["layout"].as_str().unwrap_or_default(),
+        frame["seal"].as_str().unwrap_or_default(),
+        frame["max_hash_len"]
+    );
+    println!(
+        "With `magic` in the metadata the text of every qrcode starts with {}.",
+        frame["magic_prefix"].as_str().unwrap_or_default()
+    );
+    println!();
+    println!("Frame types:");
+    for t in description["frame_types"].as_array().into_iter().flatten() {
+        println!(
+            "  {}  {:<10} {}",
+            t["tag"].as_str().unwrap_or_default(),
+            t["name"].as_str().unwrap_or_default(),
+            t["layout"].as_str().unwrap_or_default()
+        );
+    }
+    println!();
+    println!(
+        "Payload encodings: {}",
+        names(&description["payload_encodings"])
+    );
+    println!("Id types: {}", names(&description["id_types"]));
+    println!("File hashes: {}", names(&description["file_hashes"]));
+    println!("Symbologies: {}", names(&description["symbologies"]));
+    println!();
+    println!("Metadata fields (required ones marked *):");
+    let schema = &description["metadata_schema"];
+    let required = names(&schema["required"]);
+    let required: Vec<&str> = required.split(", ").collect();
+    for (name, field) in schema["properties"].as_object().into_iter().flatten() {
+        let kind = match field.get("enum") {
+            Some(values) => format!("one of {}", names(values)),
+            None => field["type"].as_str().unwrap_or_default().to_string(),
+        };
+        let mut line = format!(
+            "  {}{:<16} {}",
+            if required.contains(&name.as_str()) {
+                "*"
+            } else {
+                " "
+            },
+            name,
+            kind
+        );
+        match field.get("default") {
+            Some(Value::String(default)) => line += &format!(", default {}", default),
+            Some(default) => line += &format!(", default {}", default),
+            None => {}
+        }
+        if let Some(text) = field.get("description").and_then(Value::as_str) {
+            line += &format!("; {}", text);
+        }
+        println!("{}", line);
+    }
+}
+
+pub fn run(args: &ProtocolArgs) {
+    // --describe is required, leaving room for other actions later.
+    let description = description();
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&description).unwrap());
+    } else {
+        print_text(&description);
+    }
+}
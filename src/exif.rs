@@ -0,0 +1,91 @@
+//! Just enough EXIF to find the orientation tag of JPEG, PNG and TIFF files.
+
+const ORIENTATION_TAG: u16 = 0x0112;
+
+/// The EXIF orientation (1-8) of an encoded image, if it carries one.
+pub fn orientation(bytes: &[u8]) -> Option<u16> {
+    let tiff = if bytes.starts_with(&[0xff, 0xd8]) {
+        jpeg_exif(bytes)?
+    } else if bytes.starts_with(b"\x89PNG\r\n\x1a\n") {
+        png_exif(bytes)?
+    } else {
+        bytes
+    };
+    tiff_orientation(tiff).filter(|o| (1..=8).contains(o))
+}
+
+fn jpeg_exif(bytes: &[u8]) -> Option<&[u8]> {
+    let mut pos = 2;
+    loop {
+        let marker = bytes.get(pos..pos + 4)?;
+        if marker[0] != 0xff || marker[1] == 0xda {
+            // Start of scan; metadata segments all come before it.
+            return None;
+        }
+        let len = u16::from_be_bytes([marker[2], marker[3]]) as usize;
+        let segment = bytes.get(pos + 4..pos + 2 + len)?;
+        if marker[1] == 0xe1 && segment.starts_with(b"Exif\0\0") {
+            return Some(&segment[6..]);
+        }
+        pos += 2 + len;
+    }
+}
+
+fn png_exif(bytes: &[u8]) -> Option<&[u8]> {
+    let mut pos = 8;
+    loop {
+        let len = u32::from_be_bytes(bytes.get(pos..pos + 4)?.try_into().unwrap()) as usize;
+        let kind = bytes.get(pos + 4..pos + 8)?;
+        let data = bytes.get(pos + 8..pos + 8 + len)?;
+        match kind {
+            b"eXIf" => return Some(data),
+            // Image data has started; the tag is not allowed after it.
+            b"IDAT" => return None,
+            _ => pos += 12 + len,
+        }
+    }
+}
+
+fn tiff_orientation(tiff: &[u8]) -> Option<u16> {
+    let big_endian = match tiff.get(..4)? {
+        b"MM\0*" => true,
+        b"II*\0" => false,
+        _ => return None,
+    };
+    let u16_at = |pos: usize| -> Option<u16> {
+        let b: [u8; 2] = tiff.get(pos..pos + 2)?.try_into().unwrap();
+        Some(if big_endian {
+            u16::from_be_bytes(b)
+        } else {
+            u16::from_le_bytes(b)
+        })
+    };
+    let u32_at = |pos: usize| -> Option<u32> {
+        let b: [u8; 4] = tiff.get(pos..pos + 4)?.try_into().unwrap();
+        Some(if big_endian {
+            u32::from_be_bytes(b)
+        } else {
+            u32::from_le_bytes(b)
+        })
+    };
+    let ifd = u32_at(4)? as usize;
+    let count = u16_at(ifd)? as usize;
+    (0..count)
+        .map(|i| ifd + 2 + i * 12)
+        .find(|&entry| u16_at(entry) == Some(ORIENTATION_TAG))
+        .and_then(|entry| u16_at(entry + 8))
+}
+
+/// Turn an image stored with EXIF `orientation` upright.
+pub fn apply_orientation(img: image::DynamicImage, orientation: u16) -> image::DynamicImage {
+    match orientation {
+        2 => img.fliph(),
+        3 => img.rotate180(),
+        4 => img.flipv(),
+        5 => img.rotate90().fliph(),
+        6 => img.rotate90(),
+        7 => img.rotate270().fliph(),
+        8 => img.rotate270(),
+        _ => img,
+    }
+}
@@ -0,0 +1,96 @@
+//! Exposure of photos of a monitor. Taken too bright the light modules
+//! bleed into the dark ones, taken too dark everything sits in the bottom
+//! of the histogram, and either way a reader's threshold has little room
+//! to part them. Stretching the levels and bending the gamma so the
+//! median lands mid-gray gives it that room back.
+
+use image::{DynamicImage, GrayImage};
+
+use crate::luma::{self, Rect};
+
+/// Share of the pixels at each end of the histogram treated as outliers,
+/// so specular highlights and the bezel do not set the levels.
+const CLIP_FRACTION: f64 = 0.01;
+/// Median brightness below or above which a frame counts as under- or
+/// over-exposed.
+const DARK_MEDIAN: u8 = 64;
+const BRIGHT_MEDIAN: u8 = 192;
+/// Levels spread narrower than this count as washed out.
+const MIN_CONTRAST: u8 = 96;
+/// Bounds of the gamma applied, so a nearly uniform frame is not bent
+/// into noise.
+const MIN_GAMMA: f64 = 1.0 / 3.0;
+const MAX_GAMMA: f64 = 3.0;
+
+/// Brightness of one frame, from the histogram of its gray pixels.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Exposure {
+    /// Darkest level once the darkest [`CLIP_FRACTION`] is set aside.
+    pub low: u8,
+    pub median: u8,
+    /// Brightest level once the brightest [`CLIP_FRACTION`] is set aside.
+    pub high: u8,
+}
+impl Exposure {
+    pub fn of(gray: &GrayImage) -> Self {
+        let mut histogram = [0u64; 256];
+        for p in gray.pixels() {
+            histogram[p[0] as usize] += 1;
+        }
+        let total = gray.pixels().len() as u64;
+        let level_at = |fraction: f64| {
+            let wanted = (total as f64 * fraction) as u64;
+            let mut seen = 0;
+            for (level, &n) in histogram.iter().enumerate() {
+                seen += n;
+                if seen > wanted {
+                    return level as u8;
+                }
+            }
+            u8::MAX
+        };
+        Exposure {
+            low: level_at(CLIP_FRACTION),
+            median: level_at(0.5),
+            high: level_at(1.0 - CLIP_FRACTION),
+        }
+    }
+    pub fn measure(img: &DynamicImage) -> Self {
+        Self::of(&luma::gray(img, Rect::whole(img)))
+    }
+    /// `"under-exposed"`, `"over-exposed"`, `"washed out"` or `"normal"`.
+    pub fn label(&self) -> &'static str {
+        if self.median < DARK_MEDIAN {
+            "under-exposed"
+        } else if self.median > BRIGHT_MEDIAN {
+            "over-exposed"
+        } else if self.high.saturating_sub(self.low) < MIN_CONTRAST {
+            "washed out"
+        } else {
+            "normal"
+        }
+    }
+    pub fn is_normal(&self) -> bool {
+        self.label() == "normal"
+    }
+}
+
+/// `img` in gray with its levels stretched to the full range and its
+/// gamma set so the median is mid-gray, or `None` when it is blank.
+pub fn normalize(img: &DynamicImage) -> Option<DynamicImage> {
+    let mut gray = luma::gray(img, Rect::whole(img));
+    let exposure = Exposure::of(&gray);
+    if exposure.high <= exposure.low {
+        return None;
+    }
+    let (low, span) = (exposure.low as f64, (exposure.high - exposure.low) as f64);
+    let median = ((exposure.median as f64 - low) / span).clamp(0.01, 0.99);
+    let gamma = (0.5f64.ln() / median.ln()).clamp(MIN_GAMMA, MAX_GAMMA);
+    let mut table = [0u8; 256];
+    for (level, out) in table.iter_mut().enumerate() {
+        let stretched = ((level as f64 - low) / span).clamp(0.0, 1.0);
+        *out = (stretched.powf(gamma) * 255.0).round() as u8;
+    }
+    luma::map_levels(&mut gray, &table);
+    Some(DynamicImage::ImageLuma8(gray))
+}
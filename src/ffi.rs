@@ -0,0 +1,247 @@
+//! C ABI over [`Decoder`] for mobile camera apps that link the crate as a
+//! static or shared library. The header lives in `include/qr_recv.h` and
+//! can be regenerated with `cbindgen --config cbindgen.toml`.
+//!
+//! Like the wasm bindings, frames arrive already scanned, so nothing here
+//! touches the filesystem or zbar.
+
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::{ptr, slice};
+
+use crate::api::Decoder;
+use crate::Error;
+
+/// Opaque receiver handle.
+pub struct QrRecv {
+    inner: Decoder,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QrRecvStatus {
+    Ok = 0,
+    /// A null handle or buffer was passed.
+    NullPointer = 1,
+    /// The frame could not be decoded or failed its hash.
+    InvalidFrame = 2,
+    /// Segments or the hash frame are still missing.
+    Incomplete = 3,
+    /// Every segment arrived but the assembled file does not match its hash.
+    HashMismatch = 4,
+    /// The decoder panicked on malformed input; the handle should be freed.
+    Internal = 5,
+    /// The sender speaks a protocol major version this library does not decode.
+    UnsupportedVersion = 6,
+}
+impl From<Error> for QrRecvStatus {
+    fn from(e: Error) -> Self {
+        match e {
+            Error::InvalidFrame => QrRecvStatus::InvalidFrame,
+            Error::HashMismatch => QrRecvStatus::HashMismatch,
+            Error::UnsupportedVersion(_) => QrRecvStatus::UnsupportedVersion,
+            _ => QrRecvStatus::Incomplete,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct QrRecvProgress {
+    /// Number of data segments, only meaningful when `has_total` is set.
+    pub total: u64,
+    pub has_total: bool,
+    pub received: u64,
+    pub has_hash: bool,
+    pub complete: bool,
+}
+
+fn guarded(f: impl FnOnce() -> QrRecvStatus) -> QrRecvStatus {
+    catch_unwind(AssertUnwindSafe(f)).unwrap_or(QrRecvStatus::Internal)
+}
+
+/// Create a receiver. Free it with [`qr_recv_free`].
+#[no_mangle]
+pub extern "C" fn qr_recv_new() -> *mut QrRecv {
+    Box::into_raw(Box::new(QrRecv {
+        inner: Decoder::new(),
+    }))
+}
+
+/// # Safety
+/// `recv` must come from [`qr_recv_new`] and not be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn qr_recv_free(recv: *mut QrRecv) {
+    if !recv.is_null() {
+        drop(Box::from_raw(recv));
+    }
+}
+
+/// Feed decoded frame bytes.
+///
+/// # Safety
+/// `recv` must be a live handle and `ptr` must point to `len` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn qr_recv_push_frame(
+    recv: *mut QrRecv,
+    ptr: *const u8,
+    len: usize,
+) -> QrRecvStatus {
+    let (Some(recv), false) = (recv.as_mut(), ptr.is_null()) else {
+        return QrRecvStatus::NullPointer;
+    };
+    let frame = slice::from_raw_parts(ptr, len);
+    guarded(|| match recv.inner.push_frame(frame) {
+        Ok(_) => QrRecvStatus::Ok,
+        Err(e) => e.into(),
+    })
+}
+
+/// Feed the content of a scanned qrcode, as most platform scanners return it.
+///
+/// # Safety
+/// `recv` must be a live handle and `ptr` must point to `len` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn qr_recv_push_text(
+    recv: *mut QrRecv,
+    ptr: *const u8,
+    len: usize,
+) -> QrRecvStatus {
+    let (Some(recv), false) = (recv.as_mut(), ptr.is_null()) else {
+        return QrRecvStatus::NullPointer;
+    };
+    let text = slice::from_raw_parts(ptr, len);
+    guarded(|| match recv.inner.push_text(text) {
+        Ok(_) => QrRecvStatus::Ok,
+        Err(e) => e.into(),
+    })
+}
+
+/// # Safety
+/// `recv` must be a live handle and `out` must be writable.
+#[no_mangle]
+pub unsafe extern "C" fn qr_recv_progress(
+    recv: *const QrRecv,
+    out: *mut QrRecvProgress,
+) -> QrRecvStatus {
+    let (Some(recv), false) = (recv.as_ref(), out.is_null()) else {
+        return QrRecvStatus::NullPointer;
+    };
+    let p = recv.inner.progress();
+    out.write(QrRecvProgress {
+        total: p.total.unwrap_or(0),
+        has_total: p.total.is_some(),
+        received: p.received,
+        has_hash: p.has_hash,
+        complete: p.complete,
+    });
+    QrRecvStatus::Ok
+}
+
+/// Assemble and verify the file once the transfer is complete.
+///
+/// On success `*out`/`*out_len` hold a buffer to release with
+/// [`qr_recv_free_output`] and the receiver is reset for a new transfer.
+/// Otherwise, incomplete or failing its hash, the receiver keeps its state
+/// and more frames can be pushed.
+///
+/// # Safety
+/// `recv` must be a live handle and `out`/`out_len` must be writable.
+#[no_mangle]
+pub unsafe extern "C" fn qr_recv_take_output(
+    recv: *mut QrRecv,
+    out: *mut *mut u8,
+    out_len: *mut usize,
+) -> QrRecvStatus {
+    let (Some(recv), false, false) = (recv.as_mut(), out.is_null(), out_len.is_null()) else {
+        return QrRecvStatus::NullPointer;
+    };
+    out.write(ptr::null_mut());
+    out_len.write(0);
+    if recv.inner.unsupported_version().is_some() {
+        return QrRecvStatus::UnsupportedVersion;
+    }
+    if !recv.inner.progress().complete {
+        return QrRecvStatus::Incomplete;
+    }
+    guarded(|| match recv.inner.try_finish() {
+        Ok(data) => {
+            recv.inner = Decoder::new();
+            let data = Box::into_raw(data.into_boxed_slice());
+            out_len.write(data.len());
+            out.write(data as *mut u8);
+            QrRecvStatus::Ok
+        }
+        Err(e) => e.into(),
+    })
+}
+
+/// # Safety
+/// `ptr`/`len` must be exactly what [`qr_recv_take_output`] returned.
+#[no_mangle]
+pub unsafe extern "C" fn qr_recv_free_output(ptr: *mut u8, len: usize) {
+    if !ptr.is_null() {
+        drop(Box::from_raw(ptr::slice_from_raw_parts_mut(ptr, len)));
+    }
+}
+
+#[cfg(all(test, feature = "zbar"))]
+mod tests {
+    use super::*;
+    use crate::synth::{seal, Options, Transfer};
+
+    unsafe fn push_all(recv: *mut QrRecv, frames: &[Vec<u8>]) {
+        for frame in frames {
+            assert_eq!(
+                qr_recv_push_frame(recv, frame.as_ptr(), frame.len()),
+                QrRecvStatus::Ok
+            );
+        }
+    }
+
+    unsafe fn progress(recv: *const QrRecv) -> QrRecvProgress {
+        let mut progress = std::mem::MaybeUninit::uninit();
+        assert_eq!(
+            qr_recv_progress(recv, progress.as_mut_ptr()),
+            QrRecvStatus::Ok
+        );
+        progress.assume_init()
+    }
+
+    #[test]
+    fn take_output_resets_only_on_success() {
+        let transfer = Transfer::new(&Options::default());
+        let hash = transfer.frame_indices(b'H')[0];
+        let mut frames = transfer.frames.clone();
+        // Sealed correctly, but the hash of another file.
+        let mut other = frames[hash][..frames[hash].len() - 8].to_vec();
+        *other.last_mut().unwrap() ^= 1;
+        frames[hash] = seal(&other, 8);
+        let (mut out, mut out_len) = (ptr::null_mut(), 0);
+        unsafe {
+            let recv = qr_recv_new();
+            push_all(recv, &frames);
+            assert!(progress(recv).complete);
+            assert_eq!(
+                qr_recv_take_output(recv, &mut out, &mut out_len),
+                QrRecvStatus::HashMismatch
+            );
+            assert!(out.is_null());
+            // Kept, so the transfer can still be finished.
+            let kept = progress(recv);
+            assert!(kept.complete && kept.has_total);
+            qr_recv_free(recv);
+
+            let recv = qr_recv_new();
+            push_all(recv, &transfer.frames);
+            assert_eq!(
+                qr_recv_take_output(recv, &mut out, &mut out_len),
+                QrRecvStatus::Ok
+            );
+            assert_eq!(slice::from_raw_parts(out, out_len), transfer.file);
+            qr_recv_free_output(out, out_len);
+            let reset = progress(recv);
+            assert!(!reset.has_total && reset.received == 0);
+            qr_recv_free(recv);
+        }
+    }
+}
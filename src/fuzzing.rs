@@ -0,0 +1,141 @@
+//! Parser entry points for the cargo-fuzz targets in `fuzz/`.
+//! Not part of the stable library API.
+//!
+//! Each function must return normally for every input; a panic is a bug.
+
+use crate::api::Decoder;
+use crate::decoder::QrSendDecoder;
+use crate::protocol::{
+    blake2b, get_id_and_len, Endianness, Frame, QrSendData, QrSendMetadata, ID_TYPES, MAX_HASH_LEN,
+    PROTOCOL_VERSION,
+};
+
+/// Metadata read from the front of `input`, and the bytes after it.
+///
+/// The first byte picks the id type from its low three bits (past the end
+/// of [`ID_TYPES`], one no sender declares), the endianness from the top one
+/// and the hash length (including the invalid 0) from the rest. Three
+/// little endian `u64`s follow: the qrcode count, the segment size and the
+/// file size, a size of 0 being left undeclared. Input too short for them
+/// reads as zeros.
+fn metadata(input: &[u8]) -> (QrSendMetadata, &[u8]) {
+    let (&selector, mut rest) = input.split_first().unwrap_or((&0, &[]));
+    let mut field = || {
+        let (bytes, tail) = rest.split_at(rest.len().min(8));
+        rest = tail;
+        let mut le = [0u8; 8];
+        le[..bytes.len()].copy_from_slice(bytes);
+        u64::from_le_bytes(le)
+    };
+    let qrcode_count = field();
+    let segment_size = Some(field()).filter(|&n| n != 0);
+    let file_size = Some(field()).filter(|&n| n != 0);
+    let md = QrSendMetadata {
+        version: PROTOCOL_VERSION.to_string(),
+        qrcode_count,
+        id_type: ID_TYPES
+            .get((selector & 7) as usize)
+            .unwrap_or(&"u24")
+            .to_string(),
+        hash_len: (selector >> 3 & 0xf) as u64 * 4,
+        payload_encoding: Default::default(),
+        endianness: if selector & 0x80 == 0 {
+            Endianness::Big
+        } else {
+            Endianness::Little
+        },
+        file_size,
+        segment_size,
+        file_name: None,
+        mtime: None,
+        symbology: Default::default(),
+        color_channels: false,
+        crc32c: false,
+        file_hash: Default::default(),
+        magic: false,
+    };
+    (md, rest)
+}
+
+/// `QrSendData::from_frame` on the input after the metadata, as the body
+/// of a data frame.
+pub fn data_frame(input: &[u8]) {
+    let (md, data) = metadata(input);
+    let mut frame = vec![b'D'];
+    frame.extend_from_slice(data);
+    if let Ok(seg) = QrSendData::from_frame(frame, &md) {
+        assert!(seg.data().len() + seg.hash().len() <= data.len());
+    }
+}
+
+/// `Frame::parse` on the input after the metadata, with that metadata and
+/// its hash length, or on the rest with no metadata and every hash length
+/// when the first byte is 0.
+pub fn frame(input: &[u8]) {
+    if let Some((0, frame)) = input.split_first() {
+        for hash_len in 0..=MAX_HASH_LEN {
+            let _ = Frame::parse(frame, hash_len, None);
+        }
+        return;
+    }
+    let (md, frame) = metadata(input);
+    if let Ok(Frame::Data { content, .. }) = Frame::parse(frame, md.hash_len as usize, Some(&md)) {
+        assert!(content.len() < frame.len());
+    }
+}
+
+/// `get_id_and_len` on the input after the metadata.
+pub fn id_and_len(input: &[u8]) {
+    let (md, data) = metadata(input);
+    if let Ok((_, len)) = get_id_and_len(data, &md) {
+        assert!(len <= data.len());
+    }
+}
+
+/// Metadata assembly: the input, split at `0xff` bytes, is sent as a
+/// sequence of sealed `M` frames, each followed by a data and a hash frame
+/// so whatever metadata results gets used.
+pub fn metadata_assembly(input: &[u8]) {
+    let mut decoder = QrSendDecoder::new();
+    for chunk in input.split(|&b| b == 0xff) {
+        for tag in [b'M', b'D', b'H'] {
+            let mut frame = vec![tag];
+            frame.extend_from_slice(chunk);
+            let hash = blake2b(&frame, 8);
+            frame.extend_from_slice(&hash);
+            decoder.push(frame);
+        }
+    }
+    let _ = decoder.missing_ranges();
+    let _ = decoder.assemble();
+}
+
+/// The decoder as a library user drives it: a sealed `M` frame declaring
+/// the metadata read from the front of the input, then the rest, split at
+/// `0xff` bytes, each sent through [`Decoder::push_frame`] as it is and
+/// sealed as a data and a hash frame.
+pub fn decoder(input: &[u8]) {
+    let (md, rest) = metadata(input);
+    let hash_len = md.hash_len as usize;
+    let seal = |mut frame: Vec<u8>| {
+        let hash = blake2b(&frame, hash_len);
+        frame.extend_from_slice(&hash);
+        frame
+    };
+    let mut decoder = Decoder::new();
+    let mut frame = vec![b'M'];
+    frame.extend(serde_json::to_vec(&md).expect("metadata serializes"));
+    let _ = decoder.push_frame(&seal(frame));
+    for chunk in rest.split(|&b| b == 0xff) {
+        let _ = decoder.push_frame(chunk);
+        for tag in [b'D', b'H'] {
+            let mut frame = vec![tag];
+            frame.extend_from_slice(chunk);
+            let _ = decoder.push_frame(&seal(frame));
+        }
+    }
+    let progress = decoder.progress();
+    let missing = decoder.missing();
+    assert!(missing.len() <= progress.total.unwrap_or(0));
+    let _ = decoder.finish();
+}
@@ -0,0 +1,505 @@
+//! Gray conversion, downscaling and level mapping of large frames on the
+//! GPU, through OpenCL. At 4K every captured frame is converted and most
+//! are retried downscaled, which is where the scan loop spends its time on
+//! the CPU.
+//!
+//! The first call looks for a GPU device; without one, or when any call
+//! into OpenCL fails, these return `None` and the caller does the work on
+//! the CPU as before. Frames smaller than [`MIN_PIXELS`] are never sent,
+//! the upload would cost more than the conversion.
+
+use std::ffi::{c_char, c_void, CString};
+use std::ptr;
+use std::sync::{Mutex, MutexGuard, OnceLock, PoisonError};
+
+use image::{DynamicImage, GrayImage};
+
+use crate::luma::{self, Rect};
+
+/// Frames with fewer pixels than 2560x1440 stay on the CPU.
+const MIN_PIXELS: u64 = 2560 * 1440;
+
+type Handle = *mut c_void;
+
+const CL_SUCCESS: i32 = 0;
+const CL_DEVICE_TYPE_GPU: u64 = 1 << 2;
+const CL_MEM_WRITE_ONLY: u64 = 1 << 1;
+const CL_MEM_READ_ONLY: u64 = 1 << 2;
+const CL_MEM_COPY_HOST_PTR: u64 = 1 << 5;
+
+#[link(name = "OpenCL")]
+extern "C" {
+    fn clGetPlatformIDs(num_entries: u32, platforms: *mut Handle, num_platforms: *mut u32) -> i32;
+    fn clGetDeviceIDs(
+        platform: Handle,
+        device_type: u64,
+        num_entries: u32,
+        devices: *mut Handle,
+        num_devices: *mut u32,
+    ) -> i32;
+    fn clCreateContext(
+        properties: *const isize,
+        num_devices: u32,
+        devices: *const Handle,
+        notify: *const c_void,
+        user_data: *mut c_void,
+        errcode: *mut i32,
+    ) -> Handle;
+    fn clCreateCommandQueue(
+        context: Handle,
+        device: Handle,
+        properties: u64,
+        errcode: *mut i32,
+    ) -> Handle;
+    fn clCreateProgramWithSource(
+        context: Handle,
+        count: u32,
+        strings: *const *const c_char,
+        lengths: *const usize,
+        errcode: *mut i32,
+    ) -> Handle;
+    fn clBuildProgram(
+        program: Handle,
+        num_devices: u32,
+        devices: *const Handle,
+        options: *const c_char,
+        notify: *const c_void,
+        user_data: *mut c_void,
+    ) -> i32;
+    fn clCreateKernel(program: Handle, name: *const c_char, errcode: *mut i32) -> Handle;
+    fn clCreateBuffer(
+        context: Handle,
+        flags: u64,
+        size: usize,
+        host_ptr: *mut c_void,
+        errcode: *mut i32,
+    ) -> Handle;
+    fn clSetKernelArg(kernel: Handle, index: u32, size: usize, value: *const c_void) -> i32;
+    fn clEnqueueNDRangeKernel(
+        queue: Handle,
+        kernel: Handle,
+        work_dim: u32,
+        global_offset: *const usize,
+        global_size: *const usize,
+        local_size: *const usize,
+        num_events: u32,
+        wait_list: *const Handle,
+        event: *mut Handle,
+    ) -> i32;
+    fn clEnqueueReadBuffer(
+        queue: Handle,
+        buffer: Handle,
+        blocking: u32,
+        offset: usize,
+        size: usize,
+        ptr: *mut c_void,
+        num_events: u32,
+        wait_list: *const Handle,
+        event: *mut Handle,
+    ) -> i32;
+    fn clReleaseMemObject(mem: Handle) -> i32;
+    fn clReleaseKernel(kernel: Handle) -> i32;
+    fn clReleaseProgram(program: Handle) -> i32;
+    fn clReleaseCommandQueue(queue: Handle) -> i32;
+    fn clReleaseContext(context: Handle) -> i32;
+}
+
+/// One work item per output pixel, each computing what `luma` computes on
+/// the CPU, to the byte: the same integer gray weights, and downscaling by
+/// the rounded mean of the source pixels each output pixel covers.
+fn kernels() -> String {
+    format!(
+        r#"
+__kernel void gray(__global const uchar *src, uint channels, uint stride, uint x0, uint width,
+                   __global uchar *dst) {{
+    uint x = get_global_id(0), y = get_global_id(1);
+    __global const uchar *p = src + y * stride + (x0 + x) * channels;
+    dst[y * width + x] = (uchar)(({r}u * p[0] + {g}u * p[1] + {b}u * p[2]) / {div}u);
+}}
+
+__kernel void downscale(__global const uchar *src, uint sw, uint sh, uint dw, uint dh,
+                        __global uchar *dst) {{
+    uint x = get_global_id(0), y = get_global_id(1);
+    uint x0 = x * sw / dw, x1 = max((x + 1) * sw / dw, x0 + 1);
+    uint y0 = y * sh / dh, y1 = max((y + 1) * sh / dh, y0 + 1);
+    uint sum = 0;
+    for (uint sy = y0; sy < y1; sy++)
+        for (uint sx = x0; sx < x1; sx++)
+            sum += src[sy * sw + sx];
+    uint n = (x1 - x0) * (y1 - y0);
+    dst[y * dw + x] = (uchar)((sum + n / 2) / n);
+}}
+
+__kernel void levels(__global const uchar *src, __constant uchar *table, __global uchar *dst) {{
+    uint i = get_global_id(0);
+    dst[i] = table[src[i]];
+}}
+"#,
+        r = luma::R,
+        g = luma::G,
+        b = luma::B,
+        div = luma::LUMA_DIV,
+    )
+}
+
+/// An OpenCL object, released when dropped.
+struct Object(Handle, unsafe extern "C" fn(Handle) -> i32);
+impl Object {
+    fn new(
+        handle: Handle,
+        errcode: i32,
+        release: unsafe extern "C" fn(Handle) -> i32,
+        what: &str,
+    ) -> Result<Self, String> {
+        if handle.is_null() || errcode != CL_SUCCESS {
+            return Err(format!("{} failed with {}", what, errcode));
+        }
+        Ok(Object(handle, release))
+    }
+}
+impl Drop for Object {
+    fn drop(&mut self) {
+        unsafe { (self.1)(self.0) };
+    }
+}
+
+fn check(code: i32, what: &str) -> Result<(), String> {
+    match code {
+        CL_SUCCESS => Ok(()),
+        _ => Err(format!("{} failed with {}", what, code)),
+    }
+}
+
+enum Arg<'a> {
+    Mem(&'a Object),
+    U32(u32),
+}
+
+struct Device {
+    // Fields drop in order, kernels before the program and the context.
+    gray: Object,
+    downscale: Object,
+    levels: Object,
+    _program: Object,
+    queue: Object,
+    context: Object,
+}
+// OpenCL objects may be used from any thread; setting kernel arguments may
+// not race, which the mutex around the device rules out.
+unsafe impl Send for Device {}
+
+impl Device {
+    fn open() -> Result<Self, String> {
+        let device = Self::first_gpu()?;
+        let mut err = CL_SUCCESS;
+        unsafe {
+            let context = clCreateContext(
+                ptr::null(),
+                1,
+                &device,
+                ptr::null(),
+                ptr::null_mut(),
+                &mut err,
+            );
+            let context = Object::new(context, err, clReleaseContext, "clCreateContext")?;
+            let queue = clCreateCommandQueue(context.0, device, 0, &mut err);
+            let queue = Object::new(queue, err, clReleaseCommandQueue, "clCreateCommandQueue")?;
+            let source = CString::new(kernels()).expect("no nul in the kernel source");
+            let program =
+                clCreateProgramWithSource(context.0, 1, &source.as_ptr(), ptr::null(), &mut err);
+            let program = Object::new(program, err, clReleaseProgram, "clCreateProgramWithSource")?;
+            check(
+                clBuildProgram(
+                    program.0,
+                    1,
+                    &device,
+                    ptr::null(),
+                    ptr::null(),
+                    ptr::null_mut(),
+                ),
+                "clBuildProgram",
+            )?;
+            let kernel = |name: &str| {
+                let name = CString::new(name).expect("no nul in a kernel name");
+                let mut err = CL_SUCCESS;
+                let kernel = clCreateKernel(program.0, name.as_ptr(), &mut err);
+                Object::new(kernel, err, clReleaseKernel, "clCreateKernel")
+            };
+            Ok(Device {
+                gray: kernel("gray")?,
+                downscale: kernel("downscale")?,
+                levels: kernel("levels")?,
+                _program: program,
+                queue,
+                context,
+            })
+        }
+    }
+
+    fn first_gpu() -> Result<Handle, String> {
+        let mut platforms = [ptr::null_mut(); 16];
+        let mut count = 0;
+        check(
+            unsafe { clGetPlatformIDs(platforms.len() as u32, platforms.as_mut_ptr(), &mut count) },
+            "clGetPlatformIDs",
+        )?;
+        for &platform in &platforms[..(count as usize).min(platforms.len())] {
+            let (mut device, mut found) = (ptr::null_mut(), 0);
+            let code =
+                unsafe { clGetDeviceIDs(platform, CL_DEVICE_TYPE_GPU, 1, &mut device, &mut found) };
+            if code == CL_SUCCESS && found > 0 {
+                return Ok(device);
+            }
+        }
+        Err("no OpenCL platform has a GPU".to_string())
+    }
+
+    /// A device buffer holding a copy of `data`.
+    fn upload(&self, data: &[u8]) -> Result<Object, String> {
+        let mut err = CL_SUCCESS;
+        let mem = unsafe {
+            clCreateBuffer(
+                self.context.0,
+                CL_MEM_READ_ONLY | CL_MEM_COPY_HOST_PTR,
+                data.len(),
+                data.as_ptr() as *mut c_void,
+                &mut err,
+            )
+        };
+        Object::new(mem, err, clReleaseMemObject, "clCreateBuffer")
+    }
+
+    /// Runs `kernel` over `size` work items with `args`, then reads back
+    /// `len` bytes of `output`, its last argument.
+    fn run(
+        &self,
+        kernel: &Object,
+        args: &[Arg],
+        size: &[usize],
+        len: usize,
+    ) -> Result<Vec<u8>, String> {
+        let mut err = CL_SUCCESS;
+        let output = unsafe {
+            clCreateBuffer(
+                self.context.0,
+                CL_MEM_WRITE_ONLY,
+                len,
+                ptr::null_mut(),
+                &mut err,
+            )
+        };
+        let output = Object::new(output, err, clReleaseMemObject, "clCreateBuffer")?;
+        let mut out = vec![0u8; len];
+        unsafe {
+            for (index, arg) in args.iter().chain([Arg::Mem(&output)].iter()).enumerate() {
+                let code = match arg {
+                    Arg::Mem(mem) => clSetKernelArg(
+                        kernel.0,
+                        index as u32,
+                        size_of::<Handle>(),
+                        &mem.0 as *const Handle as *const c_void,
+                    ),
+                    Arg::U32(value) => clSetKernelArg(
+                        kernel.0,
+                        index as u32,
+                        size_of::<u32>(),
+                        value as *const u32 as *const c_void,
+                    ),
+                };
+                check(code, "clSetKernelArg")?;
+            }
+            check(
+                clEnqueueNDRangeKernel(
+                    self.queue.0,
+                    kernel.0,
+                    size.len() as u32,
+                    ptr::null(),
+                    size.as_ptr(),
+                    ptr::null(),
+                    0,
+                    ptr::null(),
+                    ptr::null_mut(),
+                ),
+                "clEnqueueNDRangeKernel",
+            )?;
+            check(
+                clEnqueueReadBuffer(
+                    self.queue.0,
+                    output.0,
+                    1,
+                    0,
+                    len,
+                    out.as_mut_ptr() as *mut c_void,
+                    0,
+                    ptr::null(),
+                    ptr::null_mut(),
+                ),
+                "clEnqueueReadBuffer",
+            )?;
+        }
+        Ok(out)
+    }
+}
+
+/// The GPU, opened on first use, or `None` when there is none.
+fn device() -> Option<MutexGuard<'static, Device>> {
+    static DEVICE: OnceLock<Option<Mutex<Device>>> = OnceLock::new();
+    DEVICE
+        .get_or_init(|| match Device::open() {
+            Ok(device) => {
+                log::info!("preprocessing large frames on the GPU");
+                Some(Mutex::new(device))
+            }
+            Err(e) => {
+                log::info!("preprocessing on the CPU: {}", e);
+                None
+            }
+        })
+        .as_ref()
+        .map(|device| device.lock().unwrap_or_else(PoisonError::into_inner))
+}
+
+fn large(width: u32, height: u32) -> bool {
+    width as u64 * height as u64 >= MIN_PIXELS
+}
+
+fn fallback<T>(result: Result<T, String>) -> Option<T> {
+    result
+        .map_err(|e| log::debug!("GPU preprocessing failed, using the CPU: {}", e))
+        .ok()
+}
+
+/// `rect` of `img` in gray, for large RGB and RGBA frames.
+pub fn gray(img: &DynamicImage, rect: Rect) -> Option<GrayImage> {
+    let (raw, channels) = match img {
+        DynamicImage::ImageRgb8(rgb) => (rgb.as_raw(), 3),
+        DynamicImage::ImageRgba8(rgba) => (rgba.as_raw(), 4),
+        _ => return None,
+    };
+    if !large(rect.width, rect.height) {
+        return None;
+    }
+    let device = device()?;
+    let stride = img.width() * channels;
+    let rows = &raw[(rect.y * stride) as usize..((rect.y + rect.height) * stride) as usize];
+    let len = rect.width as usize * rect.height as usize;
+    let out = fallback(device.upload(rows).and_then(|src| {
+        device.run(
+            &device.gray,
+            &[
+                Arg::Mem(&src),
+                Arg::U32(channels),
+                Arg::U32(stride),
+                Arg::U32(rect.x),
+                Arg::U32(rect.width),
+            ],
+            &[rect.width as usize, rect.height as usize],
+            len,
+        )
+    }))?;
+    GrayImage::from_raw(rect.width, rect.height, out)
+}
+
+/// `gray` shrunk to `width` by `height`, when it is large and that is no
+/// bigger than it.
+pub fn downscale(gray: &GrayImage, width: u32, height: u32) -> Option<GrayImage> {
+    let (sw, sh) = gray.dimensions();
+    if !large(sw, sh) || width > sw || height > sh || width == 0 || height == 0 {
+        return None;
+    }
+    let device = device()?;
+    let len = width as usize * height as usize;
+    let out = fallback(device.upload(gray.as_raw()).and_then(|src| {
+        device.run(
+            &device.downscale,
+            &[
+                Arg::Mem(&src),
+                Arg::U32(sw),
+                Arg::U32(sh),
+                Arg::U32(width),
+                Arg::U32(height),
+            ],
+            &[width as usize, height as usize],
+            len,
+        )
+    }))?;
+    GrayImage::from_raw(width, height, out)
+}
+
+/// `gray` with each level replaced by its entry in `table`, when it is
+/// large.
+pub fn levels(gray: &GrayImage, table: &[u8; 256]) -> Option<GrayImage> {
+    let (w, h) = gray.dimensions();
+    if !large(w, h) {
+        return None;
+    }
+    let device = device()?;
+    let len = gray.as_raw().len();
+    let out = fallback(device.upload(gray.as_raw()).and_then(|src| {
+        let table = device.upload(table)?;
+        device.run(
+            &device.levels,
+            &[Arg::Mem(&src), Arg::Mem(&table)],
+            &[len],
+            len,
+        )
+    }))?;
+    GrayImage::from_raw(w, h, out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgb, RgbImage};
+
+    /// A frame just large enough to be sent to the GPU.
+    fn frame() -> DynamicImage {
+        RgbImage::from_fn(2560, 1440, |x, y| {
+            Rgb([(x * 7 + y) as u8, (x ^ y) as u8, ((x * y) >> 3) as u8])
+        })
+        .into()
+    }
+
+    #[test]
+    fn gpu_matches_the_cpu() {
+        if device().is_none() {
+            eprintln!("no OpenCL GPU, skipped");
+            return;
+        }
+        let img = frame();
+        let rect = Rect {
+            x: 3,
+            y: 5,
+            width: 2557,
+            height: 1435,
+        };
+        let gray = gray(&img, rect).expect("converted on the GPU");
+        assert_eq!(gray, img.crop_imm(3, 5, 2557, 1435).to_luma8());
+        let rgba = DynamicImage::ImageRgba8(img.to_rgba8());
+        assert_eq!(
+            super::gray(&rgba, Rect::whole(&rgba)).expect("converted on the GPU"),
+            img.to_luma8()
+        );
+
+        let gray = img.to_luma8();
+        for (w, h) in [(1280, 720), (640, 360), (1000, 999), (1, 1)] {
+            assert_eq!(
+                downscale(&gray, w, h).expect("shrunk on the GPU"),
+                luma::shrink(&gray, w, h),
+                "{}x{}",
+                w,
+                h
+            );
+        }
+
+        let mut table = [0u8; 256];
+        for (level, out) in table.iter_mut().enumerate() {
+            *out = if level > 100 { 255 } else { level as u8 / 2 };
+        }
+        let mut mapped = gray.clone();
+        for p in mapped.iter_mut() {
+            *p = table[*p as usize];
+        }
+        assert_eq!(levels(&gray, &table).expect("mapped on the GPU"), mapped);
+    }
+}
@@ -0,0 +1,89 @@
+//! Output written before every segment arrived, with the missing byte
+//! ranges left as zeros and listed in a `<output-file>.holes.json` map.
+
+use serde::{Deserialize, Serialize};
+use std::{fs, io, path};
+
+use crate::decoder::QrSendDecoder;
+
+/// A run of missing segments.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Hole {
+    /// Byte offset in the output file.
+    pub offset: u64,
+    pub len: u64,
+    pub first_segment: u64,
+    pub segment_count: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct HoleMap {
+    pub file_size: u64,
+    pub segment_size: u64,
+    pub holes: Vec<Hole>,
+}
+impl HoleMap {
+    /// Where the missing segments of `decoder` fall in the file, or why the
+    /// file cannot be laid out yet.
+    pub fn of(decoder: &QrSendDecoder) -> Result<Self, &'static str> {
+        let md = decoder.metadata.as_ref().ok_or("no metadata received")?;
+        let segment_size = decoder
+            .segment_size()
+            .ok_or("no segment arrived that gives the segment size")?;
+        let last = md.qrcode_count.saturating_sub(1);
+        let file_size = match (md.file_size, decoder.data_segments.get(last)) {
+            (Some(size), _) => size,
+            (None, Some(seg)) => last
+                .checked_mul(segment_size)
+                .and_then(|offset| offset.checked_add(seg.data().len() as u64))
+                .ok_or("the segments do not fit a file")?,
+            (None, None) => {
+                return Err("the sender declared no file size and the last segment is missing")
+            }
+        };
+        let mut holes: Vec<Hole> = Vec::new();
+        for run in decoder.missing_ranges().iter() {
+            let (first, last) = (*run.start(), *run.end());
+            let offset = first.checked_mul(segment_size);
+            let end = last
+                .checked_add(1)
+                .and_then(|end| end.checked_mul(segment_size));
+            let (Some(offset), Some(end)) = (offset, end) else {
+                return Err("the segments do not fit a file");
+            };
+            holes.push(Hole {
+                offset,
+                len: end.min(file_size).saturating_sub(offset),
+                first_segment: first,
+                segment_count: last - first + 1,
+            });
+        }
+        Ok(HoleMap {
+            file_size,
+            segment_size,
+            holes,
+        })
+    }
+    pub fn path_for(output_file: &str) -> path::PathBuf {
+        path::PathBuf::from(format!("{}.holes.json", output_file))
+    }
+    /// Ids of the missing segments, in order.
+    pub fn segment_ids(&self) -> Vec<u64> {
+        self.holes
+            .iter()
+            .flat_map(|h| h.first_segment..h.first_segment + h.segment_count)
+            .collect()
+    }
+    pub fn missing_bytes(&self) -> u64 {
+        self.holes.iter().map(|h| h.len).sum()
+    }
+    pub fn load(path: &path::Path) -> io::Result<Self> {
+        let file = fs::File::open(path)?;
+        Ok(serde_json::from_reader(io::BufReader::new(file))?)
+    }
+    pub fn save(&self, path: &path::Path) -> io::Result<()> {
+        let file = fs::File::create(path)?;
+        serde_json::to_writer_pretty(io::BufWriter::new(file), self)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,688 @@
+use std::cmp::Ordering;
+use std::collections::VecDeque;
+use std::fs;
+use std::io::{self, Read};
+use std::path;
+use std::process;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering as AtomicOrdering};
+use std::sync::{mpsc, Arc};
+use std::thread;
+use std::time::Instant;
+
+use crate::cache::DecodeCache;
+use crate::exif;
+use crate::photo;
+use crate::protocol::Symbology;
+use crate::screen::ScreenRegion;
+#[cfg(feature = "external-decoders")]
+use crate::symbols::External;
+use crate::symbols::{SymbolReader, Zbar};
+use crate::synth::Rng;
+
+/// Part of each image that is scanned.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Crop {
+    /// A fixed rectangle, in image pixels.
+    Region(ScreenRegion),
+    /// Wherever the qrcode was found in earlier frames.
+    Auto,
+}
+impl FromStr for Crop {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.trim() == "auto" {
+            return Ok(Crop::Auto);
+        }
+        s.parse().map(Crop::Region)
+    }
+}
+
+/// How capture directories are read, shared by every command that takes one.
+#[derive(clap::Args, Debug, Clone, Default)]
+pub struct ReadOptions {
+    /// Also read images from subdirectories, in natural order
+    #[clap(long)]
+    pub recursive: bool,
+    /// Ignore EXIF orientation tags instead of turning photos upright
+    #[clap(long)]
+    pub no_exif_orientation: bool,
+    /// Do not retry frames that fail to decode rotated by 90, 180 and 270 degrees
+    #[clap(long)]
+    pub no_rotation_retry: bool,
+    /// Find the bright screen in each photo and warp it back to a rectangle
+    /// before scanning, for handheld photos taken at an angle
+    #[clap(long)]
+    pub perspective: bool,
+    /// Do not retry over- and under-exposed frames that fail to decode
+    /// with their levels and gamma normalized
+    #[clap(long)]
+    pub no_exposure_retry: bool,
+    /// Do not retry frames that fail to decode inside an added white border,
+    /// which screenshots cropped to the qrcode's edge need
+    #[clap(long)]
+    pub no_pad_retry: bool,
+    /// Do not retry frames that fail to decode downscaled and upscaled
+    #[clap(long)]
+    pub no_scale_retry: bool,
+    /// Debugging aid: read the images in an order shuffled with this seed,
+    /// to check that the result does not depend on capture order
+    #[clap(long, value_name = "SEED", num_args = 0..=1, default_missing_value = "0")]
+    pub shuffle: Option<u64>,
+    /// Only scan this part of each image, as x,y,w,h, or `auto` to lock onto
+    /// where the qrcode was found in earlier frames
+    #[clap(long)]
+    pub crop: Option<Crop>,
+    /// Only decode every (N+1)th data frame, rescanning the skipped ones
+    /// around whatever segments that leaves missing
+    #[clap(long, default_value_t = 0, conflicts_with = "sample_fps")]
+    pub skip: usize,
+    /// Subsample data frames to this many per second of capture, with the
+    /// same rescan as `--skip`
+    #[clap(long)]
+    pub sample_fps: Option<f64>,
+    /// Frame rate the capture was recorded at, for `--sample-fps` and the
+    /// recommended sender rate
+    #[clap(long, default_value_t = 30.0)]
+    pub source_fps: f64,
+    /// Remember what each image decoded to in this directory, so reruns
+    /// over the same capture skip scanning images seen before
+    #[clap(long)]
+    pub cache_dir: Option<path::PathBuf>,
+    /// Resolution PDF pages are rasterized at
+    #[clap(long, default_value_t = 150)]
+    pub pdf_dpi: u32,
+    /// Image files to load ahead on a background thread while the current
+    /// one is scanned; 0 loads each only when it is needed
+    #[clap(long, value_name = "N", default_value_t = 1)]
+    pub prefetch: usize,
+    /// Codes to look for, comma separated: qr, datamatrix, aztec. Anything
+    /// but qr needs a build with the `external-decoders` feature
+    #[clap(long, value_delimiter = ',', default_value = "qr", value_parser = parse_symbology)]
+    pub symbology: Vec<Symbology>,
+    /// Program reading one kind of code, as SYMBOLOGY=COMMAND; the image
+    /// path is appended and the content is expected on stdout
+    #[cfg(feature = "external-decoders")]
+    #[clap(long, value_name = "SYMBOLOGY=COMMAND")]
+    pub decoder_cmd: Vec<String>,
+}
+
+fn parse_symbology(s: &str) -> Result<Symbology, String> {
+    let symbology = <Symbology as clap::ValueEnum>::from_str(s, true)?;
+    if cfg!(not(feature = "external-decoders")) && symbology != Symbology::Qr {
+        return Err(format!(
+            "{} codes need a build with the external-decoders feature",
+            s
+        ));
+    }
+    Ok(symbology)
+}
+impl ReadOptions {
+    /// Distance between the images decoded by a subsampled data pass.
+    pub fn step(&self) -> usize {
+        match self.sample_fps {
+            Some(fps) if fps > 0.0 => (self.source_fps / fps).round().max(1.0) as usize,
+            _ => self.skip + 1,
+        }
+    }
+    /// The `--cache-dir` cache, or `None` without one or when it cannot be created.
+    pub fn open_cache(&self) -> Option<DecodeCache> {
+        let dir = self.cache_dir.as_ref()?;
+        DecodeCache::open(dir)
+            .map_err(|e| log::warn!("not caching decodes, cannot use {:?}: {}", dir, e))
+            .ok()
+    }
+    /// A reader for each `--symbology`.
+    pub fn readers(&self) -> Vec<Box<dyn SymbolReader>> {
+        if self.symbology.is_empty() {
+            return vec![Box::new(Zbar)];
+        }
+        self.symbology.iter().map(|&s| self.reader(s)).collect()
+    }
+    #[cfg(not(feature = "external-decoders"))]
+    fn reader(&self, _: Symbology) -> Box<dyn SymbolReader> {
+        Box::new(Zbar)
+    }
+    /// Qrcodes are read in process unless a `--decoder-cmd` is given for
+    /// them, other codes by their `--decoder-cmd` or the usual tool for them.
+    #[cfg(feature = "external-decoders")]
+    fn reader(&self, symbology: Symbology) -> Box<dyn SymbolReader> {
+        let command: Option<Vec<String>> = self.decoder_cmd.iter().rev().find_map(|cmd| {
+            let (name, command) = cmd.split_once('=')?;
+            let named = <Symbology as clap::ValueEnum>::from_str(name, true).ok()?;
+            (named == symbology).then(|| command.split_whitespace().map(str::to_string).collect())
+        });
+        match command.filter(|c| !c.is_empty()) {
+            None if symbology == Symbology::Qr => Box::new(Zbar),
+            command => Box::new(External {
+                symbology,
+                command: command.unwrap_or_else(|| External::default_command(symbology)),
+            }),
+        }
+    }
+}
+
+pub struct ImageSequence {
+    pub image_dir: path::PathBuf,
+    pub options: ReadOptions,
+}
+
+/// Load an image, turning it upright according to its EXIF orientation.
+pub fn load(path: &path::Path, honor_exif: bool) -> image::ImageResult<image::DynamicImage> {
+    #[cfg(feature = "raw")]
+    if photo::is_raw(path) {
+        return photo::load_raw(path);
+    }
+    let bytes = fs::read(path)?;
+    if photo::is_heif(&bytes) {
+        #[cfg(feature = "heif")]
+        return photo::load_heif(path);
+        #[cfg(not(feature = "heif"))]
+        return Err(image::ImageError::IoError(io::Error::other(
+            "HEIC photos need a build with the heif feature",
+        )));
+    }
+    let img = image::load_from_memory(&bytes)?;
+    match exif::orientation(&bytes).filter(|_| honor_exif) {
+        Some(orientation) if orientation != 1 => {
+            log::debug!("applying exif orientation {} to {:?}", orientation, path);
+            Ok(exif::apply_orientation(img, orientation))
+        }
+        _ => Ok(img),
+    }
+}
+impl IntoIterator for ImageSequence {
+    type Item = image::DynamicImage;
+    type IntoIter = ImageSequenceIterator;
+
+    fn into_iter(self) -> Self::IntoIter {
+        let mut img_paths = Vec::new();
+        let mut pages = None;
+        let mut entries: Vec<Entry> = if self.image_dir.is_dir() {
+            collect_files(&self.image_dir, self.options.recursive, &mut img_paths);
+            img_paths.into_iter().map(Entry::File).collect()
+        } else if is_pdf(&self.image_dir) {
+            match RasterizedPdf::new(&self.image_dir, self.options.pdf_dpi) {
+                Ok(pdf) => {
+                    collect_files(&pdf.dir, false, &mut img_paths);
+                    log::info!(
+                        "rasterized {} pages of {:?} at {} dpi",
+                        img_paths.len(),
+                        self.image_dir,
+                        self.options.pdf_dpi
+                    );
+                    pages = Some(Arc::new(pdf));
+                }
+                Err(e) => log::error!(
+                    "cannot rasterize {:?}, which needs pdftoppm from poppler: {}",
+                    self.image_dir,
+                    e
+                ),
+            }
+            img_paths.into_iter().map(Entry::File).collect()
+        } else {
+            Animation::entries(&self.image_dir)
+        };
+        if let Some(seed) = self.options.shuffle {
+            log::info!(
+                "reading {} images shuffled with seed {}",
+                entries.len(),
+                seed
+            );
+            Rng::new(seed).shuffle(&mut entries);
+        }
+        let mut images = ImageSequenceIterator::over(entries, !self.options.no_exif_orientation);
+        images.step = self.options.step();
+        images.pages = pages;
+        images.prefetch = self.options.prefetch;
+        images
+    }
+}
+
+/// Compare names so embedded numbers order by value: `IMG_9` before `IMG_10`.
+pub fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let (mut a, mut b) = (a.as_bytes(), b.as_bytes());
+    loop {
+        match (a.first(), b.first()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+                let a_len = a.iter().take_while(|c| c.is_ascii_digit()).count();
+                let b_len = b.iter().take_while(|c| c.is_ascii_digit()).count();
+                let (a_num, b_num) = (&a[..a_len], &b[..b_len]);
+                let a_trimmed = &a_num[a_num.iter().take_while(|&&c| c == b'0').count()..];
+                let b_trimmed = &b_num[b_num.iter().take_while(|&&c| c == b'0').count()..];
+                let ord = a_trimmed
+                    .len()
+                    .cmp(&b_trimmed.len())
+                    .then_with(|| a_trimmed.cmp(b_trimmed))
+                    // Equal values: fewer leading zeros first, so the order stays total.
+                    .then_with(|| a_len.cmp(&b_len));
+                if ord != Ordering::Equal {
+                    return ord;
+                }
+                a = &a[a_len..];
+                b = &b[b_len..];
+            }
+            (Some(x), Some(y)) => {
+                if x != y {
+                    return x.cmp(y);
+                }
+                a = &a[1..];
+                b = &b[1..];
+            }
+        }
+    }
+}
+
+/// Files of `dir` in natural order, followed by those of each subdirectory
+/// (again in natural order) when `recursive` is set.
+fn collect_files(dir: &path::Path, recursive: bool, out: &mut Vec<path::PathBuf>) {
+    let mut files = Vec::new();
+    let mut dirs = Vec::new();
+    for entry in fs::read_dir(dir).unwrap() {
+        let entry = entry.unwrap();
+        let name = entry.file_name().to_str().unwrap().to_string();
+        if entry.file_type().is_ok_and(|t| t.is_dir()) {
+            if recursive {
+                dirs.push(name);
+            }
+        } else {
+            files.push(name);
+        }
+    }
+    files.sort_by(|a, b| natural_cmp(a, b));
+    dirs.sort_by(|a, b| natural_cmp(a, b));
+    out.extend(files.iter().map(|f| dir.join(f)));
+    for d in dirs {
+        collect_files(&dir.join(d), recursive, out);
+    }
+}
+
+/// The first bytes of the file at `path`, enough to tell its format by.
+/// Extensions are not trusted: screenshots get saved without one, or with
+/// the wrong one.
+fn magic(path: &path::Path) -> Vec<u8> {
+    let mut head = Vec::new();
+    if let Ok(file) = fs::File::open(path) {
+        let _ = file.take(32).read_to_end(&mut head);
+    }
+    head
+}
+
+fn is_pdf(path: &path::Path) -> bool {
+    magic(path).starts_with(b"%PDF-")
+}
+
+/// Whether loading failed because the file is no image format at all,
+/// rather than a damaged image.
+fn is_not_an_image(e: &image::ImageError) -> bool {
+    matches!(
+        e,
+        image::ImageError::Unsupported(u)
+            if matches!(
+                u.kind(),
+                image::error::UnsupportedErrorKind::Format(image::error::ImageFormatHint::Unknown)
+            )
+    )
+}
+
+/// Pages of a PDF rasterized into a temporary directory by `pdftoppm`,
+/// removed once nothing reads from it any more.
+struct RasterizedPdf {
+    dir: path::PathBuf,
+}
+impl RasterizedPdf {
+    fn new(pdf: &path::Path, dpi: u32) -> io::Result<Self> {
+        static NEXT: AtomicU64 = AtomicU64::new(0);
+        let dir = std::env::temp_dir().join(format!(
+            "qr-recv-pdf-{}-{}",
+            std::process::id(),
+            NEXT.fetch_add(1, AtomicOrdering::Relaxed)
+        ));
+        fs::create_dir_all(&dir)?;
+        let pages = RasterizedPdf { dir };
+        let status = process::Command::new("pdftoppm")
+            .arg("-r")
+            .arg(dpi.to_string())
+            .arg("-png")
+            .arg(pdf)
+            .arg(pages.dir.join("page"))
+            .status()?;
+        if !status.success() {
+            return Err(io::Error::other(format!("pdftoppm {}", status)));
+        }
+        Ok(pages)
+    }
+}
+impl Drop for RasterizedPdf {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// One image of a capture.
+#[derive(Debug, Clone)]
+enum Entry {
+    File(path::PathBuf),
+    /// A frame of the animated GIF or APNG file, by index.
+    Frame(path::PathBuf, usize),
+}
+
+impl std::fmt::Display for Entry {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Entry::File(path) => write!(f, "{:?}", path),
+            Entry::Frame(path, index) => write!(f, "{:?} frame {}", path, index),
+        }
+    }
+}
+
+/// Image files loaded ahead of being read, on a thread of their own.
+struct Prefetch {
+    requests: mpsc::Sender<path::PathBuf>,
+    loaded: mpsc::Receiver<image::ImageResult<image::DynamicImage>>,
+    /// Entry indices requested and not yet taken, in request order, which
+    /// is the order they load in.
+    pending: VecDeque<usize>,
+}
+impl Prefetch {
+    fn new(honor_exif: bool) -> Self {
+        let (requests, request_rx) = mpsc::channel::<path::PathBuf>();
+        let (loaded_tx, loaded) = mpsc::channel();
+        thread::spawn(move || {
+            for path in request_rx {
+                if loaded_tx.send(load(&path, honor_exif)).is_err() {
+                    break;
+                }
+            }
+        });
+        Prefetch {
+            requests,
+            loaded,
+            pending: VecDeque::new(),
+        }
+    }
+    fn request(&mut self, index: usize, path: &path::Path) {
+        if self.requests.send(path.to_path_buf()).is_ok() {
+            self.pending.push_back(index);
+        }
+    }
+    /// Entry `index` if it was loaded ahead, dropping whatever was loaded
+    /// ahead of it in vain.
+    fn take(&mut self, index: usize) -> Option<image::ImageResult<image::DynamicImage>> {
+        while self.pending.front().is_some_and(|&i| i <= index) {
+            let i = self.pending.pop_front().unwrap();
+            let loaded = self.loaded.recv().ok()?;
+            if i == index {
+                return Some(loaded);
+            }
+        }
+        None
+    }
+}
+
+/// An animated GIF or APNG file, decoded frame by frame.
+struct Animation {
+    path: path::PathBuf,
+    frames: image::Frames<'static>,
+    /// Index of the frame `frames` yields next.
+    next: usize,
+}
+impl Animation {
+    fn frames(path: &path::Path) -> image::ImageResult<image::Frames<'static>> {
+        use image::codecs::{gif::GifDecoder, png::PngDecoder};
+        use image::AnimationDecoder;
+        let file = io::BufReader::new(fs::File::open(path)?);
+        if image::guess_format(&magic(path)).ok() == Some(image::ImageFormat::Gif) {
+            Ok(GifDecoder::new(file)?.into_frames())
+        } else {
+            Ok(PngDecoder::new(file)?.apng()?.into_frames())
+        }
+    }
+    /// Images of the capture file at `path`: each of its frames when it is
+    /// animated, otherwise the file itself.
+    fn entries(path: &path::Path) -> Vec<Entry> {
+        let animated = match image::guess_format(&magic(path)) {
+            Ok(image::ImageFormat::Gif) => true,
+            Ok(image::ImageFormat::Png) => fs::File::open(path)
+                .ok()
+                .and_then(|f| image::codecs::png::PngDecoder::new(io::BufReader::new(f)).ok())
+                .is_some_and(|d| d.is_apng().unwrap_or(false)),
+            _ => false,
+        };
+        if !animated {
+            return vec![Entry::File(path.to_path_buf())];
+        }
+        // Counted up front, so that sampling and rescans can address frames.
+        let count = match Self::frames(path) {
+            Ok(frames) => frames.count(),
+            Err(e) => {
+                log::warn!("cannot read animation {:?}: {}", path, e);
+                0
+            }
+        };
+        log::info!("reading {} frames of {:?}", count, path);
+        (0..count)
+            .map(|i| Entry::Frame(path.to_path_buf(), i))
+            .collect()
+    }
+    /// Frame `index`, reopening the file to go back.
+    fn frame(&mut self, index: usize) -> image::ImageResult<image::DynamicImage> {
+        if index < self.next {
+            self.frames = Self::frames(&self.path)?;
+            self.next = 0;
+        }
+        while self.next < index {
+            self.frames.next();
+            self.next += 1;
+        }
+        self.next += 1;
+        match self.frames.next() {
+            Some(frame) => {
+                // Parts of the canvas no frame has drawn yet are transparent
+                // black, which would read as dark modules.
+                let mut img = frame?.into_buffer();
+                for px in img.pixels_mut() {
+                    let alpha = px[3] as u32;
+                    for c in &mut px.0[..3] {
+                        *c = ((*c as u32 * alpha + 255 * (255 - alpha)) / 255) as u8;
+                    }
+                    px[3] = 255;
+                }
+                Ok(image::DynamicImage::ImageRgba8(img))
+            }
+            None => Err(image::ImageError::IoError(
+                io::ErrorKind::UnexpectedEof.into(),
+            )),
+        }
+    }
+}
+
+pub struct ImageSequenceIterator {
+    entries: Vec<Entry>,
+    /// The animation frames are currently read from.
+    animation: Option<Animation>,
+    /// Where the entries are when they are pages of a PDF.
+    pages: Option<Arc<RasterizedPdf>>,
+    honor_exif: bool,
+    index: usize,
+    /// Distance between images once sampling starts.
+    step: usize,
+    sampling: bool,
+    /// Which images have been read, or tried.
+    visited: Vec<bool>,
+    /// Entry index of each data segment seen, with its id.
+    sightings: Vec<(usize, u64)>,
+    /// Images skipped because they could not be read.
+    pub unreadable: u64,
+    /// Files skipped because they are not images, which are only worth a
+    /// warning all together.
+    pub not_images: u64,
+    /// Image files to keep loading ahead.
+    prefetch: usize,
+    loader: Option<Prefetch>,
+    /// Entry index of the image returned last.
+    last_read: Option<usize>,
+}
+impl ImageSequenceIterator {
+    /// Read `entries` in order, every one of them.
+    fn over(entries: Vec<Entry>, honor_exif: bool) -> Self {
+        ImageSequenceIterator {
+            visited: vec![false; entries.len()],
+            entries,
+            animation: None,
+            pages: None,
+            honor_exif,
+            index: 0,
+            step: 1,
+            sampling: false,
+            sightings: Vec::new(),
+            unreadable: 0,
+            not_images: 0,
+            prefetch: 0,
+            loader: None,
+            last_read: None,
+        }
+    }
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+    /// The file the image returned last was read from, with the frame
+    /// index inside an animation or the page of a PDF.
+    pub fn last_read(&self) -> Option<String> {
+        Some(match &self.entries[self.last_read?] {
+            Entry::File(path) => path.display().to_string(),
+            Entry::Frame(path, index) => format!("{}#{}", path.display(), index),
+        })
+    }
+    /// From here on only read every `step`th image.
+    pub fn start_sampling(&mut self) {
+        self.sampling = self.step > 1;
+    }
+    /// Record that the image returned last carried data segment `id`.
+    pub fn note_segment(&mut self, id: u64) {
+        if let Some(last) = self.index.checked_sub(self.current_step()) {
+            self.sightings.push((last, id));
+        }
+    }
+    fn current_step(&self) -> usize {
+        if self.sampling {
+            self.step
+        } else {
+            1
+        }
+    }
+    /// The images a sampled pass skipped between the sightings that bracket
+    /// each of `wanted`, in capture order. Ids past the last segment (the
+    /// hash frame) fall after it. `None` when nothing was skipped.
+    pub fn around(&self, wanted: &[u64]) -> Option<ImageSequenceIterator> {
+        if !self.sampling || wanted.is_empty() {
+            return None;
+        }
+        let end = self.entries.len();
+        let mut ranges = Vec::new();
+        let mut prev: Option<(usize, u64)> = None;
+        for next in self.sightings.iter().copied().map(Some).chain([None]) {
+            let bracketed = |m: u64| match (prev, next) {
+                (Some((_, a)), Some((_, b))) if a < b => a < m && m < b,
+                // The sender started a new pass in between.
+                (Some((_, a)), Some((_, b))) => m > a || m < b,
+                (None, Some((_, b))) => m < b,
+                (Some((_, a)), None) => m > a,
+                (None, None) => true,
+            };
+            if wanted.iter().any(|&m| bracketed(m)) {
+                let from = prev.map_or(0, |(f, _)| f + 1);
+                ranges.push(from..next.map_or(end, |(f, _)| f));
+            }
+            prev = next;
+        }
+        let paths: Vec<_> = ranges
+            .into_iter()
+            .flatten()
+            .filter(|&i| !self.visited[i])
+            .map(|i| self.entries[i].clone())
+            .collect();
+        if paths.is_empty() {
+            return None;
+        }
+        let mut rescan = ImageSequenceIterator::over(paths, self.honor_exif);
+        rescan.pages = self.pages.clone();
+        rescan.prefetch = self.prefetch;
+        Some(rescan)
+    }
+    /// Ask for the files of the next `prefetch` images to be loaded, as
+    /// far as the current step predicts them. Animation frames are left
+    /// out, being decoded in order from a single reader.
+    fn prefetch_ahead(&mut self) {
+        if self.prefetch == 0 {
+            return;
+        }
+        let wanted = (self.index..self.entries.len())
+            .step_by(self.current_step())
+            .take(self.prefetch);
+        for i in wanted {
+            let Entry::File(path) = &self.entries[i] else {
+                continue;
+            };
+            let honor_exif = self.honor_exif;
+            let loader = self.loader.get_or_insert_with(|| Prefetch::new(honor_exif));
+            if loader.pending.back().is_none_or(|&last| last < i) {
+                loader.request(i, path);
+            }
+        }
+    }
+    /// Entry `at`, the one given, from the prefetch thread when it was loaded ahead.
+    fn load(&mut self, at: usize, entry: &Entry) -> image::ImageResult<image::DynamicImage> {
+        if let Some(loaded) = self.loader.as_mut().and_then(|l| l.take(at)) {
+            return loaded;
+        }
+        let (path, index) = match entry {
+            Entry::File(path) => return load(path, self.honor_exif),
+            Entry::Frame(path, index) => (path, *index),
+        };
+        let animation = match &mut self.animation {
+            Some(animation) if animation.path == *path => animation,
+            animation => animation.insert(Animation {
+                path: path.clone(),
+                frames: Animation::frames(path)?,
+                next: 0,
+            }),
+        };
+        animation.frame(index)
+    }
+}
+impl Iterator for ImageSequenceIterator {
+    type Item = image::DynamicImage;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.index < self.entries.len() {
+            let index = self.index;
+            let entry = self.entries[index].clone();
+            self.visited[index] = true;
+            self.index += self.current_step();
+            let start = Instant::now();
+            let loaded = self.load(index, &entry);
+            self.prefetch_ahead();
+            match loaded {
+                Ok(img) => {
+                    log::debug!("read image {} in {:?}", entry, start.elapsed());
+                    self.last_read = Some(index);
+                    return Some(img);
+                }
+                Err(e) if is_not_an_image(&e) => {
+                    log::debug!("skipping {}, which is not an image", entry);
+                    self.not_images += 1;
+                }
+                Err(e) => {
+                    log::warn!("skipping unreadable image {}: {}", entry, e);
+                    self.unreadable += 1;
+                }
+            }
+        }
+        None
+    }
+}
@@ -0,0 +1,143 @@
+use crate::cbor;
+use crate::crc32c;
+use crate::decoder::scan;
+use crate::protocol::{
+    blake2b, decode_payload, guess_hash_len, read_id, sync_counter, Endianness, FrameError,
+    FrameType, MetadataChunk, ID_TYPES,
+};
+
+#[derive(clap::Args)]
+pub struct InspectArgs {
+    /// Image holding a single frame
+    frame: String,
+    /// Id type the sender used, all widths are shown when omitted
+    #[clap(long, value_parser = ID_TYPES)]
+    id_type: Option<String>,
+    /// Byte order of fixed-width ids
+    #[clap(long, value_enum, default_value_t = Endianness::Big)]
+    endianness: Endianness,
+    /// Hash length the sender used, guessed when omitted
+    #[clap(long)]
+    hash_len: Option<usize>,
+}
+
+pub fn run(args: &InspectArgs) {
+    let img = image::open(&args.frame).unwrap();
+    let symbols = scan(&img);
+    let Some(symbol) = symbols.first() else {
+        println!("no qrcode found");
+        return;
+    };
+    if symbols.len() > 1 {
+        println!("symbols found: {}, showing the first", symbols.len());
+    }
+    match std::str::from_utf8(&symbol.data) {
+        Ok(text) => println!("raw: {}", text),
+        Err(_) => println!("raw (hex): {}", hex::encode(&symbol.data)),
+    }
+    let Some((encoding, data)) = decode_payload(&symbol.data, None) else {
+        println!("not a qr-send frame");
+        return;
+    };
+    println!("payload encoding: {:?}", encoding);
+    match FrameType::of(&data) {
+        Some(t) => println!("frame type: {} ({})", t.name(), t.tag() as char),
+        None => println!("frame type: unknown"),
+    }
+    println!("frame length: {}", data.len());
+    let data = match crc32c::strip(&data) {
+        Some(body) if FrameType::of(&data) == Some(FrameType::Data) => {
+            println!("crc32c: valid");
+            body.to_vec()
+        }
+        _ => data,
+    };
+
+    let hash_len = match args.hash_len {
+        Some(len) if len > 0 && len <= 64 && len < data.len() => {
+            let split = data.len() - len;
+            if blake2b(&data[..split], len) == data[split..] {
+                println!("hash: valid ({} bytes)", len);
+            } else {
+                println!("hash: invalid ({} bytes)", len);
+            }
+            len
+        }
+        Some(len) => {
+            println!("hash: length {} does not fit this frame", len);
+            return;
+        }
+        None => match guess_hash_len(&data) {
+            Some(len) => {
+                println!("hash: valid ({} bytes, guessed)", len);
+                len
+            }
+            None => {
+                println!("hash: invalid (no length between 1 and 64 matches)");
+                return;
+            }
+        },
+    };
+    let body = &data[1..data.len() - hash_len];
+    println!("payload length: {}", body.len());
+    match FrameType::of(&data) {
+        Some(FrameType::Metadata) => match MetadataChunk::parse(body) {
+            Ok(MetadataChunk::Indexed {
+                index,
+                count,
+                check: None,
+                piece,
+            }) => println!(
+                "metadata chunk {} of {}: {}",
+                index as u32 + 1,
+                count,
+                String::from_utf8_lossy(piece)
+            ),
+            Ok(MetadataChunk::Indexed {
+                index,
+                count,
+                check: Some((len, crc)),
+                piece,
+            }) => println!(
+                "metadata chunk {} of {}, of {} bytes with crc32c {:08x}: {}",
+                index as u32 + 1,
+                count,
+                len,
+                crc,
+                String::from_utf8_lossy(piece)
+            ),
+            Ok(MetadataChunk::Cbor(chunk)) => match cbor::from_slice(chunk) {
+                Ok(value) => println!("metadata (cbor): {}", value),
+                Err(_) => println!("metadata chunk (cbor): {}", hex::encode(chunk)),
+            },
+            Ok(MetadataChunk::Json(_)) | Err(_) => {
+                println!("metadata chunk: {}", String::from_utf8_lossy(body))
+            }
+        },
+        Some(FrameType::Data) => match &args.id_type {
+            Some(t) => match read_id(body, t, args.endianness) {
+                Ok((id, len)) => {
+                    println!("segment id: {}", id);
+                    println!("content length: {}", body.len() - len);
+                }
+                Err(FrameError::InvalidId) => println!("segment id: not a valid {}", t),
+                Err(_) => println!("segment id: frame too short for {}", t),
+            },
+            None => {
+                for t in ID_TYPES {
+                    if let Ok((id, _)) = read_id(body, t, args.endianness) {
+                        println!("segment id as {}: {}", t, id);
+                    }
+                }
+            }
+        },
+        Some(FrameType::Hash) => println!("file hash: {}", hex::encode(body)),
+        Some(FrameType::Sync) => {
+            if let Some(counter) = sync_counter(&data) {
+                println!("sync counter: {}", counter);
+            }
+        }
+        Some(FrameType::Manifest) => println!("manifest: {}", String::from_utf8_lossy(body)),
+        _ => println!("payload: {}", hex::encode(body)),
+    }
+}
@@ -0,0 +1,141 @@
+//! Receiver for files sent as a sequence of qrcode frames.
+//!
+//! The supported API is what [`prelude`] re-exports: feed captured frames
+//! into a [`Decoder`], watch its [`Progress`], and take the verified file
+//! out once every segment has arrived. Everything else is internal and
+//! may change between minor releases.
+//!
+//! Scanning images needs the default `zbar` feature. Without it the
+//! decoder only accepts frames already extracted by some other scanner,
+//! which is how the `wasm` bindings are built.
+
+// Encoding helpers and counters are only reachable through the command line.
+#![cfg_attr(not(feature = "zbar"), allow(dead_code))]
+
+mod api;
+#[cfg(not(target_arch = "wasm32"))]
+mod asynchronous;
+mod base32;
+mod base45;
+mod blake3;
+mod cbor;
+mod crc32c;
+mod decoder;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+#[cfg(feature = "fuzzing")]
+#[doc(hidden)]
+pub mod fuzzing;
+mod pool;
+mod protocol;
+mod ranges;
+mod segments;
+mod sha256;
+mod split;
+mod sync;
+mod transfer;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+
+#[cfg(feature = "zbar")]
+mod advise;
+#[cfg(feature = "zbar")]
+mod analyze;
+#[cfg(feature = "zbar")]
+mod cache;
+#[cfg(feature = "zbar")]
+mod clipboard;
+#[cfg(feature = "zbar")]
+mod completions;
+#[cfg(feature = "zbar")]
+mod config;
+#[cfg(feature = "zbar")]
+mod crypto;
+#[cfg(feature = "zbar")]
+mod degrade;
+#[cfg(feature = "zbar")]
+mod describe;
+#[cfg(feature = "zbar")]
+mod exif;
+#[cfg(feature = "zbar")]
+mod exposure;
+#[cfg(feature = "gpu")]
+mod gpu;
+#[cfg(feature = "zbar")]
+mod holes;
+#[cfg(feature = "zbar")]
+mod images;
+#[cfg(feature = "zbar")]
+mod inspect;
+#[cfg(feature = "zbar")]
+mod logging;
+#[cfg(feature = "zbar")]
+mod luma;
+#[cfg(feature = "zbar")]
+mod man;
+#[cfg(feature = "zbar")]
+mod nack;
+#[cfg(feature = "zbar")]
+mod notify;
+#[cfg(feature = "zbar")]
+mod perspective;
+#[cfg(feature = "zbar")]
+mod photo;
+#[cfg(feature = "zbar")]
+mod pipe;
+#[cfg(feature = "zbar")]
+mod pipeline;
+#[cfg(feature = "gui")]
+mod preview;
+#[cfg(feature = "zbar")]
+mod qr;
+#[cfg(feature = "zbar")]
+mod receive;
+#[cfg(feature = "zbar")]
+mod replay;
+#[cfg(feature = "zbar")]
+mod report;
+#[cfg(feature = "zbar")]
+mod resume;
+#[cfg(feature = "zbar")]
+mod schedule;
+#[cfg(feature = "zbar")]
+mod screen;
+#[cfg(feature = "zbar")]
+mod selftest;
+#[cfg(feature = "zbar")]
+mod send;
+#[cfg(feature = "zbar")]
+mod session;
+#[cfg(feature = "zbar")]
+mod simulate;
+#[cfg(feature = "zbar")]
+mod sink;
+#[cfg(feature = "zbar")]
+mod stats;
+#[cfg(feature = "zbar")]
+mod symbols;
+#[cfg(feature = "zbar")]
+mod trace;
+#[cfg(feature = "zbar")]
+mod tui;
+#[cfg(feature = "zbar")]
+mod verify;
+
+pub mod prelude;
+
+pub use api::{Decoder, Error, FrameSource, Progress};
+#[cfg(not(target_arch = "wasm32"))]
+pub use asynchronous::{AsyncDecoder, ProgressWatch};
+pub use protocol::Manifest;
+pub use ranges::SegmentRanges;
+
+#[cfg(feature = "zbar")]
+#[doc(hidden)]
+pub mod bench;
+#[cfg(feature = "zbar")]
+#[doc(hidden)]
+pub mod cli;
+#[cfg(feature = "zbar")]
+#[doc(hidden)]
+pub mod synth;
@@ -0,0 +1,49 @@
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use std::io::Write;
+use std::time::Instant;
+
+/// Minimal stderr logger; timestamps are seconds since start-up.
+struct StderrLogger {
+    start: Instant,
+}
+
+impl Log for StderrLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let elapsed = self.start.elapsed().as_secs_f64();
+        let mut stderr = std::io::stderr().lock();
+        let _ = match record.level() {
+            Level::Info => writeln!(stderr, "{}", record.args()),
+            level => writeln!(stderr, "[{:>8.3}s {:<5}] {}", elapsed, level, record.args()),
+        };
+    }
+
+    fn flush(&self) {}
+}
+
+/// Map `-q`/`-v` counts onto a level: quiet shows only errors, each `-v` adds a level.
+pub fn level_filter(verbose: u8, quiet: bool) -> LevelFilter {
+    if quiet {
+        return LevelFilter::Error;
+    }
+    match verbose {
+        0 => LevelFilter::Info,
+        1 => LevelFilter::Debug,
+        _ => LevelFilter::Trace,
+    }
+}
+
+pub fn init(filter: LevelFilter) {
+    let logger = StderrLogger {
+        start: Instant::now(),
+    };
+    if log::set_boxed_logger(Box::new(logger)).is_ok() {
+        log::set_max_level(filter);
+    }
+}
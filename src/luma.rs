@@ -0,0 +1,157 @@
+//! Gray conversion for the scan loop, which sees every captured frame.
+//!
+//! `DynamicImage::to_luma8` goes pixel by pixel through generic color
+//! conversion, and cropping first copies the color pixels it then throws
+//! away. These convert straight from the raw buffer, only the rows and
+//! columns asked for, with the weights the image crate uses so results
+//! match it exactly. The inner loops are kept to integer arithmetic over
+//! fixed-size chunks so the compiler can vectorize them.
+
+use image::DynamicImage;
+
+/// Rec. 709 weights, out of `LUMA_DIV`, as in `image`'s own conversion.
+pub const R: u32 = 2126;
+pub const G: u32 = 7152;
+pub const B: u32 = 722;
+pub const LUMA_DIV: u32 = 10000;
+
+/// A rectangle of an image, already clamped to it.
+#[derive(Debug, Clone, Copy)]
+pub struct Rect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+impl Rect {
+    pub fn whole(img: &DynamicImage) -> Self {
+        Rect {
+            x: 0,
+            y: 0,
+            width: img.width(),
+            height: img.height(),
+        }
+    }
+}
+
+fn row_from<const N: usize>(src: &[u8], out: &mut Vec<u8>) {
+    out.extend(
+        src.chunks_exact(N)
+            .map(|p| ((R * p[0] as u32 + G * p[1] as u32 + B * p[2] as u32) / LUMA_DIV) as u8),
+    );
+}
+
+/// Append the gray pixels of `rect` in `img` to `out`, row after row.
+pub fn extend_gray(img: &DynamicImage, rect: Rect, out: &mut Vec<u8>) {
+    #[cfg(feature = "gpu")]
+    if let Some(gray) = crate::gpu::gray(img, rect) {
+        out.extend_from_slice(gray.as_raw());
+        return;
+    }
+    out.reserve(rect.width as usize * rect.height as usize);
+    let (x, w) = (rect.x as usize, rect.width as usize);
+    let rows = rect.y as usize..(rect.y + rect.height) as usize;
+    match img {
+        DynamicImage::ImageLuma8(gray) => {
+            let stride = gray.width() as usize;
+            for row in rows {
+                out.extend_from_slice(&gray.as_raw()[row * stride + x..][..w]);
+            }
+        }
+        DynamicImage::ImageRgb8(rgb) => {
+            let stride = rgb.width() as usize * 3;
+            for row in rows {
+                row_from::<3>(&rgb.as_raw()[row * stride + x * 3..][..w * 3], out);
+            }
+        }
+        DynamicImage::ImageRgba8(rgba) => {
+            let stride = rgba.width() as usize * 4;
+            for row in rows {
+                row_from::<4>(&rgba.as_raw()[row * stride + x * 4..][..w * 4], out);
+            }
+        }
+        _ => {
+            let view = img.crop_imm(rect.x, rect.y, rect.width, rect.height);
+            out.extend_from_slice(view.to_luma8().as_raw());
+        }
+    }
+}
+
+/// `rect` of `img` as a gray image.
+pub fn gray(img: &DynamicImage, rect: Rect) -> image::GrayImage {
+    let mut buf = Vec::new();
+    extend_gray(img, rect, &mut buf);
+    image::GrayImage::from_raw(rect.width, rect.height, buf).expect("one byte per pixel")
+}
+
+/// `gray` resized to `width` by `height`. Shrinking averages the pixels
+/// each output pixel covers, which the GPU does the same way.
+pub fn resize(gray: &image::GrayImage, width: u32, height: u32) -> image::GrayImage {
+    let (w, h) = gray.dimensions();
+    if width == 0 || height == 0 || width > w || height > h {
+        return image::imageops::resize(gray, width, height, image::imageops::FilterType::Triangle);
+    }
+    #[cfg(feature = "gpu")]
+    if let Some(scaled) = crate::gpu::downscale(gray, width, height) {
+        return scaled;
+    }
+    shrink(gray, width, height)
+}
+
+/// Pixels `i * src / dst` up to `(i + 1) * src / dst`, and at least one.
+fn span(i: u32, dst: u32, src: u32) -> std::ops::Range<u32> {
+    let start = (i as u64 * src as u64 / dst as u64) as u32;
+    let end = ((i as u64 + 1) * src as u64 / dst as u64) as u32;
+    start..end.max(start + 1)
+}
+
+/// `gray` shrunk to `width` by `height`, each pixel the rounded mean of the
+/// source pixels it covers.
+pub fn shrink(gray: &image::GrayImage, width: u32, height: u32) -> image::GrayImage {
+    let (w, h) = gray.dimensions();
+    image::GrayImage::from_fn(width, height, |x, y| {
+        let (xs, ys) = (span(x, width, w), span(y, height, h));
+        let n = xs.len() as u32 * ys.len() as u32;
+        let sum: u32 = ys
+            .flat_map(|sy| xs.clone().map(move |sx| gray.get_pixel(sx, sy)[0] as u32))
+            .sum();
+        image::Luma([((sum + n / 2) / n) as u8])
+    })
+}
+
+/// Replace each level of `gray` by its entry in `table`.
+pub fn map_levels(gray: &mut image::GrayImage, table: &[u8; 256]) {
+    #[cfg(feature = "gpu")]
+    if let Some(mapped) = crate::gpu::levels(gray, table) {
+        *gray = mapped;
+        return;
+    }
+    for p in gray.iter_mut() {
+        *p = table[*p as usize];
+    }
+}
+
+/// The red, green and blue channels of `img`, each as a gray image.
+pub fn channel_planes(img: &DynamicImage) -> [DynamicImage; 3] {
+    let rgb;
+    let raw: &[u8] = match img {
+        DynamicImage::ImageRgb8(img) => img.as_raw(),
+        _ => {
+            rgb = img.to_rgb8();
+            rgb.as_raw()
+        }
+    };
+    let len = img.width() as usize * img.height() as usize;
+    let mut planes = [(); 3].map(|_| Vec::with_capacity(len));
+    for p in raw.chunks_exact(3) {
+        for (plane, &v) in planes.iter_mut().zip(p) {
+            plane.push(v);
+        }
+    }
+    planes.map(|plane| {
+        DynamicImage::ImageLuma8(
+            image::GrayImage::from_raw(img.width(), img.height(), plane)
+                .expect("one byte per pixel"),
+        )
+    })
+}
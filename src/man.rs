@@ -0,0 +1,192 @@
+//! `qr-recv man`: a manual page in roff, generated from the command line
+//! definition itself, with every command and its options.
+
+use clap::{Arg, ArgAction, Command};
+use std::fmt::Write;
+
+use crate::cli::EXIT_STATUS;
+use crate::config;
+
+/// `text` with the characters roff treats specially escaped.
+fn roff(text: &str) -> String {
+    let escaped = text.replace('\\', "\\e").replace('-', "\\-");
+    escaped
+        .lines()
+        .map(|line| match line.chars().next() {
+            Some('.' | '\'') => format!("\\&{}", line),
+            _ => line.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn bold(text: &str) -> String {
+    format!("\\fB{}\\fR", roff(text))
+}
+
+fn italic(text: &str) -> String {
+    format!("\\fI{}\\fR", roff(text))
+}
+
+/// Name of the value `arg` takes, as the help shows it.
+fn value_name(arg: &Arg) -> String {
+    arg.get_value_names()
+        .and_then(|names| names.first())
+        .map_or_else(|| arg.get_id().as_str().to_uppercase(), |n| n.to_string())
+}
+
+/// The `.TP` entry of one option or positional argument.
+fn argument(out: &mut String, arg: &Arg) {
+    let takes_value = matches!(arg.get_action(), ArgAction::Set | ArgAction::Append);
+    let mut term = Vec::new();
+    if let Some(short) = arg.get_short() {
+        term.push(bold(&format!("-{}", short)));
+    }
+    if let Some(long) = arg.get_long() {
+        term.push(bold(&format!("--{}", long)));
+    }
+    let mut term = term.join(", ");
+    if arg.is_positional() {
+        term = italic(&value_name(arg));
+    } else if takes_value {
+        write!(term, " {}", italic(&value_name(arg))).unwrap();
+    }
+    writeln!(out, ".TP").unwrap();
+    writeln!(out, "{}", term).unwrap();
+    let help = arg
+        .get_long_help()
+        .or(arg.get_help())
+        .map(|h| h.to_string())
+        .unwrap_or_default();
+    let mut text = help.trim_end().to_string();
+    let values: Vec<String> = arg
+        .get_possible_values()
+        .iter()
+        .filter(|v| !v.is_hide_set())
+        .map(|v| v.get_name().to_string())
+        .collect();
+    if takes_value && !values.is_empty() {
+        write!(text, " [possible values: {}]", values.join(", ")).unwrap();
+    }
+    let defaults: Vec<String> = arg
+        .get_default_values()
+        .iter()
+        .map(|v| v.to_string_lossy().into_owned())
+        .collect();
+    if takes_value && !defaults.is_empty() {
+        write!(text, " [default: {}]", defaults.join(", ")).unwrap();
+    }
+    writeln!(out, "{}", roff(text.trim())).unwrap();
+}
+
+fn arguments(out: &mut String, command: &Command) {
+    for arg in command.get_arguments().filter(|a| !a.is_hide_set()) {
+        if !matches!(arg.get_action(), ArgAction::Help | ArgAction::Version) {
+            argument(out, arg);
+        }
+    }
+}
+
+/// The indented lines of one of the help texts, under a heading of its own.
+fn section(out: &mut String, heading: &str, text: &str) {
+    writeln!(out, ".SH {}", heading).unwrap();
+    writeln!(out, ".nf").unwrap();
+    for line in text.lines().skip(1) {
+        writeln!(out, "{}", roff(line.strip_prefix("  ").unwrap_or(line))).unwrap();
+    }
+    writeln!(out, ".fi").unwrap();
+}
+
+/// The whole manual page for `command`.
+pub fn page(command: &Command) -> String {
+    let name = command.get_name();
+    let mut out = String::new();
+    writeln!(
+        out,
+        ".TH {} 1 \"\" \"{} {}\"",
+        roff(&name.to_uppercase()),
+        roff(name),
+        env!("CARGO_PKG_VERSION")
+    )
+    .unwrap();
+    writeln!(out, ".SH NAME").unwrap();
+    writeln!(
+        out,
+        "{} \\- receive a file sent as a sequence of qrcodes",
+        roff(name)
+    )
+    .unwrap();
+    writeln!(out, ".SH SYNOPSIS").unwrap();
+    writeln!(
+        out,
+        "{} [{}] {} [{}]",
+        bold(name),
+        italic("OPTIONS"),
+        italic("COMMAND"),
+        italic("COMMAND OPTIONS")
+    )
+    .unwrap();
+    writeln!(out, ".SH OPTIONS").unwrap();
+    arguments(&mut out, command);
+    writeln!(out, ".SH COMMANDS").unwrap();
+    for subcommand in command.get_subcommands() {
+        writeln!(
+            out,
+            ".SS \"{} {}\"",
+            roff(name),
+            roff(subcommand.get_name())
+        )
+        .unwrap();
+        let about = subcommand
+            .get_long_about()
+            .or(subcommand.get_about())
+            .map(|a| a.to_string())
+            .unwrap_or_default();
+        writeln!(out, "{}", roff(about.trim())).unwrap();
+        arguments(&mut out, subcommand);
+    }
+    section(&mut out, "DEFAULTS", config::HELP);
+    section(&mut out, "EXIT STATUS", EXIT_STATUS);
+    out
+}
+
+pub fn run(command: &Command) {
+    print!("{}", page(command));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn page_mentions_every_visible_argument() {
+        let command = crate::cli::command();
+        let page = page(&command);
+        for subcommand in std::iter::once(&command).chain(command.get_subcommands()) {
+            assert!(page.contains(&roff(subcommand.get_name())));
+            let visible = subcommand
+                .get_arguments()
+                .filter(|a| !a.is_hide_set())
+                .filter(|a| !matches!(a.get_action(), ArgAction::Help | ArgAction::Version));
+            for arg in visible {
+                let mut terms: Vec<String> = arg
+                    .get_short()
+                    .map(|s| bold(&format!("-{}", s)))
+                    .into_iter()
+                    .collect();
+                terms.extend(arg.get_long().map(|l| bold(&format!("--{}", l))));
+                if arg.is_positional() {
+                    terms.push(italic(&value_name(arg)));
+                }
+                for term in terms {
+                    assert!(
+                        page.contains(&term),
+                        "the page lacks {} of {}",
+                        term,
+                        subcommand.get_name()
+                    );
+                }
+            }
+        }
+    }
+}
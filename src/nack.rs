@@ -0,0 +1,51 @@
+use std::path;
+
+use crate::decoder::QrSendDecoder;
+use crate::images::{ImageSequence, ReadOptions};
+use crate::session::{Session, SessionKey};
+
+#[derive(clap::Args)]
+pub struct NackArgs {
+    /// Session file written by `receive --session`
+    #[clap(short, long, required_unless_present = "from", conflicts_with = "from")]
+    session: Option<String>,
+    #[clap(flatten)]
+    session_key: SessionKey,
+    /// Capture directory, animated GIF/APNG or PDF to decode instead of a session
+    #[clap(short, long)]
+    from: Option<String>,
+    #[clap(flatten)]
+    read: ReadOptions,
+}
+
+/// Print the ids still missing, in the form `send --segments` accepts.
+pub fn run(args: &NackArgs) {
+    let decoder = match (&args.session, &args.from) {
+        (Some(session), _) => Session::load(path::Path::new(session), &args.session_key)
+            .unwrap()
+            .into_decoder(),
+        (None, Some(from)) => {
+            let mut decoder = QrSendDecoder::new();
+            decoder.rotation_retry = !args.read.no_rotation_retry;
+            decoder.perspective = args.read.perspective;
+            decoder.exposure_retry = !args.read.no_exposure_retry;
+            decoder.pad_retry = !args.read.no_pad_retry;
+            decoder.scale_retry = !args.read.no_scale_retry;
+            decoder.crop = args.read.crop;
+            decoder.cache = args.read.open_cache();
+            decoder.readers = args.read.readers();
+            let img_seq = ImageSequence {
+                image_dir: path::PathBuf::from(from),
+                options: args.read.clone(),
+            };
+            decoder.consume(&mut img_seq.into_iter());
+            decoder
+        }
+        (None, None) => unreachable!(),
+    };
+    if decoder.metadata.is_none() {
+        println!("no metadata received, every segment is missing");
+        return;
+    }
+    println!("{:#}", decoder.missing_ranges());
+}
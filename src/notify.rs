@@ -0,0 +1,139 @@
+//! Out-of-band signals for unattended captures: a terminal bell plus a
+//! desktop notification, through `notify-send` or, on macOS, `osascript`.
+
+use std::io::Write;
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+use crate::decoder::QrSendDecoder;
+use crate::receive::Outcome;
+
+/// Ring the bell and show `summary` with `body` on the desktop. A missing
+/// notification tool only costs the desktop half.
+pub fn notify(summary: &str, body: &str) {
+    log::info!("{}: {}", summary, body);
+    let _ = write!(std::io::stderr(), "\x07");
+    let mut cmd = if cfg!(target_os = "macos") {
+        let quote = |s: &str| s.replace('\\', "\\\\").replace('"', "\\\"");
+        let mut cmd = Command::new("osascript");
+        cmd.arg("-e").arg(format!(
+            "display notification \"{}\" with title \"{}\"",
+            quote(body),
+            quote(summary)
+        ));
+        cmd
+    } else {
+        let mut cmd = Command::new("notify-send");
+        cmd.args(["--app-name", "qr-recv", summary, body]);
+        cmd
+    };
+    let shown = cmd
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status();
+    if let Err(e) = shown {
+        log::debug!("no desktop notification: {}", e);
+    }
+}
+
+/// Watches a live capture for stalls and reports how it ended.
+pub struct Notifier {
+    stall_after: Duration,
+    received: usize,
+    last_progress: Instant,
+    /// A stall was reported and no segment has arrived since.
+    stalled: bool,
+}
+impl Notifier {
+    pub fn new(stall_after: Duration) -> Self {
+        Notifier {
+            stall_after,
+            received: 0,
+            last_progress: Instant::now(),
+            stalled: false,
+        }
+    }
+    /// Check for a stall after each frame, reporting each stall once.
+    pub fn on_frame(&mut self, decoder: &QrSendDecoder) {
+        if let Some(body) = self.stall(decoder) {
+            notify("qr-recv transfer stalled", &body);
+        }
+    }
+    /// What to say of a stall first seen after this frame, if one is.
+    fn stall(&mut self, decoder: &QrSendDecoder) -> Option<String> {
+        let received = decoder.data_segments.len();
+        if received != self.received {
+            self.received = received;
+            self.last_progress = Instant::now();
+            self.stalled = false;
+            return None;
+        }
+        if self.stalled || self.last_progress.elapsed() < self.stall_after {
+            return None;
+        }
+        self.stalled = true;
+        let progress = match &decoder.metadata {
+            Some(md) => format!("{} of {} segments received", received, md.qrcode_count),
+            None => "no metadata received yet".to_string(),
+        };
+        Some(format!(
+            "no new segments for {:.0}s, {}",
+            self.stall_after.as_secs_f64(),
+            progress
+        ))
+    }
+    pub fn finished(&self, outcome: Outcome, output_file: &str) {
+        match outcome {
+            Outcome::Verified => notify(
+                "qr-recv transfer complete",
+                &format!("{} verified", output_file),
+            ),
+            Outcome::HashMismatch | Outcome::SizeMismatch => notify(
+                "qr-recv verification failed",
+                &format!("{} was not written: {:?}", output_file, outcome),
+            ),
+            _ => notify(
+                "qr-recv transfer ended",
+                &format!("{}: {:?}", output_file, outcome),
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::synth::{Options, Transfer};
+
+    #[test]
+    fn each_stall_is_reported_once() {
+        let transfer = Transfer::new(&Options::default());
+        let mut decoder = QrSendDecoder::new();
+        assert_eq!(
+            Notifier::new(Duration::from_secs(3600)).stall(&decoder),
+            None
+        );
+
+        let mut notifier = Notifier::new(Duration::ZERO);
+        assert_eq!(
+            notifier.stall(&decoder).unwrap(),
+            "no new segments for 0s, no metadata received yet"
+        );
+        assert_eq!(notifier.stall(&decoder), None);
+        let mut frames = transfer.frames.iter();
+        for frame in frames.by_ref() {
+            decoder.push(frame.clone());
+            if frame[0] == b'D' {
+                break;
+            }
+        }
+        // A new segment ends the stall, so the next one is reported again.
+        assert_eq!(notifier.stall(&decoder), None);
+        assert_eq!(
+            notifier.stall(&decoder).unwrap(),
+            "no new segments for 0s, 1 of 8 segments received"
+        );
+        assert_eq!(notifier.stall(&decoder), None);
+    }
+}
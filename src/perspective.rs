@@ -0,0 +1,201 @@
+//! Perspective correction for photos of a screen taken at an angle: the
+//! bright screen is found as a quadrilateral and warped back into a
+//! rectangle, so the qrcode on it is square again.
+
+use image::{DynamicImage, GrayImage, Luma};
+
+use crate::luma::{self, Rect};
+
+/// Longer side the screen is looked for at; its outline needs no detail.
+const DETECT_SIDE: u32 = 320;
+/// Bright regions covering less of the photo than this are not a screen.
+const MIN_AREA_FRACTION: f64 = 0.05;
+
+type Point = (f64, f64);
+
+/// `img` in gray with its largest bright quadrilateral warped to fill a
+/// rectangle, or `None` when there is no such region.
+pub fn rectify(img: &DynamicImage) -> Option<DynamicImage> {
+    let gray = luma::gray(img, Rect::whole(img));
+    let [tl, tr, br, bl] = find_screen(&gray)?;
+    let dist = |a: Point, b: Point| ((a.0 - b.0).powi(2) + (a.1 - b.1).powi(2)).sqrt();
+    let width = dist(tl, tr).max(dist(bl, br)).round() as u32;
+    let height = dist(tl, bl).max(dist(tr, br)).round() as u32;
+    if width < 2 || height < 2 {
+        return None;
+    }
+    let (w, h) = ((width - 1) as f64, (height - 1) as f64);
+    let to_source = homography([(0.0, 0.0), (w, 0.0), (w, h), (0.0, h)], [tl, tr, br, bl])?;
+    let out = GrayImage::from_fn(width, height, |x, y| {
+        let (sx, sy) = apply(&to_source, (x as f64, y as f64));
+        Luma([sample(&gray, sx, sy)])
+    });
+    Some(DynamicImage::ImageLuma8(out))
+}
+
+/// Corners of the largest bright region of `gray`, clockwise from the top
+/// left, in full resolution pixels.
+fn find_screen(gray: &GrayImage) -> Option<[Point; 4]> {
+    let (w, h) = gray.dimensions();
+    let scale = (w.max(h) as f64 / DETECT_SIDE as f64).max(1.0);
+    let (sw, sh) = (
+        ((w as f64 / scale) as u32).max(1),
+        ((h as f64 / scale) as u32).max(1),
+    );
+    let small = luma::resize(gray, sw, sh);
+    let threshold = otsu(&small);
+    let bright: Vec<bool> = small.pixels().map(|p| p[0] > threshold).collect();
+
+    let mut seen = vec![false; bright.len()];
+    let mut best: Option<(usize, [Point; 4])> = None;
+    let mut stack = Vec::new();
+    for start in 0..bright.len() {
+        if !bright[start] || seen[start] {
+            continue;
+        }
+        // Extremes of x + y and x - y fall on the corners of a quadrilateral.
+        let (mut min_sum, mut max_sum, mut min_diff, mut max_diff) =
+            ((i64::MAX, 0), (i64::MIN, 0), (i64::MAX, 0), (i64::MIN, 0));
+        let mut count = 0;
+        seen[start] = true;
+        stack.push(start);
+        while let Some(i) = stack.pop() {
+            count += 1;
+            let (x, y) = ((i % sw as usize) as i64, (i / sw as usize) as i64);
+            for (extreme, key, less) in [
+                (&mut min_sum, x + y, true),
+                (&mut max_sum, x + y, false),
+                (&mut min_diff, x - y, true),
+                (&mut max_diff, x - y, false),
+            ] {
+                if (less && key < extreme.0) || (!less && key > extreme.0) {
+                    *extreme = (key, i);
+                }
+            }
+            let mut visit = |j: usize| {
+                if bright[j] && !seen[j] {
+                    seen[j] = true;
+                    stack.push(j);
+                }
+            };
+            if x > 0 {
+                visit(i - 1);
+            }
+            if x + 1 < sw as i64 {
+                visit(i + 1);
+            }
+            if y > 0 {
+                visit(i - sw as usize);
+            }
+            if y + 1 < sh as i64 {
+                visit(i + sw as usize);
+            }
+        }
+        if best.as_ref().is_some_and(|(c, _)| *c >= count) {
+            continue;
+        }
+        let corner = |i: usize| {
+            (
+                ((i % sw as usize) as f64 + 0.5) * scale,
+                ((i / sw as usize) as f64 + 0.5) * scale,
+            )
+        };
+        best = Some((
+            count,
+            [
+                corner(min_sum.1),
+                corner(max_diff.1),
+                corner(max_sum.1),
+                corner(min_diff.1),
+            ],
+        ));
+    }
+    let (count, corners) = best?;
+    (count as f64 >= MIN_AREA_FRACTION * bright.len() as f64).then_some(corners)
+}
+
+/// Threshold between the dark and bright pixels of `img` by Otsu's method.
+fn otsu(img: &GrayImage) -> u8 {
+    let mut histogram = [0u64; 256];
+    for p in img.pixels() {
+        histogram[p[0] as usize] += 1;
+    }
+    let total = img.pixels().len() as f64;
+    let sum: f64 = histogram
+        .iter()
+        .enumerate()
+        .map(|(v, &n)| v as f64 * n as f64)
+        .sum();
+    let (mut below, mut below_sum) = (0.0, 0.0);
+    let (mut best, mut best_variance) = (0, 0.0);
+    for (v, &n) in histogram.iter().enumerate() {
+        below += n as f64;
+        below_sum += v as f64 * n as f64;
+        let above = total - below;
+        if below == 0.0 || above == 0.0 {
+            continue;
+        }
+        let diff = below_sum / below - (sum - below_sum) / above;
+        let variance = below * above * diff * diff;
+        if variance > best_variance {
+            best_variance = variance;
+            best = v as u8;
+        }
+    }
+    best
+}
+
+/// The projective map taking each of `from` to the matching `to`, as the
+/// eight coefficients of its matrix with the ninth fixed to one.
+fn homography(from: [Point; 4], to: [Point; 4]) -> Option<[f64; 8]> {
+    let mut rows = [[0.0; 9]; 8];
+    for (k, (&(u, v), &(x, y))) in from.iter().zip(&to).enumerate() {
+        rows[2 * k] = [u, v, 1.0, 0.0, 0.0, 0.0, -u * x, -v * x, x];
+        rows[2 * k + 1] = [0.0, 0.0, 0.0, u, v, 1.0, -u * y, -v * y, y];
+    }
+    // Gaussian elimination with partial pivoting.
+    for col in 0..8 {
+        let pivot = (col..8).max_by(|&a, &b| rows[a][col].abs().total_cmp(&rows[b][col].abs()))?;
+        if rows[pivot][col].abs() < 1e-9 {
+            return None;
+        }
+        rows.swap(col, pivot);
+        let pivot_row = rows[col];
+        for (r, row) in rows.iter_mut().enumerate() {
+            if r != col {
+                let factor = row[col] / pivot_row[col];
+                for (value, &p) in row.iter_mut().zip(&pivot_row).skip(col) {
+                    *value -= factor * p;
+                }
+            }
+        }
+    }
+    let mut h = [0.0; 8];
+    for (i, coefficient) in h.iter_mut().enumerate() {
+        *coefficient = rows[i][8] / rows[i][i];
+    }
+    Some(h)
+}
+
+fn apply(h: &[f64; 8], (u, v): Point) -> Point {
+    let w = h[6] * u + h[7] * v + 1.0;
+    (
+        (h[0] * u + h[1] * v + h[2]) / w,
+        (h[3] * u + h[4] * v + h[5]) / w,
+    )
+}
+
+/// Bilinear sample of `img` at `(x, y)`, white outside it.
+fn sample(img: &GrayImage, x: f64, y: f64) -> u8 {
+    let (w, h) = img.dimensions();
+    if !(0.0..=(w - 1) as f64).contains(&x) || !(0.0..=(h - 1) as f64).contains(&y) {
+        return 255;
+    }
+    let (x0, y0) = (x.floor() as u32, y.floor() as u32);
+    let (x1, y1) = ((x0 + 1).min(w - 1), (y0 + 1).min(h - 1));
+    let (fx, fy) = (x - x0 as f64, y - y0 as f64);
+    let p = |x, y| img.get_pixel(x, y)[0] as f64;
+    let top = p(x0, y0) * (1.0 - fx) + p(x1, y0) * fx;
+    let bottom = p(x0, y1) * (1.0 - fx) + p(x1, y1) * fx;
+    (top * (1.0 - fy) + bottom * fy).round() as u8
+}
@@ -0,0 +1,102 @@
+//! HEIC/HEIF and camera RAW photos, which the image crate cannot read,
+//! converted by running the usual command line tools: `heif-dec` from
+//! libheif (`sips` on macOS) and `dcraw`.
+
+#[cfg(any(feature = "heif", feature = "raw"))]
+use std::io;
+#[cfg(any(feature = "heif", feature = "raw"))]
+use std::path;
+#[cfg(any(feature = "heif", feature = "raw"))]
+use std::process::Command;
+
+/// `ftyp` brands of HEIF still images and sequences, as iPhones save them.
+const HEIF_BRANDS: [&[u8]; 6] = [b"heic", b"heix", b"heim", b"heis", b"mif1", b"msf1"];
+
+#[cfg(feature = "raw")]
+const RAW_EXTENSIONS: [&str; 11] = [
+    "dng", "cr2", "cr3", "crw", "nef", "arw", "raf", "orf", "rw2", "pef", "srw",
+];
+
+/// Whether `bytes` start with the `ftyp` box of a HEIF file.
+pub fn is_heif(bytes: &[u8]) -> bool {
+    bytes.get(4..8) == Some(b"ftyp")
+        && bytes
+            .get(8..12)
+            .is_some_and(|brand| HEIF_BRANDS.contains(&brand))
+}
+
+#[cfg(feature = "raw")]
+/// Whether `path` names a camera RAW or DNG file. Many are TIFF inside, so
+/// the image crate would otherwise read their small embedded preview.
+pub fn is_raw(path: &path::Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| RAW_EXTENSIONS.iter().any(|r| e.eq_ignore_ascii_case(r)))
+}
+
+#[cfg(any(feature = "heif", feature = "raw"))]
+fn tool_error(tool: &str, e: impl std::fmt::Display) -> image::ImageError {
+    image::ImageError::IoError(io::Error::other(format!("{}: {}", tool, e)))
+}
+
+#[cfg(feature = "heif")]
+/// Decode a HEIF photo through a PNG copy written by the converter. The
+/// converter applies the rotation and mirroring the file declares.
+pub fn load_heif(path: &path::Path) -> image::ImageResult<image::DynamicImage> {
+    use std::sync::atomic::{AtomicU64, Ordering};
+    static NEXT: AtomicU64 = AtomicU64::new(0);
+    let png = std::env::temp_dir().join(format!(
+        "qr-recv-heif-{}-{}.png",
+        std::process::id(),
+        NEXT.fetch_add(1, Ordering::Relaxed)
+    ));
+    let converted = if cfg!(target_os = "macos") {
+        Command::new("sips")
+            .args(["-s", "format", "png"])
+            .arg(path)
+            .arg("--out")
+            .arg(&png)
+            .output()
+    } else {
+        // Called heif-convert before libheif 1.17.
+        match Command::new("heif-dec").arg(path).arg(&png).output() {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                Command::new("heif-convert").arg(path).arg(&png).output()
+            }
+            output => output,
+        }
+    };
+    let tool = if cfg!(target_os = "macos") {
+        "sips"
+    } else {
+        "heif-dec from libheif"
+    };
+    let img = match converted {
+        Ok(output) if output.status.success() => image::open(&png),
+        Ok(output) => Err(tool_error(
+            tool,
+            String::from_utf8_lossy(&output.stderr).trim(),
+        )),
+        Err(e) => Err(tool_error(tool, e)),
+    };
+    let _ = std::fs::remove_file(&png);
+    img
+}
+
+#[cfg(feature = "raw")]
+/// Develop a RAW photo with `dcraw`, with the camera's white balance and
+/// turned upright, from the PPM it writes to stdout.
+pub fn load_raw(path: &path::Path) -> image::ImageResult<image::DynamicImage> {
+    let output = Command::new("dcraw")
+        .args(["-c", "-w"])
+        .arg(path)
+        .output()
+        .map_err(|e| tool_error("dcraw", e))?;
+    if !output.status.success() {
+        return Err(tool_error(
+            "dcraw",
+            String::from_utf8_lossy(&output.stderr).trim(),
+        ));
+    }
+    image::load_from_memory_with_format(&output.stdout, image::ImageFormat::Pnm)
+}
@@ -0,0 +1,130 @@
+//! Encoded images streamed over a pipe by an external capture tool.
+
+use std::io::{self, BufRead};
+
+/// How images are delimited in the stream.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum StreamFormat {
+    /// Each image after its length as four big-endian bytes.
+    Length,
+    /// MIME multipart parts, as `ffmpeg -f mpjpeg` or an MJPEG camera
+    /// stream writes them.
+    Multipart,
+}
+
+/// Images read one after another from `reader`, in any format the image
+/// crate decodes. Ends at the end of the stream.
+pub struct FrameStream<R> {
+    reader: R,
+    format: StreamFormat,
+    /// Delimiter line of a multipart stream, from its first line.
+    boundary: Option<Vec<u8>>,
+    /// The delimiter before the next part has already been read.
+    at_part: bool,
+}
+impl<R: BufRead> FrameStream<R> {
+    pub fn new(reader: R, format: StreamFormat) -> Self {
+        FrameStream {
+            reader,
+            format,
+            boundary: None,
+            at_part: false,
+        }
+    }
+    /// Bytes of the next image; `None` once the stream ends.
+    fn next_frame(&mut self) -> io::Result<Option<Vec<u8>>> {
+        match self.format {
+            StreamFormat::Length => {
+                let mut len = [0; 4];
+                match self.reader.read_exact(&mut len) {
+                    Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+                    result => result?,
+                }
+                let mut frame = vec![0; u32::from_be_bytes(len) as usize];
+                self.reader.read_exact(&mut frame)?;
+                Ok(Some(frame))
+            }
+            StreamFormat::Multipart => self.next_part(),
+        }
+    }
+    fn read_line(&mut self) -> io::Result<Option<Vec<u8>>> {
+        let mut line = Vec::new();
+        if self.reader.read_until(b'\n', &mut line)? == 0 {
+            return Ok(None);
+        }
+        Ok(Some(line))
+    }
+    fn next_part(&mut self) -> io::Result<Option<Vec<u8>>> {
+        while !self.at_part {
+            let Some(line) = self.read_line()? else {
+                return Ok(None);
+            };
+            let line = line.trim_ascii_end();
+            if !line.starts_with(b"--") {
+                continue;
+            }
+            let boundary = self.boundary.get_or_insert_with(|| line.to_vec());
+            if line.strip_suffix(b"--") == Some(&boundary[..]) {
+                return Ok(None);
+            }
+            self.at_part = line == &boundary[..];
+        }
+        self.at_part = false;
+        let mut content_length = None;
+        loop {
+            let Some(line) = self.read_line()? else {
+                return Ok(None);
+            };
+            let line = String::from_utf8_lossy(&line);
+            let Some((name, value)) = line.trim().split_once(':') else {
+                break;
+            };
+            if name.trim().eq_ignore_ascii_case("content-length") {
+                content_length = value.trim().parse::<usize>().ok();
+            }
+        }
+        if let Some(len) = content_length {
+            let mut frame = vec![0; len];
+            self.reader.read_exact(&mut frame)?;
+            return Ok(Some(frame));
+        }
+        // Without a length the part runs up to the next delimiter line.
+        let boundary = self.boundary.clone().unwrap_or_default();
+        let mut frame = Vec::new();
+        while let Some(line) = self.read_line()? {
+            let trimmed = line.trim_ascii_end();
+            if trimmed == &boundary[..] || trimmed.strip_suffix(b"--") == Some(&boundary[..]) {
+                self.at_part = trimmed == &boundary[..];
+                break;
+            }
+            frame.extend_from_slice(&line);
+        }
+        // The line break before the delimiter belongs to it.
+        if frame.ends_with(b"\r\n") {
+            frame.truncate(frame.len() - 2);
+        } else if frame.ends_with(b"\n") {
+            frame.pop();
+        }
+        Ok(Some(frame))
+    }
+}
+impl<R: BufRead> Iterator for FrameStream<R> {
+    type Item = image::DynamicImage;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let frame = match self.next_frame() {
+                Ok(Some(frame)) => frame,
+                Ok(None) => return None,
+                Err(e) => {
+                    log::error!("frame stream ended: {}", e);
+                    return None;
+                }
+            };
+            match image::load_from_memory(&frame) {
+                Ok(img) => return Some(img),
+                Err(e) => log::warn!("skipping unreadable streamed image: {}", e),
+            }
+        }
+    }
+}
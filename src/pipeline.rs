@@ -0,0 +1,181 @@
+//! Staged decoding of a capture. Images are loaded on the calling thread,
+//! scanned for codes on a second and the frames found verified and stored
+//! on a third; bounded channels join the stages, so disk reads, code
+//! detection and hashing overlap instead of taking turns.
+//!
+//! Verifying and storing share the decoder's state, so they stay together.
+//!
+//! A live source gets a stage of its own instead, [`LiveQueue`], which
+//! keeps grabbing at the source's pace and drops what decoding cannot
+//! take rather than queueing it.
+
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::mpsc::{sync_channel, Receiver, TrySendError};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::thread;
+use std::time::Instant;
+
+use crate::decoder::{QrSendDecoder, Scanner};
+use crate::protocol::QrSendMetadata;
+
+/// Feed every image of `images`, in order, through the stages until the
+/// transfer completes, the budget runs out or the images do. At most
+/// `depth` images wait between two stages. Returns `images`, which may
+/// have been read further than the decoder got.
+pub fn run<I>(decoder: &mut QrSendDecoder, mut images: I, depth: usize) -> I
+where
+    I: Iterator<Item = image::DynamicImage>,
+{
+    let readers = std::mem::take(&mut decoder.readers);
+    // Published by the storing stage once it arrives. Images already
+    // scanned by then were decoded without it, which costs at most the
+    // extra channels of a few color images.
+    let metadata = OnceLock::<QrSendMetadata>::new();
+    if let Some(md) = &decoder.metadata {
+        let _ = metadata.set(md.clone());
+    }
+    let (perspective, exposure_retry, pad_retry, scale_retry, rotation_retry) = (
+        decoder.perspective,
+        decoder.exposure_retry,
+        decoder.pad_retry,
+        decoder.scale_retry,
+        decoder.rotation_retry,
+    );
+    thread::scope(|scope| {
+        let (loaded_tx, loaded_rx) = sync_channel(depth);
+        let (scanned_tx, scanned_rx) = sync_channel(depth);
+        let (readers, metadata) = (&readers, &metadata);
+        scope.spawn(move || {
+            for img in loaded_rx {
+                let foreign = AtomicU64::new(0);
+                let scanner = Scanner {
+                    readers,
+                    metadata: metadata.get(),
+                    perspective,
+                    exposure_retry,
+                    pad_retry,
+                    scale_retry,
+                    rotation_retry,
+                    foreign: &foreign,
+                };
+                let frames = scanner.frames(&img);
+                if scanned_tx.send((frames, foreign.into_inner())).is_err() {
+                    break;
+                }
+            }
+        });
+        let decoder = &mut *decoder;
+        // Dropping the receiver on the way out stops the stages upstream.
+        scope.spawn(move || {
+            for (frames, foreign) in scanned_rx {
+                decoder.push_scanned(frames, foreign);
+                if let (Some(md), None) = (&decoder.metadata, metadata.get()) {
+                    let _ = metadata.set(md.clone());
+                }
+                if decoder.is_complete() || decoder.out_of_budget() {
+                    break;
+                }
+            }
+        });
+        for img in images.by_ref() {
+            if loaded_tx.send(img).is_err() {
+                break;
+            }
+        }
+    });
+    decoder.readers = readers;
+    images
+}
+
+/// Widest [`LiveQueue`] thinning goes, keeping one grabbed frame in this many.
+const MAX_STRIDE: u64 = 64;
+
+/// Frames a [`LiveQueue`] dropped, by the second of the capture they were
+/// grabbed in.
+#[derive(Clone, Default)]
+pub struct Dropped(Arc<Mutex<Vec<u64>>>);
+impl Dropped {
+    fn count(&self, second: usize) {
+        let mut per_sec = self.0.lock().unwrap();
+        if per_sec.len() <= second {
+            per_sec.resize(second + 1, 0);
+        }
+        per_sec[second] += 1;
+    }
+    pub fn per_sec(&self) -> Vec<u64> {
+        self.0.lock().unwrap().clone()
+    }
+}
+
+/// Frames of a live source, grabbed on a thread of their own so grabbing
+/// keeps the source's pace while decoding falls behind.
+///
+/// At most `depth` frames wait. When a frame finds the queue full it is
+/// dropped and from then on only every second grabbed frame is kept, then
+/// every fourth and so on; each kept frame that finds the queue empty
+/// halves the stride again. Which frames go depends only on the order
+/// they were grabbed in and how full the queue was, never on a clock.
+pub struct LiveQueue {
+    frames: Receiver<image::DynamicImage>,
+    queued: Arc<AtomicUsize>,
+    dropped: Dropped,
+}
+impl LiveQueue {
+    pub fn new<I>(source: I, depth: usize) -> Self
+    where
+        I: Iterator<Item = image::DynamicImage> + Send + 'static,
+    {
+        let (tx, frames) = sync_channel(depth.max(1));
+        let queued = Arc::new(AtomicUsize::new(0));
+        let dropped = Dropped::default();
+        let (waiting, counts) = (queued.clone(), dropped.clone());
+        // Not joined: a source blocked on its next frame would hold up the
+        // receiver, which is done with the capture.
+        thread::spawn(move || {
+            let start = Instant::now();
+            let mut stride = 1;
+            for (index, img) in (0u64..).zip(source) {
+                let second = start.elapsed().as_secs() as usize;
+                if index % stride != 0 {
+                    counts.count(second);
+                    continue;
+                }
+                let idle = waiting.fetch_add(1, Ordering::SeqCst) == 0;
+                match tx.try_send(img) {
+                    Ok(()) if idle && stride > 1 => {
+                        stride /= 2;
+                        log::debug!("decoding caught up, keeping 1 in {} frames", stride);
+                    }
+                    Ok(()) => {}
+                    Err(TrySendError::Full(_)) => {
+                        waiting.fetch_sub(1, Ordering::SeqCst);
+                        counts.count(second);
+                        if stride < MAX_STRIDE {
+                            stride *= 2;
+                            log::debug!("decoding behind, keeping 1 in {} frames", stride);
+                        }
+                    }
+                    Err(TrySendError::Disconnected(_)) => break,
+                }
+            }
+        });
+        LiveQueue {
+            frames,
+            queued,
+            dropped,
+        }
+    }
+    /// Drop counts, still growing while the capture runs.
+    pub fn dropped(&self) -> Dropped {
+        self.dropped.clone()
+    }
+}
+impl Iterator for LiveQueue {
+    type Item = image::DynamicImage;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let img = self.frames.recv().ok()?;
+        self.queued.fetch_sub(1, Ordering::SeqCst);
+        Some(img)
+    }
+}
@@ -0,0 +1,31 @@
+//! Buffers frames are decoded into, handed back once the decoder is done
+//! with a frame, so a long transfer, whose frames are mostly copies of
+//! segments already held, reuses a few buffers rather than allocating one
+//! per code read.
+
+use std::sync::Mutex;
+
+/// Spare buffers kept; beyond that, returned buffers are freed.
+const MAX_SPARE: usize = 64;
+/// Buffers grown past this are freed rather than kept, so one oversized
+/// code does not pin its memory for the rest of the run.
+const MAX_CAPACITY: usize = 8 * 1024;
+
+static SPARE: Mutex<Vec<Vec<u8>>> = Mutex::new(Vec::new());
+
+/// An empty buffer, one handed back earlier when there is one.
+pub fn take() -> Vec<u8> {
+    SPARE.lock().unwrap().pop().unwrap_or_default()
+}
+
+/// Hand back a buffer whose content is no longer needed.
+pub fn give(mut buf: Vec<u8>) {
+    if buf.capacity() == 0 || buf.capacity() > MAX_CAPACITY {
+        return;
+    }
+    buf.clear();
+    let mut spare = SPARE.lock().unwrap();
+    if spare.len() < MAX_SPARE {
+        spare.push(buf);
+    }
+}
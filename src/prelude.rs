@@ -0,0 +1,20 @@
+//! The stable surface of the crate, covered by semver.
+//!
+//! ```no_run
+//! use qr_recv::prelude::*;
+//!
+//! let mut decoder = Decoder::new();
+//! for path in ["frame_000000.png", "frame_000001.png"] {
+//!     let progress = decoder.push_image(&image::open(path).unwrap());
+//!     if progress.complete {
+//!         break;
+//!     }
+//! }
+//! let file = decoder.finish().unwrap();
+//! ```
+
+pub use crate::api::{Decoder, Error, FrameSource, Progress};
+#[cfg(not(target_arch = "wasm32"))]
+pub use crate::asynchronous::{AsyncDecoder, ProgressWatch};
+pub use crate::protocol::Manifest;
+pub use crate::ranges::SegmentRanges;
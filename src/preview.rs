@@ -0,0 +1,175 @@
+//! Live preview for aiming the camera: the latest captured frame with the
+//! qrcodes found in it outlined, over a map of which segments arrived.
+//!
+//! The picture is written to a PNG that an image viewer keeps reloading,
+//! as screen grabs and clipboard reads go through external tools.
+
+use std::path;
+use std::process::{Child, Command};
+use std::time::{Duration, Instant};
+
+use image::{DynamicImage, Rgb, RgbImage};
+
+use crate::decoder::{scan, QrSendDecoder};
+
+/// Widest the frame is shown; larger captures are scaled down.
+const MAX_WIDTH: u32 = 960;
+/// Height the segment map aims for under the frame.
+const MAP_HEIGHT: u32 = 96;
+/// Preview updates are rendered at most this often.
+const INTERVAL: Duration = Duration::from_millis(200);
+
+const OUTLINE: Rgb<u8> = Rgb([0, 220, 60]);
+const RECEIVED: Rgb<u8> = Rgb([40, 180, 80]);
+const MISSING: Rgb<u8> = Rgb([70, 70, 70]);
+const UNKNOWN: Rgb<u8> = Rgb([30, 30, 30]);
+
+pub fn default_command() -> Vec<String> {
+    ["feh", "--reload", "0.2", "--title", "qr-recv preview"]
+        .iter()
+        .map(|s| s.to_string())
+        .collect()
+}
+
+pub struct Preview {
+    /// Viewer program, run with the path of the picture appended.
+    command: Vec<String>,
+    path: path::PathBuf,
+    viewer: Option<Child>,
+    next_at: Instant,
+    /// Set once the viewer could not be started, which ends the preview.
+    failed: bool,
+}
+impl Preview {
+    pub fn new(command: Vec<String>) -> Self {
+        Preview {
+            command,
+            path: std::env::temp_dir().join(format!("qr-recv-preview-{}.png", std::process::id())),
+            viewer: None,
+            next_at: Instant::now(),
+            failed: false,
+        }
+    }
+    /// Show `img`, the frame `decoder` was just fed, unless the last
+    /// update is too recent.
+    pub fn update(&mut self, decoder: &QrSendDecoder, img: &DynamicImage) {
+        let now = Instant::now();
+        if self.failed || now < self.next_at {
+            return;
+        }
+        self.next_at = now + INTERVAL;
+        // Renamed into place so the viewer never loads a half-written file.
+        let partial = self.path.with_extension("partial.png");
+        let saved = render(decoder, img)
+            .save(&partial)
+            .map_err(|e| e.to_string())
+            .and_then(|_| std::fs::rename(&partial, &self.path).map_err(|e| e.to_string()));
+        if let Err(e) = saved {
+            log::warn!("could not write the preview: {}", e);
+            return;
+        }
+        if self.viewer.is_none() {
+            match Command::new(&self.command[0])
+                .args(&self.command[1..])
+                .arg(&self.path)
+                .spawn()
+            {
+                Ok(child) => self.viewer = Some(child),
+                Err(e) => {
+                    log::error!("cannot run {} to show the preview: {}", self.command[0], e);
+                    self.failed = true;
+                }
+            }
+        }
+    }
+}
+impl Drop for Preview {
+    fn drop(&mut self) {
+        if let Some(mut viewer) = self.viewer.take() {
+            let _ = viewer.kill();
+            let _ = viewer.wait();
+        }
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// The frame, scaled to fit, with its qrcodes outlined and the segment map below.
+fn render(decoder: &QrSendDecoder, img: &DynamicImage) -> RgbImage {
+    let scale = (MAX_WIDTH as f64 / img.width() as f64).min(1.0);
+    let (w, h) = (
+        ((img.width() as f64 * scale) as u32).max(1),
+        ((img.height() as f64 * scale) as u32).max(1),
+    );
+    let frame = img
+        .resize_exact(w, h, image::imageops::FilterType::Triangle)
+        .into_rgb8();
+    let count = decoder.metadata.as_ref().map_or(0, |md| md.qrcode_count);
+    // Square cells, as large as fit the map's height.
+    let cell = if count == 0 {
+        MAP_HEIGHT
+    } else {
+        ((w as f64 * MAP_HEIGHT as f64 / count as f64).sqrt() as u32).clamp(1, MAP_HEIGHT.min(w))
+    };
+    let columns = (w / cell).max(1) as u64;
+    let rows = count.div_ceil(columns).max(1) as u32;
+    let mut out = RgbImage::from_pixel(w, h + rows * cell, UNKNOWN);
+    image::imageops::replace(&mut out, &frame, 0, 0);
+
+    for symbol in scan(img) {
+        let corners: Vec<(i64, i64)> = symbol
+            .points
+            .iter()
+            .map(|&(x, y)| ((x as f64 * scale) as i64, (y as f64 * scale) as i64))
+            .collect();
+        for (i, &from) in corners.iter().enumerate() {
+            line(&mut out, from, corners[(i + 1) % corners.len()], h);
+        }
+    }
+
+    for id in 0..count {
+        let color = if decoder.data_segments.contains(id) {
+            RECEIVED
+        } else {
+            MISSING
+        };
+        let (x, y) = (
+            (id % columns) as u32 * cell,
+            h + (id / columns) as u32 * cell,
+        );
+        // A one pixel gap keeps neighbouring cells apart once they are large enough.
+        let side = if cell > 3 { cell - 1 } else { cell };
+        for dy in 0..side {
+            for dx in 0..side {
+                out.put_pixel(x + dx, y + dy, color);
+            }
+        }
+    }
+    out
+}
+
+/// Draw a line from `a` to `b`, clipped to the top `height` rows of `img`.
+fn line(img: &mut RgbImage, a: (i64, i64), b: (i64, i64), height: u32) {
+    let (dx, dy) = ((b.0 - a.0).abs(), -(b.1 - a.1).abs());
+    let (sx, sy) = ((b.0 - a.0).signum(), (b.1 - a.1).signum());
+    let (mut x, mut y, mut err) = (a.0, a.1, dx + dy);
+    loop {
+        // Two pixels wide so the outline stays visible when scaled down.
+        for (px, py) in [(x, y), (x + 1, y), (x, y + 1)] {
+            if (0..img.width() as i64).contains(&px) && (0..height as i64).contains(&py) {
+                img.put_pixel(px as u32, py as u32, OUTLINE);
+            }
+        }
+        if (x, y) == b {
+            return;
+        }
+        let e2 = 2 * err;
+        if e2 >= dy {
+            err += dy;
+            x += sx;
+        }
+        if e2 <= dx {
+            err += dx;
+            y += sy;
+        }
+    }
+}
@@ -0,0 +1,1011 @@
+use base64::prelude::*;
+use blake2::digest::{Update, VariableOutput};
+use blake2::Blake2bVar;
+use serde::{Deserialize, Serialize};
+
+use crate::blake3::Blake3;
+use crate::sha256::Sha256;
+use crate::{base32, base45, cbor, crc32c, pool};
+
+/// First body byte of a CBOR metadata chunk. It never occurs in UTF-8, so
+/// it cannot be mistaken for the start of a JSON chunk.
+pub const METADATA_CBOR: u8 = 0xc0;
+/// First body byte of an indexed metadata chunk, followed by the chunk index
+/// and count and then a piece of the JSON, or `METADATA_CBOR` and CBOR,
+/// encoding. Indexed chunks may arrive in any order and repeat. Like
+/// `METADATA_CBOR` it never occurs in UTF-8.
+pub const METADATA_INDEXED: u8 = 0xc1;
+/// First body byte of a checked metadata chunk: as `METADATA_INDEXED`, with
+/// the length of the whole encoding and its CRC-32C, each a big-endian u32,
+/// between the count and the piece, so a receiver tells a set of chunks
+/// that is short or mixed from one that is merely malformed. It never
+/// occurs in UTF-8 either, so no piece of sequential JSON starts with it,
+/// whatever character the split falls before.
+pub const METADATA_CHECKED: u8 = 0xf5;
+/// Bytes of a checked chunk between the count and the piece.
+pub const METADATA_CHECK_LEN: usize = 8;
+
+/// Encoding of the metadata carried by `M` frames.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum MetadataFormat {
+    Json,
+    /// Usually fits in a single frame.
+    Cbor,
+}
+
+/// URL-safe base64 that takes input with or without padding.
+const BASE64_URL_LENIENT: base64::engine::GeneralPurpose = base64::engine::GeneralPurpose::new(
+    &base64::alphabet::URL_SAFE,
+    base64::engine::GeneralPurposeConfig::new()
+        .with_encode_padding(false)
+        .with_decode_padding_mode(base64::engine::DecodePaddingMode::Indifferent),
+);
+
+/// How frame bytes are turned into the text of a qrcode.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum PayloadEncoding {
+    #[default]
+    Base64,
+    /// Base64 with `-` and `_`, padded or not, as web senders tend to emit.
+    #[value(name = "base64url")]
+    Base64Url,
+    /// Fits QR alphanumeric mode, about 10% smaller than base64 in byte mode.
+    Base45,
+    /// Unpadded RFC 4648 base32, also alphanumeric but larger than base45;
+    /// for senders with a base32 encoder at hand.
+    Base32,
+    /// Frame bytes straight in byte mode; needs a scanner that returns them unconverted.
+    Raw,
+}
+impl PayloadEncoding {
+    pub const ALL: [PayloadEncoding; 5] = [
+        PayloadEncoding::Base64,
+        PayloadEncoding::Base64Url,
+        PayloadEncoding::Base45,
+        PayloadEncoding::Base32,
+        PayloadEncoding::Raw,
+    ];
+    pub fn encode(self, frame: &[u8]) -> Vec<u8> {
+        match self {
+            PayloadEncoding::Base64 => BASE64_STANDARD.encode(frame).into_bytes(),
+            PayloadEncoding::Base64Url => BASE64_URL_SAFE_NO_PAD.encode(frame).into_bytes(),
+            PayloadEncoding::Base45 => base45::encode(frame),
+            PayloadEncoding::Base32 => base32::encode(frame),
+            PayloadEncoding::Raw => frame.to_vec(),
+        }
+    }
+    /// The frame `text` carries, decoded into a buffer from the [`pool`].
+    pub fn decode(self, text: &[u8]) -> Option<Vec<u8>> {
+        let mut frame = pool::take();
+        let decoded = match self {
+            PayloadEncoding::Base64 => BASE64_STANDARD.decode_vec(text, &mut frame).is_ok(),
+            PayloadEncoding::Base64Url => BASE64_URL_LENIENT.decode_vec(text, &mut frame).is_ok(),
+            PayloadEncoding::Base45 => base45::decode_into(text, &mut frame),
+            PayloadEncoding::Base32 => base32::decode_into(text, &mut frame),
+            PayloadEncoding::Raw => {
+                frame.extend_from_slice(text);
+                true
+            }
+        };
+        if decoded && !frame.is_empty() {
+            return Some(frame);
+        }
+        pool::give(frame);
+        None
+    }
+}
+
+/// Byte order of fixed-width data frame ids.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Endianness {
+    #[default]
+    Big,
+    Little,
+}
+impl Endianness {
+    fn is_big(&self) -> bool {
+        *self == Endianness::Big
+    }
+}
+
+/// Kind of 2D code frames are drawn as.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Symbology {
+    #[default]
+    Qr,
+    #[value(name = "datamatrix")]
+    DataMatrix,
+    Aztec,
+}
+impl Symbology {
+    fn is_qr(&self) -> bool {
+        *self == Symbology::Qr
+    }
+}
+
+/// Hash of the whole file carried by the `H` frame.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum FileHash {
+    /// What senders used before the choice was declared.
+    #[default]
+    Md5,
+    Sha256,
+    Blake3,
+}
+impl FileHash {
+    fn is_md5(&self) -> bool {
+        *self == FileHash::Md5
+    }
+    pub fn name(self) -> &'static str {
+        match self {
+            FileHash::Md5 => "md5",
+            FileHash::Sha256 => "sha256",
+            FileHash::Blake3 => "blake3",
+        }
+    }
+    pub fn hasher(self) -> FileHasher {
+        match self {
+            FileHash::Md5 => FileHasher::Md5(md5::Context::new()),
+            FileHash::Sha256 => FileHasher::Sha256(Sha256::new()),
+            FileHash::Blake3 => FileHasher::Blake3(Blake3::new()),
+        }
+    }
+    pub fn digest(self, data: &[u8]) -> Vec<u8> {
+        let mut hasher = self.hasher();
+        hasher.update(data);
+        hasher.finalize()
+    }
+}
+
+/// Incremental [`FileHash`], fed the file in order.
+pub enum FileHasher {
+    Md5(md5::Context),
+    Sha256(Sha256),
+    Blake3(Blake3),
+}
+impl FileHasher {
+    pub fn update(&mut self, data: &[u8]) {
+        match self {
+            FileHasher::Md5(ctx) => ctx.consume(data),
+            FileHasher::Sha256(hasher) => hasher.update(data),
+            FileHasher::Blake3(hasher) => hasher.update(data),
+        }
+    }
+    pub fn finalize(self) -> Vec<u8> {
+        match self {
+            FileHasher::Md5(ctx) => ctx.compute().0.to_vec(),
+            FileHasher::Sha256(hasher) => hasher.finalize().to_vec(),
+            FileHasher::Blake3(hasher) => hasher.finalize().to_vec(),
+        }
+    }
+}
+impl std::io::Write for FileHasher {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.update(buf);
+        Ok(buf.len())
+    }
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Id type for LEB128 ids: one byte below 128 segments, up to ten for the
+/// largest transfers.
+pub const VARINT_ID: &str = "varint";
+
+/// Every id type a sender may declare.
+pub const ID_TYPES: [&str; 5] = ["u8", "u16", "u32", "u64", VARINT_ID];
+
+/// Prefix of the text of every qrcode when the metadata declares `magic`,
+/// ending in the protocol major version. It is in the QR alphanumeric set,
+/// and no base64 text contains `:`.
+pub const MAGIC: &[u8] = b"QS1:";
+
+/// Text of the qrcode carrying `frame`, after [`MAGIC`] when `magic` is set.
+pub fn payload_text(frame: &[u8], encoding: PayloadEncoding, magic: bool) -> Vec<u8> {
+    let mut text = if magic { MAGIC.to_vec() } else { Vec::new() };
+    text.extend(encoding.encode(frame));
+    text
+}
+
+/// Recover frame bytes from the text of a qrcode, whatever its encoding.
+///
+/// A leading [`MAGIC`] is stripped. Once the metadata declares it, text
+/// without it is foreign and gives `None` without being decoded.
+///
+/// Once the metadata is known its encoding is tried first and its hash
+/// length checked directly; before that every encoding is tried and hash
+/// lengths are guessed. The first result that looks like a sealed frame
+/// wins; when none does, the first decode with a registered type or a seal
+/// is returned so the caller can still count it as a bad frame. Text that
+/// is no frame in any encoding, such as a Wi-Fi qrcode on a poster behind
+/// the screen, gives `None`.
+pub fn decode_payload(
+    text: &[u8],
+    md: Option<&QrSendMetadata>,
+) -> Option<(PayloadEncoding, Vec<u8>)> {
+    if let Some(marked) = text.strip_prefix(MAGIC) {
+        if let Some(found) = decode_unmarked(marked, md) {
+            return Some(found);
+        }
+    }
+    if md.is_some_and(|md| md.magic) {
+        return None;
+    }
+    decode_unmarked(text, md)
+}
+
+fn decode_unmarked(text: &[u8], md: Option<&QrSendMetadata>) -> Option<(PayloadEncoding, Vec<u8>)> {
+    let preferred = md.map(|md| md.payload_encoding);
+    let sealed = |frame: &[u8]| match md {
+        Some(md) if md.crc32c && FrameType::of(frame) == Some(FrameType::Data) => {
+            crc32c::strip(frame).is_some()
+        }
+        Some(md) => verify_hash(frame, md.hash_len as usize),
+        None => guess_hash_len(frame).is_some(),
+    };
+    let order = preferred.into_iter().chain(
+        PayloadEncoding::ALL
+            .into_iter()
+            .filter(|&e| Some(e) != preferred),
+    );
+    let mut fallback = None;
+    for encoding in order {
+        let Some(frame) = encoding.decode(text) else {
+            continue;
+        };
+        let (registered, sealed) = (FrameType::of(&frame).is_some(), sealed(&frame));
+        if registered && sealed {
+            if let Some((_, unused)) = fallback {
+                pool::give(unused);
+            }
+            return Some((encoding, frame));
+        }
+        if (registered || sealed) && fallback.is_none() {
+            fallback = Some((encoding, frame));
+        } else {
+            pool::give(frame);
+        }
+    }
+    fallback
+}
+
+/// Type of a frame, given by its first byte.
+///
+/// Every tag a sender may use is registered here. Types the receiver has
+/// no use for yet still verify and are counted, then skipped; frames whose
+/// tag is not registered at all are counted as unknown.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum FrameType {
+    Metadata,
+    Data,
+    Hash,
+    /// The sender's display slot counter.
+    Sync,
+    /// Reserved for forward error correction over data segments.
+    Parity,
+    /// Reserved for a sender signature over the transfer; `S` is taken by sync.
+    Signature,
+    /// Reserved for missing segment lists sent back to the sender.
+    Nack,
+    /// Who sent the transfer, as a [`Manifest`]; `M` is taken by metadata.
+    Manifest,
+}
+impl FrameType {
+    pub const ALL: [FrameType; 8] = [
+        FrameType::Metadata,
+        FrameType::Data,
+        FrameType::Hash,
+        FrameType::Sync,
+        FrameType::Parity,
+        FrameType::Signature,
+        FrameType::Nack,
+        FrameType::Manifest,
+    ];
+    pub const fn tag(self) -> u8 {
+        match self {
+            FrameType::Metadata => b'M',
+            FrameType::Data => b'D',
+            FrameType::Hash => b'H',
+            FrameType::Sync => b'S',
+            FrameType::Parity => b'P',
+            FrameType::Signature => b'G',
+            FrameType::Nack => b'N',
+            FrameType::Manifest => b'I',
+        }
+    }
+    pub fn from_tag(tag: u8) -> Option<Self> {
+        Self::ALL.into_iter().find(|t| t.tag() == tag)
+    }
+    /// Type of `frame`, `None` for an empty frame or an unregistered tag.
+    pub fn of(frame: &[u8]) -> Option<Self> {
+        Self::from_tag(*frame.first()?)
+    }
+    pub fn name(self) -> &'static str {
+        match self {
+            FrameType::Metadata => "metadata",
+            FrameType::Data => "data",
+            FrameType::Hash => "hash",
+            FrameType::Sync => "sync",
+            FrameType::Parity => "parity",
+            FrameType::Signature => "signature",
+            FrameType::Nack => "nack",
+            FrameType::Manifest => "manifest",
+        }
+    }
+    /// Layout of the frame between its tag and its seal, for senders.
+    pub fn layout(self) -> &'static str {
+        match self {
+            FrameType::Metadata => {
+                "the metadata as JSON, or 0xc0 and CBOR, split across frames sent \
+                 in order; or 0xc1, the chunk index (u8), the chunk count (u8) and \
+                 a piece of either, in any order; or 0xf5 and as 0xc1, with the \
+                 length and CRC-32C of the whole (u32 big-endian each) before the \
+                 piece"
+            }
+            FrameType::Data => {
+                "the segment id as `id_type` in `endianness`, then the content; with \
+                 `crc32c` a CRC-32C of the sealed frame (u32 little-endian) follows \
+                 the seal"
+            }
+            FrameType::Hash => "the `file_hash` digest of the whole file",
+            FrameType::Sync => "the sender's display slot counter (u64 big-endian)",
+            FrameType::Manifest => {
+                "a JSON object: `transfer_id`, a UUID, and optionally `hostname`, \
+                 `created` (seconds since the Unix epoch) and `comment`; sent after \
+                 the metadata"
+            }
+            FrameType::Parity | FrameType::Signature | FrameType::Nack => {
+                "reserved; verified, counted and skipped"
+            }
+        }
+    }
+    /// Whether this receiver acts on frames of the type, rather than
+    /// counting and skipping them.
+    pub fn is_handled(self) -> bool {
+        matches!(
+            self,
+            FrameType::Metadata
+                | FrameType::Data
+                | FrameType::Hash
+                | FrameType::Sync
+                | FrameType::Manifest
+        )
+    }
+}
+
+/// Major protocol version this receiver decodes. Minor bumps only add
+/// fields older receivers can ignore; a major bump changes the frame layout.
+pub const PROTOCOL_MAJOR: u64 = 1;
+/// Version written by this sender.
+pub const PROTOCOL_VERSION: &str = "1.0";
+
+/// Senders from before the field existed speak v1.
+fn default_version() -> String {
+    "1.0".to_string()
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct QrSendMetadata {
+    /// `major.minor` protocol version.
+    #[serde(default = "default_version")]
+    pub version: String,
+    pub qrcode_count: u64,
+    pub id_type: String,
+    pub hash_len: u64,
+    #[serde(default)]
+    pub payload_encoding: PayloadEncoding,
+    /// Byte order of fixed-width ids; varint ids are always little-endian.
+    /// Left out when big-endian, so default metadata reads as it always did.
+    #[serde(default, skip_serializing_if = "Endianness::is_big")]
+    pub endianness: Endianness,
+    /// Length of the whole file in bytes; older senders leave it out.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file_size: Option<u64>,
+    /// Content length of every data segment but the last, which is no longer.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub segment_size: Option<u64>,
+    /// Name of the sent file, without any directory.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file_name: Option<String>,
+    /// Modification time of the sent file, in seconds since the Unix epoch.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mtime: Option<u64>,
+    /// Code every frame is drawn as; left out for qrcodes.
+    #[serde(default, skip_serializing_if = "Symbology::is_qr")]
+    pub symbology: Symbology,
+    /// Data frames are drawn three to an image, one in each of the red,
+    /// green and blue channels. Metadata frames stay gray so that any
+    /// receiver can read them.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub color_channels: bool,
+    /// Data frames end in a CRC-32C of the sealed frame, checked before
+    /// the slower hash.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub crc32c: bool,
+    /// Hash the `H` frame carries; left out for MD5.
+    #[serde(default, skip_serializing_if = "FileHash::is_md5")]
+    pub file_hash: FileHash,
+    /// The text of every qrcode starts with [`MAGIC`], so that once this
+    /// is known any other qrcode in view is skipped undecoded.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub magic: bool,
+}
+
+/// Serialized names of every value of `T`, as metadata spells them.
+pub fn value_names<T: Serialize + clap::ValueEnum>() -> Vec<serde_json::Value> {
+    T::value_variants()
+        .iter()
+        .map(|v| serde_json::to_value(v).unwrap())
+        .collect()
+}
+
+impl QrSendMetadata {
+    /// JSON Schema of the metadata as this receiver reads it. Fields with
+    /// defaults may be left out; unknown fields are ignored.
+    pub fn json_schema() -> serde_json::Value {
+        serde_json::json!({
+            "$schema": "https://json-schema.org/draft/2020-12/schema",
+            "title": "qr-send metadata",
+            "type": "object",
+            "required": ["qrcode_count", "id_type", "hash_len"],
+            "properties": {
+                "version": {
+                    "type": "string",
+                    "pattern": "^[0-9]+\\.[0-9]+$",
+                    "default": "1.0",
+                    "description": format!("major.minor; major {} is decoded", PROTOCOL_MAJOR),
+                },
+                "qrcode_count": {
+                    "type": "integer",
+                    "minimum": 0,
+                    "description": "number of data segments",
+                },
+                "id_type": { "enum": ID_TYPES },
+                "hash_len": {
+                    "type": "integer",
+                    "minimum": 1,
+                    "maximum": MAX_HASH_LEN,
+                    "description": "bytes of blake2b sealing every frame",
+                },
+                "payload_encoding": {
+                    "enum": value_names::<PayloadEncoding>(),
+                    "default": "base64",
+                },
+                "endianness": { "enum": value_names::<Endianness>(), "default": "big" },
+                "file_size": { "type": "integer", "minimum": 0 },
+                "segment_size": {
+                    "type": "integer",
+                    "minimum": 1,
+                    "description": "content length of every data segment but the last",
+                },
+                "file_name": { "type": "string", "description": "a single path component" },
+                "mtime": { "type": "integer", "description": "seconds since the Unix epoch" },
+                "symbology": { "enum": value_names::<Symbology>(), "default": "qr" },
+                "color_channels": { "type": "boolean", "default": false },
+                "crc32c": { "type": "boolean", "default": false },
+                "file_hash": { "enum": value_names::<FileHash>(), "default": "md5" },
+                "magic": {
+                    "type": "boolean",
+                    "default": false,
+                    "description": format!(
+                        "the text of every qrcode starts with {}",
+                        String::from_utf8_lossy(MAGIC)
+                    ),
+                },
+            },
+        })
+    }
+    /// Whether every segment but the last fits the declared file at the
+    /// declared segment size, so segment offsets cannot overflow.
+    pub fn layout_fits(&self) -> bool {
+        let Some(size) = self.segment_size else {
+            return true;
+        };
+        match size.checked_mul(self.qrcode_count.saturating_sub(1)) {
+            Some(before_last) => self.file_size.is_none_or(|len| before_last <= len),
+            None => false,
+        }
+    }
+    pub fn major_version(&self) -> Option<u64> {
+        self.version.split('.').next()?.parse().ok()
+    }
+    pub fn is_supported(&self) -> bool {
+        self.major_version() == Some(PROTOCOL_MAJOR)
+    }
+    /// The declared file name when it is safe to create in the current
+    /// directory: a single path component that is not `.` or `..`.
+    pub fn safe_file_name(&self) -> Option<&str> {
+        let name = self.file_name.as_deref()?;
+        let mut components = std::path::Path::new(name).components();
+        match (components.next(), components.next()) {
+            (Some(std::path::Component::Normal(c)), None) if c == name => Some(name),
+            _ => None,
+        }
+    }
+}
+
+/// Who sent a transfer and when, carried by the optional `I` frame so a
+/// receiver juggling many transfers can tell their captures apart.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Manifest {
+    /// UUID the sender drew for the transfer.
+    pub transfer_id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hostname: Option<String>,
+    /// When the transfer was prepared, in seconds since the Unix epoch.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub comment: Option<String>,
+}
+impl Manifest {
+    /// The transfer id when it is safe in a file name: letters, digits and
+    /// dashes, at most 64 of them.
+    pub fn file_key(&self) -> Option<&str> {
+        let id = self.transfer_id.as_str();
+        let safe = !id.is_empty()
+            && id.len() <= 64
+            && id.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-');
+        safe.then_some(id)
+    }
+}
+
+/// Why a received frame could not be parsed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameError {
+    /// The frame is shorter than its id and hash need.
+    Truncated,
+    /// The metadata names an id type this receiver does not know.
+    UnknownIdType,
+    /// A varint id that does not fit 64 bits.
+    InvalidId,
+    /// Not even a type byte.
+    Empty,
+    /// A type byte no frame type is registered under.
+    UnknownType(u8),
+    /// A data frame, which takes its id layout from metadata not yet known.
+    NoMetadata,
+    /// A segment id at or past the segment count the metadata declares.
+    IdOutOfRange(u64),
+}
+
+/// A frame split into its fields. The slices borrow from the frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Frame<'a> {
+    Metadata(MetadataChunk<'a>),
+    Data {
+        id: u64,
+        content: &'a [u8],
+    },
+    /// The [`FileHash`] digest of the whole file.
+    Hash {
+        digest: &'a [u8],
+    },
+    Sync {
+        counter: u64,
+    },
+    /// A [`Manifest`] as JSON.
+    Manifest {
+        json: &'a [u8],
+    },
+    /// A type this receiver verifies, counts and skips.
+    Reserved {
+        frame_type: FrameType,
+        body: &'a [u8],
+    },
+}
+
+/// The body of an `M` frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MetadataChunk<'a> {
+    /// A piece of the JSON metadata, in send order.
+    Json(&'a [u8]),
+    /// A piece of the CBOR metadata, in send order.
+    Cbor(&'a [u8]),
+    /// Chunk `index` of `count`, in any order, as [`METADATA_INDEXED`];
+    /// `check` holds the length and CRC-32C of the whole when declared,
+    /// as [`METADATA_CHECKED`].
+    Indexed {
+        index: u8,
+        count: u8,
+        check: Option<(usize, u32)>,
+        piece: &'a [u8],
+    },
+}
+impl<'a> MetadataChunk<'a> {
+    pub fn parse(body: &'a [u8]) -> Result<Self, FrameError> {
+        let (checked, rest) = match body.split_first() {
+            Some((&METADATA_INDEXED, rest)) => (false, rest),
+            Some((&METADATA_CHECKED, rest)) => (true, rest),
+            Some((&METADATA_CBOR, piece)) => return Ok(MetadataChunk::Cbor(piece)),
+            _ => return Ok(MetadataChunk::Json(body)),
+        };
+        let [index, count, rest @ ..] = rest else {
+            return Err(FrameError::Truncated);
+        };
+        let (check, piece) = if checked {
+            let (check, piece) = rest
+                .split_first_chunk::<METADATA_CHECK_LEN>()
+                .ok_or(FrameError::Truncated)?;
+            let (len, crc) = check.split_at(4);
+            let len = u32::from_be_bytes(len.try_into().unwrap());
+            let crc = u32::from_be_bytes(crc.try_into().unwrap());
+            (Some((len as usize, crc)), piece)
+        } else {
+            (None, rest)
+        };
+        Ok(MetadataChunk::Indexed {
+            index: *index,
+            count: *count,
+            check,
+            piece,
+        })
+    }
+}
+
+impl<'a> Frame<'a> {
+    /// Split `frame`, its type byte, body and a seal of `hash_len` bytes,
+    /// with any CRC-32C already stripped. Data frames take their id layout
+    /// from `md`. The seal is not checked, see [`verify_hash`].
+    ///
+    /// Returns an error, never panics, whatever the bytes.
+    pub fn parse(
+        frame: &'a [u8],
+        hash_len: usize,
+        md: Option<&QrSendMetadata>,
+    ) -> Result<Self, FrameError> {
+        let (&tag, rest) = frame.split_first().ok_or(FrameError::Empty)?;
+        let frame_type = FrameType::from_tag(tag).ok_or(FrameError::UnknownType(tag))?;
+        let body = rest
+            .len()
+            .checked_sub(hash_len)
+            .and_then(|end| rest.get(..end))
+            .ok_or(FrameError::Truncated)?;
+        Ok(match frame_type {
+            FrameType::Metadata => Frame::Metadata(MetadataChunk::parse(body)?),
+            FrameType::Data => {
+                let (id, content) = data_fields(body, md.ok_or(FrameError::NoMetadata)?)?;
+                Frame::Data { id, content }
+            }
+            FrameType::Hash => Frame::Hash { digest: body },
+            FrameType::Sync => {
+                let (counter, _) = body.split_first_chunk::<8>().ok_or(FrameError::Truncated)?;
+                Frame::Sync {
+                    counter: u64::from_be_bytes(*counter),
+                }
+            }
+            FrameType::Manifest => Frame::Manifest { json: body },
+            FrameType::Parity | FrameType::Signature | FrameType::Nack => {
+                Frame::Reserved { frame_type, body }
+            }
+        })
+    }
+}
+
+/// The id and content of a data frame body, without its seal.
+fn data_fields<'a>(body: &'a [u8], md: &QrSendMetadata) -> Result<(u64, &'a [u8]), FrameError> {
+    let (id, id_len) = get_id_and_len(body, md)?;
+    if id >= md.qrcode_count {
+        return Err(FrameError::IdOutOfRange(id));
+    }
+    Ok((id, body.get(id_len..).ok_or(FrameError::Truncated)?))
+}
+
+/// Width in bytes of a fixed-width `id_type`, `None` for varint and unknown types.
+pub fn try_id_len(id_type: &str) -> Option<usize> {
+    match id_type {
+        "u64" => Some(8),
+        "u32" => Some(4),
+        "u16" => Some(2),
+        "u8" => Some(1),
+        _ => None,
+    }
+}
+
+pub fn id_len(id_type: &str) -> usize {
+    try_id_len(id_type).expect("Invalid id type")
+}
+
+pub fn is_known_id_type(id_type: &str) -> bool {
+    ID_TYPES.contains(&id_type)
+}
+
+/// Whether `id_type` can number `count` segments, ids 0 to `count - 1`.
+pub fn id_type_holds(id_type: &str, count: u64) -> bool {
+    match try_id_len(id_type) {
+        Some(len) if len < 8 => count <= 1 << (8 * len),
+        _ => true,
+    }
+}
+
+/// LEB128 encoding of `value`.
+pub fn write_varint(mut value: u64, out: &mut Vec<u8>) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+/// Decode a LEB128 value from the start of `data`, with the bytes it took.
+pub fn read_varint(data: &[u8]) -> Result<(u64, usize), FrameError> {
+    let mut value = 0u64;
+    for (i, &byte) in data.iter().enumerate().take(10) {
+        let bits = (byte & 0x7f) as u64;
+        if i == 9 && bits > 1 {
+            return Err(FrameError::InvalidId);
+        }
+        value |= bits << (7 * i);
+        if byte & 0x80 == 0 {
+            return Ok((value, i + 1));
+        }
+    }
+    if data.len() >= 10 {
+        Err(FrameError::InvalidId)
+    } else {
+        Err(FrameError::Truncated)
+    }
+}
+
+/// Id bytes of data frame `id` as `md` declares them.
+pub fn encode_id(id: u64, md: &QrSendMetadata) -> Vec<u8> {
+    if md.id_type == VARINT_ID {
+        let mut out = Vec::new();
+        write_varint(id, &mut out);
+        return out;
+    }
+    let len = id_len(&md.id_type);
+    match md.endianness {
+        Endianness::Big => id.to_be_bytes()[8 - len..].to_vec(),
+        Endianness::Little => id.to_le_bytes()[..len].to_vec(),
+    }
+}
+
+/// Narrowest id type able to number `count` segments.
+pub fn smallest_id_type(count: u64) -> &'static str {
+    if count <= 1 << 8 {
+        "u8"
+    } else if count <= 1 << 16 {
+        "u16"
+    } else if count <= 1 << 32 {
+        "u32"
+    } else {
+        "u64"
+    }
+}
+
+pub fn get_id_and_len(data: &[u8], md: &QrSendMetadata) -> Result<(u64, usize), FrameError> {
+    read_id(data, &md.id_type, md.endianness)
+}
+
+/// Id at the start of `data`, with the bytes it took.
+pub fn read_id(
+    data: &[u8],
+    id_type: &str,
+    endianness: Endianness,
+) -> Result<(u64, usize), FrameError> {
+    if id_type == VARINT_ID {
+        return read_varint(data);
+    }
+    let id_len = try_id_len(id_type).ok_or(FrameError::UnknownIdType)?;
+    let bytes = data.get(..id_len).ok_or(FrameError::Truncated)?;
+    let id = match endianness {
+        Endianness::Big => bytes.iter().fold(0u64, |acc, &b| acc << 8 | b as u64),
+        Endianness::Little => bytes.iter().rev().fold(0u64, |acc, &b| acc << 8 | b as u64),
+    };
+    Ok((id, id_len))
+}
+
+/// A data segment, kept in the buffer its frame was decoded into; the
+/// content and the hash sealing it are ranges of it.
+#[derive(Debug, Clone)]
+pub struct QrSendData {
+    pub id: u64,
+    buf: Vec<u8>,
+    /// Where the content starts and the hash starts in `buf`; the hash
+    /// runs to its end.
+    content: usize,
+    hash: usize,
+}
+impl QrSendData {
+    /// A segment of `data` sealed by `hash`.
+    pub fn new(id: u64, data: &[u8], hash: &[u8]) -> Self {
+        let mut buf = Vec::with_capacity(data.len() + hash.len());
+        buf.extend_from_slice(data);
+        buf.extend_from_slice(hash);
+        QrSendData {
+            id,
+            buf,
+            content: 0,
+            hash: data.len(),
+        }
+    }
+    /// The data frame `frame`, type byte included, sealed as `md` declares.
+    /// The frame is kept, not copied; on error it is handed back.
+    pub fn from_frame(frame: Vec<u8>, md: &QrSendMetadata) -> Result<Self, (FrameError, Vec<u8>)> {
+        let split = |frame: &[u8]| {
+            let hash_len = usize::try_from(md.hash_len).map_err(|_| FrameError::Truncated)?;
+            match Frame::parse(frame, hash_len, Some(md))? {
+                // The content runs up to the hash, which ends the frame.
+                Frame::Data { id, content } => {
+                    let hash = frame.len() - hash_len;
+                    Ok((id, hash - content.len(), hash))
+                }
+                _ => Err(FrameError::UnknownType(frame[0])),
+            }
+        };
+        match split(&frame) {
+            Ok((id, content, hash)) => Ok(QrSendData {
+                id,
+                buf: frame,
+                content,
+                hash,
+            }),
+            Err(e) => Err((e, frame)),
+        }
+    }
+    pub fn data(&self) -> &[u8] {
+        &self.buf[self.content..self.hash]
+    }
+    pub fn hash(&self) -> &[u8] {
+        &self.buf[self.hash..]
+    }
+    /// The buffer, for [`pool::give`] once the segment is dropped.
+    pub fn into_buf(self) -> Vec<u8> {
+        self.buf
+    }
+}
+/// Longest per-frame hash, the longest blake2b digest.
+pub const MAX_HASH_LEN: usize = 64;
+
+pub fn blake2b(data: &[u8], hash_len: usize) -> Vec<u8> {
+    let mut hasher = Blake2bVar::new(hash_len).unwrap();
+    let mut computed = vec![0u8; hash_len];
+    hasher.update(data);
+    hasher.finalize_variable(&mut computed).unwrap();
+    computed
+}
+
+/// Whether the last `hash_len` bytes of `data` are the blake2b of the rest.
+pub fn verify_hash(data: &[u8], hash_len: usize) -> bool {
+    if hash_len == 0 || hash_len > MAX_HASH_LEN || hash_len >= data.len() {
+        return false;
+    }
+    let (content, hash) = data.split_at(data.len() - hash_len);
+    blake2b(content, hash_len) == hash
+}
+
+pub fn guess_hash_len(data: &[u8]) -> Option<usize> {
+    // Longest first: a short length matches by chance far more often (1 in
+    // 256 for one byte) than a longer one does.
+    (1..data.len().min(MAX_HASH_LEN + 1)).rev().find(|&i| {
+        let content = &data[0..data.len() - i];
+        let hash = &data[data.len() - i..];
+        blake2b(content, i) == hash
+    })
+}
+
+/// Append the per-frame hash covering the tag and body.
+fn seal(mut frame: Vec<u8>, hash_len: usize) -> Vec<u8> {
+    let hash = blake2b(&frame, hash_len);
+    frame.extend_from_slice(&hash);
+    frame
+}
+
+/// Split the encoded metadata into `M` frames of at most `chunk_size` bytes.
+pub fn metadata_frames(
+    md: &QrSendMetadata,
+    chunk_size: usize,
+    format: MetadataFormat,
+) -> Vec<Vec<u8>> {
+    let encoded = encode_metadata(md, format);
+    encoded
+        .chunks(chunk_size.max(1))
+        .map(|chunk| {
+            let mut frame = vec![FrameType::Metadata.tag()];
+            if format == MetadataFormat::Cbor {
+                frame.push(METADATA_CBOR);
+            }
+            frame.extend_from_slice(chunk);
+            seal(frame, md.hash_len as usize)
+        })
+        .collect()
+}
+
+pub fn data_frame(id: u64, content: &[u8], md: &QrSendMetadata) -> Vec<u8> {
+    let frame = sealed_data_frame(id, content, md);
+    if md.crc32c {
+        return crc32c::append(frame);
+    }
+    frame
+}
+
+/// [`data_frame`] up to its hash, without the CRC `crc32c` adds.
+pub fn sealed_data_frame(id: u64, content: &[u8], md: &QrSendMetadata) -> Vec<u8> {
+    let mut frame = vec![FrameType::Data.tag()];
+    frame.extend_from_slice(&encode_id(id, md));
+    frame.extend_from_slice(content);
+    seal(frame, md.hash_len as usize)
+}
+
+fn encode_metadata(md: &QrSendMetadata, format: MetadataFormat) -> Vec<u8> {
+    match format {
+        MetadataFormat::Json => serde_json::to_vec(md).unwrap(),
+        MetadataFormat::Cbor => cbor::to_vec(&serde_json::to_value(md).unwrap()),
+    }
+}
+
+/// Split the metadata into indexed `M` frames carrying at most `chunk_size`
+/// bytes of it each; `checked` ones, see [`METADATA_CHECKED`].
+pub fn indexed_metadata_frames(
+    md: &QrSendMetadata,
+    chunk_size: usize,
+    format: MetadataFormat,
+    checked: bool,
+) -> Vec<Vec<u8>> {
+    let mut encoded = encode_metadata(md, format);
+    if format == MetadataFormat::Cbor {
+        encoded.insert(0, METADATA_CBOR);
+    }
+    let mut check = Vec::new();
+    if checked {
+        check.extend_from_slice(&(encoded.len() as u32).to_be_bytes());
+        check.extend_from_slice(&crc32c::checksum(&encoded).to_be_bytes());
+    }
+    let chunks: Vec<&[u8]> = encoded.chunks(chunk_size.max(1)).collect();
+    let count =
+        u8::try_from(chunks.len()).expect("metadata needs over 255 chunks, raise --chunk-size");
+    chunks
+        .iter()
+        .enumerate()
+        .map(|(index, chunk)| {
+            let first = if checked {
+                METADATA_CHECKED
+            } else {
+                METADATA_INDEXED
+            };
+            let mut frame = vec![FrameType::Metadata.tag(), first, index as u8, count];
+            frame.extend_from_slice(&check);
+            frame.extend_from_slice(chunk);
+            seal(frame, md.hash_len as usize)
+        })
+        .collect()
+}
+
+/// Parse reassembled indexed metadata, JSON or `METADATA_CBOR` and CBOR.
+pub fn decode_metadata(encoded: &[u8]) -> Option<QrSendMetadata> {
+    match encoded.split_first() {
+        Some((&METADATA_CBOR, rest)) => serde_json::from_value(cbor::from_slice(rest).ok()?).ok(),
+        _ => serde_json::from_slice(encoded).ok(),
+    }
+}
+
+/// Sync frame carrying the sender's display slot counter.
+pub fn sync_frame(counter: u64, hash_len: usize) -> Vec<u8> {
+    let mut frame = vec![FrameType::Sync.tag()];
+    frame.extend_from_slice(&counter.to_be_bytes());
+    seal(frame, hash_len)
+}
+
+/// The `I` frame carrying `manifest`.
+pub fn manifest_frame(manifest: &Manifest, hash_len: usize) -> Vec<u8> {
+    let mut frame = vec![FrameType::Manifest.tag()];
+    frame.extend_from_slice(&serde_json::to_vec(manifest).expect("manifests serialize"));
+    seal(frame, hash_len)
+}
+
+pub fn sync_counter(frame: &[u8]) -> Option<u64> {
+    Some(u64::from_be_bytes(frame.get(1..9)?.try_into().unwrap()))
+}
+
+/// The `H` frame for a file whose [`FileHash`] is `digest`.
+pub fn hash_frame(digest: &[u8], md: &QrSendMetadata) -> Vec<u8> {
+    let mut frame = vec![FrameType::Hash.tag()];
+    frame.extend_from_slice(digest);
+    seal(frame, md.hash_len as usize)
+}
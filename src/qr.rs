@@ -0,0 +1,559 @@
+use clap::ValueEnum;
+use serde::Serialize;
+
+#[derive(ValueEnum, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EccLevel {
+    L,
+    M,
+    Q,
+    H,
+}
+impl EccLevel {
+    fn ordinal(self) -> usize {
+        match self {
+            EccLevel::L => 0,
+            EccLevel::M => 1,
+            EccLevel::Q => 2,
+            EccLevel::H => 3,
+        }
+    }
+    fn format_bits(self) -> u32 {
+        match self {
+            EccLevel::L => 1,
+            EccLevel::M => 0,
+            EccLevel::Q => 3,
+            EccLevel::H => 2,
+        }
+    }
+}
+
+pub const MIN_VERSION: u32 = 1;
+pub const MAX_VERSION: u32 = 40;
+/// Light border required around every symbol, in modules.
+pub const QUIET_ZONE: u32 = 4;
+
+const ECC_CODEWORDS_PER_BLOCK: [[u8; 41]; 4] = [
+    [
+        0, 7, 10, 15, 20, 26, 18, 20, 24, 30, 18, 20, 24, 26, 30, 22, 24, 28, 30, 28, 28, 28, 28,
+        30, 30, 26, 28, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30,
+    ],
+    [
+        0, 10, 16, 26, 18, 24, 16, 18, 22, 22, 26, 30, 22, 22, 24, 24, 28, 28, 26, 26, 26, 26, 28,
+        28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28,
+    ],
+    [
+        0, 13, 22, 18, 26, 18, 24, 18, 22, 20, 24, 28, 26, 24, 20, 30, 24, 28, 28, 26, 30, 28, 30,
+        30, 30, 30, 28, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30,
+    ],
+    [
+        0, 17, 28, 22, 16, 22, 28, 26, 26, 24, 28, 24, 28, 22, 24, 24, 30, 28, 28, 26, 28, 30, 24,
+        30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30,
+    ],
+];
+
+const NUM_ERROR_CORRECTION_BLOCKS: [[u8; 41]; 4] = [
+    [
+        0, 1, 1, 1, 1, 1, 2, 2, 2, 2, 4, 4, 4, 4, 4, 6, 6, 6, 6, 7, 8, 8, 9, 9, 10, 12, 12, 12, 13,
+        14, 15, 16, 17, 18, 19, 19, 20, 21, 22, 24, 25,
+    ],
+    [
+        0, 1, 1, 1, 2, 2, 4, 4, 4, 5, 5, 5, 8, 9, 9, 10, 10, 11, 13, 14, 16, 17, 17, 18, 20, 21,
+        23, 25, 26, 28, 29, 31, 33, 35, 37, 38, 40, 43, 45, 47, 49,
+    ],
+    [
+        0, 1, 1, 2, 2, 4, 4, 6, 6, 8, 8, 8, 10, 12, 16, 12, 17, 16, 18, 21, 20, 23, 23, 25, 27, 29,
+        34, 34, 35, 38, 40, 43, 45, 48, 51, 53, 56, 59, 62, 65, 68,
+    ],
+    [
+        0, 1, 1, 2, 4, 4, 4, 5, 6, 8, 8, 11, 11, 16, 16, 18, 16, 19, 21, 25, 25, 25, 34, 30, 32,
+        35, 37, 40, 42, 45, 48, 51, 54, 57, 60, 63, 66, 70, 74, 77, 81,
+    ],
+];
+
+/// Number of modules along one side of a symbol of the given version.
+pub fn side_modules(version: u32) -> u32 {
+    version * 4 + 17
+}
+
+/// Number of modules available for data and ECC codewords, after removing
+/// function patterns, format and version information.
+fn num_raw_data_modules(version: u32) -> usize {
+    let v = version as usize;
+    let mut result = (16 * v + 128) * v + 64;
+    if v >= 2 {
+        let num_align = v / 7 + 2;
+        result -= (25 * num_align - 10) * num_align - 55;
+        if v >= 7 {
+            result -= 36;
+        }
+    }
+    result
+}
+
+pub fn num_data_codewords(version: u32, ecc: EccLevel) -> usize {
+    let v = version as usize;
+    let e = ecc.ordinal();
+    num_raw_data_modules(version) / 8
+        - ECC_CODEWORDS_PER_BLOCK[e][v] as usize * NUM_ERROR_CORRECTION_BLOCKS[e][v] as usize
+}
+
+/// Bytes that fit in a single byte-mode segment of the given symbol.
+pub fn byte_capacity(version: u32, ecc: EccLevel) -> usize {
+    let count_bits = if version < 10 { 8 } else { 16 };
+    (num_data_codewords(version, ecc) * 8 - 4 - count_bits) / 8
+}
+
+/// Smallest version that holds `len` bytes in byte mode, if any.
+pub fn min_version_for(len: usize, ecc: EccLevel) -> Option<u32> {
+    (MIN_VERSION..=MAX_VERSION).find(|&v| byte_capacity(v, ecc) >= len)
+}
+
+/// Characters an alphanumeric-mode segment can carry, in code order.
+pub const ALPHANUMERIC_CHARSET: &[u8] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZ $%*+-./:";
+
+fn alphanumeric_count_bits(version: u32) -> usize {
+    match version {
+        ..=9 => 9,
+        10..=26 => 11,
+        _ => 13,
+    }
+}
+
+/// Characters that fit in a single alphanumeric-mode segment; two per 11 bits.
+pub fn alphanumeric_capacity(version: u32, ecc: EccLevel) -> usize {
+    let bits = num_data_codewords(version, ecc) * 8 - 4 - alphanumeric_count_bits(version);
+    bits / 11 * 2 + usize::from(bits % 11 >= 6)
+}
+
+/// A rendered QR symbol, `size * size` modules in row-major order.
+pub struct QrCode {
+    pub version: u32,
+    pub size: usize,
+    modules: Vec<bool>,
+    is_function: Vec<bool>,
+}
+
+impl QrCode {
+    /// Encode `data` as a single byte-mode segment in the smallest version that fits.
+    pub fn encode_bytes(data: &[u8], ecc: EccLevel) -> Option<QrCode> {
+        let version = min_version_for(data.len(), ecc)?;
+        let mut bits = BitBuffer::default();
+        bits.append(0b0100, 4);
+        bits.append(data.len() as u32, if version < 10 { 8 } else { 16 });
+        for &b in data {
+            bits.append(b as u32, 8);
+        }
+        Some(Self::from_segment(bits, version, ecc))
+    }
+
+    /// Encode `text` as a single alphanumeric-mode segment, `None` if it holds
+    /// characters outside [`ALPHANUMERIC_CHARSET`] or does not fit.
+    pub fn encode_alphanumeric(text: &[u8], ecc: EccLevel) -> Option<QrCode> {
+        let codes = text
+            .iter()
+            .map(|c| ALPHANUMERIC_CHARSET.iter().position(|a| a == c))
+            .collect::<Option<Vec<usize>>>()?;
+        let version =
+            (MIN_VERSION..=MAX_VERSION).find(|&v| alphanumeric_capacity(v, ecc) >= text.len())?;
+        let mut bits = BitBuffer::default();
+        bits.append(0b0010, 4);
+        bits.append(text.len() as u32, alphanumeric_count_bits(version));
+        for pair in codes.chunks(2) {
+            match pair {
+                [a, b] => bits.append((a * 45 + b) as u32, 11),
+                [a] => bits.append(*a as u32, 6),
+                _ => unreachable!(),
+            }
+        }
+        Some(Self::from_segment(bits, version, ecc))
+    }
+
+    /// Terminate and pad a segment's bits, then lay out the symbol with the best mask.
+    fn from_segment(mut bits: BitBuffer, version: u32, ecc: EccLevel) -> QrCode {
+        let capacity_bits = num_data_codewords(version, ecc) * 8;
+        let terminator = (capacity_bits - bits.len()).min(4);
+        bits.append(0, terminator);
+        bits.append(0, (8 - bits.len() % 8) % 8);
+        let mut codewords = bits.into_bytes();
+        for pad in [0xEC, 0x11].iter().cycle() {
+            if codewords.len() * 8 >= capacity_bits {
+                break;
+            }
+            codewords.push(*pad);
+        }
+
+        let size = side_modules(version) as usize;
+        let mut qr = QrCode {
+            version,
+            size,
+            modules: vec![false; size * size],
+            is_function: vec![false; size * size],
+        };
+        qr.draw_function_patterns(ecc);
+        let all_codewords = add_ecc_and_interleave(&codewords, version, ecc);
+        qr.draw_codewords(&all_codewords);
+
+        let mut best_mask = 0;
+        let mut min_penalty = u32::MAX;
+        for mask in 0..8 {
+            qr.apply_mask(mask);
+            qr.draw_format_bits(ecc, mask);
+            let penalty = qr.penalty_score();
+            if penalty < min_penalty {
+                best_mask = mask;
+                min_penalty = penalty;
+            }
+            qr.apply_mask(mask);
+        }
+        qr.apply_mask(best_mask);
+        qr.draw_format_bits(ecc, best_mask);
+        qr
+    }
+
+    pub fn module(&self, x: usize, y: usize) -> bool {
+        self.modules[y * self.size + x]
+    }
+
+    /// Whether the module at `(x, y)` is dark, treating everything outside the symbol as light.
+    fn dark_at(&self, x: i64, y: i64) -> bool {
+        let inside = x >= 0 && y >= 0 && (x as usize) < self.size && (y as usize) < self.size;
+        inside && self.module(x as usize, y as usize)
+    }
+
+    /// Render with `scale` pixels per module and the standard 4-module quiet zone.
+    pub fn to_image(&self, scale: u32) -> image::GrayImage {
+        let side = (self.size as u32 + 2 * QUIET_ZONE) * scale;
+        image::GrayImage::from_fn(side, side, |px, py| {
+            let x = (px / scale) as i64 - QUIET_ZONE as i64;
+            let y = (py / scale) as i64 - QUIET_ZONE as i64;
+            if self.dark_at(x, y) {
+                image::Luma([0])
+            } else {
+                image::Luma([255])
+            }
+        })
+    }
+
+    /// Render for a terminal, two modules per character cell using half blocks.
+    /// Light modules are drawn as filled blocks so the code reads correctly on
+    /// dark terminal backgrounds.
+    pub fn to_halfblocks(&self) -> String {
+        let border = QUIET_ZONE as i64;
+        let end = self.size as i64 + border;
+        let mut out = String::new();
+        for y in (-border..end).step_by(2) {
+            for x in -border..end {
+                let c = match (self.dark_at(x, y), self.dark_at(x, y + 1)) {
+                    (false, false) => '█',
+                    (false, true) => '▀',
+                    (true, false) => '▄',
+                    (true, true) => ' ',
+                };
+                out.push(c);
+            }
+            out.push('\n');
+        }
+        out
+    }
+
+    fn set_function_module(&mut self, x: usize, y: usize, dark: bool) {
+        self.modules[y * self.size + x] = dark;
+        self.is_function[y * self.size + x] = true;
+    }
+
+    fn draw_function_patterns(&mut self, ecc: EccLevel) {
+        let size = self.size;
+        for i in 0..size {
+            self.set_function_module(6, i, i % 2 == 0);
+            self.set_function_module(i, 6, i % 2 == 0);
+        }
+        self.draw_finder_pattern(3, 3);
+        self.draw_finder_pattern(size as i64 - 4, 3);
+        self.draw_finder_pattern(3, size as i64 - 4);
+
+        let positions = alignment_pattern_positions(self.version);
+        let n = positions.len();
+        for i in 0..n {
+            for j in 0..n {
+                let overlaps_finder = (i == 0 && (j == 0 || j == n - 1)) || (i == n - 1 && j == 0);
+                if !overlaps_finder {
+                    self.draw_alignment_pattern(positions[i], positions[j]);
+                }
+            }
+        }
+        // placeholder so the format areas are marked as function modules
+        self.draw_format_bits(ecc, 0);
+        self.draw_version();
+    }
+
+    fn draw_finder_pattern(&mut self, x: i64, y: i64) {
+        for dy in -4..=4i64 {
+            for dx in -4..=4i64 {
+                let (xx, yy) = (x + dx, y + dy);
+                if (0..self.size as i64).contains(&xx) && (0..self.size as i64).contains(&yy) {
+                    let dist = dx.abs().max(dy.abs());
+                    self.set_function_module(xx as usize, yy as usize, dist != 2 && dist != 4);
+                }
+            }
+        }
+    }
+
+    fn draw_alignment_pattern(&mut self, x: usize, y: usize) {
+        for dy in -2..=2i64 {
+            for dx in -2..=2i64 {
+                let (xx, yy) = ((x as i64 + dx) as usize, (y as i64 + dy) as usize);
+                self.set_function_module(xx, yy, dx.abs().max(dy.abs()) != 1);
+            }
+        }
+    }
+
+    fn draw_format_bits(&mut self, ecc: EccLevel, mask: u32) {
+        let data = ecc.format_bits() << 3 | mask;
+        let mut rem = data;
+        for _ in 0..10 {
+            rem = (rem << 1) ^ ((rem >> 9) * 0x537);
+        }
+        let bits = (data << 10 | rem) ^ 0x5412;
+        let bit = |i: usize| (bits >> i) & 1 != 0;
+
+        for i in 0..6 {
+            self.set_function_module(8, i, bit(i));
+        }
+        self.set_function_module(8, 7, bit(6));
+        self.set_function_module(8, 8, bit(7));
+        self.set_function_module(7, 8, bit(8));
+        for i in 9..15 {
+            self.set_function_module(14 - i, 8, bit(i));
+        }
+
+        let size = self.size;
+        for i in 0..8 {
+            self.set_function_module(size - 1 - i, 8, bit(i));
+        }
+        for i in 8..15 {
+            self.set_function_module(8, size - 15 + i, bit(i));
+        }
+        self.set_function_module(8, size - 8, true);
+    }
+
+    fn draw_version(&mut self) {
+        if self.version < 7 {
+            return;
+        }
+        let mut rem = self.version;
+        for _ in 0..12 {
+            rem = (rem << 1) ^ ((rem >> 11) * 0x1F25);
+        }
+        let bits = self.version << 12 | rem;
+        for i in 0..18 {
+            let dark = (bits >> i) & 1 != 0;
+            let a = self.size - 11 + i % 3;
+            let b = i / 3;
+            self.set_function_module(a, b, dark);
+            self.set_function_module(b, a, dark);
+        }
+    }
+
+    fn draw_codewords(&mut self, data: &[u8]) {
+        let size = self.size as i64;
+        let mut i = 0;
+        let mut right = size - 1;
+        while right >= 1 {
+            if right == 6 {
+                right = 5;
+            }
+            for vert in 0..size {
+                for j in 0..2 {
+                    let x = (right - j) as usize;
+                    let upward = (right + 1) & 2 == 0;
+                    let y = if upward { size - 1 - vert } else { vert } as usize;
+                    if !self.is_function[y * self.size + x] && i < data.len() * 8 {
+                        self.modules[y * self.size + x] = (data[i >> 3] >> (7 - (i & 7))) & 1 != 0;
+                        i += 1;
+                    }
+                }
+            }
+            right -= 2;
+        }
+    }
+
+    fn apply_mask(&mut self, mask: u32) {
+        for y in 0..self.size {
+            for x in 0..self.size {
+                let invert = match mask {
+                    0 => (x + y) % 2 == 0,
+                    1 => y % 2 == 0,
+                    2 => x % 3 == 0,
+                    3 => (x + y) % 3 == 0,
+                    4 => (x / 3 + y / 2) % 2 == 0,
+                    5 => x * y % 2 + x * y % 3 == 0,
+                    6 => (x * y % 2 + x * y % 3) % 2 == 0,
+                    _ => ((x + y) % 2 + x * y % 3) % 2 == 0,
+                };
+                let idx = y * self.size + x;
+                self.modules[idx] ^= invert && !self.is_function[idx];
+            }
+        }
+    }
+
+    fn penalty_score(&self) -> u32 {
+        let size = self.size;
+        let mut penalty = 0;
+        let line = |get: &dyn Fn(usize) -> bool| -> u32 {
+            let mut p = 0;
+            let mut run = 1;
+            for i in 1..size {
+                if get(i) == get(i - 1) {
+                    run += 1;
+                    if run == 5 {
+                        p += 3;
+                    } else if run > 5 {
+                        p += 1;
+                    }
+                } else {
+                    run = 1;
+                }
+            }
+            // finder-like 1:1:3:1:1 patterns with four light modules on one side
+            let pattern = [true, false, true, true, true, false, true];
+            for i in 0..size.saturating_sub(6) {
+                if (0..7).all(|k| get(i + k) == pattern[k]) {
+                    let light_before = (i >= 4) && (i - 4..i).all(|k| !get(k));
+                    let light_after = (i + 11 <= size) && (i + 7..i + 11).all(|k| !get(k));
+                    if light_before || light_after {
+                        p += 40;
+                    }
+                }
+            }
+            p
+        };
+        for y in 0..size {
+            penalty += line(&|x| self.module(x, y));
+        }
+        for x in 0..size {
+            penalty += line(&|y| self.module(x, y));
+        }
+        for y in 0..size - 1 {
+            for x in 0..size - 1 {
+                let c = self.module(x, y);
+                if c == self.module(x + 1, y)
+                    && c == self.module(x, y + 1)
+                    && c == self.module(x + 1, y + 1)
+                {
+                    penalty += 3;
+                }
+            }
+        }
+        let dark = self.modules.iter().filter(|&&m| m).count();
+        let total = size * size;
+        let k = (dark * 20).abs_diff(total * 10).div_ceil(total) as u32;
+        penalty + k.saturating_sub(1) * 10
+    }
+}
+
+#[derive(Default)]
+struct BitBuffer {
+    bits: Vec<bool>,
+}
+impl BitBuffer {
+    fn append(&mut self, value: u32, len: usize) {
+        for i in (0..len).rev() {
+            self.bits.push((value >> i) & 1 != 0);
+        }
+    }
+    fn len(&self) -> usize {
+        self.bits.len()
+    }
+    fn into_bytes(self) -> Vec<u8> {
+        self.bits
+            .chunks(8)
+            .map(|c| c.iter().fold(0u8, |acc, &b| acc << 1 | b as u8))
+            .collect()
+    }
+}
+
+fn alignment_pattern_positions(version: u32) -> Vec<usize> {
+    if version == 1 {
+        return Vec::new();
+    }
+    let num_align = version as usize / 7 + 2;
+    let step = (version as usize * 8 + num_align * 3 + 5) / (num_align * 4 - 4) * 2;
+    let size = side_modules(version) as usize;
+    let mut result: Vec<usize> = (0..num_align - 1).map(|i| size - 7 - i * step).collect();
+    result.push(6);
+    result.reverse();
+    result
+}
+
+fn add_ecc_and_interleave(data: &[u8], version: u32, ecc: EccLevel) -> Vec<u8> {
+    let (v, e) = (version as usize, ecc.ordinal());
+    let num_blocks = NUM_ERROR_CORRECTION_BLOCKS[e][v] as usize;
+    let block_ecc_len = ECC_CODEWORDS_PER_BLOCK[e][v] as usize;
+    let raw_codewords = num_raw_data_modules(version) / 8;
+    let num_short_blocks = num_blocks - raw_codewords % num_blocks;
+    let short_block_len = raw_codewords / num_blocks;
+
+    let divisor = reed_solomon_divisor(block_ecc_len);
+    let mut blocks = Vec::with_capacity(num_blocks);
+    let mut k = 0;
+    for i in 0..num_blocks {
+        let data_len = short_block_len - block_ecc_len + usize::from(i >= num_short_blocks);
+        let mut block = data[k..k + data_len].to_vec();
+        k += data_len;
+        let ecc = reed_solomon_remainder(&block, &divisor);
+        if i < num_short_blocks {
+            block.push(0);
+        }
+        block.extend_from_slice(&ecc);
+        blocks.push(block);
+    }
+
+    let mut result = Vec::with_capacity(raw_codewords);
+    for i in 0..=short_block_len {
+        for (j, block) in blocks.iter().enumerate() {
+            // short blocks carry a padding byte that is not transmitted
+            if i != short_block_len - block_ecc_len || j >= num_short_blocks {
+                result.push(block[i]);
+            }
+        }
+    }
+    result
+}
+
+fn reed_solomon_divisor(degree: usize) -> Vec<u8> {
+    let mut result = vec![0u8; degree - 1];
+    result.push(1);
+    let mut root = 1u8;
+    for _ in 0..degree {
+        for j in 0..degree {
+            result[j] = gf_multiply(result[j], root);
+            if j + 1 < result.len() {
+                result[j] ^= result[j + 1];
+            }
+        }
+        root = gf_multiply(root, 0x02);
+    }
+    result
+}
+
+fn reed_solomon_remainder(data: &[u8], divisor: &[u8]) -> Vec<u8> {
+    let mut result = vec![0u8; divisor.len()];
+    for &b in data {
+        let factor = b ^ result.remove(0);
+        result.push(0);
+        for (x, &y) in result.iter_mut().zip(divisor) {
+            *x ^= gf_multiply(y, factor);
+        }
+    }
+    result
+}
+
+/// Multiplication in GF(2^8) modulo x^8 + x^4 + x^3 + x^2 + 1.
+fn gf_multiply(x: u8, y: u8) -> u8 {
+    let mut z = 0u8;
+    for i in (0..8).rev() {
+        z = (z << 1) ^ ((z >> 7) * 0x1D);
+        z ^= ((y >> i) & 1) * x;
+    }
+    z
+}
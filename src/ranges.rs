@@ -0,0 +1,195 @@
+//! Sets of segment ids written as ranges, `17-20,944,1001-1040`, as
+//! reports list what is missing and filters take what to decode.
+
+use serde::{Serialize, Serializer};
+use std::fmt;
+use std::ops::{Range, RangeInclusive};
+use std::str::FromStr;
+
+/// Segment ids as sorted, disjoint, non-adjacent runs.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SegmentRanges(Vec<RangeInclusive<u64>>);
+impl SegmentRanges {
+    /// The set covering `ranges`, given in order.
+    pub fn from_ranges(ranges: impl IntoIterator<Item = Range<u64>>) -> Self {
+        let mut set = SegmentRanges::default();
+        for range in ranges.into_iter().filter(|r| !r.is_empty()) {
+            set.push(range.start..=range.end - 1);
+        }
+        set
+    }
+
+    /// Add a run starting at or after every one held.
+    fn push(&mut self, range: RangeInclusive<u64>) {
+        match self.0.last_mut() {
+            Some(last) if *range.start() <= last.end().saturating_add(1) => {
+                *last = *last.start()..=*last.end().max(range.end());
+            }
+            _ => self.0.push(range),
+        }
+    }
+
+    pub fn contains(&self, id: u64) -> bool {
+        self.0
+            .binary_search_by(|r| {
+                if *r.end() < id {
+                    std::cmp::Ordering::Less
+                } else if *r.start() > id {
+                    std::cmp::Ordering::Greater
+                } else {
+                    std::cmp::Ordering::Equal
+                }
+            })
+            .is_ok()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Number of ids in the set, saturating at `u64::MAX`.
+    pub fn len(&self) -> u64 {
+        self.0.iter().fold(0u64, |n, r| {
+            n.saturating_add((r.end() - r.start()).saturating_add(1))
+        })
+    }
+
+    /// The runs, in order.
+    pub fn iter(&self) -> impl Iterator<Item = RangeInclusive<u64>> + '_ {
+        self.0.iter().cloned()
+    }
+
+    /// Every id, in order, without listing them up front.
+    pub fn ids(&self) -> impl Iterator<Item = u64> + '_ {
+        self.iter().flatten()
+    }
+}
+
+/// `17-20, 944, 1001-1040`; the alternate form, `{:#}`, leaves out the
+/// spaces, for a command line.
+impl fmt::Display for SegmentRanges {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let separator = if f.alternate() { "," } else { ", " };
+        for (i, r) in self.0.iter().enumerate() {
+            if i > 0 {
+                f.write_str(separator)?;
+            }
+            if r.start() == r.end() {
+                write!(f, "{}", r.start())?;
+            } else {
+                write!(f, "{}-{}", r.start(), r.end())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Ids and `first-last` runs, separated by commas, optionally in brackets
+/// and with spaces, as [`Display`](fmt::Display) writes them.
+impl FromStr for SegmentRanges {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        let s = s.trim();
+        let s = s
+            .strip_prefix('[')
+            .and_then(|s| s.strip_suffix(']'))
+            .unwrap_or(s);
+        let id = |s: &str| {
+            s.trim()
+                .parse::<u64>()
+                .map_err(|_| format!("{:?} is not a segment id", s.trim()))
+        };
+        let mut runs = Vec::new();
+        if s.trim().is_empty() {
+            return Ok(SegmentRanges::default());
+        }
+        for part in s.split(',') {
+            if part.trim().is_empty() {
+                return Err(format!("{:?} has an empty entry", s));
+            }
+            let (first, last) = match part.split_once('-') {
+                Some((first, last)) => (id(first)?, id(last)?),
+                None => (id(part)?, id(part)?),
+            };
+            if first > last {
+                return Err(format!("{}-{} runs backwards", first, last));
+            }
+            runs.push(first..=last);
+        }
+        runs.sort_unstable_by_key(|r| *r.start());
+        let mut ranges = SegmentRanges::default();
+        for run in runs {
+            ranges.push(run);
+        }
+        Ok(ranges)
+    }
+}
+
+impl Serialize for SegmentRanges {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(&format_args!("{:#}", self))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(s: &str) -> Result<SegmentRanges, String> {
+        s.parse()
+    }
+
+    #[test]
+    fn malformed_input_is_refused() {
+        for bad in [
+            "5-3",
+            "1,,2",
+            ",1",
+            "1,",
+            "-4",
+            "4-",
+            "1-2-3",
+            "x",
+            "18446744073709551616",
+            "0-18446744073709551616",
+        ] {
+            assert!(parse(bad).is_err(), "{:?} parsed", bad);
+        }
+        assert_eq!(parse("5-3").unwrap_err(), "5-3 runs backwards");
+    }
+
+    #[test]
+    fn adjacent_and_overlapping_runs_merge() {
+        let ranges = parse("10-12, 3, 4-6, 13, 5-8, 20, 18446744073709551615").unwrap();
+        assert_eq!(ranges.0, [3..=8, 10..=13, 20..=20, u64::MAX..=u64::MAX]);
+        assert_eq!(ranges.to_string(), "3-8, 10-13, 20, 18446744073709551615");
+        assert_eq!(
+            SegmentRanges::from_ranges([0..2, 2..4, 5..5, 6..7, 6..9]).0,
+            [0..=3, 6..=8]
+        );
+        assert!(ranges.contains(8) && ranges.contains(10) && !ranges.contains(9));
+        assert_eq!(ranges.len(), 12);
+        assert_eq!(
+            ranges.ids().take(7).collect::<Vec<_>>(),
+            [3, 4, 5, 6, 7, 8, 10]
+        );
+        assert_eq!(parse("0-18446744073709551615").unwrap().len(), u64::MAX);
+    }
+
+    #[test]
+    fn display_parses_back() {
+        for s in [
+            "",
+            "[]",
+            "0",
+            "1-3",
+            "[7, 9-11, 40]",
+            "0-18446744073709551615",
+        ] {
+            let ranges = parse(s).unwrap();
+            assert_eq!(parse(&ranges.to_string()).unwrap(), ranges, "{:?}", s);
+            assert_eq!(parse(&format!("{:#}", ranges)).unwrap(), ranges, "{:?}", s);
+        }
+    }
+}
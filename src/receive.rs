@@ -0,0 +1,1624 @@
+use serde::Serialize;
+use std::collections::BTreeSet;
+use std::io;
+use std::process::ExitCode;
+use std::time::{Duration, Instant, UNIX_EPOCH};
+use std::{fs, path};
+
+use crate::advise::advise_from_run;
+use crate::clipboard::ClipboardWatch;
+use crate::decoder::{scan, QrSendDecoder};
+use crate::holes::HoleMap;
+use crate::images::{ImageSequence, ImageSequenceIterator, ReadOptions};
+use crate::notify::Notifier;
+use crate::pipe::{FrameStream, StreamFormat};
+use crate::pipeline::{self, LiveQueue};
+#[cfg(feature = "gui")]
+use crate::preview::{self, Preview};
+use crate::protocol::QrSendMetadata;
+use crate::ranges::SegmentRanges;
+use crate::report::{AggregateReport, Digests};
+use crate::schedule::LoopSchedule;
+use crate::screen::{ScreenCapture, ScreenRegion};
+use crate::session::{unix_now, RunRecord, Session, SessionKey};
+use crate::sink::{partial_path, CommandSink, FileSink, Sink, StdoutSink};
+use crate::split::SplitText;
+use crate::trace::Trace;
+use crate::transfer::{Protocol, ProtocolKind};
+use crate::tui::{AckBitmap, Dashboard, View};
+
+/// Segment ids of the transmission order a run record keeps.
+const ORDER_SAMPLE: usize = 256;
+
+#[derive(clap::Args)]
+#[clap(group(clap::ArgGroup::new("live").args(["screen_region", "clipboard", "stdin_frames"])))]
+pub struct ReceiveArgs {
+    /// Directory of captured frames, an animated GIF or APNG file, or a PDF
+    /// of printed frames; repeat to merge captures of the same sender, e.g.
+    /// two phones filming the screen from different angles
+    #[clap(short, long, required_unless_present_any = ["live", "batch", "payload_dir"])]
+    image_dir: Vec<String>,
+    /// Feed the payloads a `--dump-payloads` run wrote to this directory
+    /// straight to the decoder instead, scanning no images
+    #[clap(
+        long,
+        value_name = "DIR",
+        conflicts_with_all = ["image_dir", "live", "batch", "tui", "bitmap", "pipeline_depth"]
+    )]
+    payload_dir: Option<String>,
+    /// Receive several independent transfers, one per capture listed in
+    /// this file, one per line; `#` starts a comment
+    #[clap(
+        long,
+        value_name = "FILE",
+        conflicts_with_all = ["image_dir", "live", "output_file", "session", "tui", "bitmap"]
+    )]
+    batch: Option<String>,
+    /// Directory the files received with --batch are written to
+    #[clap(long, value_name = "DIR", default_value = ".", requires = "batch")]
+    batch_output_dir: String,
+    /// Grab frames from this region of the local display instead, as x,y,w,h
+    #[clap(long, conflicts_with = "image_dir")]
+    screen_region: Option<ScreenRegion>,
+    /// Decode each new image put on the clipboard instead, e.g. by pressing
+    /// a screenshot hotkey once per sender frame
+    #[clap(long, conflicts_with = "image_dir")]
+    clipboard: bool,
+    /// Decode images piped to stdin instead, e.g. by ffmpeg or gphoto2,
+    /// delimited by a length prefix or as multipart parts
+    #[clap(long, value_enum, value_name = "FORMAT", conflicts_with = "image_dir")]
+    stdin_frames: Option<StreamFormat>,
+    /// Frames per second to grab with --screen-region, or clipboard polls
+    /// per second with --clipboard
+    #[clap(long, default_value_t = 10.0, requires = "live")]
+    capture_fps: f64,
+    /// Stop grabbing after this many seconds even if the transfer is incomplete
+    #[clap(long, requires = "live")]
+    capture_secs: Option<f64>,
+    /// Frame format the sender uses
+    #[clap(long, value_enum, default_value_t = ProtocolKind::Auto)]
+    protocol: ProtocolKind,
+    /// Show a live dashboard of received and missing segments
+    #[clap(long)]
+    tui: bool,
+    /// Show just which segments arrived, as rows of `#` and `.` on stderr
+    /// redrawn in place, for terminals or logs where the dashboard is too much
+    #[clap(long, conflicts_with = "tui")]
+    bitmap: bool,
+    /// Ring the terminal bell and show a desktop notification when the
+    /// transfer completes, fails or stalls
+    #[clap(long)]
+    notify: bool,
+    /// Seconds without a new segment after which a capture counts as stalled
+    #[clap(long, default_value_t = 30.0, requires = "notify")]
+    stall_secs: f64,
+    /// Show the latest frame with the qrcodes found in it outlined and a
+    /// map of the segments received, to help aim the camera
+    #[cfg(feature = "gui")]
+    #[clap(long, requires = "live", conflicts_with_all = ["tui", "bitmap"])]
+    preview: bool,
+    /// Image viewer that shows the preview; the path of a PNG it should
+    /// keep reloading is appended
+    #[cfg(feature = "gui")]
+    #[clap(long, requires = "preview", value_name = "COMMAND")]
+    preview_cmd: Option<String>,
+    /// Where to write the file, or `-` for stdout; defaults to the file
+    /// name the sender declares
+    #[clap(short, long)]
+    output_file: Option<String>,
+    /// Pipe the verified file into this shell command instead, e.g.
+    /// "tar xf -"; it must exit successfully
+    #[clap(
+        long,
+        value_name = "COMMAND",
+        conflicts_with_all = ["output_file", "allow_partial", "sha256_sidecar", "batch"]
+    )]
+    pipe_to: Option<String>,
+    /// Upload the verified file with an HTTP PUT to this URL instead, e.g. a
+    /// presigned S3 URL; sent by running curl
+    #[cfg(feature = "http")]
+    #[clap(
+        long,
+        value_name = "URL",
+        conflicts_with_all = ["output_file", "pipe_to", "allow_partial", "sha256_sidecar", "batch"]
+    )]
+    put_url: Option<String>,
+    /// Replace the output file if it already exists
+    #[clap(long)]
+    force: bool,
+    /// When segments are missing, write the file anyway with zeros in their
+    /// place and list the gaps in `<output-file>.holes.json`
+    #[clap(long)]
+    allow_partial: bool,
+    /// Also write `<output-file>.sha256`, checkable with `sha256sum -c`
+    #[clap(long)]
+    sha256_sidecar: bool,
+    #[clap(flatten)]
+    read: ReadOptions,
+    /// Save decoder state here so a later `resume` can fill in missing segments
+    #[clap(long)]
+    session: Option<String>,
+    #[clap(flatten)]
+    session_key: SessionKey,
+    /// Refuse data frames whose hash length is guessed rather than taken from metadata
+    #[clap(long)]
+    strict_hash_len: bool,
+    /// Only store these segments, e.g. `17-20,944` as `nack` prints them,
+    /// for a pass that fills known gaps
+    #[clap(long, value_name = "IDS")]
+    only_segments: Option<SegmentRanges>,
+    /// Fail closed on any ambiguity: guessed hash lengths, unknown frame
+    /// types, inconsistent metadata or conflicting segments
+    #[clap(long)]
+    strict: bool,
+    /// Log ambiguities and keep decoding (the default)
+    #[clap(long, conflicts_with = "strict")]
+    lenient: bool,
+    /// Write the raw bytes of frames with unrecognized type bytes into this directory
+    #[clap(long)]
+    dump_unknown_frames: Option<String>,
+    /// Write every payload decoded into this directory, one file per code
+    /// named by the index of the image it was in, before it is verified or
+    /// parsed
+    #[clap(long, value_name = "DIR", conflicts_with = "batch")]
+    dump_payloads: Option<String>,
+    /// Write a JSON line per image read to this file: where it came from,
+    /// when, how long it took to scan, and the type, segment id and
+    /// verification outcome of each code found in it
+    #[clap(long, value_name = "FILE", conflicts_with = "batch")]
+    trace: Option<String>,
+    /// Give up after this many seconds of decoding, keeping what arrived
+    #[clap(long)]
+    max_duration: Option<f64>,
+    /// Give up after reading this many frames, keeping what arrived
+    #[clap(long)]
+    max_frames: Option<u64>,
+    /// Load, scan and verify images on separate threads, with up to N
+    /// images queued between them, to hide disk reads on big captures.
+    /// Reads every image once, in order. A live source is grabbed on a
+    /// thread of its own instead, with up to N frames queued; while decoding
+    /// lags, only every second, fourth and so on frame is kept, and the
+    /// frames dropped are counted per second in the session's run record
+    #[clap(
+        long,
+        value_name = "N",
+        conflicts_with_all = ["crop", "skip", "sample_fps", "cache_dir", "tui", "bitmap"]
+    )]
+    pipeline_depth: Option<usize>,
+    /// Log an error when the file does not arrive complete and verified,
+    /// for wrappers that must not pass over an unfinished transfer quietly
+    #[clap(long)]
+    expect_complete: bool,
+}
+
+/// How a transfer ended. Each maps to an exit status of its own, listed in
+/// [`crate::cli::EXIT_STATUS`].
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Outcome {
+    Verified,
+    HashMismatch,
+    SizeMismatch,
+    StrictViolation,
+    OutputExists,
+    WriteFailed,
+    Incomplete,
+    /// Written with zeros for the missing segments, under `--allow-partial`.
+    Partial,
+    NoMetadata,
+    UnsupportedVersion,
+}
+impl Outcome {
+    /// Exit status for this outcome.
+    pub fn exit_code(self) -> u8 {
+        match self {
+            Outcome::Verified => 0,
+            Outcome::Incomplete | Outcome::Partial => 2,
+            Outcome::HashMismatch | Outcome::SizeMismatch => 3,
+            Outcome::NoMetadata => 4,
+            Outcome::UnsupportedVersion => 5,
+            Outcome::StrictViolation => 6,
+            Outcome::OutputExists | Outcome::WriteFailed => 7,
+        }
+    }
+    /// [`Outcome::exit_code`] to exit with, saying loudly when the
+    /// transfer is unfinished and `expect_complete` wanted it done.
+    pub fn exit_status(self, expect_complete: bool) -> ExitCode {
+        if expect_complete && matches!(self, Outcome::Incomplete | Outcome::Partial) {
+            log::error!(
+                "transfer did not complete ({:?}) under --expect-complete",
+                self
+            );
+        }
+        ExitCode::from(self.exit_code())
+    }
+}
+
+/// Decode `img_seq` into `decoder`, through the stages of [`pipeline`]
+/// when given a depth, returning the record of this run.
+pub fn timed_run(
+    decoder: &mut QrSendDecoder,
+    img_seq: ImageSequence,
+    pipeline_depth: Option<usize>,
+) -> RunRecord {
+    let source = img_seq.image_dir.display().to_string();
+    let started_at = unix_now();
+    let start = Instant::now();
+    let before = decoder.data_segments.len();
+    let frames_before = decoder.frames_read;
+    decoder.decoded_ids = Some(Vec::new());
+    set_trace_source(decoder, &source);
+    let mut images = img_seq.into_iter();
+    match pipeline_depth {
+        Some(depth) => images = pipeline::run(decoder, images, depth),
+        None => decoder.consume(&mut images),
+    }
+    report_unreadable(&images);
+    let order = first_seen(&take_decoded(decoder));
+    RunRecord {
+        source,
+        started_at,
+        elapsed_secs: start.elapsed().as_secs_f64(),
+        new_segments: (decoder.data_segments.len() - before) as u64,
+        unreadable_images: images.unreadable,
+        frames: decoder.frames_read - frames_before,
+        decoded_segments: order.len() as u64,
+        dropped_per_sec: Vec::new(),
+        transmission_order: order_sample(order),
+    }
+}
+
+/// Name `source` in the trace records of the images that follow, until a
+/// capture names the file each comes from.
+fn set_trace_source(decoder: &mut QrSendDecoder, source: &str) {
+    if let Some(trace) = decoder.trace.as_mut() {
+        trace.source = source.to_string();
+    }
+}
+
+/// Ids of the segments decoded since they were last taken, in the order
+/// they were, repeats included.
+fn take_decoded(decoder: &mut QrSendDecoder) -> Vec<u64> {
+    decoder.decoded_ids.replace(Vec::new()).unwrap_or_default()
+}
+
+/// `ids` each once, in the order they first came.
+fn first_seen(ids: &[u64]) -> Vec<u64> {
+    let mut seen = BTreeSet::new();
+    ids.iter().copied().filter(|&id| seen.insert(id)).collect()
+}
+
+/// The start of `order` that a run record keeps.
+fn order_sample(mut order: Vec<u64>) -> Vec<u64> {
+    order.truncate(ORDER_SAMPLE);
+    order
+}
+
+fn report_unreadable(images: &ImageSequenceIterator) {
+    if images.unreadable > 0 {
+        log::warn!("unreadable images skipped: {}", images.unreadable);
+    }
+    if images.not_images > 0 {
+        log::warn!("files that are not images skipped: {}", images.not_images);
+    }
+}
+
+/// Feed a live capture into `decoder` until the transfer completes or the source ends,
+/// calling `on_frame` with each image once it is decoded.
+pub fn streaming_run<I, F>(
+    decoder: &mut QrSendDecoder,
+    source: String,
+    frames: I,
+    mut on_frame: F,
+) -> RunRecord
+where
+    I: Iterator<Item = image::DynamicImage>,
+    F: FnMut(&QrSendDecoder, &image::DynamicImage),
+{
+    let started_at = unix_now();
+    let start = Instant::now();
+    let before = decoder.data_segments.len();
+    let frames_before = decoder.frames_read;
+    decoder.decoded_ids = Some(Vec::new());
+    set_trace_source(decoder, &source);
+    for img in frames {
+        decoder.push_image(&img);
+        on_frame(decoder, &img);
+        if decoder.is_complete() || decoder.out_of_budget() {
+            break;
+        }
+    }
+    let order = first_seen(&take_decoded(decoder));
+    RunRecord {
+        source,
+        started_at,
+        elapsed_secs: start.elapsed().as_secs_f64(),
+        new_segments: (decoder.data_segments.len() - before) as u64,
+        unreadable_images: 0,
+        frames: decoder.frames_read - frames_before,
+        decoded_segments: order.len() as u64,
+        dropped_per_sec: Vec::new(),
+        transmission_order: order_sample(order),
+    }
+}
+
+/// Feed the payloads dumped into `dir` to `decoder`, those of one image
+/// at a time in the order the images were read, as if scanned again.
+/// Files not named as `--dump-payloads` names them count as an image each.
+pub fn payload_run(decoder: &mut QrSendDecoder, dir: &path::Path) -> io::Result<RunRecord> {
+    let mut names: Vec<String> = fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_type().is_ok_and(|t| t.is_file()))
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        .collect();
+    names.sort();
+    let source = dir.display().to_string();
+    let started_at = unix_now();
+    let start = Instant::now();
+    let before = decoder.data_segments.len();
+    let frames_before = decoder.frames_read;
+    decoder.decoded_ids = Some(Vec::new());
+    set_trace_source(decoder, &source);
+    let mut names = names.iter().peekable();
+    while let Some(name) = names.next() {
+        let image = dumped_image(name);
+        let mut decoded = vec![fs::read(dir.join(name))?];
+        while let Some(next) = names.next_if(|next| image.is_some() && dumped_image(next) == image)
+        {
+            decoded.push(fs::read(dir.join(next))?);
+        }
+        decoder.push_scanned(decoded, 0);
+        if decoder.is_complete() || decoder.out_of_budget() {
+            break;
+        }
+    }
+    let order = first_seen(&take_decoded(decoder));
+    Ok(RunRecord {
+        source,
+        started_at,
+        elapsed_secs: start.elapsed().as_secs_f64(),
+        new_segments: (decoder.data_segments.len() - before) as u64,
+        unreadable_images: 0,
+        frames: decoder.frames_read - frames_before,
+        decoded_segments: order.len() as u64,
+        dropped_per_sec: Vec::new(),
+        transmission_order: order_sample(order),
+    })
+}
+
+/// The image index in a `frame_000123_00.bin` name `--dump-payloads` wrote.
+fn dumped_image(name: &str) -> Option<u64> {
+    let rest = name.strip_prefix("frame_")?.strip_suffix(".bin")?;
+    let (image, code) = rest.split_once('_')?;
+    code.parse::<u32>().ok()?;
+    image.parse().ok()
+}
+
+/// Feed several captures of one sender into `decoder`, an image from each
+/// in turn as if they were filmed side by side, until the transfer
+/// completes or every capture ends. Returns a record per capture and logs
+/// what each contributed.
+pub fn merged_run<F>(
+    decoder: &mut QrSendDecoder,
+    sources: Vec<(String, ImageSequenceIterator)>,
+    mut on_frame: F,
+) -> Vec<RunRecord>
+where
+    F: FnMut(&QrSendDecoder, &image::DynamicImage),
+{
+    let started_at = unix_now();
+    let mut records: Vec<RunRecord> = sources
+        .iter()
+        .map(|(source, _)| RunRecord {
+            source: source.clone(),
+            started_at,
+            elapsed_secs: 0.0,
+            new_segments: 0,
+            unreadable_images: 0,
+            frames: 0,
+            decoded_segments: 0,
+            dropped_per_sec: Vec::new(),
+            transmission_order: Vec::new(),
+        })
+        .collect();
+    let mut decoded: Vec<Vec<u64>> = vec![Vec::new(); records.len()];
+    let mut images: Vec<Option<ImageSequenceIterator>> = sources
+        .into_iter()
+        .map(|(_, images)| Some(images))
+        .collect();
+    decoder.decoded_ids = Some(Vec::new());
+    'rounds: while images.iter().any(Option::is_some) {
+        for (i, slot) in images.iter_mut().enumerate() {
+            let Some(source) = slot else {
+                continue;
+            };
+            let record = &mut records[i];
+            let start = Instant::now();
+            let before = decoder.data_segments.len();
+            let Some(img) = source.next() else {
+                record.unreadable_images = source.unreadable;
+                report_unreadable(source);
+                *slot = None;
+                continue;
+            };
+            set_trace_source(decoder, &record.source);
+            decoder.push_image(&img);
+            record.frames += 1;
+            record.new_segments += (decoder.data_segments.len() - before) as u64;
+            record.elapsed_secs += start.elapsed().as_secs_f64();
+            decoded[i].append(&mut take_decoded(decoder));
+            on_frame(decoder, &img);
+            if decoder.is_complete() || decoder.out_of_budget() {
+                break 'rounds;
+            }
+        }
+    }
+    let distinct: Vec<BTreeSet<u64>> = decoded
+        .iter()
+        .map(|ids| ids.iter().copied().collect())
+        .collect();
+    for (i, record) in records.iter_mut().enumerate() {
+        if let Some(source) = &images[i] {
+            record.unreadable_images = source.unreadable;
+            report_unreadable(source);
+        }
+        let order = first_seen(&decoded[i]);
+        record.decoded_segments = order.len() as u64;
+        let only_here = order
+            .iter()
+            .filter(|id| (0..distinct.len()).all(|j| j == i || !distinct[j].contains(id)))
+            .count();
+        record.transmission_order = order_sample(order);
+        log::info!(
+            "{}: {} segments decoded from {} frames ({:.0}% yield), {} first here, {} only here",
+            record.source,
+            record.decoded_segments,
+            record.frames,
+            100.0 * record.yield_ratio(),
+            record.new_segments,
+            only_here
+        );
+    }
+    records
+}
+
+/// Verify whatever `decoder` has collected and deliver it to `sink`,
+/// reporting what is missing. Only file sinks take a partial file.
+pub fn finish(decoder: &QrSendDecoder, sink: &mut dyn Sink, allow_partial: bool) -> Outcome {
+    if decoder.unsupported_version.is_some() {
+        return Outcome::UnsupportedVersion;
+    }
+    if let Some(violation) = &decoder.violation {
+        log::error!("not writing output, strict decoding stopped: {}", violation);
+        return Outcome::StrictViolation;
+    }
+    let Some(md) = &decoder.metadata else {
+        match decoder.partial_metadata() {
+            Some(partial) => log::error!("metadata incomplete: {}", partial),
+            None => log::error!("no metadata received"),
+        }
+        return Outcome::NoMetadata;
+    };
+    log::info!("total qrcode count: {}", md.qrcode_count);
+    log::info!("received qrcode count: {}", decoder.data_segments.len());
+    decoder.sync.report();
+    match decoder.complete_after_pass {
+        Some(pass) => log::info!(
+            "segments complete after sender pass {} of {} seen",
+            pass,
+            decoder.passes
+        ),
+        None if decoder.passes > 1 => log::info!("sender passes seen: {}", decoder.passes),
+        None => {}
+    }
+    if let Some(cache) = &decoder.cache {
+        log::info!(
+            "images decoded from cache: {} of {}",
+            cache.hits,
+            cache.hits + cache.misses
+        );
+    }
+    if !decoder.conflicts.is_empty() {
+        let ids: Vec<&u64> = decoder.conflicts.keys().collect();
+        log::warn!("segments with conflicting copies: {:?}", ids);
+    }
+    if decoder.unknown_frames > 0 {
+        log::warn!("unknown frames: {}", decoder.unknown_frames);
+    }
+    if decoder.foreign_codes() > 0 {
+        log::info!("codes skipped as no frame: {}", decoder.foreign_codes());
+    }
+    if decoder.conflicting_hash_frames > 0 {
+        log::warn!(
+            "hash frames differing from the first: {} of {}",
+            decoder.conflicting_hash_frames,
+            decoder.hash_frames
+        );
+    }
+    for (frame_type, count) in &decoder.skipped_frames {
+        log::info!("skipped {} frames: {}", frame_type.name(), count);
+    }
+    if decoder.undersized_frames > 0 {
+        log::warn!("undersized data frames: {}", decoder.undersized_frames);
+    }
+    if decoder.wrong_length_frames > 0 {
+        log::warn!(
+            "data frames of the wrong length: {}",
+            decoder.wrong_length_frames
+        );
+    }
+    if decoder.out_of_range_frames > 0 {
+        log::warn!(
+            "data frames naming segments past the last: {}",
+            decoder.out_of_range_frames
+        );
+    }
+    if decoder.filtered_frames > 0 {
+        log::info!(
+            "data frames outside --only-segments: {}",
+            decoder.filtered_frames
+        );
+    }
+    let Some(computed) = decoder.segments_hash() else {
+        log::warn!("missed segments: [{}]", decoder.missing_ranges());
+        match sink.file() {
+            Some(file) if allow_partial => return write_partial(decoder, &file.path, file.force),
+            None if allow_partial => log::warn!("--allow-partial only writes files"),
+            _ => {}
+        }
+        return Outcome::Incomplete;
+    };
+    let hash = md.file_hash.name();
+    if computed != decoder.total_hash {
+        log::error!("{} check failed", hash);
+        log::error!("computed {}: {}", hash, hex::encode(&computed));
+        log::error!("received {}: {}", hash, hex::encode(&decoder.total_hash));
+        return Outcome::HashMismatch;
+    }
+    log::info!("{} check passed", hash);
+    if let Some(size) = md.file_size.filter(|&size| size != decoder.assembled_len()) {
+        log::error!(
+            "segments add up to {} bytes but the metadata declares {}",
+            decoder.assembled_len(),
+            size
+        );
+        return Outcome::SizeMismatch;
+    }
+    if sink.taken() {
+        return Outcome::OutputExists;
+    }
+    if let Err(e) = sink.deliver(decoder) {
+        log::error!("could not write {}: {}", sink.name(), e);
+        return Outcome::WriteFailed;
+    }
+    Outcome::Verified
+}
+
+/// Write what `decoder` holds to `output_file` with zeros in place of the
+/// missing segments, and the map of those holes next to it.
+fn write_partial(decoder: &QrSendDecoder, output_file: &str, force: bool) -> Outcome {
+    let map = match HoleMap::of(decoder) {
+        Ok(map) => map,
+        Err(why) => {
+            log::error!("cannot write partial output: {}", why);
+            return Outcome::Incomplete;
+        }
+    };
+    if output_taken(output_file, force) {
+        return Outcome::OutputExists;
+    }
+    let partial = partial_path(output_file);
+    let map_path = HoleMap::path_for(output_file);
+    let written = decoder
+        .write_with_holes(&partial, map.file_size, map.segment_size)
+        .and_then(|_| fs::rename(&partial, output_file))
+        .and_then(|_| map.save(&map_path));
+    if let Err(e) = written {
+        let _ = fs::remove_file(&partial);
+        log::error!("could not write {}: {}", output_file, e);
+        return Outcome::WriteFailed;
+    }
+    log::warn!(
+        "wrote {} with {} bytes missing in {} holes, listed in {:?}",
+        output_file,
+        map.missing_bytes(),
+        map.holes.len(),
+        map_path
+    );
+    Outcome::Partial
+}
+
+/// Fill the holes of an `output_file` written with `--allow-partial` from
+/// the segments `decoder` now holds, then check the whole file.
+pub fn patch_partial(decoder: &QrSendDecoder, output_file: &str) -> Outcome {
+    let map_path = HoleMap::path_for(output_file);
+    let old = match HoleMap::load(&map_path) {
+        Ok(map) => map,
+        Err(e) => {
+            log::error!("cannot read {:?}: {}", map_path, e);
+            return Outcome::WriteFailed;
+        }
+    };
+    let Some(md) = &decoder.metadata else {
+        log::error!("no metadata received");
+        return Outcome::NoMetadata;
+    };
+    let on_disk = fs::metadata(output_file).map(|m| m.len()).ok();
+    if on_disk != Some(old.file_size)
+        || decoder
+            .segment_size()
+            .is_some_and(|size| size != old.segment_size)
+    {
+        log::error!(
+            "not patching {}, it does not match {:?}",
+            output_file,
+            map_path
+        );
+        return Outcome::WriteFailed;
+    }
+    let ids = old.segment_ids();
+    let target = path::Path::new(output_file);
+    match decoder.patch_segments(target, old.segment_size, &ids) {
+        Ok(patched) => log::info!(
+            "patched {} of {} missing segments into {}",
+            patched,
+            ids.len(),
+            output_file
+        ),
+        Err(e) => {
+            log::error!("could not patch {}: {}", output_file, e);
+            return Outcome::WriteFailed;
+        }
+    }
+    let map = match HoleMap::of(decoder) {
+        Ok(map) => map,
+        Err(why) => {
+            log::error!("cannot map what is still missing: {}", why);
+            return Outcome::Incomplete;
+        }
+    };
+    if !map.holes.is_empty() {
+        log::warn!("missed segments: [{}]", decoder.missing_ranges());
+        if let Err(e) = map.save(&map_path) {
+            log::error!("could not update {:?}: {}", map_path, e);
+            return Outcome::WriteFailed;
+        }
+        log::warn!(
+            "{} still has {} bytes missing in {} holes",
+            output_file,
+            map.missing_bytes(),
+            map.holes.len()
+        );
+        return Outcome::Partial;
+    }
+    // Every byte was written, some runs ago; only the file itself can vouch for them.
+    let computed = match fs::File::open(target).and_then(|mut f| {
+        let mut hasher = md.file_hash.hasher();
+        io::copy(&mut f, &mut hasher)?;
+        Ok(hasher.finalize())
+    }) {
+        Ok(digest) => digest,
+        Err(e) => {
+            log::error!("could not read back {}: {}", output_file, e);
+            return Outcome::WriteFailed;
+        }
+    };
+    let hash = md.file_hash.name();
+    if computed != decoder.total_hash {
+        log::error!("{} check of the patched file failed", hash);
+        log::error!("computed {}: {}", hash, hex::encode(&computed));
+        log::error!("received {}: {}", hash, hex::encode(&decoder.total_hash));
+        return Outcome::HashMismatch;
+    }
+    log::info!("{} check passed", hash);
+    restore_mtime(md, target);
+    if let Err(e) = fs::remove_file(&map_path) {
+        log::warn!("could not remove {:?}: {}", map_path, e);
+    }
+    Outcome::Verified
+}
+
+/// Write the `.sha256` sidecar of a verified `output_file`.
+pub fn write_sidecar(decoder: &QrSendDecoder, output_file: &str) {
+    let digests = Digests::of(decoder).expect("verified transfer is complete");
+    match digests.write_sidecar(output_file) {
+        Ok(path) => log::info!("checksum written to {:?}", path),
+        Err(e) => log::error!("could not write the checksum sidecar: {}", e),
+    }
+}
+
+/// Whether `output_file` exists and may not be replaced, logging why.
+pub fn output_taken(output_file: &str, force: bool) -> bool {
+    let taken = !force && path::Path::new(output_file).exists();
+    if taken {
+        log::error!("{} already exists, pass --force to replace it", output_file);
+    }
+    taken
+}
+
+pub fn write_output(
+    decoder: &QrSendDecoder,
+    md: &QrSendMetadata,
+    path: &path::Path,
+) -> io::Result<()> {
+    // Written segment by segment either way, so large transfers are never
+    // held twice in memory.
+    match md.file_size {
+        // Senders that declare the size get it preallocated and written in parallel.
+        Some(_) => decoder.write_segments(path)?,
+        None => {
+            let mut out = io::BufWriter::new(fs::File::create(path)?);
+            decoder.write_in_order(&mut out)?;
+            out.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+        }
+    }
+    restore_mtime(md, path);
+    Ok(())
+}
+
+fn restore_mtime(md: &QrSendMetadata, path: &path::Path) {
+    if let Some(mtime) = md.mtime {
+        let restored = fs::File::options()
+            .write(true)
+            .open(path)
+            .and_then(|f| f.set_modified(UNIX_EPOCH + Duration::from_secs(mtime)));
+        if let Err(e) = restored {
+            log::warn!("could not restore the modification time: {}", e);
+        }
+    }
+}
+
+/// The file name from the metadata, when it is safe to write to.
+fn declared_name(decoder: &QrSendDecoder) -> Option<String> {
+    let md = decoder.metadata.as_ref()?;
+    let name = md.safe_file_name();
+    if name.is_none() && md.file_name.is_some() {
+        log::warn!("ignoring unsafe declared file name {:?}", md.file_name);
+    }
+    let name = name?.to_string();
+    log::info!("writing to {:?}, the name the sender declared", name);
+    Some(name)
+}
+
+/// The progress view the options ask for, if any.
+fn view(args: &ReceiveArgs, source: &str) -> Option<View> {
+    if args.tui {
+        return Some(View::Dashboard(Dashboard::new(source.to_string())));
+    }
+    args.bitmap.then(|| View::Bitmap(AckBitmap::new()))
+}
+
+/// Stream `frames` into `decoder`, optionally behind a progress view,
+/// calling `on_frame` after each.
+fn watch<I, F>(
+    decoder: &mut QrSendDecoder,
+    source: String,
+    frames: I,
+    view: Option<View>,
+    mut on_frame: F,
+) -> RunRecord
+where
+    I: Iterator<Item = image::DynamicImage>,
+    F: FnMut(&QrSendDecoder),
+{
+    let Some(mut view) = view else {
+        return streaming_run(decoder, source, frames, |d, _| on_frame(d));
+    };
+    let run = streaming_run(decoder, source, frames, |d, _| {
+        view.update(d);
+        on_frame(d);
+    });
+    view.draw(decoder);
+    run
+}
+
+/// Frames grabbed live from the display or the clipboard, with a name for
+/// their source; `None` when reading a capture instead.
+fn live_frames(
+    args: &ReceiveArgs,
+) -> Option<(String, Box<dyn Iterator<Item = image::DynamicImage> + Send>)> {
+    let limit = args.capture_secs.map(Duration::from_secs_f64);
+    if let Some(region) = args.screen_region {
+        let source = format!(
+            "screen:{},{},{},{}",
+            region.x, region.y, region.width, region.height
+        );
+        let capture = ScreenCapture::new(region, args.capture_fps, limit);
+        return Some((source, Box::new(capture)));
+    }
+    if args.clipboard {
+        let watch = ClipboardWatch::new(args.capture_fps, limit);
+        return Some(("clipboard".to_string(), Box::new(watch)));
+    }
+    if let Some(format) = args.stdin_frames {
+        let stream = FrameStream::new(io::BufReader::new(io::stdin()), format);
+        return Some(("stdin".to_string(), Box::new(stream)));
+    }
+    None
+}
+
+/// Log what has arrived so far, so whoever feeds the clipboard knows when
+/// to move on.
+fn ack(decoder: &QrSendDecoder) {
+    let Some(md) = &decoder.metadata else {
+        log::info!("waiting for the metadata frame");
+        return;
+    };
+    match decoder.data_segments.first_missing(md.qrcode_count) {
+        Some(next) => log::info!(
+            "{} of {} segments, next missing {}",
+            decoder.data_segments.len(),
+            md.qrcode_count,
+            next
+        ),
+        None if decoder.total_hash.is_empty() => {
+            log::info!(
+                "all {} segments, waiting for the hash frame",
+                md.qrcode_count
+            )
+        }
+        None => log::info!("all {} segments and the hash", md.qrcode_count),
+    }
+}
+
+/// Protocol of the first qrcode found in `image_dir`.
+fn detect_protocol(args: &ReceiveArgs, image_dir: &str) -> ProtocolKind {
+    let images = ImageSequence {
+        image_dir: path::PathBuf::from(image_dir),
+        options: args.read.clone(),
+    };
+    let Some(symbol) = images
+        .into_iter()
+        .find_map(|img| scan(&img).into_iter().next())
+    else {
+        return ProtocolKind::QrSend;
+    };
+    let protocol = ProtocolKind::detect(&symbol.data);
+    if protocol != ProtocolKind::QrSend {
+        log::info!("detected {:?} frames", protocol);
+    }
+    protocol
+}
+
+/// Receive a transfer in another sender's frame format: the first code of
+/// every image goes to `receiver` until the transfer is complete.
+fn run_interop(
+    args: &ReceiveArgs,
+    protocol: ProtocolKind,
+    mut receiver: impl Protocol,
+) -> ExitCode {
+    let Some(output_file) = &args.output_file else {
+        log::error!("--output-file is required for {:?} transfers", protocol);
+        return ExitCode::FAILURE;
+    };
+    let frames: Box<dyn Iterator<Item = image::DynamicImage>> = match live_frames(args) {
+        Some((_, frames)) => frames,
+        None => Box::new(args.image_dir.iter().flat_map(|image_dir| {
+            ImageSequence {
+                image_dir: path::PathBuf::from(image_dir),
+                options: args.read.clone(),
+            }
+            .into_iter()
+        })),
+    };
+    for img in frames {
+        let Some(symbol) = scan(&img).into_iter().next() else {
+            continue;
+        };
+        if !receiver.push_text(&symbol.data) {
+            log::debug!("skipping a qrcode that is not a {:?} frame", protocol);
+        }
+        if receiver.is_complete() {
+            break;
+        }
+    }
+    let (received, total) = receiver.progress();
+    log::info!("received {} of {:?} source blocks", received, total);
+    let outcome = match receiver.finish() {
+        Ok(data) => {
+            let partial = partial_path(output_file);
+            match fs::write(&partial, data).and_then(|_| fs::rename(&partial, output_file)) {
+                Ok(()) => Outcome::Verified,
+                Err(e) => {
+                    let _ = fs::remove_file(&partial);
+                    log::error!("could not write {}: {}", output_file, e);
+                    Outcome::WriteFailed
+                }
+            }
+        }
+        Err(e) => {
+            log::error!("{}", e);
+            Outcome::Incomplete
+        }
+    };
+    outcome.exit_status(args.expect_complete)
+}
+
+/// Where the options send the file, by default to the file name the
+/// sender declares; `None` when that is not usable.
+fn output_sink(args: &ReceiveArgs, decoder: &QrSendDecoder) -> Option<Box<dyn Sink>> {
+    if let Some(command_line) = &args.pipe_to {
+        return Some(Box::new(CommandSink::shell(command_line)));
+    }
+    #[cfg(feature = "http")]
+    if let Some(url) = &args.put_url {
+        return Some(Box::new(CommandSink::http_put(url)));
+    }
+    let path = match args.output_file.clone() {
+        Some(path) if path == "-" => return Some(Box::new(StdoutSink)),
+        Some(path) => path,
+        None => declared_name(decoder)?,
+    };
+    Some(Box::new(FileSink {
+        path,
+        force: args.force,
+    }))
+}
+
+/// A decoder set up as the receive options ask.
+fn new_decoder(args: &ReceiveArgs) -> QrSendDecoder {
+    let mut decoder = QrSendDecoder::new();
+    decoder.strict_hash_len = args.strict_hash_len;
+    decoder.only_segments = args.only_segments.clone();
+    decoder.strict = args.strict;
+    decoder.rotation_retry = !args.read.no_rotation_retry;
+    decoder.perspective = args.read.perspective;
+    decoder.exposure_retry = !args.read.no_exposure_retry;
+    decoder.pad_retry = !args.read.no_pad_retry;
+    decoder.scale_retry = !args.read.no_scale_retry;
+    decoder.crop = args.read.crop;
+    decoder.cache = args.read.open_cache();
+    decoder.readers = args.read.readers();
+    decoder.unknown_dump = args.dump_unknown_frames.as_ref().map(path::PathBuf::from);
+    decoder.payload_dump = args.dump_payloads.as_ref().map(path::PathBuf::from);
+    decoder.max_frames = args.max_frames;
+    decoder.deadline = args
+        .max_duration
+        .map(|secs| Instant::now() + Duration::from_secs_f64(secs));
+    decoder
+}
+
+/// Capture directories listed in the `--batch` file at `path`.
+fn read_batch(path: &str) -> io::Result<Vec<String>> {
+    Ok(fs::read_to_string(path)?
+        .lines()
+        .map(|line| line.split('#').next().unwrap_or_default().trim())
+        .filter(|line| !line.is_empty())
+        .map(str::to_string)
+        .collect())
+}
+
+/// Receive each capture listed in `batch` as a transfer of its own, into
+/// `--batch-output-dir`, with a report next to each file and a summary of
+/// them all at the end.
+///
+/// Exits with the highest status of any of the transfers.
+fn run_batch(args: &ReceiveArgs, batch: &str) -> ExitCode {
+    if !matches!(args.protocol, ProtocolKind::Auto | ProtocolKind::QrSend) {
+        log::error!("--batch only reads qr-send captures");
+        return ExitCode::FAILURE;
+    }
+    let image_dirs = match read_batch(batch) {
+        Ok(dirs) if !dirs.is_empty() => dirs,
+        Ok(_) => {
+            log::error!("{} lists no captures", batch);
+            return ExitCode::FAILURE;
+        }
+        Err(e) => {
+            log::error!("could not read {}: {}", batch, e);
+            return ExitCode::FAILURE;
+        }
+    };
+    let output_dir = path::Path::new(&args.batch_output_dir);
+    if let Err(e) = fs::create_dir_all(output_dir) {
+        log::error!("could not create {}: {}", output_dir.display(), e);
+        return ExitCode::FAILURE;
+    }
+    let mut summary = Vec::with_capacity(image_dirs.len());
+    let mut names = BTreeSet::new();
+    for (i, image_dir) in image_dirs.iter().enumerate() {
+        log::info!("transfer {} of {}: {}", i + 1, image_dirs.len(), image_dir);
+        let mut decoder = new_decoder(args);
+        let img_seq = ImageSequence {
+            image_dir: path::PathBuf::from(image_dir),
+            options: args.read.clone(),
+        };
+        let run = timed_run(&mut decoder, img_seq, args.pipeline_depth);
+        // Without a usable declared name the file is named for its capture.
+        let mut name = declared_name(&decoder).unwrap_or_else(|| {
+            path::Path::new(image_dir).file_name().map_or_else(
+                || format!("transfer-{}", i + 1),
+                |n| n.to_string_lossy().into(),
+            )
+        });
+        // Nor may one transfer of the batch replace another's file.
+        if !names.insert(name.clone()) {
+            name = format!("{}-{}", i + 1, name);
+            log::info!(
+                "another transfer of the batch is named the same, writing to {:?}",
+                name
+            );
+            names.insert(name.clone());
+        }
+        let output_file = output_dir.join(name).display().to_string();
+        let mut sink = FileSink {
+            path: output_file.clone(),
+            force: args.force,
+        };
+        let outcome = finish(&decoder, &mut sink, args.allow_partial);
+        if args.sha256_sidecar && outcome == Outcome::Verified {
+            write_sidecar(&decoder, &output_file);
+        }
+        let digests = (outcome == Outcome::Verified)
+            .then(|| Digests::of(&decoder))
+            .flatten();
+        let runs = [run];
+        let report = AggregateReport::new(
+            &runs,
+            &decoder,
+            outcome,
+            digests,
+            advise_from_run(&decoder, args.read.source_fps),
+        );
+        match report.write_alongside(&output_file) {
+            Ok(path) => log::info!("report written to {:?}", path),
+            Err(e) => log::error!("could not write the report of {}: {}", output_file, e),
+        }
+        let [run] = runs;
+        summary.push((image_dir, output_file, outcome, run));
+    }
+    let verified = summary
+        .iter()
+        .filter(|(.., outcome, _)| *outcome == Outcome::Verified)
+        .count();
+    println!("{} of {} transfers verified", verified, summary.len());
+    for (image_dir, output_file, outcome, run) in &summary {
+        println!(
+            "  {}: {:?}, {} segments from {} frames in {:.1}s -> {}",
+            image_dir, outcome, run.new_segments, run.frames, run.elapsed_secs, output_file
+        );
+    }
+    summary
+        .iter()
+        .map(|(_, _, outcome, _)| *outcome)
+        .max_by_key(|outcome| outcome.exit_code())
+        .map_or(ExitCode::SUCCESS, |outcome| {
+            outcome.exit_status(args.expect_complete)
+        })
+}
+
+/// Receive one transfer, exiting with the status of its [`Outcome`].
+pub fn run(args: &ReceiveArgs) -> ExitCode {
+    if let Some(batch) = &args.batch {
+        return run_batch(args, batch);
+    }
+    if args
+        .output_file
+        .as_ref()
+        .is_some_and(|f| f != "-" && output_taken(f, args.force))
+    {
+        return Outcome::OutputExists.exit_status(false);
+    }
+    let protocol = match (args.protocol, args.image_dir.first()) {
+        (ProtocolKind::Auto, Some(image_dir)) => detect_protocol(args, image_dir),
+        (ProtocolKind::Auto, _) => ProtocolKind::QrSend,
+        (protocol, _) => protocol,
+    };
+    match protocol {
+        ProtocolKind::Auto | ProtocolKind::QrSend => {}
+        ProtocolKind::Split => return run_interop(args, protocol, SplitText::new()),
+    }
+    let mut decoder = new_decoder(args);
+    if let Some(trace) = &args.trace {
+        match Trace::create(path::Path::new(trace)) {
+            Ok(trace) => decoder.trace = Some(trace),
+            Err(e) => {
+                log::error!("could not create {}: {}", trace, e);
+                return ExitCode::FAILURE;
+            }
+        }
+    }
+    let mut notifier = args
+        .notify
+        .then(|| Notifier::new(Duration::from_secs_f64(args.stall_secs)));
+    // Only a grab in real time tells when a looping sender shows what.
+    let mut schedule =
+        (args.screen_region.is_some() || args.stdin_frames.is_some()).then(LoopSchedule::new);
+    let mut stall_check = |d: &QrSendDecoder| {
+        if let Some(notifier) = notifier.as_mut() {
+            notifier.on_frame(d);
+        }
+        if let Some(schedule) = schedule.as_mut() {
+            schedule.on_frame(d);
+        }
+    };
+    // Behind a queue of its own, a live source drops what decoding cannot take.
+    let mut dropped = None;
+    let live = live_frames(args).map(|(source, frames)| match args.pipeline_depth {
+        Some(depth) => {
+            let queue = LiveQueue::new(frames, depth);
+            dropped = Some(queue.dropped());
+            (source, Box::new(queue) as Box<dyn Iterator<Item = _>>)
+        }
+        None => (source, frames as Box<dyn Iterator<Item = _>>),
+    });
+    let mut runs = match (live, args.image_dir.as_slice()) {
+        #[cfg(feature = "gui")]
+        (Some((source, frames)), _) if args.preview => {
+            let command = args
+                .preview_cmd
+                .as_ref()
+                .map_or_else(preview::default_command, |c| {
+                    c.split_whitespace().map(str::to_string).collect()
+                });
+            let mut preview = Preview::new(command);
+            vec![streaming_run(&mut decoder, source, frames, |d, img| {
+                preview.update(d, img);
+                if args.clipboard {
+                    ack(d);
+                }
+                stall_check(d);
+            })]
+        }
+        (Some((source, frames)), _) if args.clipboard && !args.tui && !args.bitmap => {
+            vec![streaming_run(&mut decoder, source, frames, |d, _| {
+                ack(d);
+                stall_check(d);
+            })]
+        }
+        (Some((source, frames)), _) => {
+            let view = view(args, &source);
+            vec![watch(&mut decoder, source, frames, view, stall_check)]
+        }
+        (None, [image_dir]) if args.tui || args.bitmap => {
+            let img_seq = ImageSequence {
+                image_dir: path::PathBuf::from(image_dir),
+                options: args.read.clone(),
+            };
+            let mut images = img_seq.into_iter();
+            let mut run = watch(
+                &mut decoder,
+                image_dir.clone(),
+                images.by_ref(),
+                view(args, image_dir),
+                stall_check,
+            );
+            report_unreadable(&images);
+            run.unreadable_images = images.unreadable;
+            vec![run]
+        }
+        (None, [image_dir]) => {
+            let img_seq = ImageSequence {
+                image_dir: path::PathBuf::from(image_dir),
+                options: args.read.clone(),
+            };
+            vec![timed_run(&mut decoder, img_seq, args.pipeline_depth)]
+        }
+        (None, []) => match &args.payload_dir {
+            Some(payload_dir) => match payload_run(&mut decoder, path::Path::new(payload_dir)) {
+                Ok(run) => vec![run],
+                Err(e) => {
+                    log::error!("could not read payloads from {}: {}", payload_dir, e);
+                    return ExitCode::FAILURE;
+                }
+            },
+            None => unreachable!("clap requires --image-dir without a live source"),
+        },
+        (None, image_dirs) => {
+            let sources = image_dirs
+                .iter()
+                .map(|image_dir| {
+                    let img_seq = ImageSequence {
+                        image_dir: path::PathBuf::from(image_dir),
+                        options: args.read.clone(),
+                    };
+                    (image_dir.clone(), img_seq.into_iter())
+                })
+                .collect();
+            let mut view = view(args, &image_dirs.join(", "));
+            let runs = merged_run(&mut decoder, sources, |d, _| {
+                if let Some(view) = view.as_mut() {
+                    view.update(d);
+                }
+                stall_check(d);
+            });
+            if let Some(mut view) = view {
+                view.draw(&decoder);
+            }
+            runs
+        }
+    };
+    if let (Some(dropped), [run]) = (dropped, &mut runs[..]) {
+        run.dropped_per_sec = dropped.per_sec();
+        let total: u64 = run.dropped_per_sec.iter().sum();
+        if total > 0 {
+            log::warn!(
+                "frames dropped while decoding lagged: {}, at most {} in a second",
+                total,
+                run.dropped_per_sec.iter().max().unwrap()
+            );
+        }
+    }
+    let sink = output_sink(args, &decoder);
+    // A run cut short still leaves something to resume from, under the
+    // transfer id when the sender sent one, so that sessions of transfers
+    // of the same file name stay apart. Saved before a missing sink ends the
+    // run, so what arrived is kept for a resume that names the output.
+    let session = args.session.clone().or_else(|| {
+        let file = sink.as_ref()?.file()?;
+        let key = decoder.manifest.as_ref().and_then(|m| m.file_key());
+        (decoder.budget_spent && !decoder.is_complete()).then(|| match key {
+            Some(key) => format!("{}.{}.session.json", file.path, key),
+            None => format!("{}.session.json", file.path),
+        })
+    });
+    if let Some(session) = &session {
+        let mut state = Session::from_decoder(&decoder);
+        state.runs.extend(runs);
+        if let Err(e) = state.save(path::Path::new(session), &args.session_key) {
+            log::error!("cannot save the session to {}: {}", session, e);
+        }
+    }
+    let Some(mut sink) = sink else {
+        log::error!("no --output-file given and the sender declared no usable file name");
+        if let Some(session) = &session {
+            log::info!(
+                "continue later with `qr-recv resume --session {} --output-file FILE`",
+                session
+            );
+        }
+        return match decoder.metadata {
+            None => Outcome::NoMetadata.exit_status(false),
+            Some(_) => ExitCode::FAILURE,
+        };
+    };
+    let outcome = finish(&decoder, sink.as_mut(), args.allow_partial);
+    match sink.file() {
+        Some(file) if args.sha256_sidecar && outcome == Outcome::Verified => {
+            write_sidecar(&decoder, &file.path)
+        }
+        None if args.sha256_sidecar => log::warn!("no checksum sidecar for {}", sink.name()),
+        _ => {}
+    }
+    if let Some(notifier) = &notifier {
+        notifier.finished(outcome, &sink.name());
+    }
+    let capture_fps = if args.screen_region.is_some() || args.clipboard {
+        args.capture_fps
+    } else {
+        args.read.source_fps
+    };
+    if let Some(advice) = advise_from_run(&decoder, capture_fps) {
+        log::info!(
+            "next time try: --chunk-size {} --ecc {} --fps {:.1} (qr version {})",
+            advice.chunk_size,
+            format!("{:?}", advice.ecc).to_lowercase(),
+            advice.fps,
+            advice.version
+        );
+    }
+    if let Some(session) = session.filter(|_| decoder.budget_spent && outcome != Outcome::Verified)
+    {
+        log::info!("continue later with `qr-recv resume --session {}`", session);
+    }
+    outcome.exit_status(args.expect_complete)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::holes::Hole;
+    use crate::protocol::QrSendData;
+    use crate::synth::{seal, Options, Transfer};
+
+    /// Keeps what it is given, to tell a delivered file from none.
+    #[derive(Default)]
+    struct Kept(Option<Vec<u8>>);
+    impl Sink for Kept {
+        fn name(&self) -> String {
+            "memory".to_string()
+        }
+        fn deliver(&mut self, decoder: &QrSendDecoder) -> io::Result<()> {
+            self.0 = decoder.assemble();
+            Ok(())
+        }
+    }
+
+    /// The outcomes of decoding `frames` strictly and leniently, checking
+    /// that `file` is delivered when verified and nothing otherwise.
+    fn outcomes(frames: &[Vec<u8>], file: &[u8]) -> (Outcome, Outcome) {
+        let run = |strict| {
+            let mut decoder = QrSendDecoder::new();
+            decoder.strict = strict;
+            for frame in frames {
+                decoder.push(frame.clone());
+            }
+            let mut sink = Kept::default();
+            let outcome = finish(&decoder, &mut sink, false);
+            match outcome {
+                Outcome::Verified => assert_eq!(sink.0.as_deref(), Some(file)),
+                _ => assert_eq!(sink.0, None),
+            }
+            outcome
+        };
+        (run(true), run(false))
+    }
+
+    #[test]
+    fn strict_refuses_metadata_sealed_with_another_hash_length() {
+        let transfer = Transfer::new(&Options::default());
+        let mut frames = transfer.frames.clone();
+        let first = transfer.frame_indices(b'M')[0];
+        // Resealed with 16 byte hashes, while declaring the usual 8.
+        let body = frames[first][..frames[first].len() - 8].to_vec();
+        frames[first] = seal(&body, 16);
+        assert_eq!(
+            outcomes(&frames, &transfer.file),
+            (Outcome::StrictViolation, Outcome::Verified)
+        );
+    }
+
+    #[test]
+    fn strict_refuses_unknown_frame_types() {
+        let transfer = Transfer::new(&Options::default());
+        let mut frames = transfer.frames.clone();
+        frames.insert(3, seal(b"Zsomething new", 8));
+        assert_eq!(
+            outcomes(&frames, &transfer.file),
+            (Outcome::StrictViolation, Outcome::Verified)
+        );
+    }
+
+    #[test]
+    fn strict_refuses_conflicting_segments() {
+        let transfer = Transfer::new(&Options::default());
+        let mut frames = transfer.frames.clone();
+        let data = &frames[transfer.frame_indices(b'D')[0]];
+        let mut body = data[..data.len() - 8].to_vec();
+        *body.last_mut().unwrap() ^= 1;
+        frames.push(seal(&body, 8));
+        assert_eq!(
+            outcomes(&frames, &transfer.file),
+            (Outcome::StrictViolation, Outcome::Verified)
+        );
+    }
+
+    #[test]
+    fn sealed_copy_replaces_a_conflicting_one_under_crc32c() {
+        let transfer = Transfer::new(&Options {
+            crc32c: true,
+            ..Options::default()
+        });
+        let (data, rest): (Vec<_>, Vec<_>) = transfer.frames.iter().partition(|f| f[0] == b'D');
+        let mut decoder = QrSendDecoder::new();
+        for frame in rest {
+            decoder.push(frame.clone());
+        }
+        // A corrupt copy, as a stale session can hold, with a hash that fails.
+        let corrupt = QrSendData::new(0, &[0; 512], &[0; 8]);
+        decoder.data_segments.insert(corrupt);
+        for frame in data {
+            decoder.push(frame.clone());
+        }
+        assert_eq!(decoder.conflicts.get(&0), Some(&1));
+        let mut sink = Kept::default();
+        assert_eq!(finish(&decoder, &mut sink, false), Outcome::Verified);
+        assert_eq!(sink.0.unwrap(), transfer.file);
+    }
+
+    #[test]
+    fn only_segments_filters_after_the_hash_check() {
+        let transfer = Transfer::new(&Options::default());
+        let mut decoder = QrSendDecoder::new();
+        decoder.only_segments = Some("1".parse().unwrap());
+        for frame in transfer.frames.iter().filter(|f| f[0] != b'D') {
+            decoder.push(frame.clone());
+        }
+        let data = transfer.frame_indices(b'D');
+        // Segment 0 with its u8 id flipped to 2, which no hash vouches for.
+        let mut corrupt = transfer.frames[data[0]].clone();
+        corrupt[1] = 2;
+        decoder.push(corrupt);
+        assert_eq!((decoder.rejected_frames, decoder.filtered_frames), (1, 0));
+        assert_eq!(decoder.passes, 0);
+        decoder.push(transfer.frames[data[2]].clone());
+        decoder.push(transfer.frames[data[1]].clone());
+        assert_eq!((decoder.rejected_frames, decoder.filtered_frames), (1, 1));
+        assert_eq!(decoder.data_segments.len(), 1);
+        assert!(decoder.data_segments.get(1).is_some());
+    }
+
+    /// A decoder fed every frame of `frames`.
+    fn decoded(frames: &[Vec<u8>]) -> QrSendDecoder {
+        let mut decoder = QrSendDecoder::new();
+        for frame in frames {
+            decoder.push(frame.clone());
+        }
+        decoder
+    }
+
+    #[test]
+    fn output_is_replaced_only_with_force_and_only_when_verified() {
+        let transfer = Transfer::new(&Options::default());
+        let dir = std::env::temp_dir().join(format!("qr-recv-force-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let output = dir.join("out.bin").to_string_lossy().into_owned();
+        fs::write(&output, b"older").unwrap();
+        let mut sink = FileSink {
+            path: output.clone(),
+            force: false,
+        };
+        let decoder = decoded(&transfer.frames);
+        assert_eq!(finish(&decoder, &mut sink, false), Outcome::OutputExists);
+        assert_eq!(fs::read(&output).unwrap(), b"older");
+
+        // Forced, a file failing its hash still leaves the old one alone.
+        sink.force = true;
+        let hash = transfer.frame_indices(b'H')[0];
+        let mut frames = transfer.frames.clone();
+        let body = &frames[hash][..frames[hash].len() - 8];
+        let mut other = body.to_vec();
+        *other.last_mut().unwrap() ^= 1;
+        frames[hash] = seal(&other, 8);
+        assert_eq!(
+            finish(&decoded(&frames), &mut sink, false),
+            Outcome::HashMismatch
+        );
+        assert_eq!(fs::read(&output).unwrap(), b"older");
+
+        assert_eq!(finish(&decoder, &mut sink, false), Outcome::Verified);
+        assert_eq!(fs::read(&output).unwrap(), transfer.file);
+        assert!(!partial_path(&output).exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn sidecar_and_report_carry_the_verified_digests() {
+        let transfer = Transfer::new(&Options::default());
+        let dir = std::env::temp_dir().join(format!("qr-recv-sidecar-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let output = dir.join("out.bin").to_string_lossy().into_owned();
+        let decoder = decoded(&transfer.frames);
+        write_sidecar(&decoder, &output);
+        let sha256 = hex::encode(crate::sha256::digest(&transfer.file));
+        assert_eq!(
+            fs::read_to_string(format!("{}.sha256", output)).unwrap(),
+            format!("{}  out.bin\n", sha256)
+        );
+
+        let digests = Digests::of(&decoder);
+        let report = AggregateReport::new(&[], &decoder, Outcome::Verified, digests, None);
+        let report = serde_json::to_value(&report).unwrap();
+        let digests = &report["digests"];
+        assert_eq!(digests["sha256"], sha256);
+        assert_eq!(digests["md5"], hex::encode(md5::compute(&transfer.file).0));
+        let segments = digests["segments"].as_array().unwrap();
+        assert_eq!(segments.len(), 8);
+        for (segment, data) in segments.iter().zip(transfer.file.chunks(512)) {
+            assert_eq!(segment, &hex::encode(crate::sha256::digest(data)));
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[derive(clap::Parser)]
+    struct Receive {
+        #[command(flatten)]
+        args: ReceiveArgs,
+    }
+
+    fn receive_args(args: &[&str]) -> ReceiveArgs {
+        use clap::Parser;
+        let argv = std::iter::once("receive").chain(args.iter().copied());
+        Receive::try_parse_from(argv).unwrap().args
+    }
+
+    #[test]
+    fn batch_reports_each_capture_under_its_own_name() {
+        let dir = std::env::temp_dir().join(format!("qr-recv-batch-{}", std::process::id()));
+        let blank = image::GrayImage::from_pixel(64, 64, image::Luma([255]));
+        for capture in ["x/cap", "y/cap", "z/other"] {
+            fs::create_dir_all(dir.join(capture)).unwrap();
+            blank.save(dir.join(capture).join("000.png")).unwrap();
+        }
+        let list = dir.join("captures.txt");
+        let capture = |c: &str| dir.join(c).display().to_string();
+        fs::write(
+            &list,
+            format!(
+                "# three captures\n{}\n\n{}  # filmed again\n{}\n",
+                capture("x/cap"),
+                capture("y/cap"),
+                capture("z/other")
+            ),
+        )
+        .unwrap();
+        let out = dir.join("out");
+        let args = receive_args(&[
+            "--batch",
+            list.to_str().unwrap(),
+            "--batch-output-dir",
+            out.to_str().unwrap(),
+        ]);
+        assert_eq!(run_batch(&args, list.to_str().unwrap()), ExitCode::from(4));
+        for name in ["cap", "2-cap", "other"] {
+            let report = fs::read(out.join(format!("{}.report.json", name))).unwrap();
+            let report: serde_json::Value = serde_json::from_slice(&report).unwrap();
+            assert_eq!(report["outcome"], "no_metadata", "{}", name);
+            assert!(!out.join(name).exists());
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn unfinished_transfers_exit_2() {
+        let transfer = Transfer::new(&Options::default());
+        let mut frames = transfer.frames.clone();
+        frames.remove(transfer.frame_indices(b'D')[1]);
+        let (strict, lenient) = outcomes(&frames, &transfer.file);
+        assert_eq!(
+            (strict, lenient),
+            (Outcome::Incomplete, Outcome::Incomplete)
+        );
+        assert_eq!(lenient.exit_code(), 2);
+        assert_eq!(Outcome::Partial.exit_code(), 2);
+        assert_eq!(Outcome::Verified.exit_code(), 0);
+    }
+
+    #[test]
+    fn partial_output_is_patched_on_resume() {
+        // 4096 bytes in 512 byte segments with u8 ids, of which 2, 3 and 7 are missed.
+        let transfer = Transfer::new(&Options::default());
+        let missed = |frame: &[u8]| frame[0] == b'D' && [2, 3, 7].contains(&frame[1]);
+        let dir = std::env::temp_dir().join(format!("qr-recv-partial-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let output = dir.join("out.bin").to_string_lossy().into_owned();
+        let map_path = HoleMap::path_for(&output);
+
+        let mut first = QrSendDecoder::new();
+        for frame in transfer.frames.iter().filter(|f| !missed(f)) {
+            first.push(frame.clone());
+        }
+        let mut sink = FileSink {
+            path: output.clone(),
+            force: false,
+        };
+        assert_eq!(finish(&first, &mut sink, true), Outcome::Partial);
+        let map = HoleMap::load(&map_path).unwrap();
+        assert_eq!((map.file_size, map.segment_size), (4096, 512));
+        assert_eq!(
+            map.holes,
+            [
+                Hole {
+                    offset: 1024,
+                    len: 1024,
+                    first_segment: 2,
+                    segment_count: 2,
+                },
+                Hole {
+                    offset: 3584,
+                    len: 512,
+                    first_segment: 7,
+                    segment_count: 1,
+                },
+            ]
+        );
+        let written = fs::read(&output).unwrap();
+        for (i, segment) in written.chunks(512).enumerate() {
+            if [2, 3, 7].contains(&i) {
+                assert!(segment.iter().all(|&b| b == 0), "segment {}", i);
+            } else {
+                assert_eq!(segment, &transfer.file[i * 512..][..512], "segment {}", i);
+            }
+        }
+
+        // Resumed from the session, the missed segments arrive.
+        let mut second = Session::from_decoder(&first).into_decoder();
+        for frame in transfer.frames.iter().filter(|f| missed(f)) {
+            second.push(frame.clone());
+        }
+        assert_eq!(patch_partial(&second, &output), Outcome::Verified);
+        assert_eq!(fs::read(&output).unwrap(), transfer.file);
+        assert!(!map_path.exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
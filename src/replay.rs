@@ -0,0 +1,240 @@
+//! `qr-recv replay`: feed the frames a `receive --trace` run found back
+//! through the decoder, read from its decode cache rather than scanned from
+//! the images, to rerun a transfer exactly as it went, without the capture.
+
+use std::path;
+use std::process::ExitCode;
+
+use crate::api::Error;
+use crate::cache::DecodeCache;
+use crate::decoder::QrSendDecoder;
+use crate::ranges::SegmentRanges;
+use crate::receive::{finish, Outcome};
+use crate::sink::FileSink;
+use crate::trace::Trace;
+
+#[derive(clap::Args)]
+pub struct ReplayArgs {
+    /// Trace written by `receive --trace` or `resume --trace`
+    trace: String,
+    /// Decode cache of the traced run, its `--cache-dir`
+    #[clap(long, value_name = "DIR")]
+    cache_dir: String,
+    /// Write the file here once verified; without it only the outcome is told
+    #[clap(short, long)]
+    output_file: Option<String>,
+    /// Replace the output file if it already exists
+    #[clap(long)]
+    force: bool,
+    /// Refuse data frames whose hash length is guessed rather than taken from metadata
+    #[clap(long)]
+    strict_hash_len: bool,
+    /// Only store these segments, as `receive --only-segments`
+    #[clap(long, value_name = "IDS")]
+    only_segments: Option<SegmentRanges>,
+    /// Fail closed on any ambiguity, as `receive --strict`
+    #[clap(long)]
+    strict: bool,
+    /// Write the trace of the replay to this file, to diff against the original
+    #[clap(long, value_name = "FILE")]
+    replay_trace: Option<String>,
+    /// Log an error unless the file is complete and verified
+    #[clap(long)]
+    expect_complete: bool,
+}
+
+/// The outcome of a transfer that is only checked, not written.
+fn outcome_of(decoder: &QrSendDecoder) -> Outcome {
+    if decoder.violation.is_some() {
+        return Outcome::StrictViolation;
+    }
+    match decoder.verify() {
+        Ok(()) => Outcome::Verified,
+        Err(Error::NoMetadata) => Outcome::NoMetadata,
+        Err(Error::UnsupportedVersion(_)) => Outcome::UnsupportedVersion,
+        Err(Error::HashMismatch) => Outcome::HashMismatch,
+        Err(_) => Outcome::Incomplete,
+    }
+}
+
+pub fn run(args: &ReplayArgs) -> ExitCode {
+    let records = match Trace::read(path::Path::new(&args.trace)) {
+        Ok(records) => records,
+        Err(e) => {
+            log::error!("could not read {}: {}", args.trace, e);
+            return ExitCode::FAILURE;
+        }
+    };
+    if !records.iter().any(|r| !r.cache_keys.is_empty()) {
+        log::error!(
+            "{} names no cache entries; it was written without --cache-dir",
+            args.trace
+        );
+        return ExitCode::FAILURE;
+    }
+    let mut cache = match DecodeCache::open(path::Path::new(&args.cache_dir)) {
+        Ok(cache) => cache,
+        Err(e) => {
+            log::error!("could not open cache {}: {}", args.cache_dir, e);
+            return ExitCode::FAILURE;
+        }
+    };
+    let mut decoder = QrSendDecoder::new();
+    decoder.strict_hash_len = args.strict_hash_len;
+    decoder.only_segments = args.only_segments.clone();
+    decoder.strict = args.strict;
+    if let Some(replay_trace) = &args.replay_trace {
+        match Trace::create(path::Path::new(replay_trace)) {
+            Ok(trace) => decoder.trace = Some(trace),
+            Err(e) => {
+                log::error!("could not create {}: {}", replay_trace, e);
+                return ExitCode::FAILURE;
+            }
+        }
+    }
+    let mut unreplayable = 0;
+    for record in &records {
+        if record.cache_keys.is_empty() && !record.codes.is_empty() {
+            log::warn!(
+                "frame {} ({}): codes were found but not cached, skipping it",
+                record.frame,
+                record.source
+            );
+            unreplayable += 1;
+            continue;
+        }
+        let mut decoded: Vec<Vec<u8>> = Vec::new();
+        let mut missed = false;
+        for key in &record.cache_keys {
+            let Some(frames) = cache.get(key) else {
+                missed = true;
+                break;
+            };
+            // Color channels of a gray image carry the same frame each.
+            for data in frames {
+                if !decoded.contains(&data) {
+                    decoded.push(data);
+                }
+            }
+        }
+        if missed {
+            log::warn!(
+                "frame {} ({}): not in the cache, skipping it",
+                record.frame,
+                record.source
+            );
+            unreplayable += 1;
+            continue;
+        }
+        if let Some(trace) = decoder.trace.as_mut() {
+            trace.source = record.source.clone();
+        }
+        decoder.push_scanned(decoded, record.foreign);
+    }
+    log::info!(
+        "replayed {} of {} traced images",
+        records.len() - unreplayable,
+        records.len()
+    );
+    if unreplayable > 0 {
+        log::warn!("images that could not be replayed: {}", unreplayable);
+    }
+    let outcome = match &args.output_file {
+        Some(output_file) => {
+            let mut sink = FileSink {
+                path: output_file.clone(),
+                force: args.force,
+            };
+            finish(&decoder, &mut sink, false)
+        }
+        None => {
+            let outcome = outcome_of(&decoder);
+            if let Some(md) = &decoder.metadata {
+                println!(
+                    "{} of {} segments, {:?}",
+                    decoder.data_segments.len(),
+                    md.qrcode_count,
+                    outcome
+                );
+            } else {
+                println!("{:?}", outcome);
+            }
+            outcome
+        }
+    };
+    outcome.exit_status(args.expect_complete)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::synth::{Options, Transfer};
+    use crate::trace::{Code, Record, Verdict};
+    use clap::Parser;
+    use std::fs;
+
+    #[derive(Parser)]
+    struct Replay {
+        #[command(flatten)]
+        args: ReplayArgs,
+    }
+
+    fn record(frame: u64, key: &str) -> Record {
+        Record {
+            frame,
+            source: format!("capture/{:03}.png", frame),
+            unix_ms: 0,
+            at_ms: 0.0,
+            decode_ms: None,
+            cache_keys: vec![key.to_string()],
+            foreign: 0,
+            codes: vec![Code {
+                len: 0,
+                frame_type: None,
+                segment: None,
+                verdict: Verdict::Accepted,
+            }],
+        }
+    }
+
+    #[test]
+    fn replay_reruns_the_traced_frames_from_the_cache() {
+        let transfer = Transfer::new(&Options::default());
+        let dir = std::env::temp_dir().join(format!("qr-recv-replay-{}", std::process::id()));
+        let cache = DecodeCache::open(&dir.join("cache")).unwrap();
+        let mut lines = String::new();
+        for (i, frame) in transfer.frames.iter().enumerate() {
+            let key = format!("{:032x}", i);
+            cache.put(&key, std::slice::from_ref(frame));
+            lines += &serde_json::to_string(&record(i as u64, &key)).unwrap();
+            lines.push('\n');
+        }
+        // An image whose entry is gone is skipped, not fatal.
+        lines += &serde_json::to_string(&record(99, "gone")).unwrap();
+        let trace = dir.join("trace.jsonl");
+        fs::write(&trace, lines).unwrap();
+
+        let output = dir.join("out.bin");
+        let replay_trace = dir.join("replay.jsonl");
+        let args = Replay::try_parse_from([
+            "replay".as_ref(),
+            trace.as_os_str(),
+            "--cache-dir".as_ref(),
+            dir.join("cache").as_os_str(),
+            "--output-file".as_ref(),
+            output.as_os_str(),
+            "--replay-trace".as_ref(),
+            replay_trace.as_os_str(),
+        ])
+        .unwrap()
+        .args;
+        assert_eq!(run(&args), ExitCode::SUCCESS);
+        assert_eq!(fs::read(&output).unwrap(), transfer.file);
+        let replayed = Trace::read(&replay_trace).unwrap();
+        assert_eq!(replayed.len(), transfer.frames.len());
+        assert!(replayed
+            .iter()
+            .all(|r| r.codes.iter().all(|c| c.verdict == Verdict::Accepted)));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,190 @@
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::{fs, io, path};
+
+use crate::advise::Advice;
+use crate::decoder::QrSendDecoder;
+use crate::protocol::Manifest;
+use crate::ranges::SegmentRanges;
+use crate::receive::Outcome;
+use crate::session::RunRecord;
+use crate::sha256::{self, Sha256};
+
+#[derive(Serialize)]
+struct RunContribution<'a> {
+    run: usize,
+    #[serde(flatten)]
+    record: &'a RunRecord,
+}
+
+/// Checksums of a verified transfer, so it can be re-checked without the frames.
+#[derive(Serialize)]
+pub struct Digests {
+    md5: String,
+    sha256: String,
+    /// SHA-256 of each segment, in id order.
+    segments: Vec<String>,
+}
+impl Digests {
+    /// Digests of the assembled segments, or `None` while any are missing.
+    pub fn of(decoder: &QrSendDecoder) -> Option<Self> {
+        let md = decoder.metadata.as_ref()?;
+        let mut file = Sha256::new();
+        let mut md5 = md5::Context::new();
+        let mut segments = Vec::with_capacity(md.qrcode_count as usize);
+        for i in 0..md.qrcode_count {
+            let data = decoder.data_segments.get(i)?.data();
+            file.update(data);
+            md5.consume(data);
+            segments.push(hex::encode(sha256::digest(data)));
+        }
+        Some(Digests {
+            md5: hex::encode(md5.compute().0),
+            sha256: hex::encode(file.finalize()),
+            segments,
+        })
+    }
+    /// Write `<output_file>.sha256` in the format `sha256sum -c` reads.
+    pub fn write_sidecar(&self, output_file: &str) -> io::Result<path::PathBuf> {
+        let name = path::Path::new(output_file)
+            .file_name()
+            .map_or_else(Default::default, |n| n.to_string_lossy());
+        let path = path::PathBuf::from(format!("{}.sha256", output_file));
+        fs::write(&path, format!("{}  {}\n", self.sha256, name))?;
+        Ok(path)
+    }
+}
+
+/// Consolidated view over every run that fed a session.
+#[derive(Serialize)]
+pub struct AggregateReport<'a> {
+    /// Who sent the transfer, when the sender said.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    manifest: Option<&'a Manifest>,
+    runs: Vec<RunContribution<'a>>,
+    total_elapsed_secs: f64,
+    qrcode_count: Option<u64>,
+    received_count: u64,
+    unreadable_images: u64,
+    /// Segment ids that arrived with differing content, and how often.
+    conflicts: &'a BTreeMap<u64, u64>,
+    /// Segment ids still missing, as `17-20,944`.
+    #[serde(skip_serializing_if = "SegmentRanges::is_empty")]
+    missing: SegmentRanges,
+    outcome: Outcome,
+    /// Present once the file verified.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    digests: Option<Digests>,
+    /// Sender settings suggested by how the frames decoded.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    recommendation: Option<Advice>,
+}
+impl<'a> AggregateReport<'a> {
+    pub fn new(
+        runs: &'a [RunRecord],
+        decoder: &'a QrSendDecoder,
+        outcome: Outcome,
+        digests: Option<Digests>,
+        recommendation: Option<Advice>,
+    ) -> Self {
+        AggregateReport {
+            manifest: decoder.manifest.as_ref(),
+            runs: runs
+                .iter()
+                .enumerate()
+                .map(|(i, record)| RunContribution { run: i + 1, record })
+                .collect(),
+            total_elapsed_secs: runs.iter().fold(0.0, |total, r| total + r.elapsed_secs),
+            qrcode_count: decoder.metadata.as_ref().map(|md| md.qrcode_count),
+            received_count: decoder.data_segments.len() as u64,
+            unreadable_images: runs.iter().map(|r| r.unreadable_images).sum(),
+            conflicts: &decoder.conflicts,
+            missing: decoder.missing_ranges(),
+            outcome,
+            digests,
+            recommendation,
+        }
+    }
+    pub fn print(&self) {
+        if let Some(manifest) = self.manifest {
+            println!("transfer: {}", manifest.transfer_id);
+            if let Some(hostname) = &manifest.hostname {
+                println!("sent from: {}", hostname);
+            }
+            if let Some(created) = manifest.created {
+                println!("prepared at: {} (Unix time)", created);
+            }
+            if let Some(comment) = &manifest.comment {
+                println!("comment: {}", comment);
+            }
+        }
+        for r in &self.runs {
+            println!(
+                "run {}: {} new segments from {} in {:.1}s",
+                r.run, r.record.new_segments, r.record.source, r.record.elapsed_secs
+            );
+            if r.record.frames > 0 {
+                println!(
+                    "       {} segments decoded from {} frames ({:.0}% yield)",
+                    r.record.decoded_segments,
+                    r.record.frames,
+                    100.0 * r.record.yield_ratio()
+                );
+            }
+            let dropped: u64 = r.record.dropped_per_sec.iter().sum();
+            if dropped > 0 {
+                println!("       {} frames dropped while decoding lagged", dropped);
+            }
+            if let Some(order) = self
+                .qrcode_count
+                .and_then(|count| describe_order(&r.record.transmission_order, count))
+            {
+                println!("       segments sent {}", order);
+            }
+        }
+        println!("total elapsed: {:.1}s", self.total_elapsed_secs);
+        if self.unreadable_images > 0 {
+            println!("unreadable images: {}", self.unreadable_images);
+        }
+        if !self.conflicts.is_empty() {
+            let ids: Vec<&u64> = self.conflicts.keys().collect();
+            println!("conflicting segments: {:?}", ids);
+        }
+        if !self.missing.is_empty() {
+            println!("missing segments: [{}]", self.missing);
+        }
+        println!("final status: {:?}", self.outcome);
+        if let Some(advice) = &self.recommendation {
+            advice.print();
+        }
+    }
+    /// Write the report next to `output_file` as `<output_file>.report.json`.
+    pub fn write_alongside(&self, output_file: &str) -> io::Result<path::PathBuf> {
+        let path = path::PathBuf::from(format!("{}.report.json", output_file));
+        let file = fs::File::create(&path)?;
+        serde_json::to_writer_pretty(io::BufWriter::new(file), self)?;
+        Ok(path)
+    }
+}
+
+/// How the sender ordered its segments, from the order a run first decoded
+/// them in; `None` while that looks like ascending ids with some lost.
+fn describe_order(order: &[u64], count: u64) -> Option<String> {
+    if order.len() < 3 || count == 0 {
+        return None;
+    }
+    let mut steps: BTreeMap<u64, usize> = BTreeMap::new();
+    for pair in order.windows(2) {
+        *steps
+            .entry((pair[1] + count - pair[0] % count) % count)
+            .or_default() += 1;
+    }
+    let (&stride, &times) = steps.iter().max_by_key(|&(_, &times)| times)?;
+    if stride == 1 {
+        return None;
+    }
+    if times * 2 >= order.len() - 1 {
+        return Some(format!("interleaved with a stride of {}", stride));
+    }
+    Some("out of order".to_string())
+}
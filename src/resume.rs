@@ -0,0 +1,191 @@
+use std::path;
+use std::process::ExitCode;
+
+use crate::advise::advise_from_run;
+use crate::holes::HoleMap;
+use crate::images::{ImageSequence, ReadOptions};
+use crate::ranges::SegmentRanges;
+use crate::receive::{finish, patch_partial, timed_run, write_sidecar, Outcome};
+use crate::report::{AggregateReport, Digests};
+use crate::session::{unix_now, RunRecord, Session, SessionKey};
+use crate::sink::FileSink;
+use crate::trace::Trace;
+
+#[derive(clap::Args)]
+pub struct ResumeArgs {
+    /// Session file written by an earlier `receive --session`
+    #[clap(short, long)]
+    session: String,
+    #[clap(flatten)]
+    session_key: SessionKey,
+    /// Directory, animated GIF/APNG or PDF with the new capture
+    #[clap(short, long, required_unless_present = "merge")]
+    from: Option<String>,
+    #[clap(flatten)]
+    read: ReadOptions,
+    /// Fold in segments from other session files, e.g. a second machine's
+    /// capture, opened with the same passphrase
+    #[clap(long)]
+    merge: Vec<String>,
+    /// Where to write the file; one left with holes by `--allow-partial`
+    /// is patched in place
+    #[clap(short, long)]
+    output_file: String,
+    /// Replace the output file if it already exists
+    #[clap(long)]
+    force: bool,
+    /// When segments are missing, write the file anyway with zeros in their
+    /// place and list the gaps in `<output-file>.holes.json`
+    #[clap(long)]
+    allow_partial: bool,
+    /// Also write `<output-file>.sha256`, checkable with `sha256sum -c`
+    #[clap(long)]
+    sha256_sidecar: bool,
+    /// Refuse data frames whose hash length is guessed rather than taken from metadata
+    #[clap(long)]
+    strict_hash_len: bool,
+    /// Only store these segments, e.g. `17-20,944` as `nack` prints them,
+    /// for a pass that fills known gaps
+    #[clap(long, value_name = "IDS")]
+    only_segments: Option<SegmentRanges>,
+    /// Fail closed on any ambiguity: guessed hash lengths, unknown frame
+    /// types, inconsistent metadata or conflicting segments
+    #[clap(long)]
+    strict: bool,
+    /// Log ambiguities and keep decoding (the default)
+    #[clap(long, conflicts_with = "strict")]
+    lenient: bool,
+    /// Write the raw bytes of frames with unrecognized type bytes into this directory
+    #[clap(long)]
+    dump_unknown_frames: Option<String>,
+    /// Write every payload decoded into this directory, one file per code
+    /// named by the index of the image it was in, before it is verified or
+    /// parsed
+    #[clap(long, value_name = "DIR")]
+    dump_payloads: Option<String>,
+    /// Write a JSON line per image read to this file, as `receive --trace`
+    #[clap(long, value_name = "FILE")]
+    trace: Option<String>,
+    /// Log an error unless the file is now complete and verified
+    #[clap(long)]
+    expect_complete: bool,
+}
+
+fn load_session(path: &path::Path, key: &SessionKey) -> Option<Session> {
+    Session::load(path, key)
+        .map_err(|e| log::error!("could not open session {}: {}", path.display(), e))
+        .ok()
+}
+
+pub fn run(args: &ResumeArgs) -> ExitCode {
+    let session_path = path::Path::new(&args.session);
+    let Some(mut state) = load_session(session_path, &args.session_key) else {
+        return ExitCode::FAILURE;
+    };
+    let mut runs = std::mem::take(&mut state.runs);
+    let mut decoder = state.into_decoder();
+    decoder.strict_hash_len = args.strict_hash_len;
+    decoder.only_segments = args.only_segments.clone();
+    decoder.strict = args.strict;
+    decoder.rotation_retry = !args.read.no_rotation_retry;
+    decoder.perspective = args.read.perspective;
+    decoder.exposure_retry = !args.read.no_exposure_retry;
+    decoder.pad_retry = !args.read.no_pad_retry;
+    decoder.scale_retry = !args.read.no_scale_retry;
+    decoder.crop = args.read.crop;
+    decoder.cache = args.read.open_cache();
+    decoder.readers = args.read.readers();
+    decoder.unknown_dump = args.dump_unknown_frames.as_ref().map(path::PathBuf::from);
+    decoder.payload_dump = args.dump_payloads.as_ref().map(path::PathBuf::from);
+    if let Some(trace) = &args.trace {
+        match Trace::create(path::Path::new(trace)) {
+            Ok(trace) => decoder.trace = Some(trace),
+            Err(e) => {
+                log::error!("could not create {}: {}", trace, e);
+                return ExitCode::FAILURE;
+            }
+        }
+    }
+    for other_path in &args.merge {
+        let Some(mut other) = load_session(path::Path::new(other_path), &args.session_key) else {
+            return ExitCode::FAILURE;
+        };
+        let other_runs = std::mem::take(&mut other.runs);
+        let other = other.into_decoder();
+        let before = decoder.data_segments.len();
+        let decoded_segments = other.data_segments.len() as u64;
+        if decoder.metadata.is_none() {
+            if let Some(md) = &other.metadata {
+                decoder.data_segments.reserve(md.qrcode_count);
+            }
+            decoder.metadata = other.metadata;
+        }
+        if decoder.total_hash.is_empty() {
+            decoder.total_hash = other.total_hash;
+        } else if !other.total_hash.is_empty() && other.total_hash != decoder.total_hash {
+            log::warn!(
+                "{} holds a different whole-file hash, keeping the first",
+                other_path
+            );
+        }
+        for (id, count) in other.conflicts {
+            *decoder.conflicts.entry(id).or_default() += count;
+        }
+        for seg in other.data_segments.into_values() {
+            decoder.insert_segment(seg);
+        }
+        runs.push(RunRecord {
+            source: other_path.clone(),
+            started_at: unix_now(),
+            elapsed_secs: other_runs
+                .iter()
+                .fold(0.0, |total, r| total + r.elapsed_secs),
+            new_segments: (decoder.data_segments.len() - before) as u64,
+            unreadable_images: other_runs.iter().map(|r| r.unreadable_images).sum(),
+            frames: other_runs.iter().map(|r| r.frames).sum(),
+            decoded_segments,
+            dropped_per_sec: Vec::new(),
+            transmission_order: Vec::new(),
+        });
+    }
+    if let Some(from) = &args.from {
+        let img_seq = ImageSequence {
+            image_dir: path::PathBuf::from(from),
+            options: args.read.clone(),
+        };
+        let run = timed_run(&mut decoder, img_seq, None);
+        log::info!("recovered {} new segments", run.new_segments);
+        runs.push(run);
+    }
+    let mut new_state = Session::from_decoder(&decoder);
+    new_state.runs = runs;
+    new_state.save(session_path, &args.session_key).unwrap();
+    // Output left with holes by an earlier run is patched where it lies.
+    let outcome = if HoleMap::path_for(&args.output_file).exists() && !args.force {
+        patch_partial(&decoder, &args.output_file)
+    } else {
+        let mut sink = FileSink {
+            path: args.output_file.clone(),
+            force: args.force,
+        };
+        finish(&decoder, &mut sink, args.allow_partial)
+    };
+    if args.sha256_sidecar && outcome == Outcome::Verified {
+        write_sidecar(&decoder, &args.output_file);
+    }
+    let digests = (outcome == Outcome::Verified)
+        .then(|| Digests::of(&decoder))
+        .flatten();
+
+    let report = AggregateReport::new(
+        &new_state.runs,
+        &decoder,
+        outcome,
+        digests,
+        advise_from_run(&decoder, args.read.source_fps),
+    );
+    report.print();
+    let report_path = report.write_alongside(&args.output_file).unwrap();
+    log::info!("report written to {:?}", report_path);
+    outcome.exit_status(args.expect_complete)
+}
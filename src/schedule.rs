@@ -0,0 +1,159 @@
+//! When a looping sender will next show the segments still missing, from
+//! when each segment was seen on the passes so far, so whoever holds the
+//! camera knows whether another loop is worth waiting for.
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use crate::decoder::QrSendDecoder;
+
+/// Missing segments named in one hint; the rest are only counted.
+const NAMED: usize = 5;
+
+/// Timing of a live capture of a looping sender, from the order and time
+/// data frames were decoded in.
+#[derive(Default)]
+pub struct LoopSchedule {
+    /// Entries of the decoder's `decoded_ids` already taken into account.
+    seen: usize,
+    /// When each segment was last seen, and on which sender pass.
+    sightings: HashMap<u64, (Instant, u64)>,
+    /// The latest segment seen and when.
+    latest: Option<(Instant, u64)>,
+    /// The first segment seen on the current pass and when.
+    pass_start: Option<(Instant, u64)>,
+    /// The sender pass `pass_start` is on.
+    pass: u64,
+    /// Time the sender takes over one pass, from segments seen again on
+    /// later passes.
+    period: Option<Duration>,
+    /// The pass the last hint was given after.
+    hinted_pass: u64,
+}
+impl LoopSchedule {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Take in the segments decoded since the last call, and once the
+    /// sender starts a pass over with segments still missing, log when
+    /// each will next be shown.
+    pub fn on_frame(&mut self, decoder: &QrSendDecoder) {
+        let ids = decoder.decoded_ids.as_deref().unwrap_or_default();
+        if ids.len() < self.seen {
+            // Taken by a run that ended; a new run starts counting over.
+            self.seen = 0;
+        }
+        let now = Instant::now();
+        for &id in &ids[self.seen..] {
+            let pass = decoder.passes;
+            if let Some(&(at, seen_pass)) = self.sightings.get(&id) {
+                if seen_pass < pass {
+                    // A segment seen again a pass or more later.
+                    let sample = (now - at) / (pass - seen_pass) as u32;
+                    self.period = Some(match self.period {
+                        Some(period) => (period * 3 + sample) / 4,
+                        None => sample,
+                    });
+                }
+            }
+            if self.pass_start.is_none() || self.pass != pass {
+                self.pass_start = Some((now, id));
+                self.pass = pass;
+            }
+            self.sightings.insert(id, (now, pass));
+            self.latest = Some((now, id));
+        }
+        self.seen = ids.len();
+        if decoder.passes > self.hinted_pass && decoder.passes > 1 {
+            self.hinted_pass = decoder.passes;
+            self.hint(decoder);
+        }
+    }
+
+    /// Time the sender takes over one pass of `count` segments: measured
+    /// once segments come round again, extrapolated from the current pass
+    /// before that.
+    fn period(&self, count: u64) -> Option<Duration> {
+        if self.period.is_some() {
+            return self.period;
+        }
+        let ((start, first), (at, last)) = (self.pass_start?, self.latest?);
+        (last > first).then(|| (at - start).mul_f64(count as f64 / (last - first) as f64))
+    }
+
+    /// How long until the sender next shows segment `id` of `count`.
+    pub fn next_chance(&self, id: u64, count: u64) -> Option<Duration> {
+        let period = self.period(count)?;
+        let (at, last) = self.latest?;
+        let ahead = (id + count - last % count) % count;
+        let shown_at = at + period.mul_f64(ahead as f64 / count as f64);
+        let now = Instant::now();
+        if shown_at >= now {
+            return Some(shown_at - now);
+        }
+        // Shown since and missed; the next pass that comes round.
+        let late = (now - shown_at).as_secs_f64() % period.as_secs_f64();
+        Some(period.mul_f64(1.0 - late / period.as_secs_f64()))
+    }
+
+    fn hint(&self, decoder: &QrSendDecoder) {
+        let Some(md) = &decoder.metadata else {
+            return;
+        };
+        let missing = decoder.missing_segments();
+        let mut chances: Vec<(Duration, u64)> = missing
+            .iter()
+            .filter_map(|&id| Some((self.next_chance(id, md.qrcode_count)?, id)))
+            .collect();
+        if chances.is_empty() {
+            return;
+        }
+        chances.sort();
+        let named: Vec<String> = chances
+            .iter()
+            .take(NAMED)
+            .map(|(eta, id)| format!("{} in ~{:.0}s", id, eta.as_secs_f64()))
+            .collect();
+        let others = match chances.len().saturating_sub(NAMED) {
+            0 => String::new(),
+            n => format!(" and {} more", n),
+        };
+        log::info!(
+            "{} segments missing after sender pass {}; next chance for {}{}, all within ~{:.0}s",
+            missing.len(),
+            decoder.passes - 1,
+            named.join(", "),
+            others,
+            chances.last().unwrap().0.as_secs_f64()
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn near(eta: Option<Duration>, secs: f64) -> bool {
+        eta.is_some_and(|eta| (eta.as_secs_f64() - secs).abs() < 0.1)
+    }
+
+    #[test]
+    fn next_chance_extrapolates_then_wraps_round_the_loop() {
+        let now = Instant::now();
+        let mut schedule = LoopSchedule::new();
+        assert_eq!(schedule.next_chance(50, 100), None);
+
+        // 20 segments in 2s: a 100-segment pass takes ~10s.
+        schedule.pass_start = Some((now - Duration::from_secs(2), 0));
+        schedule.latest = Some((now, 20));
+        assert!(near(schedule.next_chance(50, 100), 3.0));
+        assert!(near(schedule.next_chance(5, 100), 8.5));
+        // Just shown: a whole pass away.
+        assert!(near(schedule.next_chance(20, 100), 10.0));
+
+        // A measured period wins over the extrapolated one.
+        schedule.period = Some(Duration::from_secs(20));
+        assert!(near(schedule.next_chance(50, 100), 6.0));
+    }
+}
@@ -0,0 +1,135 @@
+use std::process::Command;
+use std::str::FromStr;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Rectangle of the local display to grab, in screen pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ScreenRegion {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+impl FromStr for ScreenRegion {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts: Vec<&str> = s.split(',').map(str::trim).collect();
+        let [x, y, w, h] = parts[..] else {
+            return Err(format!("expected x,y,w,h, got {:?}", s));
+        };
+        let region = ScreenRegion {
+            x: x.parse().map_err(|_| format!("invalid x: {:?}", x))?,
+            y: y.parse().map_err(|_| format!("invalid y: {:?}", y))?,
+            width: w.parse().map_err(|_| format!("invalid width: {:?}", w))?,
+            height: h.parse().map_err(|_| format!("invalid height: {:?}", h))?,
+        };
+        if region.width == 0 || region.height == 0 {
+            return Err("region must not be empty".to_string());
+        }
+        Ok(region)
+    }
+}
+
+/// External screenshot tools, since grabbing the display portably needs one per platform.
+#[derive(Debug, Clone, Copy)]
+enum Grabber {
+    /// `grim` on Wayland compositors.
+    Grim,
+    /// ImageMagick `import` on X11.
+    Import,
+}
+impl Grabber {
+    fn detect() -> Self {
+        if std::env::var_os("WAYLAND_DISPLAY").is_some() {
+            Grabber::Grim
+        } else {
+            Grabber::Import
+        }
+    }
+    fn command(self, r: &ScreenRegion) -> Command {
+        match self {
+            Grabber::Grim => {
+                let mut cmd = Command::new("grim");
+                cmd.args(["-t", "ppm", "-g"])
+                    .arg(format!("{},{} {}x{}", r.x, r.y, r.width, r.height))
+                    .arg("-");
+                cmd
+            }
+            Grabber::Import => {
+                let mut cmd = Command::new("import");
+                cmd.args(["-window", "root", "-crop"])
+                    .arg(format!("{}x{}+{}+{}", r.width, r.height, r.x, r.y))
+                    .arg("ppm:-");
+                cmd
+            }
+        }
+    }
+}
+
+/// Frames grabbed from a region of the local display at a fixed rate.
+///
+/// Ends when the time limit runs out or the screenshot tool cannot be run.
+pub struct ScreenCapture {
+    region: ScreenRegion,
+    grabber: Grabber,
+    interval: Duration,
+    next_at: Instant,
+    deadline: Option<Instant>,
+}
+impl ScreenCapture {
+    pub fn new(region: ScreenRegion, fps: f64, limit: Option<Duration>) -> Self {
+        let now = Instant::now();
+        ScreenCapture {
+            region,
+            grabber: Grabber::detect(),
+            interval: Duration::from_secs_f64(1.0 / fps),
+            next_at: now,
+            deadline: limit.map(|l| now + l),
+        }
+    }
+    fn grab(&self) -> Result<Option<image::DynamicImage>, String> {
+        let start = Instant::now();
+        let output = self
+            .grabber
+            .command(&self.region)
+            .output()
+            .map_err(|e| format!("cannot run {:?} screenshot tool: {}", self.grabber, e))?;
+        if !output.status.success() {
+            log::warn!(
+                "screen grab failed: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+            return Ok(None);
+        }
+        let img = image::load_from_memory(&output.stdout).ok();
+        log::debug!("grabbed screen region in {:?}", start.elapsed());
+        Ok(img)
+    }
+}
+impl Iterator for ScreenCapture {
+    type Item = image::DynamicImage;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let now = Instant::now();
+            if self.deadline.is_some_and(|d| now >= d) {
+                return None;
+            }
+            if self.next_at > now {
+                thread::sleep(self.next_at - now);
+            }
+            // Fall behind rather than burst when a grab takes longer than the interval.
+            self.next_at = Instant::now().max(self.next_at) + self.interval;
+            match self.grab() {
+                Ok(Some(img)) => return Some(img),
+                Ok(None) => continue,
+                Err(e) => {
+                    log::error!("{}", e);
+                    return None;
+                }
+            }
+        }
+    }
+}
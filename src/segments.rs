@@ -0,0 +1,225 @@
+//! The data segments a decoder holds, by id: a bitmap of the ids held
+//! beside a table of where each segment is kept, both spanning the
+//! declared segment count, so what is missing is read off a word at a time
+//! rather than by a lookup per id.
+
+use std::collections::BTreeMap;
+use std::ops::{Index, Range};
+
+use crate::protocol::QrSendData;
+
+/// Ids the dense table takes before the segment count is known. Past it,
+/// and past the count once known, segments are kept in a sparse map, so a
+/// stray id cannot size the table.
+const DENSE_WITHOUT_COUNT: u64 = 1 << 16;
+/// Most ids the dense table takes whatever count is declared, so that
+/// metadata declaring billions of segments cannot size it either; ids past
+/// it are kept in the sparse map.
+const DENSE_MAX: u64 = 1 << 22;
+/// Table entry of an id not held.
+const ABSENT: u32 = u32::MAX;
+
+#[derive(Debug, Clone, Default)]
+pub struct Segments {
+    /// Bit `id % 64` of word `id / 64` is set once segment `id` is held.
+    held: Vec<u64>,
+    /// Position in `store` of each id in the dense range, or [`ABSENT`].
+    table: Vec<u32>,
+    store: Vec<QrSendData>,
+    /// Segments with ids past the dense range.
+    sparse: BTreeMap<u64, QrSendData>,
+    /// Ids below this are kept in the dense table.
+    dense_limit: u64,
+}
+impl Segments {
+    pub fn new() -> Self {
+        Segments {
+            dense_limit: DENSE_WITHOUT_COUNT,
+            ..Default::default()
+        }
+    }
+
+    /// Take ids below `count`, up to [`DENSE_MAX`], into the table, once
+    /// the metadata declares that many segments. The table grows as they
+    /// arrive.
+    pub fn reserve(&mut self, count: u64) {
+        self.dense_limit = self.dense_limit.max(count.min(DENSE_MAX));
+        let moved: Vec<u64> = self
+            .sparse
+            .range(..self.dense_limit)
+            .map(|(&id, _)| id)
+            .collect();
+        for id in moved {
+            let seg = self.sparse.remove(&id).unwrap();
+            self.insert(seg);
+        }
+    }
+
+    fn grow(&mut self, len: u64) {
+        let len = len.min(self.dense_limit) as usize;
+        if self.table.len() < len {
+            self.table.resize(len, ABSENT);
+            self.held.resize(len.div_ceil(64), 0);
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.store.len() + self.sparse.len()
+    }
+
+    #[cfg(feature = "gui")]
+    pub fn contains(&self, id: u64) -> bool {
+        if id < self.dense_limit {
+            let word = self.held.get((id / 64) as usize).copied().unwrap_or(0);
+            return word >> (id % 64) & 1 == 1;
+        }
+        self.sparse.contains_key(&id)
+    }
+
+    pub fn get(&self, id: u64) -> Option<&QrSendData> {
+        if id < self.dense_limit {
+            let &slot = self.table.get(id as usize)?;
+            return (slot != ABSENT).then(|| &self.store[slot as usize]);
+        }
+        self.sparse.get(&id)
+    }
+
+    /// Keep `seg` under its id, returning the segment it replaces.
+    pub fn insert(&mut self, seg: QrSendData) -> Option<QrSendData> {
+        let id = seg.id;
+        if id >= self.dense_limit {
+            return self.sparse.insert(id, seg);
+        }
+        self.grow(id + 1);
+        let slot = &mut self.table[id as usize];
+        if *slot != ABSENT {
+            return Some(std::mem::replace(&mut self.store[*slot as usize], seg));
+        }
+        *slot = self.store.len() as u32;
+        self.store.push(seg);
+        self.held[(id / 64) as usize] |= 1 << (id % 64);
+        None
+    }
+
+    /// The segments held, in arrival order, then those past the dense range.
+    pub fn values(&self) -> impl Iterator<Item = &QrSendData> {
+        self.store.iter().chain(self.sparse.values())
+    }
+
+    pub fn into_values(self) -> impl Iterator<Item = QrSendData> {
+        self.store.into_iter().chain(self.sparse.into_values())
+    }
+
+    /// Ids of the segments held, in order.
+    pub fn ids(&self) -> impl Iterator<Item = u64> + '_ {
+        let dense = self.held.iter().enumerate().flat_map(|(i, &word)| {
+            (0..64)
+                .filter(move |bit| word >> bit & 1 == 1)
+                .map(move |bit| i as u64 * 64 + bit)
+        });
+        dense.chain(self.sparse.keys().copied())
+    }
+
+    /// Segments held among `ids`.
+    pub fn count_in(&self, ids: Range<u64>) -> u64 {
+        let dense_end = ids.end.min(self.held.len() as u64 * 64);
+        let mut count = 0;
+        let mut id = ids.start;
+        while id < dense_end {
+            let (word, bit) = ((id / 64) as usize, id % 64);
+            let span = (64 - bit).min(dense_end - id);
+            let mask = if span == 64 { !0 } else { (1u64 << span) - 1 };
+            count += (self.held[word] >> bit & mask).count_ones() as u64;
+            id += span;
+        }
+        count
+            + self
+                .sparse
+                .range(ids.start.max(self.dense_limit)..ids.end)
+                .count() as u64
+    }
+
+    /// Runs of the ids below `count` not held, in order.
+    pub fn missing_ranges(&self, count: u64) -> Vec<Range<u64>> {
+        let mut ranges: Vec<Range<u64>> = Vec::new();
+        let mut id = 0;
+        while id < count {
+            let Some(start) = self.next_missing(id, count) else {
+                break;
+            };
+            let end = self.next_held(start, count);
+            ranges.push(start..end);
+            id = end;
+        }
+        ranges
+    }
+
+    /// Ids below `count` not held, in order.
+    pub fn missing(&self, count: u64) -> impl Iterator<Item = u64> {
+        self.missing_ranges(count).into_iter().flatten()
+    }
+
+    /// The lowest id below `count` not held.
+    pub fn first_missing(&self, count: u64) -> Option<u64> {
+        self.next_missing(0, count)
+    }
+
+    /// The first id past the bitmap word `id` is in, or past the dense
+    /// range when that ends first.
+    fn word_end(&self, id: u64) -> u64 {
+        ((id / 64 + 1) * 64).min(self.dense_limit)
+    }
+
+    /// The first id from `from` on, below `count`, not held.
+    fn next_missing(&self, from: u64, count: u64) -> Option<u64> {
+        let mut id = from;
+        while id < count {
+            match self.held.get((id / 64) as usize) {
+                Some(&word) if id < self.dense_limit => {
+                    let free = !word >> (id % 64);
+                    if free == 0 {
+                        id = self.word_end(id);
+                        continue;
+                    }
+                    let found = id + free.trailing_zeros() as u64;
+                    if found < self.dense_limit {
+                        return (found < count).then_some(found);
+                    }
+                    id = self.dense_limit;
+                }
+                // Past the bitmap nothing is held but what the sparse map has.
+                _ if !self.sparse.contains_key(&id) => return Some(id),
+                _ => id += 1,
+            }
+        }
+        None
+    }
+
+    /// The first id from `from` on held, or `count` when none below it is.
+    fn next_held(&self, from: u64, count: u64) -> u64 {
+        let mut id = from;
+        while id < count {
+            match self.held.get((id / 64) as usize) {
+                Some(&word) if id < self.dense_limit => {
+                    let held = word >> (id % 64);
+                    if held != 0 {
+                        return (id + held.trailing_zeros() as u64).min(count);
+                    }
+                    id = self.word_end(id);
+                }
+                _ => {
+                    let next = self.sparse.range(id..count).next();
+                    return next.map_or(count, |(&id, _)| id);
+                }
+            }
+        }
+        count
+    }
+}
+impl Index<u64> for Segments {
+    type Output = QrSendData;
+
+    fn index(&self, id: u64) -> &QrSendData {
+        self.get(id).expect("segment is held")
+    }
+}
@@ -0,0 +1,147 @@
+//! `qr-recv selftest`: send random files to this build's own decoder, through
+//! rendered images, to check the build and its features in one command.
+
+use std::process;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use crate::decoder::QrSendDecoder;
+use crate::synth::{MetadataFormat, Options, PayloadEncoding, Transfer};
+
+#[derive(clap::Args)]
+pub struct SelftestArgs {
+    /// Size of each random file that is sent, in bytes
+    #[clap(long, default_value_t = 16 * 1024)]
+    size: usize,
+    /// Content bytes carried by each data frame
+    #[clap(long, default_value_t = 512)]
+    chunk_size: usize,
+    /// Seed for the random files, taken from the clock by default; a
+    /// failing seed is printed so the run can be repeated
+    #[clap(long)]
+    seed: Option<u64>,
+}
+
+/// Sender settings tried, each named for the report.
+fn cases(args: &SelftestArgs, seed: u64) -> Vec<(&'static str, Options)> {
+    let base = Options {
+        size: args.size,
+        chunk_size: args.chunk_size,
+        seed,
+        ..Options::default()
+    };
+    vec![
+        ("base64, json metadata", base.clone()),
+        (
+            "base45, cbor metadata",
+            Options {
+                payload_encoding: PayloadEncoding::Base45,
+                metadata_format: MetadataFormat::Cbor,
+                ..base.clone()
+            },
+        ),
+        (
+            "raw, crc32c",
+            Options {
+                payload_encoding: PayloadEncoding::Raw,
+                crc32c: true,
+                ..base.clone()
+            },
+        ),
+        (
+            "base32, magic prefix",
+            Options {
+                payload_encoding: PayloadEncoding::Base32,
+                magic: true,
+                ..base.clone()
+            },
+        ),
+        (
+            "color channels",
+            Options {
+                color_channels: true,
+                ..base
+            },
+        ),
+    ]
+}
+
+/// Why the transfer did not come back as sent, if it did not.
+fn check(transfer: &Transfer) -> Result<(), String> {
+    let mut decoder = QrSendDecoder::new();
+    for img in &transfer.images {
+        decoder.push_image(img);
+    }
+    let Some(md) = &decoder.metadata else {
+        return Err("no metadata decoded".to_string());
+    };
+    let missing = decoder.missing_segments();
+    if !missing.is_empty() {
+        return Err(format!(
+            "{} of {} segments not decoded",
+            missing.len(),
+            md.qrcode_count
+        ));
+    }
+    let file = decoder.assemble().expect("every segment is held");
+    if file != transfer.file {
+        return Err("assembled file differs from the one sent".to_string());
+    }
+    if decoder.total_hash != decoder.file_hash().digest(&file) {
+        return Err("hash frame missing or wrong".to_string());
+    }
+    Ok(())
+}
+
+pub fn run(args: &SelftestArgs) {
+    let seed = args.seed.unwrap_or_else(|| {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_nanos() as u64)
+    });
+    let features: Vec<&str> = [
+        ("gui", cfg!(feature = "gui")),
+        ("external-decoders", cfg!(feature = "external-decoders")),
+        ("ffi", cfg!(feature = "ffi")),
+        ("heif", cfg!(feature = "heif")),
+        ("raw", cfg!(feature = "raw")),
+        ("http", cfg!(feature = "http")),
+    ]
+    .into_iter()
+    .filter_map(|(name, on)| on.then_some(name))
+    .collect();
+    println!(
+        "qr-recv {} with features: zbar{}",
+        env!("CARGO_PKG_VERSION"),
+        features
+            .iter()
+            .map(|f| format!(", {}", f))
+            .collect::<String>()
+    );
+    // The decoder's own progress lines would bury the report.
+    if log::max_level() == log::LevelFilter::Info {
+        log::set_max_level(log::LevelFilter::Warn);
+    }
+    let mut failed = 0;
+    for (name, options) in cases(args, seed) {
+        let start = Instant::now();
+        let transfer = Transfer::new(&options);
+        match check(&transfer) {
+            Ok(()) => println!(
+                "{:>22}: ok, {} frames in {} images ({:.1?})",
+                name,
+                transfer.frames.len(),
+                transfer.images.len(),
+                start.elapsed()
+            ),
+            Err(e) => {
+                println!("{:>22}: FAILED, {}", name, e);
+                failed += 1;
+            }
+        }
+    }
+    if failed > 0 {
+        println!("{} case(s) failed, repeat with --seed {}", failed, seed);
+        process::exit(1);
+    }
+    println!("all cases passed");
+}
@@ -0,0 +1,370 @@
+use std::io::Write;
+use std::{fs, io, path, process, thread, time};
+
+use crate::protocol::{
+    self, Endianness, FileHash, FrameType, Manifest, MetadataFormat, PayloadEncoding,
+    QrSendMetadata,
+};
+use crate::qr::{EccLevel, QrCode};
+use crate::ranges::SegmentRanges;
+
+#[derive(clap::Args)]
+pub struct SendArgs {
+    /// File to transfer
+    #[clap(short, long)]
+    input: String,
+    /// Directory the frame images are written to
+    #[clap(short, long, required_unless_present = "terminal")]
+    output_dir: Option<String>,
+    /// Content bytes carried by each data frame
+    #[clap(long, default_value_t = 512)]
+    chunk_size: usize,
+    /// Length of the per-frame blake2b hash
+    #[clap(long, default_value_t = 8, value_parser = clap::value_parser!(u64).range(1..=64))]
+    hash_len: u64,
+    /// Id type for data frames, the narrowest fixed width that fits by
+    /// default; `varint` takes one byte for the first 128 segments
+    #[clap(long, value_parser = protocol::ID_TYPES)]
+    id_type: Option<String>,
+    /// Byte order of fixed-width ids
+    #[clap(long, value_enum, default_value_t = Endianness::Big)]
+    endianness: Endianness,
+    #[clap(long, value_enum, default_value_t = EccLevel::M)]
+    ecc: EccLevel,
+    /// How frame bytes are written into each qrcode
+    #[clap(long, value_enum, default_value_t = PayloadEncoding::Base64)]
+    payload_encoding: PayloadEncoding,
+    /// Encoding of the metadata frames
+    #[clap(long, value_enum, default_value_t = MetadataFormat::Json)]
+    metadata_format: MetadataFormat,
+    /// Repeat the metadata after every N data frames, so a capture that
+    /// starts late or misses a metadata frame still recovers it
+    #[clap(long)]
+    metadata_repeat: Option<usize>,
+    /// Send the metadata as indexed chunks that declare the length and
+    /// CRC-32C of the whole, so a receiver that misses one says so
+    #[clap(long)]
+    metadata_checksum: bool,
+    /// Pixels per QR module
+    #[clap(long, default_value_t = 4)]
+    scale: u32,
+    /// Only emit these data segments, e.g. `17-20,944` as `nack` prints them
+    #[clap(long, value_name = "IDS")]
+    segments: Option<SegmentRanges>,
+    /// Insert a sync frame after every N frames so the receiver can tell
+    /// dropped captures from decode failures
+    #[clap(long)]
+    sync_every: Option<usize>,
+    /// Play the frames in this terminal instead of writing images
+    #[clap(long)]
+    terminal: bool,
+    /// Terminal playback rate in frames per second
+    #[clap(long, default_value_t = 5.0)]
+    fps: f64,
+    /// Number of times terminal playback loops over the frames
+    #[clap(long, default_value_t = 1)]
+    loops: u32,
+    /// Experimental: draw three frames to an image, one in each color
+    /// channel, for receivers run with a color camera
+    #[clap(long, conflicts_with = "terminal")]
+    color_channels: bool,
+    /// End each data frame in a CRC-32C, so receivers can drop damaged
+    /// frames without hashing them
+    #[clap(long)]
+    crc32c: bool,
+    /// Hash of the whole file sent in the hash frame; `md5` for receivers
+    /// that predate the choice
+    #[clap(long, value_enum, default_value_t = FileHash::Blake3)]
+    file_hash: FileHash,
+    /// Start the text of every qrcode with a protocol prefix, so receivers
+    /// skip any other qrcode in view once they have the metadata
+    #[clap(long)]
+    magic: bool,
+    /// Send a manifest frame after the metadata, naming this host, the time
+    /// and a fresh transfer id, so receivers can tell transfers apart
+    #[clap(long)]
+    manifest: bool,
+    /// Free text for the manifest frame, e.g. what the file is for
+    #[clap(long, requires = "manifest")]
+    comment: Option<String>,
+}
+
+/// How a file is cut into frames.
+#[derive(Debug, Clone, Copy)]
+pub struct FrameOptions<'a> {
+    pub chunk_size: usize,
+    pub hash_len: u64,
+    /// The narrowest fixed width that fits when `None`.
+    pub id_type: Option<&'a str>,
+    pub endianness: Endianness,
+    pub metadata_format: MetadataFormat,
+    pub payload_encoding: PayloadEncoding,
+    /// Repeat the metadata, as indexed chunks, after every this many data frames.
+    pub metadata_repeat: Option<usize>,
+    /// Send the metadata as checked indexed chunks, repeated or not.
+    pub metadata_checksum: bool,
+    /// Declared so the receiver can name the file and restore its timestamp.
+    pub file_name: Option<&'a str>,
+    pub mtime: Option<u64>,
+    /// Declare frames drawn three to an image, see [`pack_channels`].
+    pub color_channels: bool,
+    pub crc32c: bool,
+    pub file_hash: FileHash,
+    /// Declare texts that start with [`protocol::MAGIC`], see [`render`].
+    pub magic: bool,
+    /// Sent after the metadata, and repeated with it.
+    pub manifest: Option<&'a Manifest>,
+}
+
+/// All frames for `data`, in send order: metadata and any manifest, data,
+/// then the hash frame.
+pub fn build_frames(
+    data: &[u8],
+    options: &FrameOptions,
+    segments: Option<&SegmentRanges>,
+) -> Vec<Vec<u8>> {
+    let chunk_size = options.chunk_size;
+    let chunks: Vec<&[u8]> = data.chunks(chunk_size.max(1)).collect();
+    let count = chunks.len() as u64;
+    let md = QrSendMetadata {
+        version: protocol::PROTOCOL_VERSION.to_string(),
+        qrcode_count: count,
+        id_type: options
+            .id_type
+            .unwrap_or(protocol::smallest_id_type(count))
+            .to_string(),
+        hash_len: options.hash_len,
+        payload_encoding: options.payload_encoding,
+        endianness: options.endianness,
+        file_size: Some(data.len() as u64),
+        segment_size: Some(chunk_size as u64),
+        file_name: options.file_name.map(str::to_string),
+        mtime: options.mtime,
+        symbology: Default::default(),
+        color_channels: options.color_channels,
+        crc32c: options.crc32c,
+        file_hash: options.file_hash,
+        magic: options.magic,
+    };
+    let repeat = options.metadata_repeat.filter(|&n| n > 0);
+    let mut metadata = if repeat.is_some() || options.metadata_checksum {
+        protocol::indexed_metadata_frames(
+            &md,
+            chunk_size,
+            options.metadata_format,
+            options.metadata_checksum,
+        )
+    } else {
+        protocol::metadata_frames(&md, chunk_size, options.metadata_format)
+    };
+    if let Some(manifest) = options.manifest {
+        metadata.push(protocol::manifest_frame(manifest, md.hash_len as usize));
+    }
+    let mut frames = metadata.clone();
+    let mut sent = 0;
+    for (id, chunk) in chunks.iter().enumerate() {
+        let id = id as u64;
+        if segments.is_some_and(|s| !s.contains(id)) {
+            continue;
+        }
+        if repeat.is_some_and(|n| sent > 0 && sent % n == 0) {
+            frames.extend_from_slice(&metadata);
+        }
+        frames.push(protocol::data_frame(id, chunk, &md));
+        sent += 1;
+    }
+    frames.push(protocol::hash_frame(&md.file_hash.digest(data), &md));
+    frames
+}
+
+/// Interleave sync frames into `frames`, numbering every display slot.
+///
+/// Each `pass` over the frames continues the counter, so a looping sender keeps
+/// it monotonic.
+fn with_sync(frames: &[Vec<u8>], every: Option<usize>, hash_len: u64, pass: u64) -> Vec<Vec<u8>> {
+    let Some(every) = every.filter(|&n| n > 0) else {
+        return frames.to_vec();
+    };
+    let slots_per_pass = (frames.len() + frames.len().div_ceil(every)) as u64;
+    let mut out = Vec::new();
+    for (i, frame) in frames.iter().enumerate() {
+        out.push(frame.clone());
+        if (i + 1) % every == 0 || i + 1 == frames.len() {
+            let counter = pass * slots_per_pass + out.len() as u64;
+            out.push(protocol::sync_frame(counter, hash_len as usize));
+        }
+    }
+    out
+}
+
+pub fn render(frame: &[u8], encoding: PayloadEncoding, magic: bool, ecc: EccLevel) -> QrCode {
+    let text = protocol::payload_text(frame, encoding, magic);
+    match encoding {
+        PayloadEncoding::Base45 | PayloadEncoding::Base32 => {
+            QrCode::encode_alphanumeric(&text, ecc)
+        }
+        PayloadEncoding::Base64 | PayloadEncoding::Base64Url | PayloadEncoding::Raw => {
+            QrCode::encode_bytes(&text, ecc)
+        }
+    }
+    .expect("frame too large for a single qrcode, lower --chunk-size")
+}
+
+/// Images for rendered frames, given with their tags, drawing data, hash
+/// and sync frames three to an image in the red, green and blue channels.
+/// Metadata frames are drawn gray on their own, so a receiver learns of the
+/// channels before it meets them.
+pub fn pack_channels(
+    codes: impl IntoIterator<Item = (u8, image::GrayImage)>,
+) -> Vec<image::DynamicImage> {
+    let mut images = Vec::new();
+    let mut pending = Vec::new();
+    for (tag, code) in codes {
+        if FrameType::from_tag(tag) == Some(FrameType::Metadata) {
+            if !pending.is_empty() {
+                images.push(merge_channels(&std::mem::take(&mut pending)));
+            }
+            images.push(image::DynamicImage::ImageLuma8(code));
+            continue;
+        }
+        pending.push(code);
+        if pending.len() == 3 {
+            images.push(merge_channels(&std::mem::take(&mut pending)));
+        }
+    }
+    if !pending.is_empty() {
+        images.push(merge_channels(&pending));
+    }
+    images
+}
+
+/// Up to three codes as the channels of one image, centred on white. A
+/// short group repeats its last code rather than leave a channel empty.
+fn merge_channels(codes: &[image::GrayImage]) -> image::DynamicImage {
+    let side = codes.iter().map(|c| c.width()).max().unwrap();
+    let mut img = image::RgbImage::from_pixel(side, side, image::Rgb([255; 3]));
+    for channel in 0..3 {
+        let code = &codes[channel.min(codes.len() - 1)];
+        let offset = (side - code.width()) / 2;
+        for (x, y, px) in code.enumerate_pixels() {
+            img.get_pixel_mut(x + offset, y + offset)[channel] = px[0];
+        }
+    }
+    image::DynamicImage::ImageRgb8(img)
+}
+
+/// A random UUID, version 4, naming one transfer.
+fn new_transfer_id() -> io::Result<String> {
+    let mut bytes = [0; 16];
+    io::Read::read_exact(&mut fs::File::open("/dev/urandom")?, &mut bytes)?;
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+    let hex = hex::encode(bytes);
+    Ok(format!(
+        "{}-{}-{}-{}-{}",
+        &hex[..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..]
+    ))
+}
+
+/// Name of this host, from the environment or `/etc/hostname`.
+fn hostname() -> Option<String> {
+    ["HOSTNAME", "COMPUTERNAME"]
+        .into_iter()
+        .find_map(|var| std::env::var(var).ok())
+        .or_else(|| fs::read_to_string("/etc/hostname").ok())
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+}
+
+fn play(frames: &[Vec<u8>], args: &SendArgs) {
+    let delay = time::Duration::from_secs_f64(1.0 / args.fps);
+    let mut stdout = io::stdout();
+    for pass in 0..args.loops as u64 {
+        for frame in with_sync(frames, args.sync_every, args.hash_len, pass) {
+            let qr = render(&frame, args.payload_encoding, args.magic, args.ecc);
+            write!(stdout, "\x1b[H\x1b[2J{}", qr.to_halfblocks()).unwrap();
+            stdout.flush().unwrap();
+            thread::sleep(delay);
+        }
+    }
+}
+
+pub fn run(args: &SendArgs) {
+    let data = fs::read(&args.input).unwrap();
+    if let Some(id_type) = &args.id_type {
+        let count = data.len().div_ceil(args.chunk_size.max(1)) as u64;
+        if !protocol::id_type_holds(id_type, count) {
+            log::error!(
+                "{} segments do not fit id type {}; pass a wider --id-type or a larger --chunk-size",
+                count,
+                id_type
+            );
+            process::exit(1);
+        }
+    }
+    let input = path::Path::new(&args.input);
+    let mtime = fs::metadata(input)
+        .and_then(|m| m.modified())
+        .ok()
+        .and_then(|t| t.duration_since(time::UNIX_EPOCH).ok())
+        .map(|d| d.as_secs());
+    let manifest = args.manifest.then(|| Manifest {
+        transfer_id: new_transfer_id().unwrap(),
+        hostname: hostname(),
+        created: time::SystemTime::now()
+            .duration_since(time::UNIX_EPOCH)
+            .ok()
+            .map(|d| d.as_secs()),
+        comment: args.comment.clone(),
+    });
+    if let Some(manifest) = &manifest {
+        log::info!("transfer id {}", manifest.transfer_id);
+    }
+    let options = FrameOptions {
+        chunk_size: args.chunk_size,
+        hash_len: args.hash_len,
+        id_type: args.id_type.as_deref(),
+        endianness: args.endianness,
+        metadata_format: args.metadata_format,
+        payload_encoding: args.payload_encoding,
+        metadata_repeat: args.metadata_repeat,
+        metadata_checksum: args.metadata_checksum,
+        file_name: input.file_name().and_then(|n| n.to_str()),
+        mtime,
+        color_channels: args.color_channels,
+        crc32c: args.crc32c,
+        file_hash: args.file_hash,
+        magic: args.magic,
+        manifest: manifest.as_ref(),
+    };
+    let frames = build_frames(&data, &options, args.segments.as_ref());
+    if args.terminal {
+        play(&frames, args);
+        return;
+    }
+    let frames = with_sync(&frames, args.sync_every, args.hash_len, 0);
+    let output_dir = path::Path::new(args.output_dir.as_ref().unwrap());
+    fs::create_dir_all(output_dir).unwrap();
+    let codes = frames.iter().map(|frame| {
+        render(frame, args.payload_encoding, args.magic, args.ecc).to_image(args.scale)
+    });
+    let images: Vec<image::DynamicImage> = if args.color_channels {
+        pack_channels(frames.iter().map(|f| f[0]).zip(codes))
+    } else {
+        codes.map(image::DynamicImage::ImageLuma8).collect()
+    };
+    for (i, img) in images.iter().enumerate() {
+        let path = output_dir.join(format!("frame_{:06}.png", i));
+        img.save(&path).unwrap();
+    }
+    log::info!(
+        "wrote {} frames to {} images in {:?}",
+        frames.len(),
+        images.len(),
+        output_dir
+    );
+}
@@ -0,0 +1,221 @@
+use base64::prelude::*;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::BTreeMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+use std::{fs, io, path};
+
+use crate::crypto;
+use crate::decoder::QrSendDecoder;
+use crate::protocol::{Manifest, QrSendData, QrSendMetadata};
+use crate::sink::partial_path;
+
+#[derive(Serialize, Deserialize)]
+struct SessionSegment {
+    #[serde(with = "base64_bytes")]
+    data: Vec<u8>,
+    #[serde(with = "base64_bytes")]
+    hash: Vec<u8>,
+}
+
+/// Bytes as a base64 string, refused when loading if it does not decode.
+mod base64_bytes {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&BASE64_STANDARD.encode(bytes))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        let text = String::deserialize(deserializer)?;
+        BASE64_STANDARD
+            .decode(text)
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+pub fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+/// Environment variable holding the session passphrase when no
+/// `--session-passphrase-file` is given.
+pub const PASSPHRASE_VAR: &str = "QR_RECV_SESSION_PASSPHRASE";
+
+/// Where the passphrase for encrypted session files comes from.
+#[derive(clap::Args, Debug, Clone)]
+pub struct SessionKey {
+    /// Encrypt session files with the passphrase on the first line of this
+    /// file, or the one in `QR_RECV_SESSION_PASSPHRASE`, so received data
+    /// is not left on disk in the clear
+    #[clap(long)]
+    session_passphrase_file: Option<String>,
+}
+impl SessionKey {
+    pub fn passphrase(&self) -> io::Result<Option<String>> {
+        if let Some(path) = &self.session_passphrase_file {
+            let text = fs::read_to_string(path)?;
+            return Ok(Some(text.lines().next().unwrap_or_default().to_string()));
+        }
+        Ok(std::env::var(PASSPHRASE_VAR).ok().filter(|p| !p.is_empty()))
+    }
+}
+
+/// One capture pass that fed a session.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RunRecord {
+    pub source: String,
+    /// Unix timestamp the run started at.
+    pub started_at: u64,
+    pub elapsed_secs: f64,
+    pub new_segments: u64,
+    /// Files in the capture that could not be read as images.
+    #[serde(default)]
+    pub unreadable_images: u64,
+    /// Images read from the source.
+    #[serde(default)]
+    pub frames: u64,
+    /// Distinct segments the source decoded, whether or not another source
+    /// had them first.
+    #[serde(default)]
+    pub decoded_segments: u64,
+    /// Frames a live capture dropped while decoding lagged, per second.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub dropped_per_sec: Vec<u64>,
+    /// The first segment ids the source decoded, in the order it did, each
+    /// once, to tell how the sender orders or interleaves its segments.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub transmission_order: Vec<u64>,
+}
+impl RunRecord {
+    /// Share of the source's images that gave a segment it decoded.
+    pub fn yield_ratio(&self) -> f64 {
+        if self.frames == 0 {
+            return 0.0;
+        }
+        self.decoded_segments as f64 / self.frames as f64
+    }
+}
+
+/// Decoder state persisted between runs so later captures can fill the gaps.
+#[derive(Serialize, Deserialize)]
+pub struct Session {
+    metadata: Option<QrSendMetadata>,
+    segments: BTreeMap<u64, SessionSegment>,
+    #[serde(alias = "total_md5")]
+    total_hash: String,
+    #[serde(default)]
+    conflicts: BTreeMap<u64, u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    manifest: Option<Manifest>,
+    #[serde(default)]
+    pub runs: Vec<RunRecord>,
+}
+impl Session {
+    pub fn from_decoder(decoder: &QrSendDecoder) -> Self {
+        Session {
+            metadata: decoder.metadata.clone(),
+            segments: decoder
+                .data_segments
+                .values()
+                .map(|seg| {
+                    let session_seg = SessionSegment {
+                        data: seg.data().to_vec(),
+                        hash: seg.hash().to_vec(),
+                    };
+                    (seg.id, session_seg)
+                })
+                .collect(),
+            total_hash: hex::encode(&decoder.total_hash),
+            conflicts: decoder.conflicts.clone(),
+            manifest: decoder.manifest.clone(),
+            runs: Vec::new(),
+        }
+    }
+    pub fn into_decoder(self) -> QrSendDecoder {
+        let mut decoder = QrSendDecoder::new();
+        if let Some(md) = &self.metadata {
+            decoder.data_segments.reserve(md.qrcode_count);
+        }
+        decoder.metadata = self.metadata;
+        decoder.total_hash = hex::decode(self.total_hash).unwrap_or_default();
+        decoder.conflicts = self.conflicts;
+        decoder.manifest = self.manifest;
+        for (id, seg) in self.segments {
+            decoder
+                .data_segments
+                .insert(QrSendData::new(id, &seg.data, &seg.hash));
+        }
+        decoder
+    }
+    /// Read a session file, decrypting it when it was saved with a passphrase.
+    /// Segments that are not valid base64 make it `InvalidData`.
+    pub fn load(path: &path::Path, key: &SessionKey) -> io::Result<Self> {
+        let data = fs::read(path)?;
+        if !crypto::is_sealed(&data) {
+            return Ok(serde_json::from_slice(&data)?);
+        }
+        let Some(passphrase) = key.passphrase()? else {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "{:?} is encrypted, give --session-passphrase-file or {}",
+                    path, PASSPHRASE_VAR
+                ),
+            ));
+        };
+        let json = crypto::open(passphrase.as_bytes(), &data)
+            .map_err(|why| io::Error::new(io::ErrorKind::InvalidData, why))?;
+        Ok(serde_json::from_slice(&json)?)
+    }
+    /// Write the session file, encrypted when a passphrase is given. It is
+    /// written aside and renamed over the old one, so a run killed midway
+    /// leaves the previous session intact.
+    pub fn save(&self, path: &path::Path, key: &SessionKey) -> io::Result<()> {
+        let json = serde_json::to_vec(self)?;
+        let contents = match key.passphrase()? {
+            Some(passphrase) => crypto::seal(passphrase.as_bytes(), &json)?,
+            None => json,
+        };
+        let partial = partial_path(path);
+        let written = fs::write(&partial, contents).and_then(|_| fs::rename(&partial, path));
+        if written.is_err() {
+            let _ = fs::remove_file(&partial);
+        }
+        written
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn save_replaces_and_load_refuses_bad_base64() {
+        let dir = std::env::temp_dir().join(format!("qr-recv-session-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("session.json");
+        let key = SessionKey {
+            session_passphrase_file: None,
+        };
+        let mut decoder = QrSendDecoder::new();
+        decoder
+            .data_segments
+            .insert(QrSendData::new(3, b"segment", b"hash"));
+        fs::write(&path, "an older session").unwrap();
+        Session::from_decoder(&decoder).save(&path, &key).unwrap();
+        assert!(!partial_path(&path).exists());
+        let loaded = Session::load(&path, &key).unwrap().into_decoder();
+        assert_eq!(loaded.data_segments.get(3).unwrap().data(), b"segment");
+
+        let text = fs::read_to_string(&path).unwrap();
+        let encoded = BASE64_STANDARD.encode(b"segment");
+        fs::write(&path, text.replace(&encoded, "not*base64")).unwrap();
+        let Err(e) = Session::load(&path, &key) else {
+            panic!("a segment that is not base64 loaded");
+        };
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
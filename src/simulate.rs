@@ -0,0 +1,146 @@
+//! `qr-recv simulate`: render a transfer at each sender setting, damage the
+//! frames the way a camera would, and report how many still decode, to
+//! choose sender settings before the real transfer.
+
+use std::fs;
+use std::path::PathBuf;
+use std::time::Instant;
+
+use crate::decoder::QrSendDecoder;
+use crate::degrade::Degradation;
+use crate::synth::{EccLevel, Options, PayloadEncoding, Transfer};
+
+#[derive(clap::Args)]
+pub struct SimulateArgs {
+    /// File to send, a random one of --size bytes by default
+    #[clap(short, long)]
+    input: Option<PathBuf>,
+    /// Size of the random file that is sent, in bytes
+    #[clap(long, default_value_t = 8 * 1024)]
+    size: usize,
+    /// Content bytes carried by each data frame; a list tries each
+    #[clap(long, value_delimiter = ',', default_value = "512")]
+    chunk_size: Vec<usize>,
+    /// Error correction level; a list tries each
+    #[clap(long, value_enum, value_delimiter = ',', default_value = "m")]
+    ecc: Vec<EccLevel>,
+    /// Payload encoding, as `send` has it
+    #[clap(long, value_enum, default_value_t = PayloadEncoding::Base64)]
+    payload_encoding: PayloadEncoding,
+    /// Pixels per qrcode module
+    #[clap(long, default_value_t = 4)]
+    scale: u32,
+    /// Damage done to every frame, e.g. `blur=1.5,jpeg=30,rotate=5,moire=0.2`
+    /// or `none`; repeat to try several, a standard spread by default
+    #[clap(long = "degrade", value_name = "PROFILE")]
+    degradations: Vec<Degradation>,
+    /// Decode without the rotation, exposure, pad and scale retries
+    /// `receive` makes, to see what the frames alone survive
+    #[clap(long)]
+    no_retries: bool,
+}
+
+/// How one damaged transfer decoded.
+struct Outcome {
+    /// Images with a frame read from them.
+    decoded: u64,
+    images: u64,
+    /// Distinct segments held, of the ones sent.
+    segments: usize,
+    segment_count: usize,
+    /// The assembled file matches the one sent.
+    verified: bool,
+}
+
+fn decode(transfer: &Transfer, degradation: &Degradation, retries: bool) -> Outcome {
+    let mut decoder = QrSendDecoder::new();
+    decoder.rotation_retry = retries;
+    decoder.exposure_retry = retries;
+    decoder.pad_retry = retries;
+    decoder.scale_retry = retries;
+    for img in &transfer.images {
+        decoder.push_image(&degradation.apply(img));
+    }
+    let failed = decoder.undecoded_frames
+        + decoder.rejected_frames
+        + decoder.undersized_frames
+        + decoder.wrong_length_frames
+        + decoder.out_of_range_frames;
+    let (segments, segment_count) = match &decoder.metadata {
+        Some(md) => {
+            let count = md.qrcode_count as usize;
+            (count - decoder.missing_segments().len(), count)
+        }
+        None => (0, 0),
+    };
+    let verified = decoder.metadata.is_some()
+        && decoder.missing_segments().is_empty()
+        && decoder.assemble().is_some_and(|file| file == transfer.file);
+    Outcome {
+        decoded: decoder.frames_read.saturating_sub(failed),
+        images: decoder.frames_read,
+        segments,
+        segment_count,
+        verified,
+    }
+}
+
+pub fn run(args: &SimulateArgs) {
+    let file = match &args.input {
+        Some(input) => fs::read(input).unwrap(),
+        None => crate::synth::Rng::new(0).bytes(args.size),
+    };
+    let degradations = if args.degradations.is_empty() {
+        Degradation::standard()
+    } else {
+        args.degradations.clone()
+    };
+    // The decoder's own progress lines would bury the report.
+    if log::max_level() == log::LevelFilter::Info {
+        log::set_max_level(log::LevelFilter::Warn);
+    }
+    let width = degradations
+        .iter()
+        .map(|d| d.to_string().len())
+        .max()
+        .unwrap_or(0);
+    for &chunk_size in &args.chunk_size {
+        for &ecc in &args.ecc {
+            let options = Options {
+                chunk_size,
+                ecc,
+                scale: args.scale,
+                payload_encoding: args.payload_encoding,
+                ..Options::default()
+            };
+            let transfer = Transfer::from_file(file.clone(), &options);
+            println!(
+                "chunk size {}, ecc {:?}: {} frames",
+                chunk_size,
+                ecc,
+                transfer.frames.len()
+            );
+            let mut verified = 0;
+            for degradation in &degradations {
+                let start = Instant::now();
+                let outcome = decode(&transfer, degradation, !args.no_retries);
+                let rate = outcome.decoded as f64 / outcome.images.max(1) as f64;
+                println!(
+                    "  {:>width$}: {:5.1}% of frames read, {}/{} segments, {} ({:.1?})",
+                    degradation.to_string(),
+                    rate * 100.0,
+                    outcome.segments,
+                    outcome.segment_count,
+                    if outcome.verified {
+                        "verified"
+                    } else {
+                        "incomplete"
+                    },
+                    start.elapsed(),
+                );
+                verified += outcome.verified as usize;
+            }
+            println!("  {} of {} profiles verified", verified, degradations.len());
+        }
+    }
+}
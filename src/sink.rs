@@ -0,0 +1,165 @@
+//! Where a verified transfer goes: a file, stdout, the stdin of a command
+//! such as `tar xf -`, or an HTTP PUT to a URL, e.g. a presigned S3 upload.
+//! Sinks only ever see a transfer that passed its hash check.
+
+use std::io::{self, Write};
+use std::path;
+use std::process::{Command, Stdio};
+
+use crate::decoder::QrSendDecoder;
+use crate::receive::{output_taken, write_output};
+
+/// Destination of a received file.
+pub trait Sink {
+    /// What the file is delivered to, for log lines.
+    fn name(&self) -> String;
+    /// The output file, for sinks that write one.
+    fn file(&self) -> Option<&FileSink> {
+        None
+    }
+    /// Whether delivering would replace something that must be kept,
+    /// logging why.
+    fn taken(&self) -> bool {
+        false
+    }
+    /// Deliver the complete, verified transfer held by `decoder`.
+    fn deliver(&mut self, decoder: &QrSendDecoder) -> io::Result<()>;
+}
+
+/// A file written beside its final name and renamed into place, with the
+/// modification time the sender declares.
+pub struct FileSink {
+    pub path: String,
+    /// Replace the file if it already exists.
+    pub force: bool,
+}
+impl Sink for FileSink {
+    fn name(&self) -> String {
+        self.path.clone()
+    }
+    fn file(&self) -> Option<&FileSink> {
+        Some(self)
+    }
+    fn taken(&self) -> bool {
+        output_taken(&self.path, self.force)
+    }
+    fn deliver(&mut self, decoder: &QrSendDecoder) -> io::Result<()> {
+        let md = decoder.metadata.as_ref().expect("metadata is known");
+        // Nothing appears at `path` until the whole file is written.
+        let partial = partial_path(&self.path);
+        let written =
+            write_output(decoder, md, &partial).and_then(|_| std::fs::rename(&partial, &self.path));
+        if written.is_err() {
+            let _ = std::fs::remove_file(&partial);
+        }
+        written
+    }
+}
+
+/// Hidden sibling of `output_file` the file is written to before it is
+/// renamed into place.
+pub fn partial_path(output_file: impl AsRef<path::Path>) -> path::PathBuf {
+    let target = output_file.as_ref();
+    let name = target
+        .file_name()
+        .map_or_else(Default::default, |n| n.to_string_lossy());
+    target.with_file_name(format!(".{}.partial", name))
+}
+
+/// The file on stdout, chosen with `--output-file -`. Logs go to stderr,
+/// so nothing else is mixed in.
+pub struct StdoutSink;
+impl Sink for StdoutSink {
+    fn name(&self) -> String {
+        "stdout".to_string()
+    }
+    fn deliver(&mut self, decoder: &QrSendDecoder) -> io::Result<()> {
+        let mut out = io::BufWriter::new(io::stdout().lock());
+        decoder.write_in_order(&mut out)?;
+        out.flush()
+    }
+}
+
+/// The file on the stdin of a command, which must exit successfully.
+pub struct CommandSink {
+    name: String,
+    command: Command,
+}
+impl CommandSink {
+    /// Run `command_line` through the shell, for `--pipe-to`.
+    pub fn shell(command_line: &str) -> Self {
+        let mut command = if cfg!(windows) {
+            let mut command = Command::new("cmd");
+            command.arg("/C");
+            command
+        } else {
+            let mut command = Command::new("sh");
+            command.arg("-c");
+            command
+        };
+        command.arg(command_line);
+        CommandSink {
+            name: format!("`{}`", command_line),
+            command,
+        }
+    }
+    #[cfg(feature = "http")]
+    /// Upload with an HTTP PUT to `url` by running curl, which fails on an
+    /// error status. Presigned S3 URLs take the file as it is.
+    pub fn http_put(url: &str) -> Self {
+        let mut command = Command::new("curl");
+        command
+            .args(["--fail", "--silent", "--show-error", "--upload-file", "-"])
+            .arg(url);
+        CommandSink {
+            name: url.to_string(),
+            command,
+        }
+    }
+}
+impl Sink for CommandSink {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+    fn deliver(&mut self, decoder: &QrSendDecoder) -> io::Result<()> {
+        let mut child = self.command.stdin(Stdio::piped()).spawn()?;
+        let mut stdin = io::BufWriter::new(child.stdin.take().expect("stdin is piped"));
+        let written = decoder
+            .write_in_order(&mut stdin)
+            .and_then(|_| stdin.flush());
+        drop(stdin);
+        // A command that stops reading early says why when it exits.
+        let status = child.wait()?;
+        if !status.success() {
+            return Err(io::Error::other(status.to_string()));
+        }
+        written
+    }
+}
+
+// Through `sh`, as `--pipe-to` runs commands off Windows.
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::receive::{finish, Outcome};
+    use crate::synth::{Options, Transfer};
+
+    #[test]
+    fn pipe_to_feeds_the_command_and_checks_its_status() {
+        let transfer = Transfer::new(&Options::default());
+        let mut decoder = QrSendDecoder::new();
+        for frame in &transfer.frames {
+            decoder.push(frame.clone());
+        }
+        let dir = std::env::temp_dir().join(format!("qr-recv-pipe-to-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let piped = dir.join("piped.bin");
+        let mut sink = CommandSink::shell(&format!("cat > '{}'", piped.display()));
+        assert_eq!(finish(&decoder, &mut sink, false), Outcome::Verified);
+        assert_eq!(std::fs::read(&piped).unwrap(), transfer.file);
+
+        let mut failing = CommandSink::shell("cat > /dev/null; exit 3");
+        assert_eq!(finish(&decoder, &mut failing, false), Outcome::WriteFailed);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,123 @@
+//! The `N/M <base64>` text framing of shell script senders, which `split`
+//! a file and pipe each piece through `base64` and `qrencode`.
+//!
+//! Pieces are numbered from 1 or from 0, the count follows the slash, and
+//! a space, colon or bar separates the header from the piece. Nothing is
+//! hashed, so the only check is that every piece arrived once.
+
+use std::collections::BTreeMap;
+
+use base64::prelude::*;
+
+use crate::api::Error;
+use crate::ranges::SegmentRanges;
+use crate::transfer::Protocol;
+
+/// Piece number, piece count and decoded piece of one frame.
+fn parse(text: &[u8]) -> Option<(u64, u64, Vec<u8>)> {
+    let text = std::str::from_utf8(text).ok()?.trim();
+    let split = text.find([' ', ':', '|'])?;
+    let (header, body) = (&text[..split], &text[split + 1..]);
+    let (index, count) = header.split_once('/')?;
+    let index = index.parse().ok()?;
+    let count: u64 = count.parse().ok()?;
+    if count == 0 || index > count {
+        return None;
+    }
+    // `base64` wraps lines unless told not to.
+    let body: String = body.chars().filter(|c| !c.is_ascii_whitespace()).collect();
+    let piece = BASE64_STANDARD.decode(body).ok()?;
+    Some((index, count, piece))
+}
+
+pub struct SplitText {
+    count: Option<u64>,
+    pieces: BTreeMap<u64, Vec<u8>>,
+    /// Frames that parsed but disagree with the piece count seen first.
+    pub rejected_frames: u64,
+}
+impl Default for SplitText {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+impl SplitText {
+    pub fn new() -> Self {
+        SplitText {
+            count: None,
+            pieces: BTreeMap::new(),
+            rejected_frames: 0,
+        }
+    }
+    /// Whether `text` looks like an `N/M` frame.
+    pub fn detect(text: &[u8]) -> bool {
+        parse(text).is_some()
+    }
+    /// First piece number: 0 once piece 0 has been seen, otherwise 1.
+    fn base(&self) -> u64 {
+        u64::from(!self.pieces.contains_key(&0))
+    }
+    /// The gaps between the pieces held, so a forged count costs nothing.
+    pub fn missing_pieces(&self) -> SegmentRanges {
+        let Some(count) = self.count else {
+            return SegmentRanges::default();
+        };
+        let base = self.base();
+        let end = base + count;
+        let mut next = base;
+        let mut gaps = Vec::new();
+        for &i in self.pieces.range(base..end).map(|(i, _)| i) {
+            gaps.push(next..i);
+            next = i + 1;
+        }
+        gaps.push(next..end);
+        SegmentRanges::from_ranges(gaps)
+    }
+}
+impl Protocol for SplitText {
+    fn push_text(&mut self, text: &[u8]) -> bool {
+        let Some((index, count, piece)) = parse(text) else {
+            return false;
+        };
+        if self.count.is_some_and(|c| c != count) {
+            log::warn!("piece {}/{} belongs to another transfer", index, count);
+            self.rejected_frames += 1;
+            return true;
+        }
+        if self.count.is_none() {
+            log::info!("split transfer of {} pieces", count);
+        }
+        self.count = Some(count);
+        if let Some(held) = self.pieces.get(&index) {
+            if *held != piece {
+                log::warn!(
+                    "piece {} arrived with conflicting content, keeping the earlier copy",
+                    index
+                );
+            }
+            return true;
+        }
+        log::debug!("got piece {}/{}", index, count);
+        self.pieces.insert(index, piece);
+        true
+    }
+    fn progress(&self) -> (u64, Option<u64>) {
+        (self.pieces.len() as u64, self.count)
+    }
+    fn is_complete(&self) -> bool {
+        self.count.is_some() && self.missing_pieces().is_empty()
+    }
+    fn finish(&self) -> Result<Vec<u8>, Error> {
+        let Some(count) = self.count else {
+            return Err(Error::NoMetadata);
+        };
+        let missing = self.missing_pieces();
+        if !missing.is_empty() {
+            return Err(Error::Incomplete { missing });
+        }
+        let base = self.base();
+        Ok((base..base + count)
+            .flat_map(|i| self.pieces[&i].clone())
+            .collect())
+    }
+}
@@ -0,0 +1,158 @@
+use image::GenericImageView;
+use std::time::{Duration, Instant};
+
+use crate::crc32c;
+use crate::decoder::{scan, QrSendDecoder};
+use crate::exposure::Exposure;
+use crate::protocol::{decode_payload, guess_hash_len};
+
+/// What happened to a single captured frame.
+#[derive(Debug, Clone)]
+pub struct FrameStat {
+    pub width: u32,
+    pub height: u32,
+    /// Length of the text carried by the QR symbol, if one was found.
+    pub text_len: Option<usize>,
+    /// Average side length of the detected symbol in pixels.
+    pub symbol_side: Option<f64>,
+    /// Decoded frame bytes, when the symbol held a segment that verified.
+    pub payload: Option<Vec<u8>>,
+    pub decode_time: Duration,
+    pub exposure: Exposure,
+}
+impl FrameStat {
+    pub fn detected(&self) -> bool {
+        self.text_len.is_some()
+    }
+    pub fn verified(&self) -> bool {
+        self.payload.is_some()
+    }
+}
+
+fn symbol_side(points: &[(i32, i32)]) -> Option<f64> {
+    let min_x = points.iter().map(|p| p.0).min()?;
+    let max_x = points.iter().map(|p| p.0).max()?;
+    let min_y = points.iter().map(|p| p.1).min()?;
+    let max_y = points.iter().map(|p| p.1).max()?;
+    Some(((max_x - min_x) + (max_y - min_y)) as f64 / 2.0)
+}
+
+fn median(mut values: Vec<f64>) -> Option<f64> {
+    if values.is_empty() {
+        return None;
+    }
+    values.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    Some(values[values.len() / 2])
+}
+
+pub fn frame_stat(img: &image::DynamicImage, verifier: &QrSendDecoder) -> FrameStat {
+    let (width, height) = img.dimensions();
+    let start = Instant::now();
+    let symbol = scan(img).into_iter().next();
+    let decode_time = start.elapsed();
+    let mut stat = FrameStat {
+        width,
+        height,
+        text_len: None,
+        symbol_side: None,
+        payload: None,
+        decode_time,
+        exposure: Exposure::measure(img),
+    };
+    if let Some(symbol) = symbol {
+        stat.text_len = Some(symbol.data.len());
+        stat.symbol_side = symbol_side(&symbol.points);
+        stat.payload = decode_payload(&symbol.data, None)
+            .map(|(_, frame)| frame)
+            .filter(|frame| verifier.verify_segment(crc32c::strip(frame).unwrap_or(frame)));
+    }
+    stat
+}
+
+/// Decode statistics over a whole capture, in capture order.
+pub struct CaptureStats {
+    pub frames: Vec<FrameStat>,
+}
+impl CaptureStats {
+    pub fn collect<I: Iterator<Item = image::DynamicImage>>(images: I) -> Self {
+        let verifier = QrSendDecoder::new();
+        CaptureStats {
+            frames: images.map(|img| frame_stat(&img, &verifier)).collect(),
+        }
+    }
+    pub fn detect_rate(&self) -> f64 {
+        self.rate(|f| f.detected())
+    }
+    pub fn verify_rate(&self) -> f64 {
+        self.rate(|f| f.verified())
+    }
+    fn rate(&self, pred: impl Fn(&FrameStat) -> bool) -> f64 {
+        if self.frames.is_empty() {
+            return 0.0;
+        }
+        self.frames.iter().filter(|f| pred(f)).count() as f64 / self.frames.len() as f64
+    }
+    /// Number of runs of consecutive frames showing the same payload.
+    pub fn distinct_runs(&self) -> usize {
+        let mut runs = 0;
+        let mut last: Option<&Vec<u8>> = None;
+        for payload in self.frames.iter().filter_map(|f| f.payload.as_ref()) {
+            if last != Some(payload) {
+                runs += 1;
+            }
+            last = Some(payload);
+        }
+        runs
+    }
+    /// How many captured frames each displayed sender frame spans on average.
+    pub fn captures_per_sender_frame(&self) -> Option<f64> {
+        match self.distinct_runs() {
+            0 => None,
+            runs => Some(self.frames.len() as f64 / runs as f64),
+        }
+    }
+    pub fn median_symbol_side(&self) -> Option<f64> {
+        median(self.frames.iter().filter_map(|f| f.symbol_side).collect())
+    }
+    pub fn median_text_len(&self) -> Option<usize> {
+        median(
+            self.frames
+                .iter()
+                .filter_map(|f| f.text_len.map(|l| l as f64))
+                .collect(),
+        )
+        .map(|l| l as usize)
+    }
+    pub fn frame_size(&self) -> Option<(u32, u32)> {
+        self.frames.first().map(|f| (f.width, f.height))
+    }
+    pub fn median_hash_len(&self) -> Option<usize> {
+        median(
+            self.frames
+                .iter()
+                .filter_map(|f| f.payload.as_deref().and_then(guess_hash_len))
+                .map(|l| l as f64)
+                .collect(),
+        )
+        .map(|l| l as usize)
+    }
+    /// Share of the frames whose [`Exposure::label`] is `label`.
+    pub fn exposure_rate(&self, label: &str) -> f64 {
+        self.rate(|f| f.exposure.label() == label)
+    }
+    pub fn median_brightness(&self) -> Option<u8> {
+        median(
+            self.frames
+                .iter()
+                .map(|f| f.exposure.median as f64)
+                .collect(),
+        )
+        .map(|m| m as u8)
+    }
+    pub fn mean_decode_time(&self) -> Duration {
+        match self.frames.len() {
+            0 => Duration::ZERO,
+            n => self.frames.iter().map(|f| f.decode_time).sum::<Duration>() / n as u32,
+        }
+    }
+}
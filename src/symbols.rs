@@ -0,0 +1,132 @@
+//! Readers for the 2D codes frames are drawn as.
+//!
+//! Qrcodes are read in process by zbar. With the `external-decoders`
+//! feature DataMatrix and Aztec codes are read by running a decoder
+//! program on each image, which prints the content of the code it found.
+
+use std::cell::RefCell;
+
+use crate::luma::{self, Rect};
+use crate::protocol::Symbology;
+
+/// One code found in an image.
+pub struct Symbol {
+    pub data: Vec<u8>,
+    /// Corners of the code in image pixels, when the reader reports them.
+    pub points: Vec<(i32, i32)>,
+}
+
+pub trait SymbolReader: Send + Sync {
+    fn symbology(&self) -> Symbology;
+    fn read(&self, img: &image::DynamicImage) -> Vec<Symbol>;
+}
+
+thread_local! {
+    /// Gray pixels handed to zbar, kept to save allocating them per frame.
+    static GRAY: RefCell<Vec<u8>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Qrcodes, through zbar.
+pub struct Zbar;
+impl SymbolReader for Zbar {
+    fn symbology(&self) -> Symbology {
+        Symbology::Qr
+    }
+    fn read(&self, img: &image::DynamicImage) -> Vec<Symbol> {
+        use image::GenericImageView;
+        let mut scanner = zbar_rust::ZBarImageScanner::new();
+        // zbar >= 0.23.1 numbers ZBAR_CFG_BINARY where this crate's enum has
+        // ZBarCfgNum; without it byte-mode content is transcoded as text and raw
+        // payloads come back mangled. Older zbar rejects the option, which is harmless.
+        let _ = scanner.set_config(
+            zbar_rust::ZBarSymbolType::ZBarQRCode,
+            zbar_rust::ZBarConfig::ZBarCfgNum,
+            1,
+        );
+        let (w, h) = img.dimensions();
+        let results = match img {
+            image::DynamicImage::ImageLuma8(gray) => scanner.scan_y800(gray.as_raw(), w, h),
+            _ => GRAY.with_borrow_mut(|buf| {
+                buf.clear();
+                luma::extend_gray(img, Rect::whole(img), buf);
+                scanner.scan_y800(&buf[..], w, h)
+            }),
+        };
+        results
+            .unwrap_or_default()
+            .into_iter()
+            .map(|r| Symbol {
+                data: r.data,
+                points: r.points,
+            })
+            .collect()
+    }
+}
+
+/// Qrcodes found in `img`.
+pub fn scan(img: &image::DynamicImage) -> Vec<Symbol> {
+    Zbar.read(img)
+}
+
+#[cfg(feature = "external-decoders")]
+/// A decoder program run with the path of a PNG copy of each image
+/// appended to `command`.
+pub struct External {
+    pub symbology: Symbology,
+    pub command: Vec<String>,
+}
+#[cfg(feature = "external-decoders")]
+impl External {
+    /// The usual command line tool for `symbology`.
+    pub fn default_command(symbology: Symbology) -> Vec<String> {
+        let command: &[&str] = match symbology {
+            Symbology::Qr => &["zbarimg", "--raw", "-q"],
+            Symbology::DataMatrix => &["dmtxread", "--stop-after=1"],
+            Symbology::Aztec => &["ZXingReader", "-format", "Aztec", "-bytes"],
+        };
+        command.iter().map(|s| s.to_string()).collect()
+    }
+}
+#[cfg(feature = "external-decoders")]
+impl SymbolReader for External {
+    fn symbology(&self) -> Symbology {
+        self.symbology
+    }
+    fn read(&self, img: &image::DynamicImage) -> Vec<Symbol> {
+        use std::sync::atomic::{AtomicU64, Ordering};
+        static NEXT: AtomicU64 = AtomicU64::new(0);
+        let path = std::env::temp_dir().join(format!(
+            "qr-recv-{}-{}.png",
+            std::process::id(),
+            NEXT.fetch_add(1, Ordering::Relaxed)
+        ));
+        if let Err(e) = img.save(&path) {
+            log::warn!("cannot hand image to {}: {}", self.command[0], e);
+            return Vec::new();
+        }
+        let output = std::process::Command::new(&self.command[0])
+            .args(&self.command[1..])
+            .arg(&path)
+            .output();
+        let _ = std::fs::remove_file(&path);
+        let mut data = match output {
+            Ok(output) if output.status.success() => output.stdout,
+            Ok(_) => return Vec::new(),
+            Err(e) => {
+                log::warn!("cannot run {}: {}", self.command[0], e);
+                return Vec::new();
+            }
+        };
+        // Printed with a newline, which payload encodings never end in.
+        if data.last() == Some(&b'\n') {
+            data.pop();
+        }
+        if data.is_empty() {
+            return Vec::new();
+        }
+        vec![Symbol {
+            data,
+            points: Vec::new(),
+        }]
+    }
+}
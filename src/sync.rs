@@ -0,0 +1,55 @@
+use std::collections::HashSet;
+
+/// Loss accounting derived from sender sync frames.
+///
+/// Between two sync frames the sender displayed exactly `counter delta - 1`
+/// other frames. Those that never decoded are blamed on decode failures as
+/// far as failed captures in the same interval can explain them, and on
+/// dropped captures for the rest.
+#[derive(Default, Debug, Clone)]
+pub struct SyncTracker {
+    last_counter: Option<u64>,
+    interval_payloads: HashSet<Vec<u8>>,
+    interval_failures: u64,
+    pub syncs_seen: u64,
+    pub displayed: u64,
+    pub decoded: u64,
+    pub decode_failures: u64,
+    pub capture_losses: u64,
+}
+impl SyncTracker {
+    pub fn on_failure(&mut self) {
+        self.interval_failures += 1;
+    }
+    pub fn on_payload(&mut self, payload: &[u8]) {
+        self.interval_payloads.insert(payload.to_vec());
+    }
+    pub fn on_sync(&mut self, counter: u64) {
+        self.syncs_seen += 1;
+        if let Some(last) = self.last_counter {
+            if counter > last {
+                let between = counter - last - 1;
+                let decoded = (self.interval_payloads.len() as u64).min(between);
+                let missed = between - decoded;
+                let failures = missed.min(self.interval_failures);
+                self.displayed += between;
+                self.decoded += decoded;
+                self.decode_failures += failures;
+                self.capture_losses += missed - failures;
+            }
+        }
+        self.last_counter = Some(counter);
+        self.interval_payloads.clear();
+        self.interval_failures = 0;
+    }
+    pub fn report(&self) {
+        if self.syncs_seen < 2 {
+            return;
+        }
+        log::info!("sync frames seen: {}", self.syncs_seen);
+        log::info!("sender frames between syncs: {}", self.displayed);
+        log::info!("decoded: {}", self.decoded);
+        log::info!("lost to decode failures: {}", self.decode_failures);
+        log::info!("lost in capture: {}", self.capture_losses);
+    }
+}
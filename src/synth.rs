@@ -0,0 +1,163 @@
+//! Synthetic transfers for the integration tests and benchmarks.
+//! Not part of the stable library API.
+
+use crate::protocol::{blake2b, payload_text};
+pub use crate::protocol::{
+    decode_metadata, Endianness, FileHash, Frame, FrameError, Manifest, MetadataChunk,
+    MetadataFormat, PayloadEncoding, QrSendMetadata,
+};
+pub use crate::qr::EccLevel;
+use crate::send::{build_frames, pack_channels, render, FrameOptions};
+pub use crate::split::SplitText;
+pub use crate::transfer::{Protocol, ProtocolKind};
+
+/// Deterministic xorshift generator, so failures and timings reproduce.
+pub struct Rng(u64);
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        // xorshift never leaves zero.
+        Rng(seed ^ 0x2545_f491_4f6c_dd1d)
+    }
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+    pub fn below(&mut self, n: usize) -> usize {
+        (self.next_u64() % n as u64) as usize
+    }
+    pub fn bytes(&mut self, len: usize) -> Vec<u8> {
+        (0..len).map(|_| self.next_u64() as u8).collect()
+    }
+    /// Fisher-Yates shuffle of `items`.
+    pub fn shuffle<T>(&mut self, items: &mut [T]) {
+        for i in (1..items.len()).rev() {
+            items.swap(i, self.below(i + 1));
+        }
+    }
+}
+
+/// `frame` followed by its per-frame hash, for hand-made frames.
+pub fn seal(frame: &[u8], hash_len: usize) -> Vec<u8> {
+    let mut sealed = frame.to_vec();
+    sealed.extend_from_slice(&blake2b(frame, hash_len));
+    sealed
+}
+
+/// Sender settings for a synthetic transfer.
+#[derive(Debug, Clone)]
+pub struct Options {
+    pub size: usize,
+    pub chunk_size: usize,
+    pub hash_len: u64,
+    /// The narrowest fixed width that fits when `None`, as `send` does.
+    pub id_type: Option<&'static str>,
+    pub endianness: Endianness,
+    pub payload_encoding: PayloadEncoding,
+    pub metadata_format: MetadataFormat,
+    pub metadata_repeat: Option<usize>,
+    /// Check the metadata chunks, as `send --metadata-checksum` does.
+    pub metadata_checksum: bool,
+    /// Draw frames three to an image, as `send --color-channels` does.
+    pub color_channels: bool,
+    /// End data frames in a CRC-32C, as `send --crc32c` does.
+    pub crc32c: bool,
+    /// Hash of the whole file, `send`'s BLAKE3 by default.
+    pub file_hash: FileHash,
+    /// Start every text with the protocol prefix, as `send --magic` does.
+    pub magic: bool,
+    /// Error correction level of the rendered qrcodes.
+    pub ecc: EccLevel,
+    /// Pixels per module of the rendered qrcodes.
+    pub scale: u32,
+    pub seed: u64,
+    /// Sent after the metadata, as `send --manifest` does.
+    pub manifest: Option<Manifest>,
+}
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            size: 4096,
+            chunk_size: 512,
+            hash_len: 8,
+            id_type: None,
+            endianness: Endianness::Big,
+            payload_encoding: PayloadEncoding::Base64,
+            metadata_format: MetadataFormat::Json,
+            metadata_repeat: None,
+            metadata_checksum: false,
+            color_channels: false,
+            crc32c: false,
+            file_hash: FileHash::Blake3,
+            magic: false,
+            ecc: EccLevel::M,
+            scale: 4,
+            seed: 0,
+            manifest: None,
+        }
+    }
+}
+
+/// A random file and everything `send` would produce for it.
+pub struct Transfer {
+    pub file: Vec<u8>,
+    /// Frame bytes, in send order, before payload encoding.
+    pub frames: Vec<Vec<u8>>,
+    /// Text carried by each qrcode.
+    pub texts: Vec<Vec<u8>>,
+    /// Rendered qrcode images, fewer than the frames with color channels.
+    pub images: Vec<image::DynamicImage>,
+}
+impl Transfer {
+    /// A random file of `options.size` bytes, drawn from `options.seed`.
+    pub fn new(options: &Options) -> Self {
+        Transfer::from_file(Rng::new(options.seed).bytes(options.size), options)
+    }
+    /// `file` as `send` would produce it; `options.size` is not used.
+    pub fn from_file(file: Vec<u8>, options: &Options) -> Self {
+        let frame_options = FrameOptions {
+            chunk_size: options.chunk_size,
+            hash_len: options.hash_len,
+            id_type: options.id_type,
+            endianness: options.endianness,
+            metadata_format: options.metadata_format,
+            payload_encoding: options.payload_encoding,
+            metadata_repeat: options.metadata_repeat,
+            metadata_checksum: options.metadata_checksum,
+            file_name: None,
+            mtime: None,
+            color_channels: options.color_channels,
+            crc32c: options.crc32c,
+            file_hash: options.file_hash,
+            magic: options.magic,
+            manifest: options.manifest.as_ref(),
+        };
+        let frames = build_frames(&file, &frame_options, None);
+        let (encoding, magic) = (options.payload_encoding, options.magic);
+        let texts = frames
+            .iter()
+            .map(|f| payload_text(f, encoding, magic))
+            .collect();
+        let codes = frames
+            .iter()
+            .map(|f| render(f, encoding, magic, options.ecc).to_image(options.scale));
+        let images = if options.color_channels {
+            pack_channels(frames.iter().map(|f| f[0]).zip(codes))
+        } else {
+            codes.map(Into::into).collect()
+        };
+        Transfer {
+            file,
+            frames,
+            texts,
+            images,
+        }
+    }
+    /// Send order positions of the frames whose type byte is `tag`.
+    pub fn frame_indices(&self, tag: u8) -> Vec<usize> {
+        (0..self.frames.len())
+            .filter(|&i| self.frames[i][0] == tag)
+            .collect()
+    }
+}
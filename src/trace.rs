@@ -0,0 +1,210 @@
+//! `receive --trace`: a JSON line per image the decoder reads, with what
+//! was found in it and what became of each code, for working out offline
+//! why a capture decoded the way it did, and for `qr-recv replay`.
+
+use serde::{Deserialize, Serialize};
+use std::io::{self, BufRead, Write};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use std::{fs, path};
+
+/// What became of one code found in an image.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Verdict {
+    /// Verified and taken by the decoder.
+    Accepted,
+    /// No registered frame type.
+    UnknownType,
+    /// Failed its frame hash or CRC-32C.
+    Rejected,
+    /// Too short for the id and hash the metadata declares.
+    Undersized,
+    /// Not as long as the declared segment size makes it.
+    WrongLength,
+    /// Names a segment past the count the metadata declares.
+    OutOfRange,
+    /// A verified data frame of a segment outside `--only-segments`.
+    Filtered,
+    /// Verified, but of a type the decoder counts and moves on from.
+    Skipped,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Code {
+    /// Length of the frame, after payload decoding.
+    pub len: usize,
+    /// Name of the frame type, when the type byte is a registered one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub frame_type: Option<String>,
+    /// Segment id of a data frame, once the metadata says how to read it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub segment: Option<u64>,
+    pub verdict: Verdict,
+}
+
+/// The line of one image.
+#[derive(Serialize, Deserialize)]
+pub struct Record {
+    /// Position of the image among those the decoder read.
+    pub frame: u64,
+    pub source: String,
+    /// Unix time the image was taken up, in milliseconds.
+    pub unix_ms: u64,
+    /// Milliseconds since the trace started.
+    pub at_ms: f64,
+    /// Time spent scanning the image; absent when it was scanned elsewhere.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub decode_ms: Option<f64>,
+    /// Decode cache entries the image was looked up under, one per color
+    /// channel scanned, when a cache was in use.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub cache_keys: Vec<String>,
+    /// Codes found in the image that are no frame.
+    pub foreign: u64,
+    pub codes: Vec<Code>,
+}
+
+/// The trace file, with the record of the image being read.
+pub struct Trace {
+    out: io::BufWriter<fs::File>,
+    /// Where the next image comes from, a file of the capture or its name.
+    pub source: String,
+    start: Instant,
+    record: Option<Record>,
+    failed: bool,
+}
+impl Trace {
+    pub fn create(path: &path::Path) -> io::Result<Self> {
+        Ok(Trace {
+            out: io::BufWriter::new(fs::File::create(path)?),
+            source: String::new(),
+            start: Instant::now(),
+            record: None,
+            failed: false,
+        })
+    }
+    /// The records of the trace at `path`, in the order they were written.
+    pub fn read(path: &path::Path) -> io::Result<Vec<Record>> {
+        let file = io::BufReader::new(fs::File::open(path)?);
+        let mut records = Vec::new();
+        for (i, line) in file.lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let record = serde_json::from_str(&line).map_err(|e| {
+                io::Error::new(io::ErrorKind::InvalidData, format!("line {}: {}", i + 1, e))
+            })?;
+            records.push(record);
+        }
+        Ok(records)
+    }
+    /// Start the record of image `index`.
+    pub fn begin(&mut self, index: u64) {
+        let unix_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_millis() as u64);
+        self.record = Some(Record {
+            frame: index,
+            source: self.source.clone(),
+            unix_ms,
+            at_ms: self.start.elapsed().as_secs_f64() * 1000.0,
+            decode_ms: None,
+            cache_keys: Vec::new(),
+            foreign: 0,
+            codes: Vec::new(),
+        });
+    }
+    /// Note the decode cache entry the image, or a channel of it, is under.
+    pub fn cached(&mut self, key: &str) {
+        if let Some(record) = self.record.as_mut() {
+            record.cache_keys.push(key.to_string());
+        }
+    }
+    /// Add what became of a code found in the image.
+    pub fn code(
+        &mut self,
+        len: usize,
+        frame_type: Option<&'static str>,
+        segment: Option<u64>,
+        verdict: Verdict,
+    ) {
+        if let Some(record) = self.record.as_mut() {
+            record.codes.push(Code {
+                len,
+                frame_type: frame_type.map(str::to_string),
+                segment,
+                verdict,
+            });
+        }
+    }
+    /// Write the record of the image, which took `decode_time` to scan and
+    /// held `foreign` codes that are no frame.
+    pub fn end(&mut self, decode_time: Option<Duration>, foreign: u64) {
+        let Some(mut record) = self.record.take() else {
+            return;
+        };
+        record.decode_ms = decode_time.map(|t| t.as_secs_f64() * 1000.0);
+        record.foreign = foreign;
+        let line = serde_json::to_string(&record).expect("trace records serialize");
+        if let Err(e) = writeln!(self.out, "{}", line) {
+            // Once is enough; the decode goes on without the trace.
+            if !self.failed {
+                log::error!("could not write the trace: {}", e);
+                self.failed = true;
+            }
+        }
+    }
+}
+impl Drop for Trace {
+    fn drop(&mut self) {
+        if let Err(e) = self.out.flush() {
+            log::error!("could not write the trace: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decoder::QrSendDecoder;
+    use crate::synth::{Options, Transfer};
+
+    #[test]
+    fn trace_tells_the_fate_of_each_code() {
+        let transfer = Transfer::new(&Options::default());
+        let path = std::env::temp_dir().join(format!("qr-recv-trace-{}.jsonl", std::process::id()));
+        let mut decoder = QrSendDecoder::new();
+        decoder.trace = Some(Trace::create(&path).unwrap());
+        let data = transfer.frame_indices(b'D')[0];
+        let mut corrupt = transfer.frames[data].clone();
+        corrupt[5] ^= 1;
+        decoder.push_scanned(vec![corrupt], 0);
+        decoder.push_scanned(Vec::new(), 2);
+        for frame in &transfer.frames {
+            decoder.push_scanned(vec![frame.clone()], 0);
+        }
+        drop(decoder);
+
+        let records = Trace::read(&path).unwrap();
+        assert_eq!(records.len(), transfer.frames.len() + 2);
+        assert!(records.iter().enumerate().all(|(i, r)| r.frame == i as u64));
+        // Before the metadata the hash length is guessed, and fails.
+        let [code] = &records[0].codes[..] else {
+            panic!("one code in the first image");
+        };
+        assert_eq!(code.verdict, Verdict::Rejected);
+        assert_eq!((records[1].codes.len(), records[1].foreign), (0, 2));
+        for (record, frame) in records[2..].iter().zip(&transfer.frames) {
+            let [code] = &record.codes[..] else {
+                panic!("one code in image {}", record.frame);
+            };
+            assert_eq!(code.verdict, Verdict::Accepted);
+            assert_eq!(code.len, frame.len());
+            let data = frame[0] == b'D';
+            assert_eq!(code.frame_type.as_deref() == Some("data"), data);
+            assert_eq!(code.segment, data.then(|| u64::from(frame[1])));
+        }
+        fs::remove_file(&path).unwrap();
+    }
+}
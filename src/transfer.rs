@@ -0,0 +1,93 @@
+//! The transfer protocols this receiver speaks, behind one interface.
+
+use crate::api::Error;
+use crate::decoder::QrSendDecoder;
+use crate::protocol::{decode_payload, guess_hash_len, FrameType};
+use crate::split::SplitText;
+
+/// Frame format selected with `--protocol`.
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ProtocolKind {
+    /// Whichever the first qrcode of the capture is in
+    #[default]
+    Auto,
+    /// This crate's own sender
+    QrSend,
+    /// `N/M <base64>` pieces from `split | base64 | qrencode` scripts
+    Split,
+}
+impl ProtocolKind {
+    /// The protocol a qrcode carrying `text` belongs to, `QrSend` when unsure.
+    pub fn detect(text: &[u8]) -> Self {
+        let sealed = decode_payload(text, None)
+            .is_some_and(|(_, f)| FrameType::of(&f).is_some() && guess_hash_len(&f).is_some());
+        if sealed {
+            ProtocolKind::QrSend
+        } else if SplitText::detect(text) {
+            ProtocolKind::Split
+        } else {
+            ProtocolKind::QrSend
+        }
+    }
+}
+
+/// A receiver for one transfer in some frame format.
+pub trait Protocol {
+    /// Feed the content of one scanned code, returning whether it was a
+    /// frame of this protocol.
+    fn push_text(&mut self, text: &[u8]) -> bool;
+    /// Units received so far, and how many the transfer needs once known.
+    fn progress(&self) -> (u64, Option<u64>);
+    fn is_complete(&self) -> bool;
+    /// The file, assembled and checked as far as the protocol allows.
+    fn finish(&self) -> Result<Vec<u8>, Error>;
+}
+
+impl Protocol for QrSendDecoder {
+    fn push_text(&mut self, text: &[u8]) -> bool {
+        let Some((_, frame)) = decode_payload(text, self.metadata.as_ref()) else {
+            self.push_foreign();
+            return false;
+        };
+        self.push(frame);
+        true
+    }
+    fn progress(&self) -> (u64, Option<u64>) {
+        (
+            self.data_segments.len() as u64,
+            self.metadata.as_ref().map(|md| md.qrcode_count),
+        )
+    }
+    fn is_complete(&self) -> bool {
+        QrSendDecoder::is_complete(self)
+    }
+    fn finish(&self) -> Result<Vec<u8>, Error> {
+        self.verify()?;
+        Ok(self.assemble().expect("every segment is held"))
+    }
+}
+
+impl QrSendDecoder {
+    /// Check the segments held against the sender's hash of the whole file.
+    pub fn verify(&self) -> Result<(), Error> {
+        if let Some(version) = &self.unsupported_version {
+            return Err(Error::UnsupportedVersion(version.clone()));
+        }
+        if self.metadata.is_none() {
+            return Err(Error::NoMetadata);
+        }
+        // Hashed segment by segment, so a mismatch never assembles the file.
+        let Some(computed) = self.segments_hash() else {
+            return Err(Error::Incomplete {
+                missing: self.missing_ranges(),
+            });
+        };
+        if self.total_hash.is_empty() {
+            return Err(Error::NoHash);
+        }
+        if computed != self.total_hash {
+            return Err(Error::HashMismatch);
+        }
+        Ok(())
+    }
+}
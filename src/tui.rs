@@ -0,0 +1,292 @@
+use std::fmt::Write as _;
+use std::io::{self, IsTerminal, Write};
+use std::time::{Duration, Instant};
+
+use crate::decoder::QrSendDecoder;
+use crate::protocol::{get_id_and_len, FrameType};
+
+/// Segment cells per row of the bitmap.
+const GRID_COLUMNS: u64 = 64;
+/// Rows of the bitmap before segments start sharing a cell.
+const GRID_ROWS: u64 = 16;
+/// Redraw at most this often so fast captures do not flood the terminal.
+const REDRAW_INTERVAL: Duration = Duration::from_millis(100);
+/// How often the `--bitmap` view is redrawn in place, and how often it is
+/// printed anew when stderr is no terminal.
+const BITMAP_INTERVAL: Duration = Duration::from_millis(500);
+const BITMAP_LOG_INTERVAL: Duration = Duration::from_secs(10);
+
+/// The glyphs of a cell whose segments all arrived, some did, and none did.
+type Glyphs = [char; 3];
+const DASHBOARD_GLYPHS: Glyphs = ['█', '▒', '·'];
+const BITMAP_GLYPHS: Glyphs = ['#', '+', '.'];
+
+/// How progress is shown while receiving, besides the log.
+pub enum View {
+    Dashboard(Dashboard),
+    Bitmap(AckBitmap),
+}
+impl View {
+    pub fn update(&mut self, decoder: &QrSendDecoder) {
+        match self {
+            View::Dashboard(dashboard) => dashboard.update(decoder),
+            View::Bitmap(bitmap) => bitmap.update(decoder),
+        }
+    }
+    pub fn draw(&mut self, decoder: &QrSendDecoder) {
+        match self {
+            View::Dashboard(dashboard) => dashboard.draw(decoder),
+            View::Bitmap(bitmap) => bitmap.draw(decoder),
+        }
+    }
+}
+
+/// Live terminal view of a receive in progress, drawn with plain ANSI escapes.
+pub struct Dashboard {
+    source: String,
+    start: Instant,
+    last_draw: Option<Instant>,
+}
+impl Dashboard {
+    pub fn new(source: String) -> Self {
+        Dashboard {
+            source,
+            start: Instant::now(),
+            last_draw: None,
+        }
+    }
+
+    /// Redraw unless the previous frame was drawn very recently.
+    pub fn update(&mut self, decoder: &QrSendDecoder) {
+        if self
+            .last_draw
+            .is_some_and(|t| t.elapsed() < REDRAW_INTERVAL)
+        {
+            return;
+        }
+        self.draw(decoder);
+    }
+
+    pub fn draw(&mut self, decoder: &QrSendDecoder) {
+        self.last_draw = Some(Instant::now());
+        let screen = self.render(decoder);
+        let mut stdout = io::stdout();
+        write!(stdout, "\x1b[H\x1b[2J{}", screen).unwrap();
+        stdout.flush().unwrap();
+    }
+
+    fn render(&self, decoder: &QrSendDecoder) -> String {
+        let elapsed = self.start.elapsed().as_secs_f64();
+        let received_bytes: usize = decoder.data_segments.values().map(|s| s.data().len()).sum();
+        let failed = decoder.undecoded_frames
+            + decoder.rejected_frames
+            + decoder.undersized_frames
+            + decoder.wrong_length_frames
+            + decoder.out_of_range_frames;
+        let error_rate = match decoder.frames_read {
+            0 => 0.0,
+            n => failed as f64 / n as f64,
+        };
+        let mut out = String::new();
+        writeln!(out, "qr-recv  {}  {:.1}s", self.source, elapsed).unwrap();
+        match &decoder.metadata {
+            Some(md) => writeln!(
+                out,
+                "segments {}/{}  hash {}",
+                decoder.data_segments.len(),
+                md.qrcode_count,
+                if decoder.total_hash.is_empty() {
+                    "pending"
+                } else {
+                    "received"
+                }
+            )
+            .unwrap(),
+            None => writeln!(out, "waiting for metadata").unwrap(),
+        }
+        writeln!(
+            out,
+            "throughput {:.0} bytes/s  frames {}  decode errors {:.1}%",
+            received_bytes as f64 / elapsed.max(f64::EPSILON),
+            decoder.frames_read,
+            error_rate * 100.0
+        )
+        .unwrap();
+        writeln!(out, "last frame: {}", describe_last(decoder)).unwrap();
+        writeln!(out).unwrap();
+        out.push_str(&bitmap(decoder, DASHBOARD_GLYPHS, false));
+        out
+    }
+}
+
+/// The received/missing grid alone on stderr, `#` for a segment held and
+/// `.` for one missing, each row after the id it starts at. On a terminal
+/// it is redrawn in place; otherwise it is printed anew now and then, so a
+/// log file still shows it.
+pub struct AckBitmap {
+    last_draw: Option<Instant>,
+    /// Lines of the grid drawn last, for the cursor to go back up over.
+    lines: usize,
+    in_place: bool,
+}
+impl Default for AckBitmap {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+impl AckBitmap {
+    pub fn new() -> Self {
+        AckBitmap {
+            last_draw: None,
+            lines: 0,
+            in_place: io::stderr().is_terminal(),
+        }
+    }
+
+    /// Redraw unless the grid was drawn recently.
+    pub fn update(&mut self, decoder: &QrSendDecoder) {
+        let interval = if self.in_place {
+            BITMAP_INTERVAL
+        } else {
+            BITMAP_LOG_INTERVAL
+        };
+        if self.last_draw.is_some_and(|t| t.elapsed() < interval) {
+            return;
+        }
+        self.draw(decoder);
+    }
+
+    pub fn draw(&mut self, decoder: &QrSendDecoder) {
+        self.last_draw = Some(Instant::now());
+        let mut grid = match &decoder.metadata {
+            Some(md) => format!(
+                "segments {}/{}\n",
+                decoder.data_segments.len(),
+                md.qrcode_count
+            ),
+            None => "waiting for metadata\n".to_string(),
+        };
+        grid.push_str(&bitmap(decoder, BITMAP_GLYPHS, true));
+        let mut stderr = io::stderr().lock();
+        if self.in_place && self.lines > 0 {
+            // Up to the first line of the last grid, clearing it and below.
+            write!(stderr, "\x1b[{}F\x1b[J", self.lines).unwrap();
+        }
+        write!(stderr, "{}", grid).unwrap();
+        stderr.flush().unwrap();
+        self.lines = grid.lines().count();
+    }
+}
+
+fn describe_last(decoder: &QrSendDecoder) -> String {
+    let Some(frame) = &decoder.last_payload else {
+        return "none".to_string();
+    };
+    match (FrameType::of(frame), &decoder.metadata) {
+        (Some(FrameType::Data), Some(md)) => {
+            let Ok((id, id_len)) = get_id_and_len(&frame[1..], md) else {
+                return format!("D ({} bytes, unparsable)", frame.len());
+            };
+            let content = frame
+                .len()
+                .saturating_sub(1 + id_len + md.hash_len as usize);
+            format!("D id {} ({} content bytes)", id, content)
+        }
+        _ => format!("{} ({} bytes)", frame[0] as char, frame.len()),
+    }
+}
+
+/// Received/missing grid; once there are more segments than cells each cell
+/// covers a run of ids and is drawn half-filled while only some have arrived.
+/// With `labels` each row starts with the first id it covers.
+fn bitmap(decoder: &QrSendDecoder, glyphs: Glyphs, labels: bool) -> String {
+    let Some(md) = &decoder.metadata else {
+        return String::new();
+    };
+    let count = md.qrcode_count;
+    let per_cell = count.div_ceil(GRID_COLUMNS * GRID_ROWS).max(1);
+    let cells = count.div_ceil(per_cell);
+    let label_width = (count.max(1) - 1).to_string().len();
+    let mut out = String::new();
+    for cell in 0..cells {
+        if labels && cell % GRID_COLUMNS == 0 {
+            write!(out, "{:>label_width$} ", cell * per_cell).unwrap();
+        }
+        let ids = cell * per_cell..((cell + 1) * per_cell).min(count);
+        let span = ids.end - ids.start;
+        let have = decoder.data_segments.count_in(ids);
+        out.push(if have == span {
+            glyphs[0]
+        } else if have > 0 {
+            glyphs[1]
+        } else {
+            glyphs[2]
+        });
+        if (cell + 1) % GRID_COLUMNS == 0 {
+            out.push('\n');
+        }
+    }
+    if cells % GRID_COLUMNS != 0 {
+        out.push('\n');
+    }
+    if per_cell > 1 {
+        writeln!(out, "each cell covers {} segments", per_cell).unwrap();
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::QrSendData;
+    use crate::synth::{seal, Options, Transfer};
+
+    /// A decoder given every frame of `transfer` but the data frames of `dropped`.
+    fn decoded_without(transfer: &Transfer, dropped: &[usize]) -> QrSendDecoder {
+        let data = transfer.frame_indices(b'D');
+        let mut decoder = QrSendDecoder::new();
+        for (i, frame) in transfer.frames.iter().enumerate() {
+            if !dropped.iter().any(|&id| data[id] == i) {
+                decoder.push(frame.clone());
+            }
+        }
+        decoder
+    }
+
+    #[test]
+    fn bitmap_marks_missing_segments_by_row() {
+        let transfer = Transfer::new(&Options {
+            size: 200 * 20,
+            chunk_size: 20,
+            ..Options::default()
+        });
+        let decoder = decoded_without(&transfer, &[0, 63, 64, 65, 199]);
+        let row = |label: &str, cells: String| format!("{} {}\n", label, cells);
+        let expected = [
+            row("  0", format!(".{}.", "#".repeat(62))),
+            row(" 64", format!("..{}", "#".repeat(62))),
+            row("128", "#".repeat(64)),
+            row("192", format!("{}.", "#".repeat(7))),
+        ]
+        .concat();
+        assert_eq!(bitmap(&decoder, BITMAP_GLYPHS, true), expected);
+    }
+
+    #[test]
+    fn bitmap_cells_cover_runs_past_the_grid() {
+        let mut decoder = QrSendDecoder::new();
+        decoder.push(seal(
+            br#"M{"qrcode_count":2048,"id_type":"u16","hash_len":8}"#,
+            8,
+        ));
+        for id in (0..2048).filter(|id| ![1, 2046, 2047].contains(id)) {
+            decoder.data_segments.insert(QrSendData::new(id, b"x", &[]));
+        }
+        let grid = bitmap(&decoder, BITMAP_GLYPHS, false);
+        let lines: Vec<&str> = grid.lines().collect();
+        assert_eq!(lines.len(), 17);
+        assert_eq!(lines[0], format!("+{}", "#".repeat(63)));
+        assert_eq!(lines[15], format!("{}.", "#".repeat(63)));
+        assert_eq!(lines[16], "each cell covers 2 segments");
+    }
+}
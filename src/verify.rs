@@ -0,0 +1,76 @@
+use std::process::ExitCode;
+use std::{fs, io, io::Read, path};
+
+use crate::decoder::QrSendDecoder;
+use crate::images::{ImageSequence, ReadOptions};
+use crate::protocol::FileHash;
+use crate::receive::Outcome;
+
+#[derive(clap::Args)]
+pub struct VerifyArgs {
+    /// Previously assembled output file
+    #[clap(short, long)]
+    file: String,
+    /// Capture directory holding the transfer's hash frame and metadata
+    #[clap(long)]
+    from: String,
+    #[clap(flatten)]
+    read: ReadOptions,
+}
+
+fn file_digest(path: &str, hash: FileHash) -> io::Result<Vec<u8>> {
+    let mut file = fs::File::open(path)?;
+    let mut hasher = hash.hasher();
+    let mut buf = vec![0u8; 1 << 16];
+    loop {
+        let n = file.read(&mut buf)?;
+        if n == 0 {
+            return Ok(hasher.finalize());
+        }
+        hasher.update(&buf[..n]);
+    }
+}
+
+/// Check an existing file against the hash frame of a capture, without
+/// assembling. Exits as `receive` would for the same result.
+pub fn run(args: &VerifyArgs) -> ExitCode {
+    let img_seq = ImageSequence {
+        image_dir: path::PathBuf::from(&args.from),
+        options: args.read.clone(),
+    };
+    let mut decoder = QrSendDecoder::new();
+    decoder.rotation_retry = !args.read.no_rotation_retry;
+    decoder.perspective = args.read.perspective;
+    decoder.exposure_retry = !args.read.no_exposure_retry;
+    decoder.pad_retry = !args.read.no_pad_retry;
+    decoder.scale_retry = !args.read.no_scale_retry;
+    decoder.crop = args.read.crop;
+    decoder.cache = args.read.open_cache();
+    decoder.readers = args.read.readers();
+    decoder.get_hash(&mut img_seq.into_iter());
+    if decoder.total_hash.is_empty() {
+        println!("no hash frame found in {}", args.from);
+        return Outcome::NoMetadata.exit_status(false);
+    }
+    let hash = decoder.file_hash();
+    let computed = match file_digest(&args.file, hash) {
+        Ok(computed) => computed,
+        Err(e) => {
+            log::error!("could not read {}: {}", args.file, e);
+            return ExitCode::FAILURE;
+        }
+    };
+    println!(
+        "expected {}: {}",
+        hash.name(),
+        hex::encode(&decoder.total_hash)
+    );
+    println!("computed {}: {}", hash.name(), hex::encode(&computed));
+    if computed == decoder.total_hash {
+        println!("{} check passed", hash.name());
+        ExitCode::SUCCESS
+    } else {
+        println!("{} check failed", hash.name());
+        Outcome::HashMismatch.exit_status(false)
+    }
+}
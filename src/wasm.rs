@@ -0,0 +1,104 @@
+//! Browser bindings over [`Decoder`].
+//!
+//! Scanning is left to the page, e.g. a `getUserMedia` stream fed through a
+//! JavaScript qrcode reader; each symbol's text or decoded bytes is pushed
+//! here and the protocol is handled on the Rust side.
+
+use wasm_bindgen::prelude::*;
+
+use crate::api::{Decoder, Progress};
+
+#[wasm_bindgen]
+pub struct Receiver {
+    inner: Decoder,
+}
+
+/// Snapshot of [`Progress`] for JavaScript.
+#[wasm_bindgen(js_name = Progress)]
+pub struct JsProgress {
+    total: Option<u64>,
+    received: u64,
+    has_hash: bool,
+    complete: bool,
+}
+#[wasm_bindgen(js_class = Progress)]
+impl JsProgress {
+    /// Number of data segments, `undefined` until the metadata arrives.
+    #[wasm_bindgen(getter)]
+    pub fn total(&self) -> Option<u64> {
+        self.total
+    }
+    #[wasm_bindgen(getter)]
+    pub fn received(&self) -> u64 {
+        self.received
+    }
+    #[wasm_bindgen(getter)]
+    pub fn has_hash(&self) -> bool {
+        self.has_hash
+    }
+    #[wasm_bindgen(getter)]
+    pub fn complete(&self) -> bool {
+        self.complete
+    }
+}
+impl From<Progress> for JsProgress {
+    fn from(p: Progress) -> Self {
+        JsProgress {
+            total: p.total,
+            received: p.received,
+            has_hash: p.has_hash,
+            complete: p.complete,
+        }
+    }
+}
+
+fn js_error(e: crate::Error) -> JsError {
+    JsError::new(&e.to_string())
+}
+
+impl Default for Receiver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[wasm_bindgen]
+impl Receiver {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Receiver {
+        Receiver {
+            inner: Decoder::new(),
+        }
+    }
+
+    /// Feed decoded frame bytes; throws if the frame fails its hash.
+    pub fn push_frame(&mut self, frame: &[u8]) -> Result<JsProgress, JsError> {
+        self.inner
+            .push_frame(frame)
+            .map(Into::into)
+            .map_err(js_error)
+    }
+
+    /// Feed the content of a scanned qrcode.
+    pub fn push_text(&mut self, text: &str) -> Result<JsProgress, JsError> {
+        self.inner
+            .push_text(text.as_bytes())
+            .map(Into::into)
+            .map_err(js_error)
+    }
+
+    pub fn progress(&self) -> JsProgress {
+        self.inner.progress().into()
+    }
+
+    /// Segments still missing, for showing the sender what to repeat, as
+    /// `17-20,944` runs rather than one id each.
+    pub fn missing(&self) -> String {
+        format!("{:#}", self.inner.missing())
+    }
+
+    /// Assemble and verify the file; consumes the receiver.
+    pub fn finish(self) -> Result<Vec<u8>, JsError> {
+        self.inner.finish().map_err(js_error)
+    }
+}
@@ -0,0 +1,933 @@
+//! Full decoder runs over synthetic transfers.
+#![cfg(feature = "zbar")]
+
+use base64::prelude::*;
+use qr_recv::prelude::*;
+use qr_recv::synth::{
+    decode_metadata, seal, Endianness, FileHash, Frame, MetadataChunk, MetadataFormat, Options,
+    PayloadEncoding, Protocol, ProtocolKind, Rng, SplitText, Transfer,
+};
+
+fn receive_images<'a>(images: impl IntoIterator<Item = &'a image::DynamicImage>) -> Decoder {
+    let mut decoder = Decoder::new();
+    for img in images {
+        decoder.push_image(img);
+    }
+    decoder
+}
+
+#[test]
+fn in_order_across_id_types_and_hash_lengths() {
+    for (seed, id_type) in [None, Some("u8"), Some("u16"), Some("u32"), Some("u64")]
+        .into_iter()
+        .enumerate()
+    {
+        for hash_len in [1, 8, 32, 64] {
+            let options = Options {
+                size: 1500,
+                chunk_size: 200,
+                hash_len,
+                id_type,
+                seed: seed as u64,
+                ..Options::default()
+            };
+            let transfer = Transfer::new(&options);
+            let decoder = receive_images(&transfer.images);
+            assert!(decoder.progress().complete, "{:?}", options);
+            assert_eq!(decoder.finish().unwrap(), transfer.file, "{:?}", options);
+        }
+    }
+}
+
+#[test]
+fn frame_sizes() {
+    for (size, chunk_size) in [(1, 512), (511, 512), (512, 512), (513, 512), (3000, 64)] {
+        let transfer = Transfer::new(&Options {
+            size,
+            chunk_size,
+            ..Options::default()
+        });
+        let decoder = receive_images(&transfer.images);
+        assert_eq!(
+            decoder.finish().unwrap(),
+            transfer.file,
+            "{} / {}",
+            size,
+            chunk_size
+        );
+    }
+}
+
+#[test]
+fn empty_file() {
+    let transfer = Transfer::new(&Options {
+        size: 0,
+        ..Options::default()
+    });
+    let decoder = receive_images(&transfer.images);
+    assert_eq!(decoder.finish().unwrap(), Vec::<u8>::new());
+}
+
+#[test]
+fn shuffled_data_frames() {
+    let transfer = Transfer::new(&Options {
+        size: 5000,
+        chunk_size: 256,
+        ..Options::default()
+    });
+    let mut order = transfer.frame_indices(b'D');
+    order.extend(transfer.frame_indices(b'H'));
+    Rng::new(7).shuffle(&mut order);
+    let metadata = transfer.frame_indices(b'M');
+    let decoder = receive_images(metadata.iter().chain(&order).map(|&i| &transfer.images[i]));
+    assert_eq!(decoder.finish().unwrap(), transfer.file);
+}
+
+#[test]
+fn fully_shuffled_looping_sender() {
+    let transfer = Transfer::new(&Options {
+        size: 5000,
+        chunk_size: 256,
+        ..Options::default()
+    });
+    let mut rng = Rng::new(11);
+    let mut order: Vec<usize> = (0..transfer.images.len()).collect();
+    let mut decoder = Decoder::new();
+    for _ in 0..2 {
+        rng.shuffle(&mut order);
+        for &i in &order {
+            decoder.push_image(&transfer.images[i]);
+        }
+    }
+    assert_eq!(decoder.finish().unwrap(), transfer.file);
+}
+
+#[test]
+fn any_frame_order_gives_the_same_file() {
+    for (metadata_format, hash_len) in [(MetadataFormat::Json, 8), (MetadataFormat::Cbor, 3)] {
+        let transfer = Transfer::new(&Options {
+            size: 3000,
+            chunk_size: 200,
+            hash_len,
+            metadata_format,
+            ..Options::default()
+        });
+        for seed in 0..16 {
+            let mut order: Vec<usize> = (0..transfer.frames.len()).collect();
+            Rng::new(seed).shuffle(&mut order);
+            let mut decoder = Decoder::new();
+            for &i in &order {
+                decoder.push_frame(&transfer.frames[i]).unwrap();
+            }
+            assert_eq!(decoder.finish().unwrap(), transfer.file, "seed {}", seed);
+        }
+    }
+}
+
+#[test]
+fn missing_frames_are_reported() {
+    let transfer = Transfer::new(&Options {
+        size: 3000,
+        chunk_size: 200,
+        ..Options::default()
+    });
+    let data = transfer.frame_indices(b'D');
+    let dropped = [data[0], data[4], data[data.len() - 1]];
+    let decoder = receive_images(
+        (0..transfer.images.len())
+            .filter(|i| !dropped.contains(i))
+            .map(|i| &transfer.images[i]),
+    );
+    let progress = decoder.progress();
+    assert!(!progress.complete);
+    assert_eq!(progress.total, Some(data.len() as u64));
+    assert_eq!(progress.received, data.len() as u64 - 3);
+    let expected = vec![0, 4, data.len() as u64 - 1];
+    assert_eq!(decoder.missing().ids().collect::<Vec<_>>(), expected);
+    match decoder.finish() {
+        Err(Error::Incomplete { missing }) => {
+            assert_eq!(missing.ids().collect::<Vec<_>>(), expected)
+        }
+        other => panic!(
+            "expected missing segments, got {:?}",
+            other.map(|f| f.len())
+        ),
+    }
+}
+
+#[test]
+fn missing_hash_frame() {
+    let transfer = Transfer::new(&Options::default());
+    let hash = transfer.frame_indices(b'H');
+    let decoder = receive_images(
+        (0..transfer.images.len())
+            .filter(|i| !hash.contains(i))
+            .map(|i| &transfer.images[i]),
+    );
+    assert!(!decoder.progress().has_hash);
+    assert!(matches!(decoder.finish(), Err(Error::NoHash)));
+}
+
+#[test]
+fn missing_metadata() {
+    let transfer = Transfer::new(&Options::default());
+    let metadata = transfer.frame_indices(b'M');
+    let decoder = receive_images(
+        (0..transfer.images.len())
+            .filter(|i| !metadata.contains(i))
+            .map(|i| &transfer.images[i]),
+    );
+    assert_eq!(decoder.progress().total, None);
+    assert!(matches!(decoder.finish(), Err(Error::NoMetadata)));
+}
+
+#[test]
+fn duplicated_frames() {
+    let transfer = Transfer::new(&Options {
+        size: 3000,
+        chunk_size: 200,
+        ..Options::default()
+    });
+    let mut rng = Rng::new(3);
+    let mut order: Vec<usize> = (0..transfer.images.len()).collect();
+    for _ in 0..20 {
+        let i = rng.below(transfer.images.len());
+        order.insert(i, order[i]);
+    }
+    let decoder = receive_images(order.iter().map(|&i| &transfer.images[i]));
+    assert_eq!(
+        decoder.progress().received,
+        transfer.frame_indices(b'D').len() as u64
+    );
+    assert_eq!(decoder.finish().unwrap(), transfer.file);
+}
+
+#[test]
+fn text_in_every_payload_encoding() {
+    for metadata_format in [MetadataFormat::Json, MetadataFormat::Cbor] {
+        for payload_encoding in [
+            PayloadEncoding::Base64,
+            PayloadEncoding::Base64Url,
+            PayloadEncoding::Base45,
+            PayloadEncoding::Base32,
+            PayloadEncoding::Raw,
+        ] {
+            let transfer = Transfer::new(&Options {
+                size: 2000,
+                chunk_size: 128,
+                payload_encoding,
+                metadata_format,
+                ..Options::default()
+            });
+            let mut decoder = Decoder::new();
+            for text in &transfer.texts {
+                decoder.push_text(text).unwrap();
+            }
+            assert_eq!(
+                decoder.finish().unwrap(),
+                transfer.file,
+                "{:?} / {:?}",
+                metadata_format,
+                payload_encoding
+            );
+        }
+    }
+}
+
+#[test]
+fn stray_codes_are_skipped() {
+    let transfer = Transfer::new(&Options {
+        size: 2000,
+        chunk_size: 200,
+        ..Options::default()
+    });
+    let mut decoder = Decoder::new();
+    for text in &transfer.texts {
+        for stray in [
+            &b"WIFI:S:Office;T:WPA;P:hunter2;;"[..],
+            b"https://example.com/",
+        ] {
+            assert!(matches!(decoder.push_text(stray), Err(Error::InvalidFrame)));
+        }
+        decoder.push_text(text).unwrap();
+    }
+    assert_eq!(decoder.finish().unwrap(), transfer.file);
+}
+
+#[test]
+fn unmarked_codes_are_skipped_once_magic_is_declared() {
+    let options = Options {
+        size: 2000,
+        chunk_size: 200,
+        ..Options::default()
+    };
+    let marked = Transfer::new(&Options {
+        magic: true,
+        ..options.clone()
+    });
+    // The same frames without the prefix, as a second sender would show.
+    let unmarked = Transfer::new(&options);
+    let mut decoder = Decoder::new();
+    for (text, stray) in marked.texts.iter().zip(&unmarked.texts) {
+        decoder.push_text(text).unwrap();
+        assert!(matches!(decoder.push_text(stray), Err(Error::InvalidFrame)));
+    }
+    assert_eq!(decoder.finish().unwrap(), marked.file);
+}
+
+#[test]
+fn corrupted_frames_are_rejected() {
+    let transfer = Transfer::new(&Options {
+        size: 2000,
+        chunk_size: 200,
+        ..Options::default()
+    });
+    let mut decoder = Decoder::new();
+    let mut rng = Rng::new(5);
+    for frame in &transfer.frames {
+        if frame[0] == b'D' {
+            let mut corrupt = frame.clone();
+            let at = 1 + rng.below(corrupt.len() - 1);
+            corrupt[at] ^= 0x40;
+            assert!(matches!(
+                decoder.push_frame(&corrupt),
+                Err(Error::InvalidFrame)
+            ));
+        }
+        decoder.push_frame(frame).unwrap();
+    }
+    assert_eq!(decoder.finish().unwrap(), transfer.file);
+}
+
+#[test]
+fn reserved_and_unknown_frame_types_are_skipped() {
+    let transfer = Transfer::new(&Options {
+        size: 2000,
+        chunk_size: 200,
+        ..Options::default()
+    });
+    let mut decoder = Decoder::new();
+    for (i, frame) in transfer.frames.iter().enumerate() {
+        // Parity, signature, nack and an unregistered tag.
+        let tag = b"PGNX"[i % 4];
+        decoder.push_frame(&seal(&[tag, 1, 2, 3], 8)).unwrap();
+        decoder.push_frame(frame).unwrap();
+    }
+    assert_eq!(decoder.finish().unwrap(), transfer.file);
+}
+
+#[test]
+fn undersized_data_frames_are_rejected() {
+    let transfer = Transfer::new(&Options {
+        size: 2000,
+        chunk_size: 200,
+        hash_len: 4,
+        id_type: Some("u32"),
+        ..Options::default()
+    });
+    let mut decoder = Decoder::new();
+    for &i in &transfer.frame_indices(b'M') {
+        decoder.push_frame(&transfer.frames[i]).unwrap();
+    }
+    // Sealed correctly, but too short for a four byte id.
+    for body in [&b"D"[..], b"D\x00", b"D\x00\x00\x01"] {
+        assert!(matches!(
+            decoder.push_frame(&seal(body, 4)),
+            Err(Error::InvalidFrame)
+        ));
+    }
+    assert_eq!(decoder.progress().received, 0);
+}
+
+#[test]
+fn data_frames_of_the_wrong_length_are_rejected() {
+    let transfer = Transfer::new(&Options {
+        size: 1950,
+        chunk_size: 200,
+        hash_len: 4,
+        id_type: Some("u32"),
+        ..Options::default()
+    });
+    let mut decoder = Decoder::new();
+    for &i in &transfer.frame_indices(b'M') {
+        decoder.push_frame(&transfer.frames[i]).unwrap();
+    }
+    // Resealed one byte short or long, as a bit error could leave them.
+    for &i in &transfer.frame_indices(b'D') {
+        let body = &transfer.frames[i][..transfer.frames[i].len() - 4];
+        let mut long = body.to_vec();
+        long.push(0);
+        for bad in [&body[..body.len() - 1], &long[..]] {
+            assert!(matches!(
+                decoder.push_frame(&seal(bad, 4)),
+                Err(Error::InvalidFrame)
+            ));
+        }
+    }
+    assert_eq!(decoder.progress().received, 0);
+    for frame in &transfer.frames {
+        decoder.push_frame(frame).unwrap();
+    }
+    assert_eq!(decoder.finish().unwrap(), transfer.file);
+}
+
+#[test]
+fn data_frames_past_the_segment_count_are_rejected() {
+    let transfer = Transfer::new(&Options {
+        size: 1000,
+        chunk_size: 200,
+        hash_len: 4,
+        id_type: Some("u32"),
+        ..Options::default()
+    });
+    let count = transfer.frame_indices(b'D').len() as u32;
+    let mut decoder = Decoder::new();
+    for &i in &transfer.frame_indices(b'M') {
+        decoder.push_frame(&transfer.frames[i]).unwrap();
+    }
+    // A data frame renumbered to one past the last segment and resealed.
+    let first = &transfer.frames[transfer.frame_indices(b'D')[0]];
+    let mut renumbered = first[..first.len() - 4].to_vec();
+    renumbered[1..5].copy_from_slice(&count.to_be_bytes());
+    assert!(matches!(
+        decoder.push_frame(&seal(&renumbered, 4)),
+        Err(Error::InvalidFrame)
+    ));
+    for frame in &transfer.frames {
+        decoder.push_frame(frame).unwrap();
+    }
+    assert_eq!(decoder.finish().unwrap(), transfer.file);
+}
+
+#[test]
+fn missing_segments_across_bitmap_words() {
+    let transfer = Transfer::new(&Options {
+        size: 200 * 20,
+        chunk_size: 20,
+        ..Options::default()
+    });
+    let dropped = [0, 63, 64, 65, 127, 128, 199];
+    let data = transfer.frame_indices(b'D');
+    let mut decoder = Decoder::new();
+    for (i, frame) in transfer.frames.iter().enumerate() {
+        match data.iter().position(|&d| d == i) {
+            Some(id) if dropped.contains(&(id as u64)) => {}
+            _ => {
+                decoder.push_frame(frame).unwrap();
+            }
+        }
+    }
+    let missing = decoder.missing();
+    assert_eq!(missing.ids().collect::<Vec<_>>(), dropped);
+    assert_eq!(missing.to_string(), "0, 63-65, 127-128, 199");
+    assert_eq!(decoder.progress().received, 200 - dropped.len() as u64);
+}
+
+#[test]
+fn forged_segment_count_reports_one_run() {
+    let hash_len = Options::default().hash_len as usize;
+    let json = format!(
+        r#"M{{"qrcode_count":{},"id_type":"u64","hash_len":{}}}"#,
+        u64::MAX,
+        hash_len
+    );
+    let mut decoder = Decoder::new();
+    decoder
+        .push_frame(&seal(json.as_bytes(), hash_len))
+        .unwrap();
+    assert_eq!(decoder.progress().total, Some(u64::MAX));
+    let missing = decoder.missing();
+    assert_eq!(missing.len(), u64::MAX);
+    assert_eq!(missing.iter().count(), 1);
+    match decoder.finish() {
+        Err(Error::Incomplete { missing }) => assert_eq!(missing.len(), u64::MAX),
+        other => panic!(
+            "expected missing segments, got {:?}",
+            other.map(|f| f.len())
+        ),
+    }
+}
+
+#[test]
+fn huge_declared_counts_do_not_size_the_segment_table() {
+    let mut decoder = Decoder::new();
+    let metadata = br#"M{"qrcode_count":4294967295,"id_type":"u32","hash_len":8}"#;
+    decoder.push_frame(&seal(metadata, 8)).unwrap();
+    let mut data = vec![b'D'];
+    data.extend_from_slice(&4294967290u32.to_be_bytes());
+    data.extend_from_slice(b"content");
+    let progress = decoder.push_frame(&seal(&data, 8)).unwrap();
+    assert_eq!(progress.total, Some(4294967295));
+    assert_eq!(progress.received, 1);
+}
+
+#[test]
+fn segments_that_overflow_the_declared_file_are_refused() {
+    let mut decoder = Decoder::new();
+    let metadata = br#"M{"qrcode_count":4294967296,"id_type":"u64","hash_len":8,"segment_size":8589934592,"file_size":5}"#;
+    decoder.push_frame(&seal(metadata, 8)).unwrap();
+    let mut data = vec![b'D'];
+    data.extend_from_slice(&4294967295u64.to_be_bytes());
+    data.extend_from_slice(b"12345");
+    decoder.push_frame(&seal(&data, 8)).unwrap();
+    assert_eq!(decoder.progress().total, None);
+    assert_eq!(decoder.progress().received, 0);
+}
+
+#[test]
+fn crc32c_checked_data_frames() {
+    let transfer = Transfer::new(&Options {
+        size: 2000,
+        chunk_size: 200,
+        crc32c: true,
+        ..Options::default()
+    });
+    assert_eq!(
+        receive_images(&transfer.images).finish().unwrap(),
+        transfer.file
+    );
+
+    let mut decoder = Decoder::new();
+    for frame in &transfer.frames {
+        if frame[0] == b'D' {
+            let mut bad_crc = frame.clone();
+            let last = bad_crc.len() - 1;
+            bad_crc[last] ^= 1;
+            assert!(matches!(
+                decoder.push_frame(&bad_crc),
+                Err(Error::InvalidFrame)
+            ));
+        }
+        decoder.push_frame(frame).unwrap();
+    }
+    assert_eq!(decoder.finish().unwrap(), transfer.file);
+}
+
+#[test]
+fn finish_into_a_writer() {
+    let transfer = Transfer::new(&Options {
+        size: 10_000,
+        chunk_size: 300,
+        ..Options::default()
+    });
+    let mut decoder = Decoder::new();
+    for frame in &transfer.frames {
+        decoder.push_frame(frame).unwrap();
+    }
+    let mut out = Vec::new();
+    assert_eq!(decoder.finish_into(&mut out).unwrap(), 10_000);
+    assert_eq!(out, transfer.file);
+}
+
+#[test]
+fn every_file_hash() {
+    for (file_hash, len) in [
+        (FileHash::Md5, 16),
+        (FileHash::Sha256, 32),
+        (FileHash::Blake3, 32),
+    ] {
+        let transfer = Transfer::new(&Options {
+            size: 5000,
+            file_hash,
+            ..Options::default()
+        });
+        let hash = &transfer.frames[transfer.frame_indices(b'H')[0]];
+        assert_eq!(hash.len(), 1 + len + 8);
+        let mut decoder = Decoder::new();
+        for frame in &transfer.frames {
+            decoder.push_frame(frame).unwrap();
+        }
+        assert_eq!(decoder.finish().unwrap(), transfer.file);
+
+        let mut wrong = hash[..1 + len].to_vec();
+        wrong[1] ^= 1;
+        let mut decoder = Decoder::new();
+        for frame in &transfer.frames {
+            let frame = match frame[0] {
+                b'H' => seal(&wrong, 8),
+                _ => frame.clone(),
+            };
+            decoder.push_frame(&frame).unwrap();
+        }
+        assert!(matches!(decoder.finish(), Err(Error::HashMismatch)));
+    }
+}
+
+#[test]
+fn varint_and_little_endian_ids() {
+    for (size, chunk_size) in [(2000, 100), (40000, 128)] {
+        for endianness in [Endianness::Big, Endianness::Little] {
+            for id_type in ["u16", "u32", "varint"] {
+                let options = Options {
+                    size,
+                    chunk_size,
+                    id_type: Some(id_type),
+                    endianness,
+                    ..Options::default()
+                };
+                let transfer = Transfer::new(&options);
+                let mut decoder = Decoder::new();
+                for frame in &transfer.frames {
+                    decoder.push_frame(frame).unwrap();
+                }
+                assert_eq!(decoder.finish().unwrap(), transfer.file, "{:?}", options);
+            }
+        }
+    }
+}
+
+/// Metadata chunk count of an indexed transfer.
+fn metadata_chunks(transfer: &Transfer) -> usize {
+    transfer.frames[transfer.frame_indices(b'M')[0]][3] as usize
+}
+
+#[test]
+fn repeated_metadata_survives_missed_and_reordered_chunks() {
+    for metadata_format in [MetadataFormat::Json, MetadataFormat::Cbor] {
+        let transfer = Transfer::new(&Options {
+            size: 3000,
+            chunk_size: 24,
+            metadata_format,
+            metadata_repeat: Some(5),
+            ..Options::default()
+        });
+        let per_copy = metadata_chunks(&transfer);
+        assert!(per_copy > 1, "metadata should span several chunks");
+        // Copy k loses its chunk k, so no single copy is whole, and the
+        // chunks that remain arrive in reverse.
+        let metadata = transfer.frame_indices(b'M');
+        let mut order: Vec<usize> = (0..transfer.frames.len())
+            .filter(|i| match metadata.iter().position(|m| m == i) {
+                Some(n) => n % per_copy != n / per_copy,
+                None => true,
+            })
+            .collect();
+        order[..per_copy - 1].reverse();
+        let mut decoder = Decoder::new();
+        // A looping sender: the second pass fills what the first lost
+        // before the metadata was known.
+        for _ in 0..2 {
+            for &i in &order {
+                decoder.push_frame(&transfer.frames[i]).unwrap();
+            }
+        }
+        assert_eq!(
+            decoder.finish().unwrap(),
+            transfer.file,
+            "{:?}",
+            metadata_format
+        );
+    }
+}
+
+#[test]
+fn sequential_json_metadata_split_inside_non_ascii_text() {
+    // Every character of the name starts with 0xc2 in UTF-8.
+    let json = r#"{"qrcode_count":1,"id_type":"u8","hash_len":8,"file_name":"©°£±x.bin"}"#;
+    let json = json.as_bytes();
+    for split in 1..json.len() {
+        let mut decoder = Decoder::new();
+        for piece in [&json[..split], &json[split..]] {
+            decoder
+                .push_frame(&seal(&[b"M", piece].concat(), 8))
+                .unwrap();
+        }
+        assert_eq!(decoder.progress().total, Some(1), "split at {}", split);
+    }
+}
+
+#[test]
+fn checked_metadata_chunks_in_any_order() {
+    for metadata_format in [MetadataFormat::Json, MetadataFormat::Cbor] {
+        let transfer = Transfer::new(&Options {
+            size: 3000,
+            chunk_size: 24,
+            metadata_format,
+            metadata_checksum: true,
+            ..Options::default()
+        });
+        assert!(metadata_chunks(&transfer) > 1);
+        let metadata = transfer.frame_indices(b'M');
+        // Without its last chunk the metadata never completes.
+        let mut decoder = Decoder::new();
+        for (i, frame) in transfer.frames.iter().enumerate() {
+            if i != *metadata.last().unwrap() {
+                decoder.push_frame(frame).unwrap();
+            }
+        }
+        assert!(matches!(decoder.finish(), Err(Error::NoMetadata)));
+        let mut decoder = Decoder::new();
+        for frame in transfer.frames.iter().rev() {
+            decoder.push_frame(frame).unwrap();
+        }
+        assert_eq!(
+            decoder.finish().unwrap(),
+            transfer.file,
+            "{:?}",
+            metadata_format
+        );
+    }
+}
+
+#[test]
+fn frame_parse_never_panics() {
+    let transfer = Transfer::new(&Options {
+        size: 2000,
+        chunk_size: 100,
+        ..Options::default()
+    });
+    let hash_len = Options::default().hash_len as usize;
+    let mut json = Vec::new();
+    for i in transfer.frame_indices(b'M') {
+        let Ok(Frame::Metadata(MetadataChunk::Json(piece))) =
+            Frame::parse(&transfer.frames[i], hash_len, None)
+        else {
+            panic!("metadata frame {} does not parse", i);
+        };
+        json.extend_from_slice(piece);
+    }
+    let md = decode_metadata(&json).unwrap();
+    for (i, frame) in transfer.frames.iter().enumerate() {
+        let parsed = Frame::parse(frame, hash_len, Some(&md)).unwrap();
+        if let Frame::Data { id, content } = parsed {
+            assert_eq!(
+                content,
+                &transfer.file[id as usize * 100..][..content.len()]
+            );
+        } else {
+            assert!(frame[0] != b'D', "frame {}: {:?}", i, parsed);
+        }
+    }
+    // Random bytes, and every frame cut short and with a bit flipped.
+    let mut rng = Rng::new(13);
+    let mut inputs: Vec<Vec<u8>> = (0..200).map(|i| rng.bytes(i % 80)).collect();
+    for frame in &transfer.frames {
+        inputs.extend((0..frame.len()).map(|end| frame[..end].to_vec()));
+        let mut flipped = frame.clone();
+        flipped[rng.below(frame.len())] ^= 1 << rng.below(8);
+        inputs.push(flipped);
+    }
+    for input in &inputs {
+        for hash_len in 0..=64 {
+            let _ = Frame::parse(input, hash_len, None);
+            if let Ok(Frame::Data { content, .. }) = Frame::parse(input, hash_len, Some(&md)) {
+                assert!(content.len() < input.len());
+            }
+        }
+    }
+    // The same inputs through decoders given metadata with arbitrary
+    // counts, sizes and id types, sealed and as they are.
+    let id_types = ["u8", "u16", "u32", "u64", "varint", "u24", ""];
+    let pick = |rng: &mut Rng| match rng.below(4) {
+        0 => 0,
+        1 => rng.below(300) as u64,
+        2 => u64::MAX - rng.below(3) as u64,
+        _ => rng.next_u64(),
+    };
+    let mut accepted = 0;
+    for _ in 0..64 {
+        let mut json = format!(
+            r#"{{"qrcode_count":{},"id_type":"{}","hash_len":{}"#,
+            pick(&mut rng),
+            id_types[rng.below(id_types.len())],
+            hash_len
+        );
+        for field in ["segment_size", "file_size"] {
+            if rng.below(2) == 0 {
+                json += &format!(r#","{}":{}"#, field, pick(&mut rng));
+            }
+        }
+        json.push('}');
+        let mut decoder = Decoder::new();
+        let _ = decoder.push_frame(&seal(&[b"M", json.as_bytes()].concat(), hash_len));
+        if decoder.progress().total.is_some() {
+            accepted += 1;
+        }
+        for input in inputs.iter().step_by(13) {
+            let _ = decoder.push_frame(input);
+            let _ = decoder.push_frame(&seal(input, hash_len));
+        }
+        let total = decoder.progress().total;
+        assert!(decoder.missing().len() <= total.unwrap_or(0));
+        let _ = decoder.finish();
+    }
+    assert!(accepted > 0, "no metadata was accepted");
+}
+
+#[test]
+fn metadata_copies_are_majority_voted() {
+    let transfer = Transfer::new(&Options {
+        size: 3000,
+        chunk_size: 24,
+        metadata_repeat: Some(5),
+        ..Options::default()
+    });
+    let per_copy = metadata_chunks(&transfer);
+    let first = &transfer.frames[transfer.frame_indices(b'M')[0]];
+    // A chunk that seals but claims protocol version 2.
+    let body = &first[..first.len() - 8];
+    let at = body.windows(3).position(|w| w == b"1.0").unwrap();
+    let mut forged = body.to_vec();
+    forged[at] = b'2';
+    let mut decoder = Decoder::new();
+    decoder.push_frame(&seal(&forged, 8)).unwrap();
+    for frame in &transfer.frames {
+        decoder.push_frame(frame).unwrap();
+    }
+    for frame in &transfer.frames {
+        decoder.push_frame(frame).unwrap();
+    }
+    assert!(per_copy > 1);
+    assert_eq!(decoder.unsupported_version(), None);
+    assert_eq!(decoder.finish().unwrap(), transfer.file);
+}
+
+#[test]
+fn color_channels_carry_three_frames_per_image() {
+    let options = Options {
+        size: 3000,
+        chunk_size: 200,
+        color_channels: true,
+        ..Options::default()
+    };
+    let transfer = Transfer::new(&options);
+    assert!(transfer.images.len() * 2 < transfer.frames.len());
+    let decoder = receive_images(&transfer.images);
+    assert!(decoder.progress().complete);
+    assert_eq!(decoder.finish().unwrap(), transfer.file);
+}
+
+#[test]
+fn split_text_pieces_numbered_from_one_or_zero() {
+    let file = Rng::new(4).bytes(2000);
+    let pieces: Vec<&[u8]> = file.chunks(300).collect();
+    for (base, separator) in [(1, " "), (0, ":"), (1, "|")] {
+        let mut frames: Vec<Vec<u8>> = pieces
+            .iter()
+            .enumerate()
+            .map(|(i, piece)| {
+                format!(
+                    "{}/{}{}{}\n",
+                    i + base,
+                    pieces.len(),
+                    separator,
+                    BASE64_STANDARD.encode(piece)
+                )
+                .into_bytes()
+            })
+            .collect();
+        let held = frames.remove(3);
+        frames.push(frames[2].clone());
+        Rng::new(base as u64).shuffle(&mut frames);
+        assert_eq!(ProtocolKind::detect(&held), ProtocolKind::Split);
+        let mut receiver = SplitText::new();
+        for frame in &frames {
+            assert!(receiver.push_text(frame));
+        }
+        match receiver.finish() {
+            Err(Error::Incomplete { missing }) => {
+                assert_eq!(missing.ids().collect::<Vec<_>>(), [3 + base as u64])
+            }
+            other => panic!("expected piece 3 missing, got {:?}", other.map(|f| f.len())),
+        }
+        assert!(receiver.push_text(&held));
+        assert_eq!(receiver.finish().unwrap(), file, "{} {:?}", base, separator);
+    }
+}
+
+#[test]
+fn protocols_are_told_apart() {
+    let transfer = Transfer::new(&Options::default());
+    for text in &transfer.texts {
+        assert_eq!(ProtocolKind::detect(text), ProtocolKind::QrSend);
+    }
+}
+
+#[test]
+fn repeated_hash_frames_keep_the_first() {
+    let transfer = Transfer::new(&Options {
+        size: 2000,
+        chunk_size: 200,
+        ..Options::default()
+    });
+    let hash = transfer.frame_indices(b'H')[0];
+    // A copy sealed correctly but for another file, e.g. a second sender in view.
+    let mut other = transfer.frames[hash][..transfer.frames[hash].len() - 8].to_vec();
+    *other.last_mut().unwrap() ^= 1;
+    let other = seal(&other, 8);
+    let mut decoder = Decoder::new();
+    for _ in 0..2 {
+        for frame in &transfer.frames {
+            decoder.push_frame(frame).unwrap();
+            if frame[0] == b'H' {
+                decoder.push_frame(&other).unwrap();
+            }
+        }
+    }
+    assert_eq!(decoder.finish().unwrap(), transfer.file);
+}
+
+/// Run `future` to completion on this thread, as the simplest executor would.
+fn block_on<F: std::future::Future>(future: F) -> F::Output {
+    struct Unpark(std::thread::Thread);
+    impl std::task::Wake for Unpark {
+        fn wake(self: std::sync::Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+    let waker = std::sync::Arc::new(Unpark(std::thread::current())).into();
+    let mut cx = std::task::Context::from_waker(&waker);
+    let mut future = std::pin::pin!(future);
+    loop {
+        if let std::task::Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+            return output;
+        }
+        std::thread::park();
+    }
+}
+
+#[test]
+fn async_decoder_completes_while_another_task_pushes() {
+    let transfer = Transfer::new(&Options::default());
+    let decoder = AsyncDecoder::new();
+    let start = decoder.progress();
+    let mut watch = decoder.watch();
+    let complete = decoder.complete();
+    let pusher = decoder.clone();
+    let texts = transfer.texts.clone();
+    let pushing = std::thread::spawn(move || {
+        for text in &texts {
+            block_on(pusher.push_text(text)).unwrap();
+        }
+    });
+    assert_ne!(block_on(watch.changed()), start);
+    assert!(block_on(complete).complete);
+    pushing.join().unwrap();
+    assert_eq!(block_on(decoder.finish()).unwrap(), transfer.file);
+}
+
+#[test]
+fn manifest_names_the_transfer() {
+    let manifest = Manifest {
+        transfer_id: "0f8e4a52-3c1d-4b6e-9a7f-2d5c8b1e6f30".to_string(),
+        hostname: Some("sender".to_string()),
+        created: Some(1_700_000_000),
+        comment: Some("nightly backup".to_string()),
+    };
+    let transfer = Transfer::new(&Options {
+        size: 2000,
+        chunk_size: 200,
+        metadata_repeat: Some(4),
+        manifest: Some(manifest.clone()),
+        ..Options::default()
+    });
+    let mut decoder = Decoder::new();
+    for frame in &transfer.frames {
+        decoder.push_frame(frame).unwrap();
+    }
+    // Another sender in view does not displace the first manifest.
+    let other = br#"I{"transfer_id":"another"}"#;
+    decoder.push_frame(&seal(other, 8)).unwrap();
+    assert_eq!(decoder.manifest(), Some(&manifest));
+    assert_eq!(decoder.finish().unwrap(), transfer.file);
+}